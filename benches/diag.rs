@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use zeta_note::{
+    diag,
+    facts::{self, FactsDB, NoteFacts},
+    store::{NoteFile, NoteText, Version},
+};
+
+const LINKS: usize = 500;
+
+/// A workspace with a note that links to every other note, a few links are broken.
+fn facts_with_links() -> FactsDB {
+    let root = Path::new("/notes");
+    let mut facts = FactsDB::empty();
+    let mut insert = |name: &str, content: String| {
+        let file = NoteFile::new(root, &root.join(format!("{}.md", name)));
+        facts.insert_note(file, NoteText::new(Version::Vs(0), content.into()));
+    };
+
+    let mut links = String::from("# Links\n\n");
+    for i in 0..LINKS {
+        links.push_str(&format!("- [:note-{}@## Heading]\n", i));
+        if i % 10 != 0 {
+            insert(
+                &format!("note-{}", i),
+                format!("# Note {}\n\n## Heading\n", i),
+            );
+        }
+    }
+    insert("links", links);
+
+    facts
+}
+
+fn check_intern_links(c: &mut Criterion) {
+    let facts = facts_with_links();
+    let id = facts
+        .note_index()
+        .find_by_path(Path::new("/notes/links.md"))
+        .unwrap();
+    let note = facts.note_facts(id);
+    // Parse all notes upfront to measure just the link check
+    note.diag();
+
+    c.bench_function("check_intern_links", |b| {
+        b.iter(|| diag::check_intern_links(facts.db(), &note))
+    });
+}
+
+/// Resolving every link of the note from scratch compared to the targets
+/// memoized until the note or the set of notes changes.
+fn link_targets(c: &mut Criterion) {
+    let facts = facts_with_links();
+    let id = facts
+        .note_index()
+        .find_by_path(Path::new("/notes/links.md"))
+        .unwrap();
+    let note = facts.note_facts(id);
+    note.diag();
+
+    c.bench_function("resolve_link_targets", |b| {
+        b.iter(|| {
+            let strukt = note.structure();
+            strukt
+                .intern_links_with_ids(&note.intern_link_ids())
+                .into_iter()
+                .map(|link| facts::resolve_link_target(facts.db(), id, link))
+                .collect::<Vec<_>>()
+        })
+    });
+    c.bench_function("memoized_link_targets", |b| b.iter(|| note.link_targets()));
+}
+
+criterion_group!(benches, check_intern_links, link_targets);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+use std::path::{Path, PathBuf};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use zeta_note::{
+    config::Config,
+    facts::{FactsDB, NoteFacts},
+    store::{self, NoteFilter},
+};
+
+const NOTES: usize = 5000;
+
+/// A folder of notes with a few headings and links to other notes each.
+fn write_notes() -> PathBuf {
+    let root = std::env::temp_dir().join("zeta-note-bench-scan");
+    let _ = std::fs::remove_dir_all(&root);
+    for i in 0..NOTES {
+        let dir = root.join(format!("dir-{}", i % 50));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut content = format!("# Note {}\n\nIntro with #tag-{}.\n", i, i % 20);
+        for h in 0..5 {
+            content.push_str(&format!(
+                "\n## Heading {}\n\nSee [:note-{}@## Heading {}] and [x](note-{}).\n",
+                h,
+                (i + h + 1) % NOTES,
+                h,
+                (i * 7 + h) % NOTES
+            ));
+        }
+        std::fs::write(dir.join(format!("note-{}.md", i)), content).unwrap();
+    }
+    root
+}
+
+fn read_facts(
+    runtime: &tokio::runtime::Runtime,
+    root: &Path,
+    files: &[PathBuf],
+    filter: &NoteFilter,
+) -> FactsDB {
+    runtime
+        .block_on(FactsDB::from_files(root, files, filter))
+        .unwrap()
+}
+
+fn scan(c: &mut Criterion) {
+    let root = write_notes();
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (files, filter) = store::scan_notes(&root, &Config::default()).unwrap();
+
+    c.bench_function("read_5000_notes", |b| {
+        b.iter(|| read_facts(&runtime, &root, &files, &filter))
+    });
+
+    let mut group = c.benchmark_group("parse_5000_notes");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter_batched(
+            || read_facts(&runtime, &root, &files, &filter),
+            |facts| {
+                for id in facts.note_index().ids() {
+                    facts.note_facts(id).structure();
+                }
+                facts
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("parallel", |b| {
+        b.iter_batched(
+            || read_facts(&runtime, &root, &files, &filter),
+            |facts| {
+                facts.parse_all();
+                facts
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scan);
+criterion_main!(benches);
@@ -0,0 +1,387 @@
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use lsp_types::DiagnosticSeverity;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tracing::{debug, warn};
+
+use crate::{
+    diag::{Diag, DiagOptions},
+    store::{LinkResolution, NameMatching, NoteFile, TitleFallback},
+};
+
+/// Server settings passed by the client via `initializationOptions` and
+/// updated by `workspace/didChangeConfiguration`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Overrides of the default severity keyed by a diagnostic code,
+    /// e.g. `{"dup-heading": "hint"}`.
+    pub diag_severity: HashMap<String, Severity>,
+    /// Skip files matched by `.gitignore` and `.ignore` when looking for notes.
+    pub respect_gitignore: bool,
+    /// Look for notes in hidden files and directories like `.notes/` too.
+    /// `.git` is skipped either way.
+    pub scan_hidden: bool,
+    /// Extensions of note files (without the leading dot).
+    pub extensions: Vec<String>,
+    /// Whether links to notes are case-sensitive.
+    pub name_matching: NameMatching,
+    /// Match links to notes ignoring case and treating spaces, hyphens and
+    /// underscores alike, e.g. `My Note` matches `my_note`. Takes precedence
+    /// over `name_matching`.
+    pub link_name_normalization: bool,
+    /// Whether links to notes are matched against file names, titles, or both.
+    pub link_resolution: LinkResolution,
+    /// Level of the heading that serves as the note title, e.g. `2` for notes
+    /// that start with `## Title`.
+    pub title_heading_level: u8,
+    /// Title of notes without a title heading: none, or their first line.
+    pub title_fallback: TitleFallback,
+    /// Quiet period in milliseconds after the last edit of a note before its
+    /// diagnostics are recomputed.
+    pub diag_debounce_ms: u64,
+    /// Report headings (other than titles) that appear in several notes.
+    pub cross_note_heading_dups: bool,
+    /// Report cycles in the graph of links between notes.
+    pub link_cycles: bool,
+    /// Report headings deeper than this level, e.g. `3` to flag `####`.
+    /// Disabled by default.
+    pub max_heading_level: Option<u8>,
+    /// Report tags outside of these namespaces, e.g. `["project", "area"]`
+    /// allows `#project/x` but not `#random`. Disabled if empty.
+    pub allowed_tag_prefixes: Vec<String>,
+    /// Complete Markdown links with snippets that fill in the label and the
+    /// closing bracket.
+    pub completion_snippets: bool,
+    /// How completion inserts links to notes: by the file name, its slug, or
+    /// the note title.
+    pub completion_link_format: LinkFormat,
+    /// Show the file name of the note each link resolves to as an inlay hint.
+    pub inlay_hints: bool,
+    /// Check all notes once the workspace is indexed rather than only the ones
+    /// opened in the editor.
+    pub diag_on_startup: bool,
+    /// Publish at most this many diagnostics of a note, the ones closest to
+    /// the start of the note. Unlimited by default.
+    pub max_diagnostics_per_file: Option<usize>,
+    /// Gitignore-style patterns of draft notes relative to the folder root,
+    /// e.g. `drafts/`. Broken links in drafts are warnings rather than errors.
+    pub drafts: PathPatterns,
+    /// Gitignore-style patterns of folders with files like images relative to
+    /// the folder root, e.g. `assets/`. Markdown links into them only have to
+    /// point to an existing file rather than to a note.
+    pub resource_roots: PathPatterns,
+    /// Content of notes created by the code action for a missing note.
+    /// `{title}` is replaced with the note title and `{date}` with the
+    /// current date, e.g. `2021-05-17`.
+    pub new_note_template: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            diag_severity: HashMap::new(),
+            respect_gitignore: true,
+            scan_hidden: false,
+            extensions: vec!["md".to_string(), "markdown".to_string()],
+            name_matching: NameMatching::default(),
+            link_name_normalization: false,
+            link_resolution: LinkResolution::default(),
+            title_heading_level: 1,
+            title_fallback: TitleFallback::default(),
+            diag_debounce_ms: 300,
+            cross_note_heading_dups: false,
+            link_cycles: false,
+            max_heading_level: None,
+            allowed_tag_prefixes: Vec::new(),
+            completion_snippets: true,
+            completion_link_format: LinkFormat::default(),
+            inlay_hints: false,
+            diag_on_startup: true,
+            max_diagnostics_per_file: None,
+            drafts: PathPatterns::default(),
+            resource_roots: PathPatterns::default(),
+            new_note_template: "# {title}\n".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Config from `initializationOptions`. Like with [`Config::with_settings`],
+    /// invalid settings are skipped and keep their defaults.
+    pub fn from_json(value: &serde_json::Value) -> Config {
+        Config::default().with_settings(value)
+    }
+
+    /// Apply settings from `workspace/didChangeConfiguration` on top of this
+    /// config. Settings may be nested under the `zeta-note` section. Missing
+    /// keys keep their current values and unknown keys are ignored. Invalid
+    /// settings are skipped and keep their current values too.
+    pub fn with_settings(&self, settings: &serde_json::Value) -> Config {
+        let settings = settings
+            .get("zeta-note")
+            .or_else(|| settings.get("zetaNote"))
+            .unwrap_or(settings);
+        let settings = match settings.as_object() {
+            Some(settings) => settings,
+            _ => return self.clone(),
+        };
+
+        let mut merged = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(current)) => current,
+            _ => return self.clone(),
+        };
+        let mut config = self.clone();
+        for (key, value) in settings {
+            let current = match merged.insert(key.clone(), value.clone()) {
+                Some(current) => current,
+                None => {
+                    debug!("Ignoring unknown setting {}", key);
+                    merged.remove(key);
+                    continue;
+                }
+            };
+            // Settings are checked one by one, so that an invalid one doesn't
+            // discard the others
+            match serde_json::from_value(serde_json::Value::Object(merged.clone())) {
+                Ok(valid) => config = valid,
+                Err(err) => {
+                    warn!("Ignoring invalid setting {}: {}", key, err);
+                    merged.insert(key.clone(), current);
+                }
+            }
+        }
+        config
+    }
+
+    /// How links are matched against note names, taking `link_name_normalization`
+    /// into account.
+    pub fn note_name_matching(&self) -> NameMatching {
+        if self.link_name_normalization {
+            NameMatching::Normalized
+        } else {
+            self.name_matching
+        }
+    }
+
+    pub fn diag_debounce(&self) -> Duration {
+        Duration::from_millis(self.diag_debounce_ms)
+    }
+
+    pub fn diag_options(&self) -> DiagOptions {
+        DiagOptions {
+            cross_note_heading_dups: self.cross_note_heading_dups,
+            link_cycles: self.link_cycles,
+            max_heading_level: self.max_heading_level,
+            allowed_tag_prefixes: self.allowed_tag_prefixes.clone(),
+        }
+    }
+
+    pub fn diag_severity(&self, diag: &Diag) -> DiagnosticSeverity {
+        self.diag_severity
+            .get(diag.code())
+            .map(|&sev| sev.into())
+            .unwrap_or_else(|| diag.severity())
+    }
+
+    /// Content of a new note with the `title` according to `new_note_template`.
+    pub fn new_note_text(&self, title: &str) -> String {
+        let date = chrono::Local::now().format("%Y-%m-%d").to_string();
+        self.new_note_template
+            .replace("{date}", &date)
+            .replace("{title}", title)
+    }
+
+    /// Whether the note matches one of the `drafts` patterns.
+    pub fn is_draft(&self, file: &NoteFile) -> bool {
+        match file.path.strip_prefix(&file.root) {
+            Ok(rel_path) => self.drafts.matches(rel_path),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Gitignore-style patterns of paths relative to a folder root, compiled when
+/// the config is loaded. (De)serialized as a list of patterns.
+#[derive(Debug, Clone)]
+pub struct PathPatterns {
+    patterns: Vec<String>,
+    matcher: Arc<Gitignore>,
+}
+
+impl PathPatterns {
+    pub fn new(patterns: Vec<String>) -> PathPatterns {
+        // The matcher is rooted nowhere, it only takes paths relative to a folder
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in &patterns {
+            if let Err(err) = builder.add_line(None, pattern) {
+                debug!("Invalid path pattern {}: {}", pattern, err);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|err| {
+            debug!("Failed to build path patterns: {}", err);
+            Gitignore::empty()
+        });
+        PathPatterns {
+            patterns,
+            matcher: Arc::new(matcher),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether `rel_path`, relative to the folder root, or one of its parent
+    /// folders matches a pattern.
+    pub fn matches(&self, rel_path: &Path) -> bool {
+        !self.is_empty()
+            && self
+                .matcher
+                .matched_path_or_any_parents(rel_path, false)
+                .is_ignore()
+    }
+}
+
+impl Default for PathPatterns {
+    fn default() -> Self {
+        PathPatterns::new(Vec::new())
+    }
+}
+
+impl PartialEq for PathPatterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.patterns == other.patterns
+    }
+}
+
+impl Eq for PathPatterns {}
+
+impl Serialize for PathPatterns {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.patterns.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PathPatterns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(PathPatterns::new)
+    }
+}
+
+/// The form of a completed link to a note.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkFormat {
+    /// File name, e.g. `My Note`, or the path in Markdown links.
+    #[default]
+    Filename,
+    /// Slug of the file name, e.g. `my-note`.
+    Slug,
+    /// Note title from the frontmatter or the level-1 heading.
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(sev: Severity) -> Self {
+        match sev {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Information => DiagnosticSeverity::INFORMATION,
+            Severity::Hint => DiagnosticSeverity::HINT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn settings_merge_into_config() {
+        let config = Config {
+            diag_severity: HashMap::from([("dup-heading".to_string(), Severity::Hint)]),
+            title_heading_level: 2,
+            ..Config::default()
+        };
+
+        let changed = config.with_settings(&json!({
+            "zeta-note": {
+                "respectGitignore": false,
+                "diagSeverity": {"broken-note-link": "warning"},
+                "unknownSetting": true
+            }
+        }));
+        assert_eq!(
+            changed,
+            Config {
+                respect_gitignore: false,
+                diag_severity: HashMap::from([("broken-note-link".to_string(), Severity::Warning)]),
+                title_heading_level: 2,
+                ..Config::default()
+            }
+        );
+        let broken_link = Diag::BrokenInternLinkToNote {
+            linked_note: "missing".into(),
+            heading: None,
+            suggestion: None,
+        };
+        assert_eq!(broken_link.severity(), DiagnosticSeverity::ERROR);
+        assert_eq!(
+            changed.diag_severity(&broken_link),
+            DiagnosticSeverity::WARNING
+        );
+
+        let unnested = config.with_settings(&json!({"titleHeadingLevel": 3}));
+        assert_eq!(unnested.title_heading_level, 3);
+        assert_eq!(unnested.diag_severity, config.diag_severity);
+
+        let invalid = config.with_settings(&json!({"titleHeadingLevel": "two"}));
+        assert_eq!(invalid, config);
+    }
+
+    #[test]
+    fn invalid_settings_keep_valid_ones() {
+        let config = Config::from_json(&json!({
+            "titleHeadingLevel": "two",
+            "respectGitignore": false,
+            "extensions": "md"
+        }));
+        assert_eq!(
+            config,
+            Config {
+                respect_gitignore: false,
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn drafts_match_relative_to_folder_root() {
+        let config = Config::default().with_settings(&json!({"drafts": ["drafts/", "*.wip.md"]}));
+        let draft = |path: &str| {
+            config.is_draft(&NoteFile::new(
+                Path::new("/notes"),
+                &Path::new("/notes").join(path),
+            ))
+        };
+
+        assert!(draft("drafts/a.md"));
+        assert!(draft("drafts/nested/a.md"));
+        assert!(draft("ideas/b.wip.md"));
+        assert!(!draft("a.md"));
+        assert!(!draft("notes/drafts.md"));
+    }
+}
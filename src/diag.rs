@@ -1,50 +1,242 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     ops::Range,
+    path::{Path, PathBuf},
 };
 
-use lsp_document::{Pos, TextAdapter};
-use lsp_types::{Diagnostic, DiagnosticSeverity, PublishDiagnosticsParams, Url};
+use lsp_document::{Pos, TextMap};
+use lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    PublishDiagnosticsParams, Url,
+};
 use tracing::debug;
 
 use crate::{
-    facts::{Facts, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
-    parser::{Heading, Node, NoteName},
-    store::NoteFile,
+    config::{Config, PathPatterns},
+    encoding::EncodedText,
+    facts::{self, Facts, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
+    parser::{self, Heading, InternLink, Node, NoteName, Suppression},
+    store::{LinkResolution, NoteFile},
+    structure::NoteID,
+    util,
 };
 
 #[derive(Debug, Default)]
 pub struct DiagCollection {
     pub store: HashMap<NoteFile, HashSet<DiagWithLoc>>,
+    /// Notes linking to a (lowercased) note name or title, i.e. notes whose
+    /// diagnostics depend on the note with this name. Links are registered
+    /// whether they resolve or not, so that creating a note re-checks links
+    /// that were broken before.
+    linked_from: HashMap<String, HashSet<NoteFile>>,
+    /// Notes linking to a file in one of the `resource_roots` folders, keyed
+    /// by [`resource_key`], so that they are rechecked when the file changes.
+    linked_resources: HashMap<PathBuf, HashSet<NoteFile>>,
+    /// Workspace-level diagnostics (orphans, duplicate titles).
+    workspace: HashSet<(NoteFile, DiagWithLoc)>,
+}
+
+impl DiagCollection {
+    /// Recompute diagnostics for all notes in `facts`.
+    ///
+    /// Returns the notes whose diagnostics changed, including the notes that
+    /// are gone, e.g. with a removed folder.
+    pub fn recompute_all(&mut self, facts: &FactsDB) -> Vec<NoteFile> {
+        let index = facts.note_index();
+        self.workspace = facts.db().workspace_diag(()).iter().cloned().collect();
+
+        let mut removed: Vec<NoteFile> = self
+            .store
+            .keys()
+            .filter(|file| index.find_by_path(&file.path).is_none())
+            .cloned()
+            .collect();
+        for file in &removed {
+            self.forget(file);
+        }
+
+        let mut changed: Vec<NoteFile> = index
+            .files()
+            .filter(|file| self.update_note(file, facts))
+            .cloned()
+            .collect();
+        changed.append(&mut removed);
+        changed.sort_by(|a, b| a.path.cmp(&b.path));
+        changed
+    }
+
+    /// Recompute diagnostics after the note `changed` was edited.
+    ///
+    /// Only the note itself, the notes linking to it, and the notes whose
+    /// workspace-level diagnostics changed are checked. Returns the notes whose
+    /// diagnostics changed.
+    pub fn recompute_for(&mut self, changed: &NoteFile, facts: &FactsDB) -> Vec<NoteFile> {
+        let mut affected = HashSet::new();
+        affected.insert(changed.clone());
+
+        // The note may be gone already, but the links to its name are still there
+        let mut keys = vec![dependency_key(changed.name.to_str())];
+        if let Some(note_id) = facts.note_index().find_by_path(&changed.path) {
+            let note = facts.note_facts(note_id);
+            keys.extend(note.effective_title().map(|t| dependency_key(&t)));
+        }
+        for key in keys {
+            if let Some(files) = self.linked_from.get(&key) {
+                affected.extend(files.iter().cloned());
+            }
+        }
+        if let Some(files) = self.linked_resources.get(&resource_key(&changed.path)) {
+            affected.extend(files.iter().cloned());
+        }
+
+        let workspace: HashSet<_> = facts.db().workspace_diag(()).iter().cloned().collect();
+        affected.extend(
+            workspace
+                .symmetric_difference(&self.workspace)
+                .map(|(file, _)| file.clone()),
+        );
+        self.workspace = workspace;
+
+        let mut updated: Vec<NoteFile> = affected
+            .into_iter()
+            .filter(|file| self.update_note(file, facts))
+            .collect();
+        updated.sort_by(|a, b| a.path.cmp(&b.path));
+        debug!("recompute_for: {} notes updated", updated.len());
+        updated
+    }
+
+    /// Recheck only the notes in `files`, e.g. a batch of the initial pass over
+    /// the workspace.
+    ///
+    /// Returns the notes whose diagnostics changed.
+    pub fn recompute_notes(&mut self, files: &[NoteFile], facts: &FactsDB) -> Vec<NoteFile> {
+        self.workspace = facts.db().workspace_diag(()).iter().cloned().collect();
+        files
+            .iter()
+            .filter(|file| self.update_note(file, facts))
+            .cloned()
+            .collect()
+    }
+
+    /// Recheck a single note and record its dependencies.
+    ///
+    /// Returns `true` if the diagnostics of the note changed.
+    fn update_note(&mut self, file: &NoteFile, facts: &FactsDB) -> bool {
+        let note_id = match facts.note_index().find_by_path(&file.path) {
+            Some(id) => id,
+            None => return self.forget(file),
+        };
+        let note = facts.note_facts(note_id);
+
+        for files in self.linked_from.values_mut() {
+            files.remove(file);
+        }
+        for files in self.linked_resources.values_mut() {
+            files.remove(file);
+        }
+        let strukt = note.structure();
+        for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+            if let Some(name) = &link.note_name {
+                self.linked_from
+                    .entry(dependency_key(name.to_str()))
+                    .or_default()
+                    .insert(file.clone());
+            }
+        }
+
+        let links = resource_links(&facts.db().resource_roots(()), &note);
+        for (path, _, _) in &links {
+            self.linked_resources
+                .entry(resource_key(path))
+                .or_default()
+                .insert(file.clone());
+        }
+
+        let mut diag: HashSet<DiagWithLoc> = note.diag().iter().cloned().collect();
+        diag.extend(check_resource_links(&links));
+        let changed = self.store.get(file) != Some(&diag);
+        self.store.insert(file.clone(), diag);
+        changed
+    }
+
+    fn forget(&mut self, file: &NoteFile) -> bool {
+        for files in self.linked_from.values_mut() {
+            files.remove(file);
+        }
+        for files in self.linked_resources.values_mut() {
+            files.remove(file);
+        }
+        self.store.remove(file).is_some()
+    }
+}
+
+/// Names are matched case-insensitively and by the last path component here,
+/// so that links by path like `../note.md` depend on the note `note` too: it's
+/// fine to recheck more notes than necessary, but not less.
+fn dependency_key(name: &str) -> String {
+    let name = name.rsplit('/').next().unwrap_or(name);
+    let name = parser::NOTE_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(&format!(".{}", ext)))
+        .unwrap_or(name);
+    name.to_lowercase()
 }
 
+/// `source` of the published diagnostics.
+pub const DIAG_SOURCE: &str = "zeta-note";
+
 pub fn to_publish(
     file: &NoteFile,
     diags: &HashSet<DiagWithLoc>,
     facts: &FactsDB,
+    config: &Config,
 ) -> Option<PublishDiagnosticsParams> {
     let index = facts.note_index();
 
     let note = facts.note_facts(index.find_by_path(&file.path)?);
     let text_version = note.text().version.to_lsp_version();
     let indexed_text = note.indexed_text();
+    let suppressions = note.suppressions();
+    let is_draft = config.is_draft(file);
 
-    let lsp_diags: Vec<Diagnostic> = diags
-        .iter()
+    let mut lsp_diags: Vec<Diagnostic> = normalize(diags)
+        .into_iter()
+        .filter(|(d, r)| !is_suppressed(&suppressions, d, r))
         .filter_map(|(d, r)| {
             let range = match indexed_text.range_to_lsp_range(r) {
                 Some(r) => r,
                 _ => return None,
             };
 
+            let mut severity = config.diag_severity(d);
+            let is_broken_link = matches!(
+                d,
+                Diag::BrokenInternLinkToNote { .. }
+                    | Diag::BrokenInternLinkToHeading { .. }
+                    | Diag::StrayLinkPunctuation { .. }
+                    | Diag::BrokenResourceLink { .. }
+            );
+            if is_draft && is_broken_link && severity == DiagnosticSeverity::ERROR {
+                severity = DiagnosticSeverity::WARNING;
+            }
+
             Some(Diagnostic {
                 range,
-                severity: Some(DiagnosticSeverity::ERROR),
+                severity: Some(severity),
+                code: Some(NumberOrString::String(d.code().to_string())),
+                source: Some(DIAG_SOURCE.to_string()),
                 message: d.to_message(),
+                related_information: related_information(file, d, &indexed_text),
                 ..Diagnostic::default()
             })
         })
         .collect();
+    // Diagnostics come from a set, so order them for reproducible output
+    lsp_diags.sort_by(|a, b| diag_order_key(a).cmp(&diag_order_key(b)));
+    if let Some(max) = config.max_diagnostics_per_file {
+        truncate(&mut lsp_diags, max);
+    }
 
     let param = PublishDiagnosticsParams {
         uri: Url::from_file_path(file.path.clone()).unwrap(),
@@ -55,71 +247,499 @@ pub fn to_publish(
     Some(param)
 }
 
+/// Published diagnostics are ordered by position, then by code and message.
+fn diag_order_key(diag: &Diagnostic) -> (u32, u32, Option<&str>, &str) {
+    let code = match &diag.code {
+        Some(NumberOrString::String(code)) => Some(code.as_str()),
+        _ => None,
+    };
+    (
+        diag.range.start.line,
+        diag.range.start.character,
+        code,
+        &diag.message,
+    )
+}
+
+/// Other places in the note a duplicate diagnostic refers to.
+fn related_information(
+    file: &NoteFile,
+    diag: &Diag,
+    indexed_text: &EncodedText,
+) -> Option<Vec<DiagnosticRelatedInformation>> {
+    let others = match diag {
+        Diag::DupTitle { others, .. } | Diag::DupHeading { others, .. } => others,
+        _ => return None,
+    };
+    let uri = Url::from_file_path(&file.path).ok()?;
+    let related = others
+        .iter()
+        .filter_map(|span| {
+            Some(DiagnosticRelatedInformation {
+                location: Location {
+                    uri: uri.clone(),
+                    range: indexed_text.range_to_lsp_range(span)?,
+                },
+                message: "Also defined here".to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    Some(related).filter(|related| !related.is_empty())
+}
+
+/// Keep the first `max` diagnostics by position and replace the rest with a
+/// single diagnostic saying how many were hidden.
+fn truncate(diags: &mut Vec<Diagnostic>, max: usize) {
+    if diags.len() <= max {
+        return;
+    }
+    diags.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+    let hidden = diags.split_off(max);
+    diags.push(Diagnostic {
+        range: hidden[0].range,
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        source: Some(DIAG_SOURCE.to_string()),
+        message: format!("{} more problems hidden", hidden.len()),
+        ..Diagnostic::default()
+    });
+}
+
+/// Whether a suppression comment on the same line or on the line above
+/// silences the diagnostic.
+fn is_suppressed(suppressions: &[Node<Suppression>], diag: &Diag, span: &Range<Pos>) -> bool {
+    let line = span.start.line;
+    suppressions.iter().any(|s| {
+        s.span.start.line <= line && line <= s.span.end.line + 1 && s.applies_to(diag.code())
+    })
+}
+
+/// Collapse diagnostics with the same message that start on the same line and
+/// overlap, e.g. spans of the same link that differ only by trailing
+/// whitespace. The widest span is kept.
+fn normalize(diags: &HashSet<DiagWithLoc>) -> Vec<&DiagWithLoc> {
+    let mut sorted: Vec<&DiagWithLoc> = diags.iter().collect();
+    // Within a group wider spans go first
+    sorted.sort_by(|(d1, r1), (d2, r2)| {
+        (r1.start.line, d1.to_message(), r1.start)
+            .cmp(&(r2.start.line, d2.to_message(), r2.start))
+            .then_with(|| r2.end.cmp(&r1.end))
+    });
+
+    let mut normalized: Vec<&DiagWithLoc> = Vec::new();
+    for diag in sorted {
+        let duplicate = normalized.iter_mut().rev().find(|(d, r)| {
+            r.start.line == diag.1.start.line
+                && diag.1.start <= r.end
+                && d.to_message() == diag.0.to_message()
+        });
+        match duplicate {
+            Some(kept) if diag.1.end > kept.1.end => *kept = diag,
+            Some(_) => {}
+            None => normalized.push(diag),
+        }
+    }
+    normalized
+}
+
 pub type DiagWithLoc = (Diag, Range<Pos>);
 
+/// Switches for optional checks.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DiagOptions {
+    pub cross_note_heading_dups: bool,
+    pub link_cycles: bool,
+    /// Deepest allowed heading level, unlimited if `None`.
+    pub max_heading_level: Option<u8>,
+    /// Namespaces tags have to be in, e.g. `project` for `#project/x`. Any
+    /// tag is fine if empty.
+    pub allowed_tag_prefixes: Vec<String>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Diag {
+    MissingTitle {
+        /// Level of the heading expected to be the title.
+        level: u8,
+    },
     DupTitle {
         title: Node<Heading>,
+        /// Spans of the other titles in the note.
+        others: Vec<Range<Pos>>,
     },
     DupHeading {
         heading: Node<Heading>,
+        /// Spans of the other headings with the same text in the note.
+        others: Vec<Range<Pos>>,
     },
     BrokenInternLinkToNote {
         linked_note: NoteName,
+        /// Heading the link points to inside of the missing note, if any.
+        heading: Option<String>,
+        /// Existing note with a similar name.
+        suggestion: Option<NoteName>,
     },
     BrokenInternLinkToHeading {
         linked_note: NoteName,
         heading: String,
+        /// Heading that differs only by case, as the link should refer to it.
+        suggestion: Option<String>,
+    },
+    OrphanNote {
+        note: NoteName,
+    },
+    InvalidFrontmatter {
+        message: String,
+    },
+    DupNoteTitle {
+        title: String,
+        others: Vec<NoteName>,
+    },
+    AmbiguousInternLink {
+        linked_note: NoteName,
+        candidates: Vec<NoteName>,
+    },
+    AmbiguousHeadingAnchor {
+        heading: Node<Heading>,
+        slug: String,
+        others: Vec<String>,
+    },
+    UndefinedLinkReference {
+        reference: String,
+    },
+    DuplicateLinkReference {
+        label: String,
+    },
+    CrossNoteDupHeading {
+        heading: String,
+        others: Vec<NoteName>,
+    },
+    LinkCycle {
+        cycle: Vec<NoteName>,
+    },
+    SelfLink {
+        note: NoteName,
+    },
+    SkippedHeadingLevel {
+        from: u8,
+        to: u8,
+    },
+    NameShadowsTitle {
+        linked_note: NoteName,
+        titled_note: NoteName,
+    },
+    EmptyHeading {
+        heading: Node<Heading>,
+    },
+    StrayLinkPunctuation {
+        linked_note: NoteName,
+        /// The target without the trailing punctuation, which resolves.
+        intended: NoteName,
+    },
+    HeadingTooDeep {
+        level: u8,
+        max: u8,
+    },
+    /// A line anchor like `L10-L20` past the end of the note.
+    LinkLineOutOfRange {
+        linked_note: NoteName,
+        start: u32,
+        end: u32,
+        line_count: u32,
+    },
+    EmptyLinkLabel {
+        linked_note: NoteName,
+    },
+    /// A tag outside of the allowed namespaces, e.g. `#random` when only
+    /// `project` is allowed.
+    UnknownTagNamespace {
+        tag: String,
+    },
+    /// A link into one of the `resource_roots` folders to a missing file.
+    BrokenResourceLink {
+        target: String,
     },
 }
 
 impl Diag {
+    /// A stable identifier of the diagnostic kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Diag::MissingTitle { .. } => "missing-title",
+            Diag::DupTitle { .. } => "dup-title",
+            Diag::DupHeading { .. } => "dup-heading",
+            Diag::BrokenInternLinkToNote { .. } => "broken-note-link",
+            Diag::BrokenInternLinkToHeading { .. } => "broken-heading-link",
+            Diag::OrphanNote { .. } => "orphan-note",
+            Diag::InvalidFrontmatter { .. } => "invalid-frontmatter",
+            Diag::DupNoteTitle { .. } => "dup-note-title",
+            Diag::AmbiguousInternLink { .. } => "ambiguous-note-link",
+            Diag::AmbiguousHeadingAnchor { .. } => "ambiguous-heading-anchor",
+            Diag::UndefinedLinkReference { .. } => "undefined-link-ref",
+            Diag::DuplicateLinkReference { .. } => "dup-link-ref",
+            Diag::CrossNoteDupHeading { .. } => "cross-note-dup-heading",
+            Diag::LinkCycle { .. } => "link-cycle",
+            Diag::SelfLink { .. } => "self-link",
+            Diag::SkippedHeadingLevel { .. } => "skipped-heading-level",
+            Diag::NameShadowsTitle { .. } => "name-shadows-title",
+            Diag::EmptyHeading { .. } => "empty-heading",
+            Diag::StrayLinkPunctuation { .. } => "stray-link-punctuation",
+            Diag::HeadingTooDeep { .. } => "heading-too-deep",
+            Diag::LinkLineOutOfRange { .. } => "link-line-out-of-range",
+            Diag::EmptyLinkLabel { .. } => "empty-link-label",
+            Diag::BrokenResourceLink { .. } => "broken-resource-link",
+            Diag::UnknownTagNamespace { .. } => "unknown-tag-namespace",
+        }
+    }
+
+    /// Default severity; can be overridden by the user in [`Config`].
+    pub fn severity(&self) -> DiagnosticSeverity {
+        match self {
+            Diag::MissingTitle { .. } => DiagnosticSeverity::WARNING,
+            Diag::DupTitle { .. } => DiagnosticSeverity::ERROR,
+            Diag::DupHeading { .. } => DiagnosticSeverity::ERROR,
+            Diag::BrokenInternLinkToNote { .. } => DiagnosticSeverity::ERROR,
+            Diag::BrokenInternLinkToHeading { .. } => DiagnosticSeverity::WARNING,
+            Diag::OrphanNote { .. } => DiagnosticSeverity::HINT,
+            Diag::InvalidFrontmatter { .. } => DiagnosticSeverity::ERROR,
+            Diag::DupNoteTitle { .. } => DiagnosticSeverity::WARNING,
+            Diag::AmbiguousInternLink { .. } => DiagnosticSeverity::WARNING,
+            Diag::AmbiguousHeadingAnchor { .. } => DiagnosticSeverity::WARNING,
+            Diag::UndefinedLinkReference { .. } => DiagnosticSeverity::ERROR,
+            Diag::DuplicateLinkReference { .. } => DiagnosticSeverity::WARNING,
+            Diag::CrossNoteDupHeading { .. } => DiagnosticSeverity::HINT,
+            Diag::LinkCycle { .. } => DiagnosticSeverity::INFORMATION,
+            Diag::SelfLink { .. } => DiagnosticSeverity::INFORMATION,
+            Diag::SkippedHeadingLevel { .. } => DiagnosticSeverity::WARNING,
+            Diag::NameShadowsTitle { .. } => DiagnosticSeverity::HINT,
+            Diag::EmptyHeading { .. } => DiagnosticSeverity::WARNING,
+            Diag::StrayLinkPunctuation { .. } => DiagnosticSeverity::ERROR,
+            Diag::HeadingTooDeep { .. } => DiagnosticSeverity::WARNING,
+            Diag::LinkLineOutOfRange { .. } => DiagnosticSeverity::WARNING,
+            Diag::EmptyLinkLabel { .. } => DiagnosticSeverity::WARNING,
+            Diag::BrokenResourceLink { .. } => DiagnosticSeverity::ERROR,
+            Diag::UnknownTagNamespace { .. } => DiagnosticSeverity::WARNING,
+        }
+    }
+
     pub fn to_message(&self) -> String {
         match self {
-            Diag::DupTitle { title } => format!(
+            Diag::MissingTitle { level } => {
+                format!("Note has no title (expected a level-{} heading)", level)
+            }
+            Diag::DupTitle { title, .. } => format!(
                 "Duplicate title `{}`. Each note should have at most one title",
                 title.text
             ),
-            Diag::DupHeading { heading } => format!("Duplicate heading `{}`", heading.text),
-            Diag::BrokenInternLinkToNote { linked_note } => {
-                format!("Reference to non-existent note `{}`", linked_note)
+            Diag::DupHeading { heading, .. } => {
+                format!("Duplicate heading `{}`", heading.text)
+            }
+            Diag::BrokenInternLinkToNote {
+                linked_note,
+                heading,
+                suggestion,
+            } => {
+                let mut message = format!("Reference to non-existent note `{}`", linked_note);
+                if let Some(heading) = heading {
+                    message.push_str(&format!(" (expected heading `{}`)", heading));
+                }
+                if let Some(suggestion) = suggestion {
+                    message.push_str(&format!(" (did you mean `{}`?)", suggestion));
+                }
+                message
             }
             Diag::BrokenInternLinkToHeading {
                 linked_note,
                 heading,
+                suggestion,
+            } => match suggestion {
+                Some(suggestion) => format!(
+                    "Reference to non-existent heading `{}`{} (did you mean `{}`?)",
+                    linked_note, heading, suggestion
+                ),
+                None => format!(
+                    "Reference to non-existent heading `{}`{}",
+                    linked_note, heading
+                ),
+            },
+            Diag::OrphanNote { note } => {
+                format!("Note `{}` is not referenced by any other note", note)
+            }
+            Diag::InvalidFrontmatter { message } => {
+                format!("Invalid YAML frontmatter: {}", message)
+            }
+            Diag::DupNoteTitle { title, others } => {
+                let others = others
+                    .iter()
+                    .map(|n| format!("`{}`", n))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Title `{}` is also used by {}", title, others)
+            }
+            Diag::AmbiguousInternLink {
+                linked_note,
+                candidates,
+            } => {
+                let candidates = candidates
+                    .iter()
+                    .map(|n| format!("`{}`", n))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "Reference to `{}` is ambiguous: matches {}",
+                    linked_note, candidates
+                )
+            }
+            Diag::AmbiguousHeadingAnchor {
+                heading,
+                slug,
+                others,
+            } => {
+                let others = others
+                    .iter()
+                    .map(|h| format!("`{}`", h))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "Heading `{}` has the same anchor `#{}` as {}",
+                    heading.text, slug, others
+                )
+            }
+            Diag::UndefinedLinkReference { reference } => {
+                format!("Link reference `[{}]` is not defined", reference)
+            }
+            Diag::DuplicateLinkReference { label } => format!(
+                "Link reference `[{}]` is already defined above; links use the first definition",
+                label
+            ),
+            Diag::CrossNoteDupHeading { heading, others } => {
+                let others = others
+                    .iter()
+                    .map(|n| format!("`{}`", n))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Heading `{}` also appears in {}", heading, others)
+            }
+            Diag::LinkCycle { cycle } => {
+                let chain = cycle
+                    .iter()
+                    .chain(cycle.first())
+                    .map(|n| format!("`{}`", n))
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                format!("Notes link in a cycle: {}", chain)
+            }
+            Diag::SelfLink { note } => format!("Note `{}` links to itself", note),
+            Diag::SkippedHeadingLevel { from, to } => format!(
+                "Heading level skips from {} to {} (expected at most {})",
+                from,
+                to,
+                from + 1
+            ),
+            Diag::NameShadowsTitle {
+                linked_note,
+                titled_note,
             } => format!(
-                "Reference to non-existent heading `{}`{}",
-                linked_note, heading
+                "Reference to `{}` points to the note with this name, not to `{}` with this title",
+                linked_note, titled_note
             ),
+            Diag::EmptyHeading { heading } => format!("Empty heading `{}`", heading.text),
+            Diag::StrayLinkPunctuation {
+                linked_note,
+                intended,
+            } => format!(
+                "Link target `{}` includes stray punctuation (did you mean `{}`?)",
+                linked_note, intended
+            ),
+            Diag::HeadingTooDeep { level, max } => {
+                format!("Heading level {} is deeper than the allowed {}", level, max)
+            }
+            Diag::LinkLineOutOfRange {
+                linked_note,
+                start,
+                end,
+                line_count,
+            } => {
+                let lines = if start == end {
+                    format!("Line {}", start)
+                } else {
+                    format!("Lines {}-{}", start, end)
+                };
+                format!(
+                    "{} out of range of note `{}` with {} lines",
+                    lines, linked_note, line_count
+                )
+            }
+            Diag::EmptyLinkLabel { linked_note } => {
+                format!("Link to `{}` has an empty label", linked_note)
+            }
+            Diag::BrokenResourceLink { target } => {
+                format!("Reference to non-existent file `{}`", target)
+            }
+            Diag::UnknownTagNamespace { tag } => {
+                format!("Tag `#{}` is not in one of the allowed namespaces", tag)
+            }
         }
     }
 }
 
-pub fn check_title(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+pub fn check_frontmatter(note: &impl NoteFacts) -> Vec<DiagWithLoc> {
+    let frontmatter = match note.frontmatter() {
+        Some(fm) => fm,
+        _ => return Vec::new(),
+    };
+
+    match serde_yaml::from_str::<serde_yaml::Value>(&frontmatter.yaml) {
+        Ok(_) => Vec::new(),
+        Err(err) => vec![(
+            Diag::InvalidFrontmatter {
+                message: err.to_string(),
+            },
+            frontmatter.span.clone(),
+        )],
+    }
+}
+
+/// Checks that the note has exactly one title: a heading of `level` or a title
+/// in the frontmatter.
+pub fn check_title(note: &impl NoteFactsExt, level: u8) -> Vec<DiagWithLoc> {
     debug!("check_title: start");
 
-    let hd_ids = note.headings_matching(|hd| hd.level == 1);
+    let hd_ids = note.headings_matching(|hd| hd.level == level);
     debug!("check_title: found {} title ids", hd_ids.len());
 
+    if hd_ids.is_empty() && note.frontmatter_title().is_none() && note.first_line_title().is_none()
+    {
+        let file_start = Pos::new(0, 0)..Pos::new(0, 0);
+        debug!("check_title: reporting missing title");
+        return vec![(Diag::MissingTitle { level }, file_start)];
+    }
+
     let strukt = note.structure();
-    let duplicates = strukt.headings_with_ids(&hd_ids).into_iter().skip(1);
+    let titles = strukt.headings_with_ids(&hd_ids);
+    let duplicates = titles.iter().skip(1);
 
     let duplicate_diags = duplicates
-        .map(|t| (Diag::DupTitle { title: t.clone() }, t.span.clone()))
+        .map(|t| {
+            let diag = Diag::DupTitle {
+                title: (*t).clone(),
+                others: other_spans(&titles, t),
+            };
+            (diag, t.span.clone())
+        })
         .collect::<Vec<_>>();
 
     debug!("check_title: reporting {}", duplicate_diags.len());
     duplicate_diags
 }
 
-pub fn check_headings(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+pub fn check_headings(note: &impl NoteFactsExt, title_level: u8) -> Vec<DiagWithLoc> {
     debug!("check_headings: start");
 
+    // Ordered, so that the first of the duplicates is always the one kept
     let mut hd_ids_to_inspect = note
-        .headings_matching(|hd| hd.level > 1)
+        .headings_matching(|hd| hd.level != title_level && !hd.is_empty())
         .into_iter()
-        .collect::<HashSet<_>>();
+        .collect::<BTreeSet<_>>();
     debug!(
         "check_headings: found {} heading ids",
         hd_ids_to_inspect.len()
@@ -148,45 +768,357 @@ pub fn check_headings(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
 
     let duplicate_diags = duplicates
         .into_iter()
-        .map(|h| (Diag::DupHeading { heading: h.clone() }, h.span.clone()))
+        .map(|h| {
+            let same_text =
+                strukt.headings_with_ids(&note.headings_matching(|hd| hd.text == h.text));
+            let diag = Diag::DupHeading {
+                heading: h.clone(),
+                others: other_spans(&same_text, h),
+            };
+            (diag, h.span.clone())
+        })
         .collect::<Vec<_>>();
 
     debug!("check_headings: reporting {}", duplicate_diags.len());
     duplicate_diags
 }
 
+/// Spans of `headings` other than `heading`, in the order of the note.
+fn other_spans(headings: &[&Node<Heading>], heading: &Node<Heading>) -> Vec<Range<Pos>> {
+    let mut spans: Vec<_> = headings
+        .iter()
+        .filter(|other| other.span != heading.span)
+        .map(|other| other.span.clone())
+        .collect();
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+/// Distinct headings that produce the same anchor, e.g. `## My Heading` and
+/// `## My-Heading`. Exact duplicates are reported by [`check_headings`].
+pub fn check_heading_anchors(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+    let strukt = note.structure();
+    let mut by_slug: HashMap<String, Vec<&Node<Heading>>> = HashMap::new();
+    for heading in strukt.headings_with_ids(&note.headings()) {
+        let slug = heading.slug();
+        if !slug.is_empty() {
+            by_slug.entry(slug).or_default().push(heading);
+        }
+    }
+
+    let mut diags = Vec::new();
+    for (slug, headings) in by_slug {
+        for heading in &headings {
+            let mut others = headings
+                .iter()
+                .filter(|h| h.text != heading.text)
+                .map(|h| h.text.clone())
+                .collect::<Vec<_>>();
+            others.sort();
+            others.dedup();
+            if others.is_empty() {
+                continue;
+            }
+
+            let diag = Diag::AmbiguousHeadingAnchor {
+                heading: (*heading).clone(),
+                slug: slug.clone(),
+                others,
+            };
+            diags.push((diag, heading.span.clone()));
+        }
+    }
+    diags
+}
+
+/// Headings without text, e.g. a stray `##` left while editing. These have no
+/// anchor, so links can't point to them.
+pub fn check_empty_headings(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+    let strukt = note.structure();
+    strukt
+        .headings_with_ids(&note.headings_matching(|hd| hd.is_empty()))
+        .into_iter()
+        .map(|hd| {
+            (
+                Diag::EmptyHeading {
+                    heading: hd.clone(),
+                },
+                hd.span.clone(),
+            )
+        })
+        .collect()
+}
+
+/// Headings more than one level deeper than the heading before them, e.g. `###`
+/// right after `#`. The first heading of the note can have any level.
+pub fn check_heading_levels(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+    let strukt = note.structure();
+    let mut headings = strukt.headings_with_ids(&note.headings());
+    headings.sort_by_key(|hd| hd.span.start);
+
+    let mut diags = Vec::new();
+    for pair in headings.windows(2) {
+        let (prev, cur) = (pair[0], pair[1]);
+        if cur.level > prev.level + 1 {
+            let diag = Diag::SkippedHeadingLevel {
+                from: prev.level,
+                to: cur.level,
+            };
+            diags.push((diag, cur.span.clone()));
+        }
+    }
+    diags
+}
+
+/// Headings of a level deeper than `max`, e.g. `####` when at most `###` is
+/// allowed.
+pub fn check_heading_depth(note: &impl NoteFactsExt, max: u8) -> Vec<DiagWithLoc> {
+    let strukt = note.structure();
+    strukt
+        .headings_with_ids(&note.headings_matching(|hd| hd.level > max))
+        .into_iter()
+        .map(|hd| {
+            let diag = Diag::HeadingTooDeep {
+                level: hd.level,
+                max,
+            };
+            (diag, hd.span.clone())
+        })
+        .collect()
+}
+
+/// Tags whose first segment, e.g. `project` in `#project/x`, is not one of the
+/// `allowed` prefixes. Prefixes may be written as `project/` or `#project` too.
+pub fn check_tag_namespaces(note: &impl NoteFactsExt, allowed: &[String]) -> Vec<DiagWithLoc> {
+    let is_allowed = |namespace: &str| {
+        allowed.iter().any(|prefix| {
+            prefix
+                .trim_start_matches(parser::HEADING_START)
+                .trim_end_matches('/')
+                == namespace
+        })
+    };
+    let strukt = note.structure();
+    strukt
+        .tags_with_ids(&note.tags())
+        .into_iter()
+        .filter(|tag| !is_allowed(tag.name.split('/').next().unwrap_or_default()))
+        .map(|tag| {
+            let diag = Diag::UnknownTagNamespace {
+                tag: tag.name.clone(),
+            };
+            (diag, tag.span.clone())
+        })
+        .collect()
+}
+
+/// Name of a note other than `note_id` that is a few typos away from `name`.
+/// Short names get no suggestions, as almost any other short name is close.
+fn closest_note_name(facts: &dyn Facts, note_id: NoteID, name: &NoteName) -> Option<NoteName> {
+    let max_distance = (name.to_str().chars().count() / 3).min(3);
+    let index = facts.note_index(());
+    index
+        .ids()
+        .filter(|&id| id != note_id)
+        .map(|id| index.find_by_id(id).name)
+        .map(|candidate| {
+            (
+                util::levenshtein(name.to_str(), candidate.to_str()),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|(d1, n1), (d2, n2)| d1.cmp(d2).then_with(|| n1.cmp(n2)))
+        .map(|(_, candidate)| (*candidate).clone())
+}
+
+/// Text of a heading that matches `text` when case is ignored, with the `#`
+/// markers if `text` has them.
+fn heading_in_other_case(note: &impl NoteFactsExt, text: &str) -> Option<String> {
+    let bare = |t: &str| {
+        t.trim_start_matches(parser::HEADING_START)
+            .trim()
+            .to_lowercase()
+    };
+    let id = *note
+        .headings_matching(|h| bare(&h.text) == bare(text))
+        .first()?;
+    let structure = note.structure();
+    let heading = &structure.heading_by_id(id).text;
+    if text.starts_with(parser::HEADING_START) {
+        Some(heading.clone())
+    } else {
+        Some(
+            heading
+                .trim_start_matches(parser::HEADING_START)
+                .trim()
+                .to_string(),
+        )
+    }
+}
+
+/// Inline links with an empty label like `[](note)`, which render as an empty
+/// clickable region. The span covers the `[]`.
+pub fn check_link_labels(note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
+    let strukt = note.structure();
+    strukt
+        .intern_links_with_ids(&note.intern_link_ids())
+        .into_iter()
+        .filter_map(|link| {
+            let (label, _) = link.inline_parts()?;
+            let text = label
+                .strip_prefix(parser::LINK_START)?
+                .strip_suffix(parser::LINK_SUFFIX)?;
+            if !text.trim().is_empty() {
+                return None;
+            }
+            let start = link.span.start;
+            let span = start..Pos::new(start.line, start.col + label.len() as u32);
+            let linked_note = link
+                .note_name
+                .clone()
+                .unwrap_or_else(|| (*note.file().name).clone());
+            Some((Diag::EmptyLinkLabel { linked_note }, span))
+        })
+        .collect()
+}
+
+/// Reference-style links `[label][ref]` without a `[ref]: ...` definition.
+pub fn check_link_refs(note: &impl NoteFacts) -> Vec<DiagWithLoc> {
+    note.structure()
+        .undefined_link_refs()
+        .into_iter()
+        .map(|link_ref| {
+            let diag = Diag::UndefinedLinkReference {
+                reference: link_ref.reference.clone(),
+            };
+            (diag, link_ref.span.clone())
+        })
+        .collect()
+}
+
+/// Repeated `[ref]: ...` definitions of the same label. Links use the first
+/// definition as in CommonMark, so the later ones are reported.
+pub fn check_dup_link_refs(note: &impl NoteFacts) -> Vec<DiagWithLoc> {
+    let mut seen = HashSet::new();
+    parser::scrape_link_defs(&*note.indexed_text())
+        .into_iter()
+        .filter(|def| !seen.insert(parser::link_label_key(&def.label)))
+        .map(|def| {
+            let diag = Diag::DuplicateLinkReference {
+                label: def.label.trim().to_string(),
+            };
+            (diag, def.span.clone())
+        })
+        .collect()
+}
+
 pub fn check_intern_links(facts: &dyn Facts, note: &impl NoteFactsExt) -> Vec<DiagWithLoc> {
     let mut diags = Vec::new();
 
     let strukt = note.structure();
-    let intern_link_ids = note.intern_link_ids();
-    let intern_links = strukt.intern_links_with_ids(&intern_link_ids);
+    let file = note.file();
+    let resource_roots = facts.resource_roots(());
 
-    for intern_link in intern_links {
+    for &(link_id, target_id) in note.link_targets().iter() {
+        let intern_link = strukt.intern_link_by_id(link_id);
         let target_name = intern_link
             .note_name
             .clone()
             .unwrap_or_else(|| (*note.file().name).clone());
-        let target_id = facts.note_index(()).find_by_name(&target_name);
+        let index = facts.note_index(());
+        let candidates = match &intern_link.note_name {
+            Some(name) if !intern_link.is_path() => index.find_all_by_name(name),
+            _ => Vec::new(),
+        };
+        if candidates.len() > 1 {
+            let mut candidates = candidates
+                .into_iter()
+                .map(|id| (*index.find_by_id(id).name).clone())
+                .collect::<Vec<_>>();
+            candidates.sort();
+            diags.push((
+                Diag::AmbiguousInternLink {
+                    linked_note: target_name,
+                    candidates,
+                },
+                intern_link.span.clone(),
+            ));
+            continue;
+        }
+
+        // A note name wins over a title of another note
+        if let (LinkResolution::Both, [by_name]) = (facts.link_resolution(()), &candidates[..]) {
+            let by_title = intern_link
+                .note_name
+                .as_ref()
+                .and_then(|name| facts::resolve_note_title(facts, name))
+                .filter(|id| id != by_name);
+            if let Some(by_title) = by_title {
+                diags.push((
+                    Diag::NameShadowsTitle {
+                        linked_note: target_name.clone(),
+                        titled_note: (*index.find_by_id(by_title).name).clone(),
+                    },
+                    intern_link.span.clone(),
+                ));
+            }
+        }
+
         match target_id {
             Some(id) => {
                 let target_note = NoteFactsDB::new(facts, id);
-                if let Some(heading) = &intern_link.heading {
-                    if target_note.heading_with_text(heading).is_none() {
+                let heading = match &intern_link.heading {
+                    Some(heading) if target_note.heading_for_link(heading).is_none() => heading,
+                    _ => continue,
+                };
+                if let Some((start, end)) = parser::parse_line_anchor(heading) {
+                    if target_note.line_anchor_span(heading).is_none() {
+                        let line_count = target_note.indexed_text().text().lines().count();
                         diags.push((
-                            Diag::BrokenInternLinkToHeading {
+                            Diag::LinkLineOutOfRange {
                                 linked_note: target_name,
-                                heading: heading.to_string(),
+                                start,
+                                end,
+                                line_count: line_count as u32,
                             },
                             intern_link.span.clone(),
                         ));
                     }
+                } else {
+                    diags.push((
+                        Diag::BrokenInternLinkToHeading {
+                            linked_note: target_name,
+                            heading: heading.to_string(),
+                            suggestion: heading_in_other_case(&target_note, heading),
+                        },
+                        intern_link.span.clone(),
+                    ));
                 }
             }
             _ => {
+                // Links to resources are checked by `check_resource_links`
+                let into_resources = intern_link.is_markdown()
+                    && resource_target(&resource_roots, &file, target_name.to_str()).is_some();
+                if into_resources {
+                    continue;
+                }
+                if let Some(intended) = without_stray_punctuation(facts, note.id(), intern_link) {
+                    diags.push((
+                        Diag::StrayLinkPunctuation {
+                            linked_note: target_name,
+                            intended,
+                        },
+                        intern_link.span.clone(),
+                    ));
+                    continue;
+                }
                 diags.push((
                     Diag::BrokenInternLinkToNote {
+                        suggestion: closest_note_name(facts, note.id(), &target_name)
+                            .filter(|_| !intern_link.is_path()),
                         linked_note: target_name,
+                        heading: intern_link.heading.clone(),
                     },
                     intern_link.span.clone(),
                 ));
@@ -196,3 +1128,1762 @@ pub fn check_intern_links(facts: &dyn Facts, note: &impl NoteFactsExt) -> Vec<Di
 
     diags
 }
+
+/// Path of the file a link from `file` to `dest` points to, if it's in one of
+/// the `resource_roots` folders. A `dest` starting with `/` is relative to the
+/// folder root.
+fn resource_target(roots: &PathPatterns, file: &NoteFile, dest: &str) -> Option<PathBuf> {
+    if roots.is_empty() {
+        return None;
+    }
+    let path = match dest.strip_prefix('/') {
+        Some(from_root) => file.root.join(from_root),
+        None => file.path.parent()?.join(dest),
+    };
+    let path = util::normalize_path(&path);
+    let rel_path = path.strip_prefix(&file.root).ok()?;
+    if rel_path.as_os_str().is_empty() || !roots.matches(rel_path) {
+        return None;
+    }
+    Some(path)
+}
+
+/// Markdown links into the `resource_roots` folders, e.g. `[x](assets/diagram)`
+/// or `[x](assets/diagram.png)`, with the paths of the files they point to.
+fn resource_links(
+    roots: &PathPatterns,
+    note: &impl NoteFactsExt,
+) -> Vec<(PathBuf, String, Range<Pos>)> {
+    let file = note.file();
+    if roots.is_empty() {
+        return Vec::new();
+    }
+
+    let strukt = note.structure();
+    let intern_dests = strukt
+        .intern_links_with_ids(&note.intern_link_ids())
+        .into_iter()
+        .filter(|link| link.is_markdown())
+        .filter_map(|link| Some((link.note_name.as_ref()?.to_str().to_string(), &link.span)));
+    // Targets with other extensions than of notes, e.g. `diagram.png`
+    let extern_dests = strukt.external_links().into_iter().filter_map(|link| {
+        let dest = link.dest()?;
+        if dest.contains(':') || dest.starts_with(parser::HEADING_START) {
+            return None;
+        }
+        let path = dest.split([parser::HEADING_START, '?']).next()?;
+        Some((util::percent_decode(path).to_string(), &link.span))
+    });
+
+    intern_dests
+        .chain(extern_dests)
+        .filter_map(|(dest, span)| {
+            let path = resource_target(roots, &file, &dest)?;
+            Some((path, dest, span.clone()))
+        })
+        .collect()
+}
+
+/// Check that `links` into the `resource_roots` folders point to existing
+/// files. A link without an extension matches a file with any extension.
+///
+/// Files other than notes aren't facts, so this looks at the disk every time
+/// rather than being a part of the memoized [`NoteFacts::diag`].
+fn check_resource_links(links: &[(PathBuf, String, Range<Pos>)]) -> Vec<DiagWithLoc> {
+    links
+        .iter()
+        .filter(|(path, _, _)| !path.is_file() && !util::exists_with_any_extension(path))
+        .map(|(_, dest, span)| {
+            let diag = Diag::BrokenResourceLink {
+                target: dest.clone(),
+            };
+            (diag, span.clone())
+        })
+        .collect()
+}
+
+/// Resource files are matched by the path without an extension, as links to
+/// them may leave it out.
+fn resource_key(path: &Path) -> PathBuf {
+    path.with_extension("")
+}
+
+/// All diagnostics of the note: the memoized ones and the ones of links into
+/// the `resource_roots` folders.
+pub fn note_diags(facts: &FactsDB, note: &NoteFactsDB) -> HashSet<DiagWithLoc> {
+    let links = resource_links(&facts.db().resource_roots(()), note);
+    let mut diags: HashSet<DiagWithLoc> = note.diag().iter().cloned().collect();
+    diags.extend(check_resource_links(&links));
+    diags
+}
+
+/// Punctuation that ends up in a link target when it's typed as `[x](note.)`
+/// instead of `[x](note).`
+const STRAY_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?'];
+
+/// Target of `link` without the trailing punctuation, if the link resolves
+/// once the punctuation is dropped.
+fn without_stray_punctuation(
+    facts: &dyn Facts,
+    note_id: NoteID,
+    link: &InternLink,
+) -> Option<NoteName> {
+    let name = link.note_name.as_ref()?.to_str();
+    let trimmed = name.trim_end_matches(STRAY_PUNCTUATION);
+    if trimmed.is_empty() || trimmed == name {
+        return None;
+    }
+    let candidate = InternLink {
+        note_name: Some(trimmed.into()),
+        ..link.clone()
+    };
+    facts::resolve_link_target(facts, note_id, &candidate)?;
+    Some(trimmed.into())
+}
+
+/// Workspace-level check for notes that no other note links to.
+pub fn check_orphans(facts: &dyn Facts) -> Vec<(NoteFile, DiagWithLoc)> {
+    debug!("check_orphans: start");
+
+    let index = facts.note_index(());
+    let mut linked_ids = HashSet::new();
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        let strukt = note.structure();
+        for &(link_id, target_id) in note.link_targets().iter() {
+            let target_id =
+                target_id.filter(|_| strukt.intern_link_by_id(link_id).note_name.is_some());
+            // Links to self don't make a note any less of an orphan
+            if let Some(target_id) = target_id.filter(|&id| id != note_id) {
+                linked_ids.insert(target_id);
+            }
+        }
+    }
+
+    let mut diags = Vec::new();
+    for note_id in index.ids() {
+        if linked_ids.contains(&note_id) {
+            continue;
+        }
+
+        let note = NoteFactsDB::new(facts, note_id);
+        let file = note.file();
+        let span = title_span(&note);
+        let diag = Diag::OrphanNote {
+            note: (*file.name).clone(),
+        };
+        diags.push((file, (diag, span)));
+    }
+
+    debug!("check_orphans: reporting {}", diags.len());
+    diags
+}
+
+/// Workspace-level check for headings (other than titles) that appear in several notes.
+///
+/// Only the first occurrence within a note is reported, the rest are reported
+/// by [`check_headings`].
+pub fn check_cross_note_heading_dups(facts: &dyn Facts) -> Vec<(NoteFile, DiagWithLoc)> {
+    let index = facts.note_index(());
+    let title_level = facts.title_level(());
+    let mut by_text: BTreeMap<String, Vec<(NoteFile, Range<Pos>)>> = BTreeMap::new();
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        let strukt = note.structure();
+        let mut seen = HashSet::new();
+        let heading_ids = note.headings_matching(|h| h.level != title_level);
+        for heading in strukt.headings_with_ids(&heading_ids) {
+            if seen.insert(&heading.text) {
+                by_text
+                    .entry(heading.text.clone())
+                    .or_default()
+                    .push((note.file(), heading.span.clone()));
+            }
+        }
+    }
+
+    let mut diags = Vec::new();
+    for (heading, occurrences) in by_text {
+        if occurrences.len() < 2 {
+            continue;
+        }
+
+        for (file, span) in &occurrences {
+            let mut others = occurrences
+                .iter()
+                .filter(|(f, _)| f != file)
+                .map(|(f, _)| (*f.name).clone())
+                .collect::<Vec<_>>();
+            others.sort();
+            let diag = Diag::CrossNoteDupHeading {
+                heading: heading.clone(),
+                others,
+            };
+            diags.push((file.clone(), (diag, span.clone())));
+        }
+    }
+    diags
+}
+
+/// Stop looking for cycles after this many, the number of cycles can grow
+/// exponentially with the number of links.
+const MAX_LINK_CYCLES: usize = 1000;
+
+/// Simple cycles in the graph of links between notes, each reported once and
+/// starting from the note with the smallest name. A note linking to itself by
+/// name is a cycle of one note.
+pub fn check_link_cycles(facts: &dyn Facts) -> Vec<Vec<NoteID>> {
+    let index = facts.note_index(());
+    let mut ids: Vec<NoteID> = index.ids().collect();
+    ids.sort_by_key(|&id| index.find_by_id(id).name);
+    let rank: HashMap<NoteID, usize> = ids.iter().enumerate().map(|(r, &id)| (id, r)).collect();
+
+    // Adjacency by rank, sorted for a stable order of cycles
+    let edges: Vec<Vec<usize>> = ids
+        .iter()
+        .map(|&id| {
+            let targets: BTreeSet<usize> = link_targets(facts, id)
+                .into_iter()
+                .map(|(target_id, _)| rank[&target_id])
+                .collect();
+            targets.into_iter().collect()
+        })
+        .collect();
+
+    // Every cycle is found once: from its smallest node through larger nodes only
+    let mut cycles: Vec<Vec<usize>> = Vec::new();
+    for start in 0..ids.len() {
+        let mut path = vec![start];
+        let mut on_path = vec![false; ids.len()];
+        on_path[start] = true;
+        // DFS with explicit stack of (node, next edge to try)
+        let mut stack = vec![(start, 0)];
+        while let Some((node, edge_idx)) = stack.pop() {
+            if cycles.len() >= MAX_LINK_CYCLES {
+                break;
+            }
+            match edges[node].get(edge_idx) {
+                Some(&next) => {
+                    stack.push((node, edge_idx + 1));
+                    if next == start {
+                        cycles.push(path.clone());
+                    } else if next > start && !on_path[next] {
+                        on_path[next] = true;
+                        path.push(next);
+                        stack.push((next, 0));
+                    }
+                }
+                None => {
+                    on_path[node] = false;
+                    path.pop();
+                }
+            }
+        }
+    }
+
+    cycles
+        .into_iter()
+        .map(|cycle| cycle.into_iter().map(|r| ids[r]).collect())
+        .collect()
+}
+
+/// Notes linked from the note by name together with the span of the first link to each.
+fn link_targets(facts: &dyn Facts, note_id: NoteID) -> Vec<(NoteID, Range<Pos>)> {
+    let note = NoteFactsDB::new(facts, note_id);
+    let strukt = note.structure();
+    let mut targets: Vec<(NoteID, Range<Pos>)> = Vec::new();
+    for &(link_id, target_id) in note.link_targets().iter() {
+        let link = strukt.intern_link_by_id(link_id);
+        if let Some(target_id) = target_id.filter(|_| link.note_name.is_some()) {
+            if targets.iter().all(|(id, _)| *id != target_id) {
+                targets.push((target_id, link.span.clone()));
+            }
+        }
+    }
+    targets
+}
+
+/// Workspace-level diagnostics for link cycles reported on the first link of a cycle.
+pub fn check_link_cycle_diags(facts: &dyn Facts) -> Vec<(NoteFile, DiagWithLoc)> {
+    let index = facts.note_index(());
+    let mut diags = Vec::new();
+    for cycle in check_link_cycles(facts) {
+        let (src_id, dst_id) = (cycle[0], cycle[1 % cycle.len()]);
+        let span = link_targets(facts, src_id)
+            .into_iter()
+            .find(|(id, _)| *id == dst_id)
+            .map(|(_, span)| span);
+        let span = match span {
+            Some(span) => span,
+            None => continue,
+        };
+
+        let mut cycle = cycle
+            .into_iter()
+            .map(|id| (*index.find_by_id(id).name).clone())
+            .collect::<Vec<_>>();
+        let diag = if cycle.len() == 1 {
+            Diag::SelfLink {
+                note: cycle.remove(0),
+            }
+        } else {
+            Diag::LinkCycle { cycle }
+        };
+        diags.push((index.find_by_id(src_id), (diag, span)));
+    }
+    diags
+}
+
+/// Workspace-level check for notes that share the same title.
+pub fn check_dup_note_titles(facts: &dyn Facts) -> Vec<(NoteFile, DiagWithLoc)> {
+    debug!("check_dup_note_titles: start");
+
+    let index = facts.note_index(());
+    let mut by_title: HashMap<String, Vec<NoteFactsDB>> = HashMap::new();
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        if let Some(title) = note.effective_title() {
+            by_title.entry(title).or_default().push(note);
+        }
+    }
+
+    let mut diags = Vec::new();
+    for (title, notes) in by_title.into_iter().filter(|(_, notes)| notes.len() > 1) {
+        for note in &notes {
+            let others = notes
+                .iter()
+                .filter(|other| other.id != note.id)
+                .map(|other| (*other.file().name).clone())
+                .collect();
+            let diag = Diag::DupNoteTitle {
+                title: title.clone(),
+                others,
+            };
+            diags.push((note.file(), (diag, title_span(note))));
+        }
+    }
+
+    diags
+}
+
+/// Where the title of the note is defined, or the start of the note if it has no title.
+fn title_span(note: &NoteFactsDB) -> Range<Pos> {
+    if note.frontmatter_title().is_some() {
+        if let Some(frontmatter) = note.frontmatter() {
+            return frontmatter.span;
+        }
+    }
+
+    note.title()
+        .map(|id| note.structure().heading_by_id(id).span.clone())
+        .or_else(|| Some(note.first_line_title()?.span))
+        .unwrap_or_else(|| Pos::new(0, 0)..Pos::new(0, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        config::{PathPatterns, Severity},
+        encoding::PositionEncoding,
+        store::{NameMatching, NoteText, TitleFallback, Version},
+    };
+    use pretty_assertions::assert_eq;
+
+    fn facts_with_notes(notes: &[(&str, &str)]) -> FactsDB {
+        FactsDB::from_texts(Path::new("/notes"), notes)
+    }
+
+    fn publish(facts: &FactsDB, name: &str, config: &Config) -> PublishDiagnosticsParams {
+        let id = facts
+            .note_index()
+            .find_by_path(&Path::new("/notes").join(name))
+            .unwrap();
+        let note = facts.note_facts(id);
+        let diags = note_diags(facts, &note);
+        to_publish(&note.file(), &diags, facts, config).unwrap()
+    }
+
+    fn check_note(facts: &FactsDB, name: &str, code: &str) -> Vec<DiagWithLoc> {
+        let id = facts
+            .note_index()
+            .find_by_path(&Path::new("/notes").join(name))
+            .unwrap();
+        let mut diags = facts
+            .note_facts(id)
+            .diag()
+            .iter()
+            .filter(|(d, _)| d.code() == code)
+            .cloned()
+            .collect::<Vec<_>>();
+        diags.sort_by_key(|(_, span)| span.start);
+        diags
+    }
+
+    #[test]
+    fn missing_title() {
+        let facts = facts_with_notes(&[("a.md", "## Heading\n\nText\n")]);
+        let diags = check_note(&facts, "a.md", "missing-title");
+        assert_eq!(
+            diags,
+            vec![(
+                Diag::MissingTitle { level: 1 },
+                Pos::new(0, 0)..Pos::new(0, 0)
+            )]
+        );
+
+        let facts = facts_with_notes(&[("a.md", "# Title\n\n## Heading\n")]);
+        assert_eq!(check_note(&facts, "a.md", "missing-title"), vec![]);
+    }
+
+    #[test]
+    fn title_at_other_level() {
+        let mut facts = facts_with_notes(&[
+            ("a.md", "## Title\n\n### Sub\n\n## Again\n"),
+            ("b.md", "# Collection\n\n[:Title]\n"),
+        ]);
+        let a = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/a.md"))
+            .unwrap();
+        let b = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/b.md"))
+            .unwrap();
+
+        let at_level_1 = check_title(&facts.note_facts(a), 1);
+        assert_eq!(
+            at_level_1,
+            vec![(
+                Diag::MissingTitle { level: 1 },
+                Pos::new(0, 0)..Pos::new(0, 0)
+            )]
+        );
+        assert_eq!(check_title(&facts.note_facts(b), 2).len(), 1);
+
+        facts.set_title_level(2);
+        let at_level_2 = check_title(&facts.note_facts(a), 2);
+        assert_eq!(at_level_2.len(), 1);
+        assert_eq!(at_level_2[0].0.code(), "dup-title");
+        assert_eq!(at_level_2[0].1.start, Pos::new(4, 0));
+        assert_eq!(check_note(&facts, "a.md", "missing-title"), vec![]);
+        assert_eq!(check_note(&facts, "a.md", "dup-title").len(), 1);
+        // `## Again` is the duplicate title rather than a duplicate heading
+        assert_eq!(check_note(&facts, "a.md", "dup-heading"), vec![]);
+
+        // Links resolve by the title at the configured level
+        assert_eq!(check_note(&facts, "b.md", "broken-note-link"), vec![]);
+        facts.set_title_level(1);
+        assert_eq!(check_note(&facts, "b.md", "broken-note-link").len(), 1);
+    }
+
+    #[test]
+    fn first_line_titles() {
+        let mut facts = facts_with_notes(&[
+            ("a.md", "\nShopping list\n\n- Milk\n"),
+            ("b.md", "---\ntags: [x]\n---\n\nShopping list\n"),
+            ("c.md", "# Groceries\n\nShopping list\n"),
+            ("d.md", "# D\n\n[:Shopping list] [:Groceries]\n"),
+        ]);
+        assert_eq!(check_note(&facts, "a.md", "missing-title").len(), 1);
+        assert_eq!(check_note(&facts, "d.md", "broken-note-link").len(), 1);
+
+        facts.set_title_fallback(TitleFallback::FirstLine);
+        assert_eq!(check_note(&facts, "a.md", "missing-title"), vec![]);
+        assert_eq!(check_note(&facts, "b.md", "missing-title"), vec![]);
+        assert_eq!(check_note(&facts, "d.md", "broken-note-link"), vec![]);
+
+        // The heading is still the title of a note that has one
+        assert_eq!(
+            check_note(&facts, "a.md", "dup-note-title"),
+            vec![(
+                Diag::DupNoteTitle {
+                    title: "Shopping list".to_string(),
+                    others: vec!["b".into()]
+                },
+                Pos::new(1, 0)..Pos::new(1, 13)
+            )]
+        );
+        assert_eq!(
+            check_note(&facts, "b.md", "dup-note-title")[0].1.start,
+            Pos::new(4, 0)
+        );
+        assert_eq!(check_note(&facts, "c.md", "dup-note-title"), vec![]);
+    }
+
+    #[test]
+    fn broken_wiki_links() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[[b#Sub]] [[b#Missing]] [[c]]\n"),
+            ("b.md", "# B\n\n## Sub\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-heading-link"),
+            vec![(
+                Diag::BrokenInternLinkToHeading {
+                    linked_note: "b".into(),
+                    heading: "Missing".to_string(),
+                    suggestion: None
+                },
+                Pos::new(2, 10)..Pos::new(2, 23)
+            )]
+        );
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-note-link"),
+            vec![(
+                Diag::BrokenInternLinkToNote {
+                    linked_note: "c".into(),
+                    heading: None,
+                    suggestion: None
+                },
+                Pos::new(2, 24)..Pos::new(2, 29)
+            )]
+        );
+    }
+
+    #[test]
+    fn heading_links_by_slug() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A Title\n\n[:b@#my-heading] [[b#my-heading]] [:@#a-title] [:b@#missing]\n",
+            ),
+            ("b.md", "# B\n\n## My Heading!\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-heading-link"),
+            vec![(
+                Diag::BrokenInternLinkToHeading {
+                    linked_note: "b".into(),
+                    heading: "#missing".to_string(),
+                    suggestion: None
+                },
+                Pos::new(2, 47)..Pos::new(2, 60)
+            )]
+        );
+        let id = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/a.md"))
+            .unwrap();
+        assert_eq!(facts.note_facts(id).valid_intern_links().len(), 3);
+    }
+
+    #[test]
+    fn duplicates_point_to_each_other() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n## Sub\n\n# B\n\n## Sub\n")]);
+        let published = publish(&facts, "a.md", &Config::default());
+        let uri = Url::from_file_path("/notes/a.md").unwrap();
+        let related = |code: &str| {
+            let diags = published
+                .diagnostics
+                .iter()
+                .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+                .collect::<Vec<_>>();
+            assert_eq!(diags.len(), 1);
+            let related = diags[0].related_information.clone().unwrap();
+            let lines = related
+                .iter()
+                .map(|r| {
+                    assert_eq!(r.location.uri, uri);
+                    assert_eq!(r.message, "Also defined here");
+                    r.location.range.start.line
+                })
+                .collect::<Vec<_>>();
+            (diags[0].range.start.line, lines)
+        };
+
+        assert_eq!(related("dup-title"), (4, vec![0]));
+        let (line, lines) = related("dup-heading");
+        // Either of the headings is reported, the other one is related
+        assert_eq!(lines, vec![if line == 2 { 6 } else { 2 }]);
+    }
+
+    #[test]
+    fn duplicate_link_references() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[B][foo] [bar]\n\n[foo]: b\n[Foo]:  c\n[bar]: b\n[ foo ]: d\n",
+            ),
+            ("b.md", "# B\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "dup-link-ref"),
+            vec![
+                (
+                    Diag::DuplicateLinkReference {
+                        label: "Foo".to_string()
+                    },
+                    Pos::new(5, 0)..Pos::new(5, 9)
+                ),
+                (
+                    Diag::DuplicateLinkReference {
+                        label: "foo".to_string()
+                    },
+                    Pos::new(7, 0)..Pos::new(7, 10)
+                )
+            ]
+        );
+        // Links resolve to the first definition
+        assert!(check_note(&facts, "a.md", "broken-note-link").is_empty());
+    }
+
+    #[test]
+    fn reference_links() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[B][b] [Sub][sub] [c][] [x][missing] [web] [y][]\n\n\
+                 [b]: b\n[sub]: b.md#Missing\n[c]: c.md\n[web]: https://example.com\n",
+            ),
+            ("b.md", "# B\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "undefined-link-ref"),
+            vec![
+                (
+                    Diag::UndefinedLinkReference {
+                        reference: "missing".to_string()
+                    },
+                    Pos::new(2, 24)..Pos::new(2, 36)
+                ),
+                (
+                    Diag::UndefinedLinkReference {
+                        reference: "y".to_string()
+                    },
+                    Pos::new(2, 43)..Pos::new(2, 46)
+                )
+            ]
+        );
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-heading-link"),
+            vec![(
+                Diag::BrokenInternLinkToHeading {
+                    linked_note: "b.md".into(),
+                    heading: "Missing".to_string(),
+                    suggestion: None
+                },
+                Pos::new(2, 7)..Pos::new(2, 17)
+            )]
+        );
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-note-link"),
+            vec![(
+                Diag::BrokenInternLinkToNote {
+                    linked_note: "c.md".into(),
+                    heading: None,
+                    suggestion: None
+                },
+                Pos::new(2, 18)..Pos::new(2, 21)
+            )]
+        );
+        assert_eq!(check_note(&facts, "b.md", "orphan-note"), vec![]);
+    }
+
+    #[test]
+    fn path_links() {
+        let facts = facts_with_notes(&[
+            (
+                "sub/a.md",
+                "# A\n\n[Same](./c.md) [Up](../b.md) [Deep](deeper/d) [Gone](../missing.md)\n",
+            ),
+            ("b.md", "# B\n"),
+            ("sub/c.md", "# C\n"),
+            ("sub/deeper/d.md", "# D\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "sub/a.md", "broken-note-link"),
+            vec![(
+                Diag::BrokenInternLinkToNote {
+                    linked_note: "../missing.md".into(),
+                    heading: None,
+                    suggestion: None
+                },
+                Pos::new(2, 46)..Pos::new(2, 67)
+            )]
+        );
+        for name in ["b.md", "sub/c.md", "sub/deeper/d.md"] {
+            assert_eq!(check_note(&facts, name, "orphan-note"), vec![]);
+        }
+
+        // Paths are relative to the linking note, not to the root
+        let facts = facts_with_notes(&[("a.md", "# A\n\n[C](c.md)\n"), ("sub/c.md", "# C\n")]);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link").len(), 1);
+    }
+
+    #[test]
+    fn heading_link_in_other_case() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[x](b#Introduction) [:b@## INTRODUCTION] [:b@## Other]\n",
+            ),
+            ("b.md", "# B\n\n## introduction\n"),
+        ]);
+
+        let suggestions: Vec<_> = check_note(&facts, "a.md", "broken-heading-link")
+            .into_iter()
+            .map(|(diag, _)| match diag {
+                Diag::BrokenInternLinkToHeading { suggestion, .. } => suggestion,
+                other => panic!("Unexpected diag: {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            suggestions,
+            vec![
+                Some("introduction".to_string()),
+                Some("## introduction".to_string()),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_note_link_suggestions() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:projcet] [:project-plan] [:xz]\n"),
+            ("project.md", "# Project\n"),
+            ("xy.md", "# XY\n"),
+        ]);
+
+        let suggestions: Vec<_> = check_note(&facts, "a.md", "broken-note-link")
+            .into_iter()
+            .map(|(diag, _)| match diag {
+                Diag::BrokenInternLinkToNote { suggestion, .. } => suggestion,
+                other => panic!("Unexpected diag: {:?}", other),
+            })
+            .collect();
+        // `project-plan` is too far from `project`, `xz` is too short
+        assert_eq!(suggestions, vec![Some("project".into()), None, None]);
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-note-link")[0]
+                .0
+                .to_message(),
+            "Reference to non-existent note `projcet` (did you mean `project`?)"
+        );
+    }
+
+    #[test]
+    fn no_broken_links_in_code() {
+        let facts = facts_with_notes(&[(
+            "a.md",
+            "# A\n\n```markdown\n[example](foo) [:foo]\n```\n\nUse `[x](y)` or `[[y]]`.\n\n[real](foo)\n",
+        )]);
+        let broken = check_note(&facts, "a.md", "broken-note-link");
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].1.start.line, 8);
+    }
+
+    #[test]
+    fn no_broken_links_to_urls() {
+        let facts = facts_with_notes(&[(
+            "a.md",
+            "# A\n\n<https://example.com>, https://example.org and [x](https://example.net)\n",
+        )]);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+
+        let note = facts.note_facts(facts.note_index().find_by_name(&"a".into()).unwrap());
+        assert_eq!(note.structure().external_links().len(), 3);
+        assert!(note.intern_link_ids().is_empty());
+    }
+
+    #[test]
+    fn line_anchor_links() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[x](b#L3-L4) [y](b#L2) [z](b#L4-L9) [w](b#L5) [v](b#L3-L2)\n",
+            ),
+            ("b.md", "# B\n\nFirst line\nSecond\n"),
+        ]);
+
+        let diags = check_note(&facts, "a.md", "link-line-out-of-range");
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    Diag::LinkLineOutOfRange {
+                        linked_note: "b".into(),
+                        start: 4,
+                        end: 9,
+                        line_count: 4
+                    },
+                    Pos::new(2, 23)..Pos::new(2, 35)
+                ),
+                (
+                    Diag::LinkLineOutOfRange {
+                        linked_note: "b".into(),
+                        start: 5,
+                        end: 5,
+                        line_count: 4
+                    },
+                    Pos::new(2, 36)..Pos::new(2, 45)
+                ),
+                (
+                    Diag::LinkLineOutOfRange {
+                        linked_note: "b".into(),
+                        start: 3,
+                        end: 2,
+                        line_count: 4
+                    },
+                    Pos::new(2, 46)..Pos::new(2, 58)
+                ),
+            ]
+        );
+        assert_eq!(
+            diags[0].0.to_message(),
+            "Lines 4-9 out of range of note `b` with 4 lines"
+        );
+        assert_eq!(
+            diags[1].0.to_message(),
+            "Line 5 out of range of note `b` with 4 lines"
+        );
+        // Not reported as missing headings either
+        assert_eq!(check_note(&facts, "a.md", "broken-heading-link"), vec![]);
+    }
+
+    #[test]
+    fn empty_link_labels() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[](b) [ ](b#sub) [x](b) [:b] [](#own)\n\n## Own\n",
+            ),
+            ("b.md", "# B\n\n## Sub\n"),
+        ]);
+
+        let diags = check_note(&facts, "a.md", "empty-link-label");
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    Diag::EmptyLinkLabel {
+                        linked_note: "b".into()
+                    },
+                    Pos::new(2, 0)..Pos::new(2, 2)
+                ),
+                (
+                    Diag::EmptyLinkLabel {
+                        linked_note: "b".into()
+                    },
+                    Pos::new(2, 6)..Pos::new(2, 9)
+                ),
+                (
+                    Diag::EmptyLinkLabel {
+                        linked_note: "a".into()
+                    },
+                    Pos::new(2, 29)..Pos::new(2, 31)
+                ),
+            ]
+        );
+        assert_eq!(diags[0].0.to_message(), "Link to `b` has an empty label");
+    }
+
+    #[test]
+    fn broken_note_link_with_heading() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n[x](ghost#section) [[ghost]]\n")]);
+
+        let messages: Vec<_> = check_note(&facts, "a.md", "broken-note-link")
+            .into_iter()
+            .map(|(diag, _)| diag.to_message())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Reference to non-existent note `ghost` (expected heading `section`)",
+                "Reference to non-existent note `ghost`"
+            ]
+        );
+    }
+
+    #[test]
+    fn percent_encoded_links() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[x](my%20note) [y](sub%2Fnote) [z](no%20such)\n",
+            ),
+            ("my note.md", "# My Note\n"),
+            ("sub/note.md", "# Sub\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "broken-note-link"),
+            vec![(
+                Diag::BrokenInternLinkToNote {
+                    linked_note: "no such".into(),
+                    heading: None,
+                    suggestion: None
+                },
+                Pos::new(2, 31)..Pos::new(2, 45)
+            )]
+        );
+    }
+
+    #[test]
+    fn tag_namespaces() {
+        let mut facts = facts_with_notes(&[("a.md", "# A\n\n#random #project/x #area\n")]);
+        // Off by default
+        assert_eq!(check_note(&facts, "a.md", "unknown-tag-namespace"), vec![]);
+
+        facts.set_diag_options(DiagOptions {
+            allowed_tag_prefixes: vec!["project".to_string()],
+            ..DiagOptions::default()
+        });
+        let diags = check_note(&facts, "a.md", "unknown-tag-namespace");
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    Diag::UnknownTagNamespace {
+                        tag: "random".to_string()
+                    },
+                    Pos::new(2, 0)..Pos::new(2, 7)
+                ),
+                (
+                    Diag::UnknownTagNamespace {
+                        tag: "area".to_string()
+                    },
+                    Pos::new(2, 19)..Pos::new(2, 24)
+                ),
+            ]
+        );
+        assert_eq!(
+            diags[0].0.to_message(),
+            "Tag `#random` is not in one of the allowed namespaces"
+        );
+    }
+
+    #[test]
+    fn resource_links() {
+        let root = std::env::temp_dir().join("zeta-note-diag-resources");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("assets")).unwrap();
+        std::fs::write(root.join("assets").join("diagram.png"), "").unwrap();
+        std::fs::write(root.join("assets").join("photo.png"), "").unwrap();
+
+        let text = "# A\n\n[d](assets/diagram) [p](assets/photo.png) [m](assets/missing) \
+                    [n](assets/gone.png) [o](other)\n";
+        let mut facts = FactsDB::from_texts(&root, &[("a.md", text)]);
+        facts.set_resource_roots(PathPatterns::new(vec!["assets/".to_string()]));
+        let id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let diags = note_diags(&facts, &facts.note_facts(id));
+        let with_code = |code: &str| {
+            let mut diags: Vec<_> = diags
+                .iter()
+                .filter(|(d, _)| d.code() == code)
+                .cloned()
+                .collect();
+            diags.sort_by_key(|(_, span)| span.start);
+            diags
+        };
+
+        assert_eq!(
+            with_code("broken-resource-link"),
+            vec![
+                (
+                    Diag::BrokenResourceLink {
+                        target: "assets/missing".to_string()
+                    },
+                    Pos::new(2, 42)..Pos::new(2, 61)
+                ),
+                (
+                    Diag::BrokenResourceLink {
+                        target: "assets/gone.png".to_string()
+                    },
+                    Pos::new(2, 62)..Pos::new(2, 82)
+                ),
+            ]
+        );
+        // Links outside of resource folders still have to point to notes
+        let broken_notes: Vec<_> = with_code("broken-note-link")
+            .into_iter()
+            .map(|(d, _)| d.to_message())
+            .collect();
+        assert_eq!(broken_notes, vec!["Reference to non-existent note `other`"]);
+
+        // Adding a missing file rechecks the notes linking to it
+        let mut diag_col = DiagCollection::default();
+        diag_col.recompute_all(&facts);
+        let a = facts.note_index().find_by_id(id);
+        let added = root.join("assets").join("missing.svg");
+        std::fs::write(&added, "").unwrap();
+        assert_eq!(
+            diag_col.recompute_for(&NoteFile::new(&root, &added), &facts),
+            vec![a.clone()]
+        );
+        let broken_resources = diag_col.store[&a]
+            .iter()
+            .filter(|(d, _)| d.code() == "broken-resource-link")
+            .count();
+        assert_eq!(broken_resources, 1);
+    }
+
+    #[test]
+    fn invalid_frontmatter() {
+        let facts = facts_with_notes(&[
+            ("a.md", "---\ntitle: [A\n---\n# A\n"),
+            ("b.md", "---\ntitle: B\n---\n# B\n"),
+        ]);
+
+        let diags = check_note(&facts, "a.md", "invalid-frontmatter");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].1, Pos::new(0, 0)..Pos::new(2, 3));
+        assert!(diags[0]
+            .0
+            .to_message()
+            .starts_with("Invalid YAML frontmatter: "));
+        // The body is still parsed
+        assert_eq!(check_note(&facts, "a.md", "missing-title"), vec![]);
+
+        assert_eq!(check_note(&facts, "b.md", "invalid-frontmatter"), vec![]);
+    }
+
+    #[test]
+    fn frontmatter_title() {
+        let facts = facts_with_notes(&[
+            ("a.md", "---\ntitle: Alpha\n---\nText [:Alpha] [:Beta]\n"),
+            ("b.md", "---\ntitle: Beta\n---\n# Title\n\n[:Alpha]\n"),
+            ("c.md", "# Beta\n"),
+        ]);
+
+        assert_eq!(check_note(&facts, "a.md", "missing-title"), vec![]);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        assert_eq!(check_note(&facts, "b.md", "broken-note-link"), vec![]);
+        assert_eq!(check_note(&facts, "a.md", "orphan-note"), vec![]);
+
+        assert_eq!(check_note(&facts, "a.md", "dup-note-title"), vec![]);
+        assert_eq!(
+            check_note(&facts, "b.md", "dup-note-title"),
+            vec![(
+                Diag::DupNoteTitle {
+                    title: "Beta".to_string(),
+                    others: vec!["c".into()]
+                },
+                Pos::new(0, 0)..Pos::new(2, 3)
+            )]
+        );
+        assert_eq!(
+            check_note(&facts, "c.md", "dup-note-title"),
+            vec![(
+                Diag::DupNoteTitle {
+                    title: "Beta".to_string(),
+                    others: vec!["b".into()]
+                },
+                Pos::new(0, 0)..Pos::new(0, 6)
+            )]
+        );
+    }
+
+    #[test]
+    fn links_across_extensions() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:b] [:b@## Sub]\n"),
+            ("b.markdown", "# B\n\n## Sub\n\n[:a]\n"),
+        ]);
+
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        assert_eq!(check_note(&facts, "a.md", "broken-heading-link"), vec![]);
+        assert_eq!(check_note(&facts, "b.markdown", "broken-note-link"), vec![]);
+    }
+
+    #[test]
+    fn case_insensitive_names() {
+        let mut facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:My-Note] [:b]\n"),
+            ("my-note.md", "# My note\n"),
+            ("B.md", "# B\n"),
+            ("b.md", "# b\n"),
+        ]);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link").len(), 1);
+        assert_eq!(check_note(&facts, "a.md", "ambiguous-note-link"), vec![]);
+
+        facts.set_name_matching(NameMatching::CaseInsensitive);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        assert_eq!(
+            check_note(&facts, "a.md", "ambiguous-note-link"),
+            vec![(
+                Diag::AmbiguousInternLink {
+                    linked_note: "b".into(),
+                    candidates: vec!["B".into(), "b".into()]
+                },
+                Pos::new(2, 11)..Pos::new(2, 15)
+            )]
+        );
+    }
+
+    #[test]
+    fn normalized_names() {
+        let mut facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n[:My Note] [:my-note] [:MY_NOTE] [:my  note] [:my project]\n",
+            ),
+            ("my_note.md", "# Something\n"),
+            ("proj.md", "# My Project\n"),
+        ]);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link").len(), 5);
+
+        facts.set_name_matching(NameMatching::Normalized);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+    }
+
+    #[test]
+    fn names_in_subfolders() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[x](note) [y](sub/note) [z](dup)\n"),
+            ("other/b.md", "# B\n\n[x](sub/note) [[dup]] [[sub/note]]\n"),
+            ("sub/note.md", "# Note\n"),
+            ("one/dup.md", "# Dup 1\n"),
+            ("two/dup.md", "# Dup 2\n"),
+        ]);
+
+        let ambiguous = |span| {
+            (
+                Diag::AmbiguousInternLink {
+                    linked_note: "dup".into(),
+                    candidates: vec!["one/dup".into(), "two/dup".into()],
+                },
+                span,
+            )
+        };
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        assert_eq!(
+            check_note(&facts, "a.md", "ambiguous-note-link"),
+            vec![ambiguous(Pos::new(2, 24)..Pos::new(2, 32))]
+        );
+        assert_eq!(check_note(&facts, "other/b.md", "broken-note-link"), vec![]);
+        assert_eq!(
+            check_note(&facts, "other/b.md", "ambiguous-note-link"),
+            vec![ambiguous(Pos::new(2, 14)..Pos::new(2, 21))]
+        );
+
+        // An ambiguous name doesn't resolve to any of the candidates
+        let index = facts.note_index();
+        assert_eq!(index.find_by_name(&"dup".into()), None);
+        assert_eq!(
+            index.find_by_name(&"note".into()),
+            index.find_by_path(Path::new("/notes/sub/note.md"))
+        );
+    }
+
+    #[test]
+    fn empty_headings() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n##   \n\n## Real\n\n##\n")]);
+
+        let diags = check_note(&facts, "a.md", "empty-heading");
+        assert_eq!(
+            diags
+                .iter()
+                .map(|(_, span)| span.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Pos::new(2, 0)..Pos::new(2, 2),
+                Pos::new(6, 0)..Pos::new(6, 2)
+            ]
+        );
+        assert_eq!(diags[0].0.to_message(), "Empty heading `##`");
+        // Stray markers are not duplicates of each other
+        assert_eq!(check_note(&facts, "a.md", "dup-heading"), vec![]);
+    }
+
+    #[test]
+    fn skipped_heading_levels() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\n### Deep\n\n## Back\n\n#### Deeper\n\n### Up\n",
+            ),
+            ("b.md", "## Only\n\n### Sub\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "skipped-heading-level"),
+            vec![
+                (
+                    Diag::SkippedHeadingLevel { from: 1, to: 3 },
+                    Pos::new(2, 0)..Pos::new(2, 8)
+                ),
+                (
+                    Diag::SkippedHeadingLevel { from: 2, to: 4 },
+                    Pos::new(6, 0)..Pos::new(6, 11)
+                ),
+            ]
+        );
+        // The first heading sets the baseline
+        assert_eq!(check_note(&facts, "b.md", "skipped-heading-level"), vec![]);
+    }
+
+    #[test]
+    fn heading_depth() {
+        let mut facts =
+            facts_with_notes(&[("a.md", "# A\n\n## B\n\n### C\n\n#### D\n\n##### E\n")]);
+        // Off by default
+        assert_eq!(check_note(&facts, "a.md", "heading-too-deep"), vec![]);
+
+        facts.set_diag_options(DiagOptions {
+            max_heading_level: Some(3),
+            ..DiagOptions::default()
+        });
+        let diags = check_note(&facts, "a.md", "heading-too-deep");
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    Diag::HeadingTooDeep { level: 4, max: 3 },
+                    Pos::new(6, 0)..Pos::new(6, 6)
+                ),
+                (
+                    Diag::HeadingTooDeep { level: 5, max: 3 },
+                    Pos::new(8, 0)..Pos::new(8, 7)
+                ),
+            ]
+        );
+        assert_eq!(
+            diags[0].0.to_message(),
+            "Heading level 4 is deeper than the allowed 3"
+        );
+    }
+
+    #[test]
+    fn ambiguous_heading_anchors() {
+        let facts = facts_with_notes(&[(
+            "a.md",
+            "# A\n\n## My Heading\n\n## My-Heading\n\n### my heading?\n\n## Same\n\n## Same\n",
+        )]);
+
+        let diags = check_note(&facts, "a.md", "ambiguous-heading-anchor")
+            .into_iter()
+            .map(|(d, span)| match d {
+                Diag::AmbiguousHeadingAnchor { slug, others, .. } => {
+                    (span.start.line, slug, others)
+                }
+                other => panic!("Unexpected diag: {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    2,
+                    "my-heading".to_string(),
+                    vec!["## My-Heading".to_string(), "### my heading?".to_string()]
+                ),
+                (
+                    4,
+                    "my-heading".to_string(),
+                    vec!["## My Heading".to_string(), "### my heading?".to_string()]
+                ),
+                (
+                    6,
+                    "my-heading".to_string(),
+                    vec!["## My Heading".to_string(), "## My-Heading".to_string()]
+                ),
+            ]
+        );
+        // Exact duplicates are reported separately
+        assert_eq!(check_note(&facts, "a.md", "dup-heading").len(), 1);
+    }
+
+    #[test]
+    fn cross_note_heading_dups() {
+        let notes = [
+            ("a.md", "# A\n\n## Setup\n\n## Setup\n\n## Notes\n"),
+            ("b.md", "# B\n\n## Setup\n\n### Notes\n"),
+            ("c.md", "# C\n\n## Setup\n\n## Usage\n"),
+        ];
+        let mut facts = facts_with_notes(&notes);
+        assert_eq!(check_note(&facts, "a.md", "cross-note-dup-heading"), vec![]);
+
+        facts.set_diag_options(DiagOptions {
+            cross_note_heading_dups: true,
+            ..DiagOptions::default()
+        });
+        assert_eq!(
+            check_note(&facts, "a.md", "cross-note-dup-heading"),
+            vec![(
+                Diag::CrossNoteDupHeading {
+                    heading: "## Setup".to_string(),
+                    others: vec!["b".into(), "c".into()]
+                },
+                Pos::new(2, 0)..Pos::new(2, 8)
+            )]
+        );
+        // The duplicate within the note is reported by `check_headings` only
+        assert_eq!(check_note(&facts, "a.md", "dup-heading").len(), 1);
+        assert_eq!(
+            check_note(&facts, "b.md", "cross-note-dup-heading").len(),
+            1
+        );
+        assert_eq!(
+            check_note(&facts, "c.md", "cross-note-dup-heading").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn link_cycles() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:b] [:a] [:@## Sub]\n\n## Sub\n"),
+            ("b.md", "# B\n\n[:c] [:d]\n"),
+            ("c.md", "# C\n\n[:a] [:b]\n"),
+            ("d.md", "# D\n\n[:missing]\n"),
+            ("x.md", "# X\n\n[:y]\n"),
+            ("y.md", "# Y\n\n[:x]\n"),
+        ]);
+
+        let names = |names: &[&str]| names.iter().map(|&n| n.into()).collect::<Vec<NoteName>>();
+        let index = facts.note_index();
+        let cycles = check_link_cycles(facts.db())
+            .into_iter()
+            .map(|cycle| {
+                cycle
+                    .into_iter()
+                    .map(|id| (*index.find_by_id(id).name).clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cycles,
+            vec![
+                names(&["a"]),
+                names(&["a", "b", "c"]),
+                names(&["b", "c"]),
+                names(&["x", "y"]),
+            ]
+        );
+
+        let mut facts = facts;
+        assert_eq!(check_note(&facts, "a.md", "link-cycle"), vec![]);
+        facts.set_diag_options(DiagOptions {
+            link_cycles: true,
+            ..DiagOptions::default()
+        });
+        assert_eq!(
+            check_note(&facts, "a.md", "self-link"),
+            vec![(
+                Diag::SelfLink { note: "a".into() },
+                Pos::new(2, 5)..Pos::new(2, 9)
+            )]
+        );
+        assert_eq!(
+            check_note(&facts, "a.md", "link-cycle"),
+            vec![(
+                Diag::LinkCycle {
+                    cycle: names(&["a", "b", "c"])
+                },
+                Pos::new(2, 0)..Pos::new(2, 4)
+            )]
+        );
+        assert_eq!(check_note(&facts, "b.md", "link-cycle").len(), 1);
+        assert_eq!(check_note(&facts, "d.md", "link-cycle"), vec![]);
+    }
+
+    #[test]
+    fn recompute_only_dependents() {
+        let root = Path::new("/notes");
+        let mut facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:b@## Sub]\n"),
+            ("b.md", "# B\n\n[:a]\n"),
+            ("c.md", "# C\n\n[:a]\n"),
+        ]);
+        let file = |name: &str| NoteFile::new(root, &root.join(name));
+        let edit = |facts: &mut FactsDB, name: &str, text: &str| {
+            let note_file = file(name);
+            let text = NoteText::new(Version::Vs(1), text.into());
+            match facts.note_index().find_by_path(&note_file.path) {
+                Some(id) => facts.update_note(id, text),
+                None => facts.insert_note(note_file, text),
+            }
+        };
+
+        let mut col = DiagCollection::default();
+        assert_eq!(col.recompute_all(&facts).len(), 3);
+        assert_eq!(col.recompute_all(&facts), vec![]);
+
+        // Fixes the broken link in `a`
+        edit(&mut facts, "b.md", "# B\n\n[:a]\n\n## Sub\n");
+        assert_eq!(col.recompute_for(&file("b.md"), &facts), vec![file("a.md")]);
+
+        // Nothing depends on `c`
+        edit(&mut facts, "c.md", "# C\n\n[:a] [:d]\n");
+        assert_eq!(col.recompute_for(&file("c.md"), &facts), vec![file("c.md")]);
+
+        // A new note resolves a previously broken link
+        edit(&mut facts, "d.md", "# D\n");
+        assert_eq!(
+            col.recompute_for(&file("d.md"), &facts),
+            vec![file("c.md"), file("d.md")]
+        );
+        assert_eq!(check_note(&facts, "c.md", "broken-note-link"), vec![]);
+    }
+
+    #[test]
+    fn recompute_in_batches() {
+        let root = Path::new("/notes");
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:b@## Sub]\n"),
+            ("b.md", "# B\n\n[:a]\n"),
+            ("c.md", "# C\n\n[:a]\n"),
+        ]);
+        let file = |name: &str| NoteFile::new(root, &root.join(name));
+
+        let mut col = DiagCollection::default();
+        assert_eq!(
+            col.recompute_notes(&[file("a.md")], &facts),
+            vec![file("a.md")]
+        );
+        assert_eq!(col.store.len(), 1);
+        assert_eq!(
+            col.recompute_notes(&[file("b.md"), file("c.md")], &facts),
+            vec![file("b.md"), file("c.md")]
+        );
+        // Batches cover the same notes as the full pass
+        assert_eq!(col.recompute_all(&facts), vec![]);
+    }
+
+    #[test]
+    fn links_by_title() {
+        let mut facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:My Great Idea] [:idea]\n"),
+            ("20230101.md", "# My Great Idea\n"),
+            ("idea.md", "# Idea\n"),
+            ("b.md", "# idea\n"),
+        ]);
+
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        assert_eq!(
+            check_note(&facts, "a.md", "name-shadows-title"),
+            vec![(
+                Diag::NameShadowsTitle {
+                    linked_note: "idea".into(),
+                    titled_note: "b".into()
+                },
+                Pos::new(2, 17)..Pos::new(2, 24)
+            )]
+        );
+
+        facts.set_link_resolution(LinkResolution::Filename);
+        let broken = check_note(&facts, "a.md", "broken-note-link");
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].1, Pos::new(2, 0)..Pos::new(2, 16));
+        assert_eq!(check_note(&facts, "a.md", "name-shadows-title"), vec![]);
+
+        facts.set_link_resolution(LinkResolution::Title);
+        assert_eq!(check_note(&facts, "a.md", "broken-note-link"), vec![]);
+        let a = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/a.md"))
+            .unwrap();
+        let b = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/b.md"))
+            .unwrap();
+        let link = facts.note_facts(a).intern_link_ids()[1];
+        let strukt = facts.note_facts(a).structure();
+        assert_eq!(
+            facts::resolve_link_target(facts.db(), a, strukt.intern_link_by_id(link)),
+            Some(b)
+        );
+    }
+
+    #[test]
+    fn orphan_notes() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:b]\n"),
+            ("b.md", "# B\n\n[:b@## Self]\n"),
+            ("c.md", "Text only\n\n[:c]\n"),
+        ]);
+
+        assert_eq!(
+            check_note(&facts, "a.md", "orphan-note"),
+            vec![(
+                Diag::OrphanNote { note: "a".into() },
+                Pos::new(0, 0)..Pos::new(0, 3)
+            )]
+        );
+        assert_eq!(check_note(&facts, "b.md", "orphan-note"), vec![]);
+        assert_eq!(
+            check_note(&facts, "c.md", "orphan-note"),
+            vec![(
+                Diag::OrphanNote { note: "c".into() },
+                Pos::new(0, 0)..Pos::new(0, 0)
+            )]
+        );
+    }
+
+    #[test]
+    fn collapse_duplicate_spans() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n[:b]  and [:b]\n")]);
+        let id = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/a.md"))
+            .unwrap();
+        let note = facts.note_facts(id);
+        let broken = Diag::BrokenInternLinkToNote {
+            linked_note: "b".into(),
+            heading: None,
+            suggestion: None,
+        };
+
+        let diags: HashSet<DiagWithLoc> = vec![
+            (broken.clone(), Pos::new(2, 0)..Pos::new(2, 4)),
+            (broken.clone(), Pos::new(2, 0)..Pos::new(2, 6)),
+        ]
+        .into_iter()
+        .collect();
+        let published = to_publish(&note.file(), &diags, &facts, &Config::default()).unwrap();
+        assert_eq!(published.diagnostics.len(), 1);
+        assert_eq!(
+            published.diagnostics[0].range,
+            lsp_types::Range::new(
+                lsp_types::Position::new(2, 0),
+                lsp_types::Position::new(2, 6)
+            )
+        );
+
+        // Separate occurrences on the same line are kept
+        let diags: HashSet<DiagWithLoc> = vec![
+            (broken.clone(), Pos::new(2, 0)..Pos::new(2, 4)),
+            (broken, Pos::new(2, 11)..Pos::new(2, 15)),
+        ]
+        .into_iter()
+        .collect();
+        let published = to_publish(&note.file(), &diags, &facts, &Config::default()).unwrap();
+        assert_eq!(published.diagnostics.len(), 2);
+    }
+
+    #[test]
+    fn published_in_stable_order() {
+        let notes = [
+            (
+                "a.md",
+                "# A\n\n[:b] [:c] []() [:@## Nope]\n\n## Dup\n\n## Dup\n\n[:d]\n",
+            ),
+            ("c.md", "# C\n"),
+        ];
+        let run = || {
+            let facts = facts_with_notes(&notes);
+            publish(&facts, "a.md", &Config::default())
+                .diagnostics
+                .into_iter()
+                .map(|d| {
+                    let code = match d.code {
+                        Some(NumberOrString::String(code)) => code,
+                        _ => String::new(),
+                    };
+                    (d.range.start.line, d.range.start.character, code, d.message)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let first = run();
+        assert!(first.len() > 3);
+        assert_eq!(first, run());
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(first, sorted);
+    }
+
+    #[test]
+    fn severity_defaults_and_overrides() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n[:b]\n\n[:@## Missing]\n")]);
+
+        let published = publish(&facts, "a.md", &Config::default());
+        let mut severities = published
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.severity))
+            .collect::<Vec<_>>();
+        severities.sort_by_key(|(line, _)| *line);
+        assert_eq!(
+            severities,
+            vec![
+                (0, Some(DiagnosticSeverity::HINT)),
+                (2, Some(DiagnosticSeverity::ERROR)),
+                (4, Some(DiagnosticSeverity::WARNING))
+            ]
+        );
+
+        let mut config = Config::default();
+        config
+            .diag_severity
+            .insert("broken-note-link".to_string(), Severity::Hint);
+        let published = publish(&facts, "a.md", &config);
+        let broken_note = published
+            .diagnostics
+            .iter()
+            .find(|d| d.range.start.line == 2)
+            .unwrap();
+        assert_eq!(broken_note.severity, Some(DiagnosticSeverity::HINT));
+    }
+
+    #[test]
+    fn broken_links_in_drafts() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:missing]\n"),
+            ("drafts/b.md", "# B\n\n[:missing]\n"),
+        ]);
+        let config = Config {
+            drafts: PathPatterns::new(vec!["drafts/".to_string()]),
+            ..Config::default()
+        };
+        let broken_severity = |name: &str| {
+            publish(&facts, name, &config)
+                .diagnostics
+                .into_iter()
+                .find(|d| d.code == Some(NumberOrString::String("broken-note-link".to_string())))
+                .and_then(|d| d.severity)
+        };
+
+        assert_eq!(broken_severity("a.md"), Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            broken_severity("drafts/b.md"),
+            Some(DiagnosticSeverity::WARNING)
+        );
+    }
+
+    #[test]
+    fn stray_punctuation_in_link_targets() {
+        let facts = facts_with_notes(&[
+            (
+                "a.md",
+                "# A\n\nSee [x](b). And [y](b), or [z](b)?\n\nAlso [w](b.) and [:b,] and [[b?]] and [v](c.)\n",
+            ),
+            ("b.md", "# B\n\n[:a]\n"),
+        ]);
+
+        let stray = |name: &str| Diag::StrayLinkPunctuation {
+            linked_note: name.into(),
+            intended: "b".into(),
+        };
+        assert_eq!(
+            check_note(&facts, "a.md", "stray-link-punctuation"),
+            vec![
+                (stray("b."), Pos::new(4, 5)..Pos::new(4, 12)),
+                (stray("b,"), Pos::new(4, 17)..Pos::new(4, 22)),
+                (stray("b?"), Pos::new(4, 27)..Pos::new(4, 33)),
+            ]
+        );
+
+        // Punctuation after the closing `)` is not a part of the target
+        let broken = check_note(&facts, "a.md", "broken-note-link");
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].1, Pos::new(4, 38)..Pos::new(4, 45));
+    }
+
+    #[test]
+    fn broken_link_range_in_position_encoding() {
+        let mut facts = facts_with_notes(&[("a.md", "# A\n\n## 🎉 Party [:missing]\n")]);
+        let broken_range = |facts: &FactsDB| {
+            publish(facts, "a.md", &Config::default())
+                .diagnostics
+                .into_iter()
+                .find(|d| d.code == Some(NumberOrString::String("broken-note-link".to_string())))
+                .map(|d| d.range)
+        };
+
+        // The emoji takes 2 UTF-16 code units and 4 bytes
+        assert_eq!(
+            broken_range(&facts),
+            Some(lsp_types::Range::new(
+                lsp_types::Position::new(2, 12),
+                lsp_types::Position::new(2, 22)
+            ))
+        );
+
+        facts.set_position_encoding(PositionEncoding::Utf8);
+        assert_eq!(
+            broken_range(&facts),
+            Some(lsp_types::Range::new(
+                lsp_types::Position::new(2, 14),
+                lsp_types::Position::new(2, 24)
+            ))
+        );
+    }
+
+    #[test]
+    fn suppression_comments() {
+        let facts = facts_with_notes(&[(
+            "a.md",
+            "# A\n\n<!-- zeta-note: ignore broken-note-link -->\n[:b] [:@## Missing]\n\n<!-- zeta-note: ignore -->\n[:c] [:@## Other]\n\n[:d] <!-- zeta-note: ignore broken-heading-link -->\n\n[:e]\n",
+        )]);
+        let published = publish(&facts, "a.md", &Config::default());
+        let mut codes = published
+            .diagnostics
+            .iter()
+            .filter_map(|d| match &d.code {
+                Some(NumberOrString::String(code)) => Some((d.range.start.line, code.as_str())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        codes.sort();
+        assert_eq!(
+            codes,
+            vec![
+                (0, "orphan-note"),
+                (3, "broken-heading-link"),
+                (8, "broken-note-link"),
+                (10, "broken-note-link"),
+            ]
+        );
+    }
+
+    #[test]
+    fn max_diagnostics_per_file() {
+        let facts = facts_with_notes(&[
+            ("a.md", "# A\n\n[:e]\n\n[:d] [:c]\n\n[:b]\n"),
+            ("z.md", "# Z\n\n[:a]\n"),
+        ]);
+        let config = Config {
+            max_diagnostics_per_file: Some(2),
+            ..Config::default()
+        };
+        let published = publish(&facts, "a.md", &config);
+        let diags = published
+            .diagnostics
+            .iter()
+            .map(|d| (d.range.start, d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diags,
+            vec![
+                (
+                    lsp_types::Position::new(2, 0),
+                    "Reference to non-existent note `e`"
+                ),
+                (
+                    lsp_types::Position::new(4, 0),
+                    "Reference to non-existent note `d`"
+                ),
+                (lsp_types::Position::new(4, 5), "2 more problems hidden"),
+            ]
+        );
+
+        let published = publish(&facts, "a.md", &Config::default());
+        assert_eq!(published.diagnostics.len(), 4);
+    }
+
+    #[test]
+    fn code_and_source() {
+        let facts = facts_with_notes(&[("a.md", "# A\n\n[:b]\n")]);
+        let published = publish(&facts, "a.md", &Config::default());
+        let broken_note = published
+            .diagnostics
+            .iter()
+            .find(|d| d.range.start.line == 2)
+            .unwrap();
+        assert_eq!(
+            broken_note.code,
+            Some(NumberOrString::String("broken-note-link".to_string()))
+        );
+        assert_eq!(broken_note.source.as_deref(), Some("zeta-note"));
+    }
+}
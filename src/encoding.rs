@@ -0,0 +1,137 @@
+use std::{ops::Deref, ops::Range, sync::Arc};
+
+use lsp_document::{IndexedText, Pos, TextAdapter};
+
+/// Encoding of the `character` offsets in LSP positions agreed on with the
+/// client via `positionEncoding`. LSP defaults to UTF-16.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionEncoding {
+    #[default]
+    Utf16,
+    /// Byte offsets, the same as the columns of [`Pos`].
+    Utf8,
+}
+
+impl PositionEncoding {
+    pub fn parse(name: &str) -> Option<PositionEncoding> {
+        match name {
+            "utf-16" => Some(PositionEncoding::Utf16),
+            "utf-8" => Some(PositionEncoding::Utf8),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PositionEncoding::Utf16 => "utf-16",
+            PositionEncoding::Utf8 => "utf-8",
+        }
+    }
+
+    /// The first of the encodings offered by the client that the server
+    /// supports, UTF-16 if there is none.
+    pub fn negotiate<'a>(offered: impl IntoIterator<Item = &'a str>) -> PositionEncoding {
+        offered
+            .into_iter()
+            .find_map(PositionEncoding::parse)
+            .unwrap_or_default()
+    }
+}
+
+/// Text of a note that converts between [`Pos`] and LSP positions in the
+/// negotiated encoding. Otherwise it's the same as [`IndexedText`].
+#[derive(Debug, Clone)]
+pub struct EncodedText {
+    text: Arc<IndexedText<Arc<str>>>,
+    encoding: PositionEncoding,
+}
+
+impl EncodedText {
+    pub fn new(text: Arc<IndexedText<Arc<str>>>, encoding: PositionEncoding) -> EncodedText {
+        EncodedText { text, encoding }
+    }
+
+    pub fn pos_to_lsp_pos(&self, pos: &Pos) -> Option<lsp_types::Position> {
+        // Checks that the position is inside of the text
+        let utf16 = self.text.pos_to_lsp_pos(pos)?;
+        match self.encoding {
+            PositionEncoding::Utf16 => Some(utf16),
+            PositionEncoding::Utf8 => Some(lsp_types::Position::new(pos.line, pos.col)),
+        }
+    }
+
+    pub fn lsp_pos_to_pos(&self, pos: &lsp_types::Position) -> Option<Pos> {
+        match self.encoding {
+            PositionEncoding::Utf16 => self.text.lsp_pos_to_pos(pos),
+            PositionEncoding::Utf8 => {
+                let pos = Pos::new(pos.line, pos.character);
+                self.text.pos_to_lsp_pos(&pos)?;
+                Some(pos)
+            }
+        }
+    }
+
+    pub fn range_to_lsp_range(&self, range: &Range<Pos>) -> Option<lsp_types::Range> {
+        Some(lsp_types::Range::new(
+            self.pos_to_lsp_pos(&range.start)?,
+            self.pos_to_lsp_pos(&range.end)?,
+        ))
+    }
+
+    pub fn lsp_range_to_range(&self, range: &lsp_types::Range) -> Option<Range<Pos>> {
+        Some(self.lsp_pos_to_pos(&range.start)?..self.lsp_pos_to_pos(&range.end)?)
+    }
+}
+
+impl Deref for EncodedText {
+    type Target = IndexedText<Arc<str>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.text
+    }
+}
+
+/// Turn the byte offset `col` in `line` into an offset in UTF-16 code units.
+pub fn utf8_to_utf16_col(line: &str, col: u32) -> u32 {
+    let prefix = line.get(..col as usize).unwrap_or(line);
+    prefix.encode_utf16().count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn negotiate_first_supported() {
+        assert_eq!(
+            PositionEncoding::negotiate(["utf-32", "utf-8", "utf-16"]),
+            PositionEncoding::Utf8
+        );
+        assert_eq!(
+            PositionEncoding::negotiate(["utf-32"]),
+            PositionEncoding::Utf16
+        );
+        assert_eq!(PositionEncoding::negotiate([]), PositionEncoding::Utf16);
+    }
+
+    #[test]
+    fn positions_in_both_encodings() {
+        let text: Arc<str> = "# A\n\n## 🎉 Party\n".into();
+        let text = Arc::new(IndexedText::new(text));
+        // After the emoji
+        let pos = Pos::new(2, 7);
+
+        let utf16 = EncodedText::new(text.clone(), PositionEncoding::Utf16);
+        let lsp_pos = lsp_types::Position::new(2, 5);
+        assert_eq!(utf16.pos_to_lsp_pos(&pos), Some(lsp_pos));
+        assert_eq!(utf16.lsp_pos_to_pos(&lsp_pos), Some(pos));
+
+        let utf8 = EncodedText::new(text, PositionEncoding::Utf8);
+        let lsp_pos = lsp_types::Position::new(2, 7);
+        assert_eq!(utf8.pos_to_lsp_pos(&pos), Some(lsp_pos));
+        assert_eq!(utf8.lsp_pos_to_pos(&lsp_pos), Some(pos));
+
+        assert_eq!(utf8_to_utf16_col("## 🎉 Party", 7), 5);
+    }
+}
@@ -1,21 +1,29 @@
 use std::{
-    ops::{Deref, Range},
+    collections::HashMap,
+    ops::Range,
     path::{Path, PathBuf},
     sync::Arc,
 };
 
 use anyhow::Result;
+use futures::{stream, StreamExt, TryStreamExt};
+use rayon::prelude::*;
 
-use glob::Pattern;
-use salsa;
+use salsa::{self, ParallelDatabase};
 
 use crate::{
-    diag::{self, DiagWithLoc},
-    parser::{self, Heading},
-    store::{self, NoteFile, NoteIndex, NoteText},
-    structure::{ElementID, HeadingID, InternLinkID, NoteID, Structure},
+    config::PathPatterns,
+    diag::{self, DiagOptions, DiagWithLoc},
+    encoding::{EncodedText, PositionEncoding},
+    parser::{self, Frontmatter, Heading, InternLink, Node, NoteName, Suppression, HEADING_START},
+    store::{
+        self, LinkResolution, NameMatching, NoteFile, NoteFilter, NoteIndex, NoteText,
+        TitleFallback,
+    },
+    structure::{ElementID, HeadingID, InternLinkID, NoteID, Structure, TagID, TagIndex},
+    util,
 };
-use lsp_document::{IndexedText, Pos, TextAdapter, TextMap};
+use lsp_document::{IndexedText, Pos, TextMap};
 
 #[salsa::query_group(FactsStorage)]
 pub trait Facts<'a>: salsa::Database {
@@ -25,13 +33,39 @@ pub trait Facts<'a>: salsa::Database {
     #[salsa::input]
     fn note_content(&self, note_file: NoteFile) -> NoteText;
 
+    #[salsa::input]
+    fn link_resolution(&self, key: ()) -> LinkResolution;
+
+    #[salsa::input]
+    fn diag_options(&self, key: ()) -> DiagOptions;
+
+    /// Level of the heading that serves as the note title.
+    #[salsa::input]
+    fn title_level(&self, key: ()) -> u8;
+
+    /// Where the title of a note without a title heading comes from.
+    #[salsa::input]
+    fn title_fallback(&self, key: ()) -> TitleFallback;
+
+    /// Gitignore-style patterns of folders with resources like images.
+    #[salsa::input]
+    fn resource_roots(&self, key: ()) -> PathPatterns;
+
+    /// Encoding of LSP positions negotiated with the client.
+    #[salsa::input]
+    fn position_encoding(&self, key: ()) -> PositionEncoding;
+
     fn note_text(&self, note_id: NoteID) -> NoteText;
     fn note_indexed_text(&self, note_id: NoteID) -> Arc<IndexedText<Arc<str>>>;
     fn note_structure(&self, note_id: NoteID) -> Structure;
     fn note_title(&self, note_id: NoteID) -> Option<HeadingID>;
+    /// First non-empty line of the note after the frontmatter that's not a heading.
+    fn note_first_line(&self, note_id: NoteID) -> Option<Node<String>>;
     fn note_elements(&self, note_id: NoteID) -> Arc<[ElementID]>;
     fn note_headings(&self, note_id: NoteID) -> Arc<[HeadingID]>;
     fn note_intern_links(&self, note_id: NoteID) -> Arc<[InternLinkID]>;
+    /// Notes the links of the note point to, `None` for broken links.
+    fn note_link_targets(&self, note_id: NoteID) -> Arc<[(InternLinkID, Option<NoteID>)]>;
     fn note_valid_intern_links(
         &self,
         note_id: NoteID,
@@ -41,7 +75,18 @@ pub trait Facts<'a>: salsa::Database {
         note_id: NoteID,
         headind_id: HeadingID,
     ) -> Arc<[(NoteID, InternLinkID)]>;
+    fn note_frontmatter(&self, note_id: NoteID) -> Option<Node<Frontmatter>>;
+    fn note_frontmatter_title(&self, note_id: NoteID) -> Option<String>;
+    fn note_suppressions(&self, note_id: NoteID) -> Arc<[Node<Suppression>]>;
+    fn title_index(&self, key: ()) -> Arc<HashMap<String, NoteID>>;
+    fn slug_index(&self, key: ()) -> Arc<HashMap<String, NoteID>>;
+    fn note_tags(&self, note_id: NoteID) -> Arc<[TagID]>;
+    fn tag_index(&self, key: ()) -> Arc<TagIndex>;
+    fn inbound_link_counts(&self, key: ()) -> Arc<HashMap<NoteID, usize>>;
     fn note_diag(&self, note_id: NoteID) -> Arc<[DiagWithLoc]>;
+    fn workspace_diag(&self, key: ()) -> Arc<[(NoteFile, DiagWithLoc)]>;
+    fn workspace_diag_by_file(&self, key: ()) -> Arc<HashMap<NoteFile, Vec<DiagWithLoc>>>;
+    fn note_workspace_diag(&self, note_id: NoteID) -> Arc<[DiagWithLoc]>;
 }
 
 #[salsa::database(FactsStorage)]
@@ -58,6 +103,17 @@ impl std::fmt::Debug for FactsDBInternal {
 
 impl salsa::Database for FactsDBInternal {}
 
+impl salsa::ParallelDatabase for FactsDBInternal {
+    fn snapshot(&self) -> salsa::Snapshot<Self> {
+        salsa::Snapshot::new(FactsDBInternal {
+            storage: self.storage.snapshot(),
+        })
+    }
+}
+
+/// Number of notes read from disk at the same time.
+const READ_CONCURRENCY: usize = 64;
+
 #[derive(Debug, Default)]
 pub struct FactsDB(FactsDBInternal);
 
@@ -65,6 +121,12 @@ impl FactsDB {
     pub fn empty() -> Self {
         let mut db = Self::default();
         db.0.set_note_index((), NoteIndex::default());
+        db.0.set_link_resolution((), LinkResolution::default());
+        db.0.set_diag_options((), DiagOptions::default());
+        db.0.set_title_level((), 1);
+        db.0.set_title_fallback((), TitleFallback::default());
+        db.0.set_resource_roots((), PathPatterns::default());
+        db.0.set_position_encoding((), PositionEncoding::default());
         db
     }
 
@@ -75,8 +137,53 @@ impl FactsDB {
         self.0.set_note_content(note_file, note);
     }
 
-    pub fn remove_note(&self) {
-        todo!()
+    pub fn insert_notes(&mut self, notes: Vec<(NoteFile, NoteText)>) {
+        let idx = self.note_index();
+        let new_idx = idx.with_note_files(notes.iter().map(|(file, _)| file.clone()));
+        self.0.set_note_index((), new_idx);
+        for (note_file, note) in notes {
+            self.0.set_note_content(note_file, note);
+        }
+    }
+
+    /// Forget all notes of the folder `root`.
+    pub fn remove_root(&mut self, root: &Path) {
+        let idx = self.note_index().without_root(root);
+        self.0.set_note_index((), idx);
+    }
+
+    pub fn set_name_matching(&mut self, name_matching: NameMatching) {
+        let idx = self.note_index().with_name_matching(name_matching);
+        self.0.set_note_index((), idx);
+    }
+
+    pub fn set_link_resolution(&mut self, link_resolution: LinkResolution) {
+        self.0.set_link_resolution((), link_resolution);
+    }
+
+    pub fn set_diag_options(&mut self, options: DiagOptions) {
+        self.0.set_diag_options((), options);
+    }
+
+    pub fn set_title_level(&mut self, level: u8) {
+        self.0.set_title_level((), level);
+    }
+
+    pub fn set_title_fallback(&mut self, fallback: TitleFallback) {
+        self.0.set_title_fallback((), fallback);
+    }
+
+    pub fn set_resource_roots(&mut self, patterns: PathPatterns) {
+        self.0.set_resource_roots((), patterns);
+    }
+
+    pub fn set_position_encoding(&mut self, encoding: PositionEncoding) {
+        self.0.set_position_encoding((), encoding);
+    }
+
+    pub fn remove_note(&mut self, path: &Path) {
+        let idx = self.note_index().without_path(path);
+        self.0.set_note_index((), idx);
     }
 
     pub fn update_note(&mut self, note_id: NoteID, note: NoteText) {
@@ -84,18 +191,52 @@ impl FactsDB {
         self.0.set_note_content(file, note);
     }
 
-    pub async fn from_files(root: &Path, files: &[PathBuf], ignores: &[Pattern]) -> Result<Self> {
+    pub async fn from_files(root: &Path, files: &[PathBuf], filter: &NoteFilter) -> Result<Self> {
         let mut empty = Self::empty();
+        empty.add_files(root, files, filter).await?;
+        Ok(empty)
+    }
 
-        for file in files {
-            empty.with_file(root, file, ignores).await?;
-        }
+    /// Read the notes from `files` of the folder `root` and add them all at once.
+    /// Several notes are read concurrently.
+    pub async fn add_files(
+        &mut self,
+        root: &Path,
+        files: &[PathBuf],
+        filter: &NoteFilter,
+    ) -> Result<()> {
+        let notes: Vec<_> = stream::iter(files)
+            .map(|path| async move {
+                let note = store::read_note(path, root, filter).await?;
+                Ok::<_, anyhow::Error>(note.map(|note| (NoteFile::new(root, path), note)))
+            })
+            .buffered(READ_CONCURRENCY)
+            .try_collect()
+            .await?;
+        self.insert_notes(notes.into_iter().flatten().collect());
+        Ok(())
+    }
 
-        Ok(empty)
+    /// Parse all notes on a pool of threads, so that later queries find their
+    /// structure ready. As adding notes renumbers them, this is best done once
+    /// all of them are added.
+    pub fn parse_all(&self) {
+        let ids: Vec<NoteID> = self.note_index().ids().collect();
+        let chunk_size = (ids.len() / rayon::current_num_threads()).max(1);
+        // Snapshots can't be shared between threads, each chunk gets its own
+        let chunks: Vec<_> = ids
+            .chunks(chunk_size)
+            .map(|chunk| (self.0.snapshot(), chunk))
+            .collect();
+        chunks.into_par_iter().for_each(|(db, chunk)| {
+            for &note_id in chunk {
+                db.note_structure(note_id);
+            }
+        });
     }
 
-    pub async fn with_file(&mut self, root: &Path, path: &Path, ignores: &[Pattern]) -> Result<()> {
-        let note = store::read_note(path, root, ignores).await?;
+    pub async fn with_file(&mut self, root: &Path, path: &Path, filter: &NoteFilter) -> Result<()> {
+        let note = store::read_note(path, root, filter).await?;
         if let Some(note) = note {
             let note_file = NoteFile::new(root, path);
             self.insert_note(note_file, note);
@@ -104,7 +245,19 @@ impl FactsDB {
         Ok(())
     }
 
-    pub fn note_facts(&self, note_id: NoteID) -> NoteFactsDB {
+    /// Build facts from in-memory note texts given as `(relative path, content)`.
+    #[cfg(test)]
+    pub(crate) fn from_texts(root: &Path, notes: &[(&str, &str)]) -> Self {
+        let mut facts = Self::empty();
+        for (rel_path, content) in notes {
+            let path = root.join(rel_path);
+            let text = NoteText::new(store::Version::Vs(0), (*content).into());
+            facts.insert_note(NoteFile::new(root, &path), text);
+        }
+        facts
+    }
+
+    pub fn note_facts(&self, note_id: NoteID) -> NoteFactsDB<'_> {
         NoteFactsDB {
             id: note_id,
             db: &self.0,
@@ -114,20 +267,31 @@ impl FactsDB {
     pub fn note_index(&self) -> NoteIndex {
         self.0.note_index(())
     }
+
+    pub fn tag_index(&self) -> Arc<TagIndex> {
+        self.0.tag_index(())
+    }
+
+    pub fn db(&self) -> &dyn Facts {
+        &self.0
+    }
 }
 
 // Narrow facts to a particular note (simpler UX)
 
 pub trait NoteFacts {
     fn text(&self) -> NoteText;
-    fn indexed_text(&self) -> Arc<IndexedText<Arc<str>>>;
+    fn indexed_text(&self) -> EncodedText;
     fn structure(&self) -> Structure;
     fn title(&self) -> Option<HeadingID>;
     fn elements(&self) -> Arc<[ElementID]>;
     fn headings(&self) -> Arc<[HeadingID]>;
     fn intern_link_ids(&self) -> Arc<[InternLinkID]>;
+    fn link_targets(&self) -> Arc<[(InternLinkID, Option<NoteID>)]>;
     fn valid_intern_links(&self) -> Arc<[(InternLinkID, NoteID, Option<HeadingID>)]>;
     fn intern_links_to_heading(&self, heading_id: HeadingID) -> Arc<[(NoteID, InternLinkID)]>;
+    fn frontmatter(&self) -> Option<Node<Frontmatter>>;
+    fn suppressions(&self) -> Arc<[Node<Suppression>]>;
     fn diag(&self) -> Arc<[DiagWithLoc]>;
 }
 pub trait NoteFactsExt: NoteFacts {
@@ -135,10 +299,18 @@ pub trait NoteFactsExt: NoteFacts {
     fn file(&self) -> NoteFile;
     fn headings_matching(&self, pred: impl Fn(&Heading) -> bool) -> Vec<HeadingID>;
     fn heading_with_text(&self, text: &str) -> Option<HeadingID>;
+    fn heading_by_slug(&self, slug: &str) -> Option<HeadingID>;
+    fn heading_for_link(&self, text: &str) -> Option<HeadingID>;
+    fn line_anchor_span(&self, anchor: &str) -> Option<Range<Pos>>;
     fn element_at_pos(&self, pos: Pos) -> Option<ElementID>;
     fn element_at_lsp_pos(&self, pos: &lsp_types::Position) -> Option<ElementID>;
     fn elements_in_range(&self, range: &Range<Pos>) -> Vec<ElementID>;
     fn elements_in_lsp_range(&self, range: &lsp_types::Range) -> Option<Vec<ElementID>>;
+    fn first_paragraph(&self) -> Option<String>;
+    fn tags(&self) -> Arc<[TagID]>;
+    fn frontmatter_title(&self) -> Option<String>;
+    fn first_line_title(&self) -> Option<Node<String>>;
+    fn effective_title(&self) -> Option<String>;
 }
 pub struct NoteFactsDB<'a> {
     db: &'a dyn Facts,
@@ -156,8 +328,11 @@ impl<'a> NoteFacts for NoteFactsDB<'a> {
         self.db.note_text(self.id)
     }
 
-    fn indexed_text(&self) -> Arc<IndexedText<Arc<str>>> {
-        self.db.note_indexed_text(self.id)
+    fn indexed_text(&self) -> EncodedText {
+        EncodedText::new(
+            self.db.note_indexed_text(self.id),
+            self.db.position_encoding(()),
+        )
     }
 
     fn structure(&self) -> Structure {
@@ -180,6 +355,18 @@ impl<'a> NoteFacts for NoteFactsDB<'a> {
         self.db.note_intern_links(self.id)
     }
 
+    fn link_targets(&self) -> Arc<[(InternLinkID, Option<NoteID>)]> {
+        self.db.note_link_targets(self.id)
+    }
+
+    fn frontmatter(&self) -> Option<Node<Frontmatter>> {
+        self.db.note_frontmatter(self.id)
+    }
+
+    fn suppressions(&self) -> Arc<[Node<Suppression>]> {
+        self.db.note_suppressions(self.id)
+    }
+
     fn diag(&self) -> Arc<[DiagWithLoc]> {
         self.db.note_diag(self.id)
     }
@@ -194,6 +381,34 @@ impl<'a> NoteFacts for NoteFactsDB<'a> {
 }
 
 impl<'a> NoteFactsExt for NoteFactsDB<'a> {
+    fn frontmatter_title(&self) -> Option<String> {
+        self.db.note_frontmatter_title(self.id)
+    }
+
+    /// The first line of a note without a title heading when titles fall back
+    /// to it, see [`TitleFallback::FirstLine`].
+    fn first_line_title(&self) -> Option<Node<String>> {
+        if self.db.title_fallback(()) != TitleFallback::FirstLine || self.title().is_some() {
+            return None;
+        }
+        self.db.note_first_line(self.id)
+    }
+
+    /// Title from the frontmatter if there is one, otherwise the text of the
+    /// title heading, or the first line if titles fall back to it.
+    fn effective_title(&self) -> Option<String> {
+        self.frontmatter_title()
+            .or_else(|| {
+                let title = self.structure().heading_by_id(self.title()?).text.clone();
+                Some(title.trim_start_matches(HEADING_START).trim().to_string())
+            })
+            .or_else(|| Some(self.first_line_title()?.inner))
+    }
+
+    fn tags(&self) -> Arc<[TagID]> {
+        self.db.note_tags(self.id)
+    }
+
     fn headings_matching(&self, pred: impl Fn(&Heading) -> bool) -> Vec<HeadingID> {
         let structure = self.structure();
         self.headings()
@@ -210,7 +425,42 @@ impl<'a> NoteFactsExt for NoteFactsDB<'a> {
     }
 
     fn heading_with_text(&self, text: &str) -> Option<HeadingID> {
-        self.headings_matching(|h| h.text == text).first().copied()
+        // Wiki-style links refer to headings without the `#` markers
+        let matches = |h: &Heading| {
+            h.text == text
+                || (!text.starts_with(HEADING_START)
+                    && h.text.trim_start_matches(HEADING_START).trim() == text.trim())
+        };
+        self.headings_matching(matches).first().copied()
+    }
+
+    fn heading_by_slug(&self, slug: &str) -> Option<HeadingID> {
+        // Markdown anchors come with a `#`, e.g. `#my-heading`
+        let slug = slug.trim_start_matches(HEADING_START);
+        if slug.is_empty() {
+            return None;
+        }
+        self.headings_matching(|h| h.slug() == slug)
+            .first()
+            .copied()
+    }
+
+    /// Find the heading a link points to: by the literal text first, then by the anchor slug.
+    fn heading_for_link(&self, text: &str) -> Option<HeadingID> {
+        self.heading_with_text(text)
+            .or_else(|| self.heading_by_slug(text))
+    }
+
+    /// Span of the lines a line anchor like `L10-L20` points to, `None` if
+    /// it's not a line anchor or the lines are not in the note.
+    fn line_anchor_span(&self, anchor: &str) -> Option<Range<Pos>> {
+        let (start, end) = parser::parse_line_anchor(anchor)?;
+        if start == 0 || start > end {
+            return None;
+        }
+        let text = self.indexed_text();
+        let last_line = text.text().lines().nth(end as usize - 1)?;
+        Some(Pos::new(start - 1, 0)..Pos::new(end - 1, last_line.len() as u32))
     }
 
     fn element_at_pos(&self, pos: Pos) -> Option<ElementID> {
@@ -269,6 +519,29 @@ impl<'a> NoteFactsExt for NoteFactsDB<'a> {
         Some(self.elements_in_range(&range))
     }
 
+    fn first_paragraph(&self) -> Option<String> {
+        let text = self.indexed_text();
+        // The title may come from the frontmatter, then there's no title heading
+        let title_end = self
+            .title()
+            .map(|id| self.structure().heading_by_id(id).span.end);
+        let frontmatter_end = self.frontmatter().map(|fm| fm.span.end);
+        let body_start = title_end
+            .max(frontmatter_end)
+            .unwrap_or_else(|| Pos::new(0, 0));
+        let body_end = text.offset_to_pos(text.text().len())?;
+        let body = text.substr(body_start..body_end)?;
+
+        let paragraph = body
+            .lines()
+            .skip_while(|line| line.trim().is_empty())
+            .take_while(|line| !line.trim().is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(paragraph).filter(|p| !p.is_empty())
+    }
+
     fn file(&self) -> NoteFile {
         self.db.note_index(()).find_by_id(self.id)
     }
@@ -306,16 +579,220 @@ fn note_headings(db: &dyn Facts, note_id: NoteID) -> Arc<[HeadingID]> {
 
 fn note_title(db: &dyn Facts, note_id: NoteID) -> Option<HeadingID> {
     let index = db.note_structure(note_id);
+    let level = db.title_level(());
     db.note_headings(note_id)
         .iter()
-        .find(|id| index.heading_by_id(**id).level == 1)
+        .find(|id| index.heading_by_id(**id).level == level)
         .copied()
 }
 
+fn note_first_line(db: &dyn Facts, note_id: NoteID) -> Option<Node<String>> {
+    let text = db.note_indexed_text(note_id);
+    let body_start = db
+        .note_frontmatter(note_id)
+        .map_or(0, |frontmatter| frontmatter.span.end.line as usize + 1);
+    text.text()
+        .lines()
+        .enumerate()
+        .skip(body_start)
+        .find_map(|(line_no, line)| {
+            let title = line.trim();
+            if title.is_empty() || title.starts_with(HEADING_START) {
+                return None;
+            }
+            let start = (line.len() - line.trim_start().len()) as u32;
+            let end = start + title.len() as u32;
+            let line_no = line_no as u32;
+            Some(Node::new(
+                title.to_string(),
+                Pos::new(line_no, start)..Pos::new(line_no, end),
+            ))
+        })
+}
+
 fn note_intern_links(db: &dyn Facts, note_id: NoteID) -> Arc<[InternLinkID]> {
     db.note_structure(note_id).intern_links().into()
 }
 
+fn note_frontmatter(db: &dyn Facts, note_id: NoteID) -> Option<Node<Frontmatter>> {
+    parser::scrape_frontmatter(&*db.note_indexed_text(note_id))
+}
+
+fn note_suppressions(db: &dyn Facts, note_id: NoteID) -> Arc<[Node<Suppression>]> {
+    parser::scrape_suppressions(&*db.note_indexed_text(note_id)).into()
+}
+
+fn note_frontmatter_title(db: &dyn Facts, note_id: NoteID) -> Option<String> {
+    let frontmatter = db.note_frontmatter(note_id)?;
+    let yaml: serde_yaml::Value = serde_yaml::from_str(&frontmatter.yaml).ok()?;
+    let title = yaml.get("title")?.as_str()?.trim();
+    Some(title).filter(|t| !t.is_empty()).map(str::to_string)
+}
+
+/// Target of the link found in the note `note_id`. A link without a note name
+/// points to the note itself.
+pub fn resolve_link_target(db: &dyn Facts, note_id: NoteID, link: &InternLink) -> Option<NoteID> {
+    match &link.note_name {
+        Some(dest) if link.is_markdown() => resolve_link_dest(db, note_id, dest.to_str()),
+        Some(name) => resolve_note_name(db, name),
+        None => Some(note_id),
+    }
+}
+
+/// Note the destination of a Markdown link found in the note `note_id` points
+/// to, e.g. `../note.md` or `note`. An empty destination points to the note itself.
+///
+/// A path like `sub/note` that isn't relative to the note is matched against
+/// note names.
+pub fn resolve_link_dest(db: &dyn Facts, note_id: NoteID, dest: &str) -> Option<NoteID> {
+    if dest.is_empty() {
+        Some(note_id)
+    } else if parser::is_path_target(dest) {
+        resolve_note_path(db, note_id, dest).or_else(|| {
+            if dest.starts_with('.') || Path::new(dest).extension().is_some() {
+                return None;
+            }
+            match db.note_index(()).find_all_by_name(&dest.into())[..] {
+                [id] => Some(id),
+                _ => None,
+            }
+        })
+    } else {
+        resolve_note_name(db, &dest.into())
+    }
+}
+
+/// Find the note at `target` relative to the directory of the note `note_id`.
+/// The extension of the note file may be omitted, e.g. `../note` for `../note.md`.
+pub fn resolve_note_path(db: &dyn Facts, note_id: NoteID, target: &str) -> Option<NoteID> {
+    let index = db.note_index(());
+    let file = index.find_by_id(note_id);
+    let path = util::normalize_path(&file.path.parent()?.join(target));
+    index
+        .find_by_path(&path)
+        .or_else(|| index.find_by_path_stem(&path))
+}
+
+/// Find the note a link with `name` points to: by the note name first, then by
+/// the title, unless [`LinkResolution`] says otherwise.
+///
+/// A name written as a slug, e.g. `my-note`, also matches the slug of a note
+/// name or title like `My Note`.
+pub fn resolve_note_name(db: &dyn Facts, name: &NoteName) -> Option<NoteID> {
+    let by_name = || db.note_index(()).find_by_name(name);
+    let by_title = || resolve_note_title(db, name);
+    let by_slug = || {
+        let name = name.to_str();
+        if note_name_slug(name) != name {
+            return None;
+        }
+        db.slug_index(()).get(name).copied()
+    };
+    match db.link_resolution(()) {
+        LinkResolution::Filename => by_name(),
+        LinkResolution::Title => by_title(),
+        LinkResolution::Both => by_name().or_else(by_title),
+    }
+    .or_else(by_slug)
+}
+
+/// Slug of a note name or title, e.g. `sub/my-note` for `sub/My Note`.
+pub fn note_name_slug(name: &str) -> String {
+    name.split('/')
+        .map(parser::slugify)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Find the note with the title `title`, from the frontmatter or the title heading.
+/// Titles are matched the same way as note names, see [`NameMatching`].
+pub fn resolve_note_title(db: &dyn Facts, title: &NoteName) -> Option<NoteID> {
+    let name_matching = db.note_index(()).name_matching();
+    db.title_index(())
+        .get(name_matching.normalize(title.to_str()).as_ref())
+        .copied()
+}
+
+/// Notes by their effective title normalized the same way as note names, see
+/// [`NameMatching`]. When several notes share a title the first one wins.
+fn title_index(db: &dyn Facts, _key: ()) -> Arc<HashMap<String, NoteID>> {
+    let index = db.note_index(());
+    let name_matching = index.name_matching();
+    let mut titles = HashMap::new();
+    for note_id in index.ids() {
+        if let Some(title) = NoteFactsDB::new(db, note_id).effective_title() {
+            let key = name_matching.normalize(&title).into_owned();
+            titles.entry(key).or_insert(note_id);
+        }
+    }
+    Arc::new(titles)
+}
+
+/// Notes by the slugs of their names and titles, whichever the [`LinkResolution`]
+/// allows. When several notes share a slug the first one wins.
+fn slug_index(db: &dyn Facts, _key: ()) -> Arc<HashMap<String, NoteID>> {
+    let resolution = db.link_resolution(());
+    let mut index = HashMap::new();
+    for note_id in db.note_index(()).ids() {
+        let note = NoteFactsDB::new(db, note_id);
+        if resolution != LinkResolution::Title {
+            let slug = note_name_slug(note.file().name.to_str());
+            index.entry(slug).or_insert(note_id);
+        }
+        if resolution != LinkResolution::Filename {
+            if let Some(title) = note.effective_title() {
+                index.entry(note_name_slug(&title)).or_insert(note_id);
+            }
+        }
+    }
+    Arc::new(index)
+}
+
+fn note_tags(db: &dyn Facts, note_id: NoteID) -> Arc<[TagID]> {
+    db.note_structure(note_id).tags().into()
+}
+
+fn tag_index(db: &dyn Facts, _key: ()) -> Arc<TagIndex> {
+    let mut index = TagIndex::default();
+    for note_id in db.note_index(()).ids() {
+        let strukt = db.note_structure(note_id);
+        for &tag_id in db.note_tags(note_id).iter() {
+            index.insert(&strukt.tag_by_id(tag_id).name, note_id, tag_id);
+        }
+    }
+    Arc::new(index)
+}
+
+/// Number of links that point to each note, the same ones as the backlinks of
+/// the note, including links within the note itself.
+fn inbound_link_counts(db: &dyn Facts, _key: ()) -> Arc<HashMap<NoteID, usize>> {
+    let mut counts = HashMap::new();
+    for note_id in db.note_index(()).ids() {
+        let note = NoteFactsDB::new(db, note_id);
+        for (_, target_id) in note.link_targets().iter() {
+            if let Some(target_id) = target_id {
+                *counts.entry(*target_id).or_default() += 1;
+            }
+        }
+    }
+    Arc::new(counts)
+}
+
+/// Resolved once per change of the note or of the notes in the workspace, as
+/// diagnostics, backlinks and inbound link counts all need the targets.
+fn note_link_targets(db: &dyn Facts, note_id: NoteID) -> Arc<[(InternLinkID, Option<NoteID>)]> {
+    let note = NoteFactsDB::new(db, note_id);
+    let strukt = note.structure();
+    note.intern_link_ids()
+        .iter()
+        .map(|&rid| {
+            let target_id = resolve_link_target(db, note_id, strukt.intern_link_by_id(rid));
+            (rid, target_id)
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
 fn note_valid_intern_links(
     db: &dyn Facts,
     note_id: NoteID,
@@ -324,25 +801,20 @@ fn note_valid_intern_links(
     let cur_strukt = cur_note.structure();
 
     cur_note
-        .intern_link_ids()
+        .link_targets()
         .iter()
-        .filter_map(|&rid| {
+        .filter_map(|&(rid, target_id)| {
             let intern_link = cur_strukt.intern_link_by_id(rid);
-            let target_note_name = intern_link
-                .note_name
-                .clone()
-                .unwrap_or_else(|| cur_note.file().name.deref().clone());
-
-            if let Some(target_id) = db.note_index(()).find_by_name(&target_note_name) {
-                let target_note = NoteFactsDB::new(db, target_id);
-                match &intern_link.heading {
-                    Some(heading_text) => target_note
-                        .heading_with_text(heading_text)
-                        .map(|id| (rid, target_id, Some(id))),
-                    _ => Some((rid, target_id, target_note.title())),
-                }
-            } else {
-                None
+            let target_note = NoteFactsDB::new(db, target_id?);
+            match &intern_link.heading {
+                Some(heading_text) => match target_note.heading_for_link(heading_text) {
+                    Some(id) => Some((rid, target_note.id, Some(id))),
+                    // Links to lines point to no heading
+                    _ => target_note
+                        .line_anchor_span(heading_text)
+                        .map(|_| (rid, target_note.id, None)),
+                },
+                _ => Some((rid, target_note.id, target_note.title())),
             }
         })
         .collect::<Vec<_>>()
@@ -385,9 +857,183 @@ fn note_intern_links_to_heading(
 fn note_diag(db: &dyn Facts, note_id: NoteID) -> Arc<[DiagWithLoc]> {
     let note_facts = NoteFactsDB::new(db, note_id);
     let mut diags = Vec::new();
-    diags.append(&mut diag::check_title(&note_facts));
-    diags.append(&mut diag::check_headings(&note_facts));
+    diags.append(&mut diag::check_frontmatter(&note_facts));
+    let title_level = db.title_level(());
+    diags.append(&mut diag::check_title(&note_facts, title_level));
+    diags.append(&mut diag::check_headings(&note_facts, title_level));
+    diags.append(&mut diag::check_heading_anchors(&note_facts));
+    diags.append(&mut diag::check_empty_headings(&note_facts));
+    diags.append(&mut diag::check_heading_levels(&note_facts));
+    let options = db.diag_options(());
+    if let Some(max) = options.max_heading_level {
+        diags.append(&mut diag::check_heading_depth(&note_facts, max));
+    }
+    if !options.allowed_tag_prefixes.is_empty() {
+        diags.append(&mut diag::check_tag_namespaces(
+            &note_facts,
+            &options.allowed_tag_prefixes,
+        ));
+    }
     diags.append(&mut diag::check_intern_links(db, &note_facts));
+    diags.append(&mut diag::check_link_labels(&note_facts));
+    diags.append(&mut diag::check_link_refs(&note_facts));
+    diags.append(&mut diag::check_dup_link_refs(&note_facts));
 
+    diags.extend(db.note_workspace_diag(note_id).iter().cloned());
+
+    diags.into()
+}
+
+fn workspace_diag(db: &dyn Facts, _key: ()) -> Arc<[(NoteFile, DiagWithLoc)]> {
+    let mut diags = diag::check_orphans(db);
+    diags.append(&mut diag::check_dup_note_titles(db));
+    let options = db.diag_options(());
+    if options.cross_note_heading_dups {
+        diags.append(&mut diag::check_cross_note_heading_dups(db));
+    }
+    if options.link_cycles {
+        diags.append(&mut diag::check_link_cycle_diags(db));
+    }
     diags.into()
 }
+
+fn workspace_diag_by_file(db: &dyn Facts, _key: ()) -> Arc<HashMap<NoteFile, Vec<DiagWithLoc>>> {
+    let mut by_file: HashMap<NoteFile, Vec<DiagWithLoc>> = HashMap::new();
+    for (file, diag) in db.workspace_diag(()).iter() {
+        by_file.entry(file.clone()).or_default().push(diag.clone());
+    }
+    Arc::new(by_file)
+}
+
+/// Workspace-level diagnostics of a single note. As long as they stay the same,
+/// the note's diagnostics aren't recomputed when other notes change.
+fn note_workspace_diag(db: &dyn Facts, note_id: NoteID) -> Arc<[DiagWithLoc]> {
+    let file = db.note_index(()).find_by_id(note_id);
+    match db.workspace_diag_by_file(()).get(&file) {
+        Some(diags) => diags.as_slice().into(),
+        None => Arc::new([]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn tag_index_across_notes() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("a.md", "# A #project/foo\n\nText #todo `#code`\n"),
+                ("b.md", "# B\n\n#todo and #todo\n"),
+            ],
+        );
+        let index = facts.tag_index();
+
+        assert_eq!(
+            index.names().collect::<Vec<_>>(),
+            vec!["project/foo", "todo"]
+        );
+        assert_eq!(index.count("todo"), 3);
+        assert_eq!(index.count("code"), 0);
+
+        let a = facts.note_facts(facts.note_index().find_by_name(&"a".into()).unwrap());
+        let strukt = a.structure();
+        let tags = strukt.tags_with_ids(&a.tags());
+        assert_eq!(
+            tags.iter().map(|t| t.text.as_str()).collect::<Vec<_>>(),
+            vec!["#project/foo", "#todo"]
+        );
+    }
+
+    #[test]
+    fn link_targets_follow_notes() {
+        let root = Path::new("/notes");
+        let mut facts = FactsDB::from_texts(root, &[("a.md", "# A\n\n[:b] [:a]\n")]);
+        // Note ids change as notes come and go
+        let a = |facts: &FactsDB| facts.note_index().find_by_name(&"a".into()).unwrap();
+        let targets = |facts: &FactsDB| {
+            let index = facts.note_index();
+            facts
+                .note_facts(a(facts))
+                .link_targets()
+                .iter()
+                .map(|(_, target)| target.map(|id| index.find_by_id(id).name.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(targets(&facts), vec![None, Some("a".to_string())]);
+
+        let text = |content: &str| NoteText::new(store::Version::Vs(1), content.into());
+        facts.insert_note(NoteFile::new(root, &root.join("b.md")), text("# B\n"));
+        assert_eq!(
+            targets(&facts),
+            vec![Some("b".to_string()), Some("a".to_string())]
+        );
+
+        facts.update_note(a(&facts), text("# A\n\n[:c]\n"));
+        assert_eq!(targets(&facts), vec![None]);
+
+        facts.update_note(a(&facts), text("# A\n\n[:b]\n"));
+        assert_eq!(targets(&facts), vec![Some("b".to_string())]);
+        facts.remove_note(&root.join("b.md"));
+        assert_eq!(targets(&facts), vec![None]);
+    }
+
+    #[test]
+    fn note_diag_ignores_workspace_diag_of_other_notes() {
+        let root = Path::new("/notes");
+        let mut facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:b]\n"),
+                ("b.md", "# B\n\n[:a]\n"),
+                ("c.md", "# C\n"),
+                ("d.md", "# D\n"),
+            ],
+        );
+        let id =
+            |facts: &FactsDB, name: &str| facts.note_index().find_by_name(&name.into()).unwrap();
+        let a_diag = facts.note_facts(id(&facts, "a")).diag();
+        let c_diag = facts.note_facts(id(&facts, "c")).diag();
+
+        // `c` is no longer an orphan, which doesn't concern `a`
+        let text = NoteText::new(store::Version::Vs(1), "# D\n\n[:c]\n".into());
+        facts.update_note(id(&facts, "d"), text);
+        assert!(Arc::ptr_eq(
+            &a_diag,
+            &facts.note_facts(id(&facts, "a")).diag()
+        ));
+        assert!(!Arc::ptr_eq(
+            &c_diag,
+            &facts.note_facts(id(&facts, "c")).diag()
+        ));
+    }
+
+    #[test]
+    fn parse_all_notes() {
+        let notes: Vec<_> = (0..50)
+            .map(|i| {
+                (
+                    format!("note-{}.md", i),
+                    format!("# Note {}\n\n[:note-{}]\n", i, i + 1),
+                )
+            })
+            .collect();
+        let notes: Vec<_> = notes
+            .iter()
+            .map(|(p, c)| (p.as_str(), c.as_str()))
+            .collect();
+        let facts = FactsDB::from_texts(Path::new("/notes"), &notes);
+        facts.parse_all();
+
+        for id in facts.note_index().ids() {
+            let note = facts.note_facts(id);
+            let expected = format!(
+                "Note {}",
+                note.file().name.to_str().trim_start_matches("note-")
+            );
+            assert_eq!(note.effective_title(), Some(expected));
+        }
+    }
+}
@@ -1,4 +1,6 @@
+pub mod config;
 pub mod diag;
+pub mod encoding;
 pub mod facts;
 pub mod lsp;
 pub mod parser;
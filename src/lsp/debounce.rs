@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Delays work on a note until there were no edits to it for a quiet period.
+///
+/// Each note has its own timer, so edits to one note don't postpone the work
+/// on the others.
+#[derive(Debug)]
+pub struct Debouncer {
+    delay: Duration,
+    deadlines: HashMap<PathBuf, Instant>,
+    ready: Vec<PathBuf>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay,
+            deadlines: HashMap::new(),
+            ready: Vec::new(),
+        }
+    }
+
+    /// Use `delay` for work scheduled from now on.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// Schedule the work on the note at `path`, resetting its timer.
+    pub fn touch(&mut self, path: PathBuf) {
+        self.touch_at(path, Instant::now())
+    }
+
+    fn touch_at(&mut self, path: PathBuf, now: Instant) {
+        if !self.ready.contains(&path) {
+            self.deadlines.insert(path, now + self.delay);
+        }
+    }
+
+    /// Make the pending work on the note at `path` due right away.
+    pub fn flush(&mut self, path: &Path) {
+        if let Some((path, _)) = self.deadlines.remove_entry(path) {
+            self.ready.push(path);
+        }
+    }
+
+    /// The earliest moment some pending work becomes due.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.values().min().copied()
+    }
+
+    /// Take the notes whose work is due at `now`.
+    pub fn take_due(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut due = std::mem::take(&mut self.ready);
+        self.deadlines.retain(|path, deadline| {
+            if *deadline <= now {
+                due.push(path.clone());
+                false
+            } else {
+                true
+            }
+        });
+        due.sort();
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const DELAY: Duration = Duration::from_millis(300);
+
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from("/notes").join(name)
+    }
+
+    #[test]
+    fn edits_reset_own_timer() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let mut debouncer = Debouncer::new(DELAY);
+
+        debouncer.touch_at(path("a.md"), ms(0));
+        debouncer.touch_at(path("b.md"), ms(100));
+        debouncer.touch_at(path("a.md"), ms(200));
+        assert_eq!(debouncer.next_deadline(), Some(ms(400)));
+
+        assert!(debouncer.take_due(ms(350)).is_empty());
+        assert_eq!(debouncer.take_due(ms(400)), vec![path("b.md")]);
+        assert_eq!(debouncer.take_due(ms(500)), vec![path("a.md")]);
+        assert_eq!(debouncer.next_deadline(), None);
+    }
+
+    #[test]
+    fn flush_makes_work_due() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(DELAY);
+
+        debouncer.touch_at(path("a.md"), start);
+        debouncer.touch_at(path("b.md"), start);
+        debouncer.flush(&path("a.md"));
+        debouncer.flush(&path("c.md"));
+
+        assert_eq!(debouncer.take_due(start), vec![path("a.md")]);
+        assert_eq!(debouncer.take_due(start + DELAY), vec![path("b.md")]);
+    }
+}
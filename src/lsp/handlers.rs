@@ -2,6 +2,7 @@ use std::{
     borrow::Borrow,
     collections::HashSet,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use anyhow::Result;
@@ -9,11 +10,13 @@ use anyhow::Result;
 use lsp_document::TextMap;
 
 use lsp_types::{
-    CodeLens, CodeLensParams, Command, DidChangeTextDocumentParams, DocumentLink,
-    DocumentLinkParams, GotoDefinitionParams, Hover, HoverContents, HoverParams, Location,
-    MarkupContent, Position, PublishDiagnosticsParams, SemanticToken, SemanticTokenType,
-    SemanticTokensLegend, SemanticTokensParams, SemanticTokensRangeParams, SymbolInformation,
-    TextDocumentIdentifier, TextDocumentItem, Url, WorkspaceFoldersChangeEvent,
+    request::Request, CodeLens, CodeLensParams, Command, DidChangeTextDocumentParams,
+    DidChangeWatchedFilesParams, DocumentLink, DocumentLinkParams, DocumentSymbol,
+    GotoDefinitionParams, Hover, HoverContents, HoverParams, Location, MarkupContent, Position,
+    PublishDiagnosticsParams, ReferenceParams, SemanticToken, SemanticTokenModifier,
+    SemanticTokenType, SemanticTokens, SemanticTokensLegend, SemanticTokensParams,
+    SemanticTokensRangeParams, SymbolInformation, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Url, WorkspaceFoldersChangeEvent,
 };
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
@@ -21,17 +24,29 @@ use serde_json;
 
 use tracing::debug;
 
+use crate::store::NoteFolder;
 use crate::{
-    diag::{self, DiagCollection, DiagWithLoc},
-    facts::{NoteFacts, NoteFactsDB, NoteFactsExt},
-    parser::{Element, NoteName},
-    store::{NoteFile, NoteText, Version},
+    config::Config,
+    diag::{self, DiagCollection},
+    encoding::{utf8_to_utf16_col, PositionEncoding},
+    facts::{self, Facts, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
+    parser::Element,
+    store::{self, NoteFile, NoteText, Version},
+    structure::{ElementID, HeadingID, NoteID},
 };
 use crate::{lsp::server::ClientName, store::Workspace};
-use crate::{store::NoteFolder, util::text_matches_query};
-use lsp_document::{self, IndexedText, TextAdapter};
+use lsp_document::{self, IndexedText, Pos, TextAdapter};
 
+pub mod code_action;
+pub mod command;
 pub mod completion;
+pub mod document_link;
+pub mod folding;
+pub mod formatting;
+pub mod highlight;
+pub mod inlay_hint;
+pub mod rename;
+pub mod selection;
 
 //////////////////////////////////////////
 // Workspace
@@ -40,6 +55,7 @@ pub mod completion;
 pub async fn note_change_workspace_folders(
     workspace: &mut Workspace,
     event: &WorkspaceFoldersChangeEvent,
+    config: &Config,
 ) -> Result<()> {
     for removed in &event.removed {
         let path = removed
@@ -51,12 +67,151 @@ pub async fn note_change_workspace_folders(
 
     for added in &event.added {
         let folder = NoteFolder::from_workspace_folder(added);
-        workspace.add_folder(folder).await?;
+        workspace.add_folder(folder, config).await?;
     }
 
     Ok(())
 }
 
+/// Rescan folders whose ignore files changed so that newly (un)ignored notes are picked up.
+pub async fn note_change_watched_files(
+    workspace: &mut Workspace,
+    params: &DidChangeWatchedFilesParams,
+    config: &Config,
+) -> Result<()> {
+    for change in &params.changes {
+        let path = match change.uri.to_file_path() {
+            Ok(path) => path,
+            _ => continue,
+        };
+        if store::is_ignore_file(&path) {
+            debug!("Ignore file changed: {}", path.display());
+            workspace.rescan_folder(&path, config).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Switch the workspace from `old` to `new` settings. Folders are rescanned
+/// when the set of note files may differ under the new settings.
+pub async fn note_change_configuration(
+    workspace: &mut Workspace,
+    old: &Config,
+    new: &Config,
+) -> Result<()> {
+    if old.note_name_matching() != new.note_name_matching()
+        || old.link_resolution != new.link_resolution
+        || old.title_heading_level != new.title_heading_level
+        || old.title_fallback != new.title_fallback
+        || old.diag_options() != new.diag_options()
+        || old.resource_roots != new.resource_roots
+    {
+        workspace.apply_config(new);
+    }
+
+    if old.respect_gitignore != new.respect_gitignore
+        || old.scan_hidden != new.scan_hidden
+        || old.extensions != new.extensions
+    {
+        let roots: Vec<PathBuf> = workspace
+            .roots()
+            .iter()
+            .map(|folder| folder.root.clone())
+            .collect();
+        for root in roots {
+            debug!("Rescanning {} with new settings", root.display());
+            workspace.rescan_folder(&root, new).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply changes made to `paths` outside of the editor, e.g. created, modified,
+/// or deleted notes. Notes opened in the editor are left as is.
+///
+/// Returns paths of the notes that were updated or removed, and of the changed
+/// files in the `resource_roots` folders.
+pub async fn note_change_on_disk(
+    workspace: &mut Workspace,
+    paths: &[PathBuf],
+    config: &Config,
+) -> Result<Vec<PathBuf>> {
+    let facts = &workspace.facts;
+    let open: HashSet<Arc<Path>> = facts
+        .note_index()
+        .ids()
+        .map(|id| facts.note_facts(id))
+        .filter(|note| matches!(note.text().version, Version::Vs(_)))
+        .map(|note| note.file().path)
+        .collect();
+    let is_open = |path: &Path| open.contains(path);
+
+    let mut changed = Vec::new();
+    for path in paths {
+        if store::is_ignore_file(path) {
+            debug!("Ignore file changed: {}", path.display());
+            let folder_notes = |workspace: &Workspace| -> Vec<PathBuf> {
+                let root = path.parent().unwrap_or(path);
+                workspace
+                    .facts
+                    .note_index()
+                    .files()
+                    .filter(|file| file.path.starts_with(root))
+                    .map(|file| file.path.to_path_buf())
+                    .collect()
+            };
+            // Notes that are gone need their diagnostics cleared too
+            changed.extend(folder_notes(workspace));
+            workspace.rescan_folder(path, config).await?;
+            changed.extend(folder_notes(workspace));
+            continue;
+        }
+
+        let (folder, facts, filter) = match workspace.owning_folder_mut(path) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        // Notes linking to a resource are rechecked when the file comes or goes
+        let in_resources = path
+            .strip_prefix(&folder.root)
+            .is_ok_and(|rel_path| facts.db().resource_roots(()).matches(rel_path));
+        if in_resources && !path.is_dir() && !filter.is_note_file(path, &folder.root) {
+            changed.push(path.clone());
+            continue;
+        }
+
+        if path.is_dir() {
+            let found = store::find_notes(path, config).await?;
+            let new_notes: Vec<PathBuf> = found.into_iter().filter(|p| !is_open(p)).collect();
+            facts.add_files(&folder.root, &new_notes, filter).await?;
+            changed.extend(new_notes);
+        } else if path.exists() {
+            if !is_open(path) && filter.is_note_file(path, &folder.root) {
+                facts.with_file(&folder.root, path, filter).await?;
+                changed.push(path.clone());
+            }
+        } else {
+            // A removed folder takes all its notes with it
+            let removed: Vec<PathBuf> = facts
+                .note_index()
+                .files()
+                .filter(|file| file.path.starts_with(path) && !is_open(&file.path))
+                .map(|file| file.path.to_path_buf())
+                .collect();
+            for path in &removed {
+                facts.remove_note(path);
+            }
+            changed.extend(removed);
+        }
+    }
+
+    debug!("note_change_on_disk: {} notes changed", changed.len());
+    Ok(changed)
+}
+
 //////////////////////////////////////////
 // Text Sync
 /////////////////////////////////////////
@@ -94,11 +249,18 @@ pub fn note_apply_changes(
         }
 
         let mut final_text = note.text().content.to_string();
+        let encoding = facts.db().position_encoding(());
 
         for lsp_change in &changes.content_changes {
+            let mut lsp_change = lsp_change.clone();
+            if encoding == PositionEncoding::Utf8 {
+                lsp_change.range = lsp_change
+                    .range
+                    .map(|range| utf8_range_to_utf16(&final_text, range));
+            }
             let indexed = IndexedText::new(final_text.as_ref());
             let change = indexed
-                .lsp_change_to_change(lsp_change.clone())
+                .lsp_change_to_change(lsp_change)
                 .expect("Couldn't translate LSP document change event");
             final_text = lsp_document::apply_change(&indexed, change);
         }
@@ -109,6 +271,16 @@ pub fn note_apply_changes(
     }
 }
 
+/// Translate a range with UTF-8 columns into the UTF-16 ones
+/// `lsp_document` expects.
+fn utf8_range_to_utf16(text: &str, range: lsp_types::Range) -> lsp_types::Range {
+    let convert = |pos: lsp_types::Position| {
+        let line = text.split('\n').nth(pos.line as usize).unwrap_or("");
+        lsp_types::Position::new(pos.line, utf8_to_utf16_col(line, pos.character))
+    };
+    lsp_types::Range::new(convert(range.start), convert(range.end))
+}
+
 pub fn note_open(workspace: &mut Workspace, path: &Path, document: &TextDocumentItem) {
     if let Some((folder, facts, _)) = workspace.owning_folder_mut(path) {
         let note = NoteText::new(Version::Vs(document.version), document.text.clone().into());
@@ -138,58 +310,91 @@ pub fn status_notification(num_notes: usize) -> lsp_server::Notification {
 // Symbols
 /////////////////////////////////////////
 
+/// Outline of the note where headings of higher levels contain headings of
+/// lower levels.
 #[allow(deprecated)]
-pub fn document_symbols(workspace: &Workspace, path: &Path, query: &str) -> Vec<SymbolInformation> {
-    debug!("document_symbols: start");
-
-    let mut symbols = Vec::new();
-
-    let (_, facts) = match workspace.owning_folder(path) {
-        Some(x) => x,
-        _ => return symbols,
-    };
-
-    let note_id = match facts.note_index().find_by_path(path) {
-        Some(t) => t,
-        _ => return symbols,
-    };
-    debug!("document_symbols: note_id={:?}", note_id);
+pub fn document_symbols(note: &impl NoteFactsExt) -> Vec<DocumentSymbol> {
+    let strukt = note.structure();
+    let indexed_text = note.indexed_text();
 
-    let note = facts.note_facts(note_id);
-    let structure = note.structure();
+    let mut roots = Vec::new();
+    // Chain of currently open headings: (level, symbol)
+    let mut open: Vec<(u8, DocumentSymbol)> = Vec::new();
 
-    let matching_ids = note.headings_matching(|hd| text_matches_query(hd.text.as_str(), query));
-    debug!("document_symbols: found {} ids", matching_ids.len());
+    fn close_last(open: &mut Vec<(u8, DocumentSymbol)>, roots: &mut Vec<DocumentSymbol>) {
+        if let Some((_, symbol)) = open.pop() {
+            match open.last_mut() {
+                Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+                _ => roots.push(symbol),
+            }
+        }
+    }
 
-    let matching_els = structure.headings_with_ids(&matching_ids);
-    for hd in matching_els {
-        let lsp_range = match note.indexed_text().range_to_lsp_range(&hd.span) {
+    for hd in strukt.headings_with_ids(&note.headings()) {
+        let range = match indexed_text.range_to_lsp_range(&hd.span) {
             Some(r) => r,
             _ => continue,
         };
-        let uri = Url::from_file_path(&note.file().path).unwrap();
-        let location = lsp_types::Location::new(uri, lsp_range);
-        let symbol = lsp_types::SymbolInformation {
-            name: hd.text.clone(),
-            kind: lsp_types::SymbolKind::STRING,
+        let kind = if hd.level == 1 {
+            lsp_types::SymbolKind::NAMESPACE
+        } else {
+            lsp_types::SymbolKind::STRING
+        };
+        let symbol = DocumentSymbol {
+            name: hd.text.trim_start_matches('#').trim().to_string(),
+            detail: None,
+            kind,
             tags: None,
             deprecated: None,
-            location,
-            container_name: None,
+            range,
+            selection_range: range,
+            children: None,
         };
-        symbols.push(symbol)
+
+        while matches!(open.last(), Some((level, _)) if *level >= hd.level) {
+            close_last(&mut open, &mut roots);
+        }
+        open.push((hd.level, symbol));
     }
 
-    symbols
+    while !open.is_empty() {
+        close_last(&mut open, &mut roots);
+    }
+
+    roots
 }
 
-pub fn workspace_symbols(workspace: &Workspace, query: &str) -> Vec<SymbolInformation> {
+/// All headings across the notes whose text contains `query` (case-insensitive).
+#[allow(deprecated)]
+pub fn workspace_symbols(facts: &dyn Facts, query: &str) -> Vec<SymbolInformation> {
+    let query = query.to_lowercase();
     let mut symbols = Vec::new();
-    for (_, facts, _) in &workspace.folders {
-        let note_index = facts.note_index();
-        let files = note_index.files();
-        for nf in files {
-            symbols.append(&mut document_symbols(workspace, &nf.path, query));
+
+    for note_id in facts.note_index(()).ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        let file = note.file();
+        let strukt = note.structure();
+        let indexed_text = note.indexed_text();
+
+        for hd in strukt.headings_with_ids(&note.headings()) {
+            let name = hd.text.trim_start_matches('#').trim();
+            if !name.to_lowercase().contains(&query) {
+                continue;
+            }
+
+            let lsp_range = match indexed_text.range_to_lsp_range(&hd.span) {
+                Some(r) => r,
+                _ => continue,
+            };
+            let uri = Url::from_file_path(&file.path).unwrap();
+            symbols.push(SymbolInformation {
+                name: name.to_string(),
+                kind: lsp_types::SymbolKind::STRING,
+                tags: None,
+                deprecated: None,
+                location: Location::new(uri, lsp_range),
+                container_name: Some(file.name.to_string()),
+            });
         }
     }
 
@@ -207,35 +412,42 @@ pub fn hover(workspace: &Workspace, params: HoverParams) -> Option<Hover> {
         .uri
         .to_file_path()
         .unwrap();
-    let pos = params.text_document_position_params.position;
-
-    let (folder, facts) = workspace.owning_folder(&path)?;
-    let root = &folder.root;
+    let lsp_pos = params.text_document_position_params.position;
 
+    let (_, facts) = workspace.owning_folder(&path)?;
     let note_id = facts.note_index().find_by_path(&path)?;
-    let note_name = NoteName::from_path(&path, root);
-    let note = facts.note_facts(note_id);
+    let pos = facts
+        .note_facts(note_id)
+        .indexed_text()
+        .lsp_pos_to_pos(&lsp_pos)?;
+
+    link_hover(facts.db(), note_id, pos).or_else(|| heading_hover(facts.db(), note_id, pos))
+}
+
+/// Preview of the link target at `pos`.
+///
+/// For a link to a heading it's the heading's section; for a link to a note
+/// it's the note's title and the first paragraph.
+pub fn link_hover(facts: &dyn Facts, note_id: NoteID, pos: Pos) -> Option<Hover> {
+    let note = NoteFactsDB::new(facts, note_id);
     let note_structure = note.structure();
-    let hovered_el = note_structure.element_by_id(note.element_at_lsp_pos(&pos)?);
+    let hovered_el = note_structure.element_by_id(note.element_at_pos(pos)?);
 
     if let Element::InternLink(intern_link) = hovered_el {
         let range = note.indexed_text().range_to_lsp_range(&intern_link.span);
 
-        let target_note_name = intern_link.note_name.clone().unwrap_or(note_name);
-
-        let target_id = facts.note_index().find_by_name(&target_note_name)?;
-        let target_note = facts.note_facts(target_id);
-        let target_struct = target_note.structure();
-        let target_text = target_note.indexed_text();
-        let text = if let Some(heading) = &intern_link.heading {
-            let heading = target_struct.heading_by_id(target_note.heading_with_text(heading)?);
-
-            target_text
-                .substr(heading.scope.clone())
-                .unwrap()
-                .to_string()
-        } else {
-            target_text.text().to_string()
+        let text = match facts::resolve_link_target(facts, note_id, intern_link) {
+            Some(target_id) => {
+                let target_note = NoteFactsDB::new(facts, target_id);
+                match &intern_link.heading {
+                    Some(heading) => match target_note.heading_for_link(heading) {
+                        Some(heading_id) => section_text(&target_note, heading_id)?,
+                        _ => format!("Heading `{}` not found", heading),
+                    },
+                    _ => note_preview(&target_note),
+                }
+            }
+            _ => "Note not found".to_string(),
         };
 
         let markup = MarkupContent {
@@ -252,6 +464,54 @@ pub fn hover(workspace: &Workspace, params: HoverParams) -> Option<Hover> {
     None
 }
 
+/// Text of the section of the heading `heading_id`, the heading included.
+fn section_text(note: &NoteFactsDB, heading_id: HeadingID) -> Option<String> {
+    let scope = note.structure().heading_by_id(heading_id).scope.clone();
+    Some(note.indexed_text().substr(scope)?.to_string())
+}
+
+/// Number of links to the heading at `pos` and the notes they come from.
+///
+/// Links to a note without a heading count as links to its title.
+pub fn heading_hover(facts: &dyn Facts, note_id: NoteID, pos: Pos) -> Option<Hover> {
+    let note = NoteFactsDB::new(facts, note_id);
+    let heading_id = match note.element_at_pos(pos)? {
+        ElementID::Heading(id) => id,
+        _ => return None,
+    };
+    let heading = note.structure().heading_by_id(heading_id).clone();
+
+    let links = note.intern_links_to_heading(heading_id);
+    let index = facts.note_index(());
+    let mut sources = links
+        .iter()
+        .map(|(src_id, _)| index.find_by_id(*src_id).name.to_string())
+        .collect::<Vec<_>>();
+    sources.sort();
+    sources.dedup();
+
+    let text = match links.len() {
+        0 => "No backlinks".to_string(),
+        count => {
+            let noun = if count == 1 { "backlink" } else { "backlinks" };
+            let list = sources
+                .iter()
+                .map(|name| format!("- `{}`", name))
+                .collect::<Vec<_>>()
+                .join("\n");
+            format!("{} {} from:\n\n{}", count, noun, list)
+        }
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: lsp_types::MarkupKind::Markdown,
+            value: text,
+        }),
+        range: note.indexed_text().range_to_lsp_range(&heading.span),
+    })
+}
+
 pub fn goto_definition(workspace: &Workspace, params: GotoDefinitionParams) -> Option<Location> {
     let path = params
         .text_document_position_params
@@ -259,33 +519,57 @@ pub fn goto_definition(workspace: &Workspace, params: GotoDefinitionParams) -> O
         .uri
         .to_file_path()
         .unwrap();
-    let pos = params.text_document_position_params.position;
-
-    let (folder, facts) = workspace.owning_folder(&path)?;
-    let root = &folder.root;
+    let lsp_pos = params.text_document_position_params.position;
 
+    let (_, facts) = workspace.owning_folder(&path)?;
     let source_id = facts.note_index().find_by_path(&path)?;
-    let source_note = facts.note_facts(source_id);
+    let pos = facts
+        .note_facts(source_id)
+        .indexed_text()
+        .lsp_pos_to_pos(&lsp_pos)?;
+
+    goto_def(facts.db(), source_id, pos)
+}
+
+/// The title of the note followed by its first paragraph.
+pub(crate) fn note_preview(note: &NoteFactsDB) -> String {
+    let title = match note.frontmatter_title() {
+        Some(title) => Some(format!("# {}", title)),
+        _ => note
+            .title()
+            .map(|id| note.structure().heading_by_id(id).text.clone()),
+    };
+    let parts = [title, note.first_paragraph()];
+    parts.into_iter().flatten().collect::<Vec<_>>().join("\n\n")
+}
+
+/// Location of the heading the link at `pos` points to.
+///
+/// Links without a heading point to the title of the target note, and links
+/// with a line anchor like `#L10-L20` point to these lines.
+pub fn goto_def(facts: &dyn Facts, note_id: NoteID, pos: Pos) -> Option<Location> {
+    let source_note = NoteFactsDB::new(facts, note_id);
     let souce_index = source_note.structure();
-    let encl_el = souce_index.element_by_id(source_note.element_at_lsp_pos(&pos)?);
+    let encl_el = souce_index.element_by_id(source_note.element_at_pos(pos)?);
 
     if let Element::InternLink(intern_link) = encl_el {
-        let target_note_name = intern_link
-            .note_name
-            .clone()
-            .unwrap_or_else(|| NoteName::from_path(&path, root));
-
-        let target_id = facts.note_index().find_by_name(&target_note_name)?;
-        let target_note = facts.note_facts(target_id);
+        let target_id = facts::resolve_link_target(facts, note_id, intern_link)?;
+        let target_note = NoteFactsDB::new(facts, target_id);
         let target_struct = target_note.structure();
-        let target_heading = if let Some(link_heading) = &intern_link.heading {
-            target_struct.heading_by_id(target_note.heading_with_text(link_heading)?)
+        let target_span = if let Some(link_heading) = &intern_link.heading {
+            match target_note.heading_for_link(link_heading) {
+                Some(id) => target_struct.heading_by_id(id).span.clone(),
+                _ => target_note.line_anchor_span(link_heading)?,
+            }
         } else {
-            target_struct.heading_by_id(target_note.title()?)
+            target_struct
+                .heading_by_id(target_note.title()?)
+                .span
+                .clone()
         };
         let range = target_note
             .indexed_text()
-            .range_to_lsp_range(&target_heading.span)
+            .range_to_lsp_range(&target_span)
             .unwrap();
 
         return Some(Location {
@@ -297,25 +581,88 @@ pub fn goto_definition(workspace: &Workspace, params: GotoDefinitionParams) -> O
     None
 }
 
+//////////////////////////////////////////
+// References
+/////////////////////////////////////////
+
+pub fn references(workspace: &Workspace, params: ReferenceParams) -> Option<Vec<Location>> {
+    let path = params
+        .text_document_position
+        .text_document
+        .uri
+        .to_file_path()
+        .unwrap();
+    let pos = params.text_document_position.position;
+
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+
+    match note.element_at_lsp_pos(&pos)? {
+        ElementID::Heading(id) => Some(heading_references(facts.db(), note.id, id)),
+        _ => None,
+    }
+}
+
+/// Locations of all links that point to the heading `heading_id` in the note `note_id`.
+///
+/// Links without a heading are considered to point to the title of the note.
+pub fn heading_references(
+    facts: &dyn Facts,
+    note_id: NoteID,
+    heading_id: HeadingID,
+) -> Vec<Location> {
+    let note = NoteFactsDB::new(facts, note_id);
+
+    let mut locations = Vec::new();
+    for (src_note_id, src_link_id) in note.intern_links_to_heading(heading_id).iter() {
+        let src_note = NoteFactsDB::new(facts, *src_note_id);
+        let src_strukt = src_note.structure();
+
+        let src_link = src_strukt.intern_link_by_id(*src_link_id);
+        let lsp_range = match src_note.indexed_text().range_to_lsp_range(&src_link.span) {
+            Some(r) => r,
+            _ => continue,
+        };
+
+        locations.push(Location {
+            uri: Url::from_file_path(src_note.file().path).unwrap(),
+            range: lsp_range,
+        });
+    }
+
+    locations
+}
+
 //////////////////////////////////////////
 // Semantic tokens
 /////////////////////////////////////////
 
+/// Token types in the order of the legend: heading text, note names and
+/// heading anchors in links.
+const SEMANTIC_TOKEN_TYPES: [SemanticTokenType; 3] = [
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::PROPERTY,
+];
+
+/// Modifier of the tokens of links that don't resolve.
+const SEMANTIC_TOKEN_BROKEN: SemanticTokenModifier = SemanticTokenModifier::new("broken");
+/// Bit of [`SEMANTIC_TOKEN_BROKEN`] in the modifiers of a token.
+const SEMANTIC_TOKEN_BROKEN_BIT: u32 = 1;
+
+/// A token with an absolute position: the range, the type, and the modifiers.
+type SemanticTokenSpan = (lsp_types::Range, SemanticTokenType, u32);
+
 pub fn semantic_token_type_mapping(tok_type: &SemanticTokenType) -> u32 {
-    if *tok_type == SemanticTokenType::CLASS {
-        // Heading
-        0
-    } else if *tok_type == SemanticTokenType::PROPERTY {
-        // Reference
-        1
-    } else {
-        unimplemented!("Unsupported token type: {}", tok_type.as_str())
+    match SEMANTIC_TOKEN_TYPES.iter().position(|t| t == tok_type) {
+        Some(idx) => idx as u32,
+        None => unimplemented!("Unsupported token type: {}", tok_type.as_str()),
     }
 }
 
 static LAZY_SEMANTIC_TOKENS_LEGEND: Lazy<SemanticTokensLegend> = Lazy::new(|| {
-    let token_types = vec![SemanticTokenType::CLASS, SemanticTokenType::PROPERTY];
-    let token_modifiers = Vec::new();
+    let token_types = SEMANTIC_TOKEN_TYPES.to_vec();
+    let token_modifiers = vec![SEMANTIC_TOKEN_BROKEN];
     SemanticTokensLegend {
         token_types,
         token_modifiers,
@@ -329,76 +676,132 @@ pub fn semantic_tokens_legend() -> &'static SemanticTokensLegend {
 pub fn semantic_tokens_range(
     workspace: &Workspace,
     params: SemanticTokensRangeParams,
-) -> Option<Vec<SemanticToken>> {
+) -> Option<SemanticTokens> {
     let path = params.text_document.uri.to_file_path().unwrap();
     let (_, facts) = workspace.owning_folder(&path)?;
     let range = params.range;
     let note_id = facts.note_index().find_by_path(&path)?;
     let note = facts.note_facts(note_id);
-    let element_ids = note.elements_in_lsp_range(&range)?;
-    let strukt = note.structure();
-    let elements = strukt.elements_with_ids(&element_ids).collect();
-    Some(semantic_tokens_encode(note, elements))
+    let tokens = semantic_token_spans(&note)
+        .into_iter()
+        .filter(|(span, ..)| span.start < range.end && range.start < span.end)
+        .collect();
+    Some(SemanticTokens {
+        data: semantic_tokens_encode(tokens),
+        ..SemanticTokens::default()
+    })
 }
 
 pub fn semantic_tokens_full(
     workspace: &Workspace,
     params: SemanticTokensParams,
-) -> Option<Vec<SemanticToken>> {
+) -> Option<SemanticTokens> {
     let path = params.text_document.uri.to_file_path().unwrap();
     let (_, facts) = workspace.owning_folder(&path)?;
     let note_id = facts.note_index().find_by_path(&path)?;
-    let note = facts.note_facts(note_id);
-    let strukt = note.structure();
+    Some(semantic_tokens(&facts.note_facts(note_id)))
+}
 
-    let elements = strukt.elements().into_iter().map(|(_, ewl)| ewl).collect();
-    Some(semantic_tokens_encode(note, elements))
+/// Tokens for the text of headings, and for note names and heading anchors in
+/// links. Tokens of broken links are marked with the `broken` modifier.
+pub fn semantic_tokens(note: &impl NoteFactsExt) -> SemanticTokens {
+    SemanticTokens {
+        data: semantic_tokens_encode(semantic_token_spans(note)),
+        ..SemanticTokens::default()
+    }
 }
 
-fn semantic_tokens_encode(
-    note: NoteFactsDB<'_>,
-    mut elements: Vec<&Element>,
-) -> Vec<SemanticToken> {
-    // Sort before so that deltas are ok to calculate
-    elements.sort_by_key(|e| e.span().start);
+fn semantic_token_spans(note: &impl NoteFactsExt) -> Vec<SemanticTokenSpan> {
+    let strukt = note.structure();
+    let indexed_text = note.indexed_text();
+    let mut spans = Vec::new();
 
-    let mut encoded = Vec::new();
-    let mut cur_line = 0;
-    let mut cur_char_offset = 0;
+    for heading in strukt.headings_with_ids(&note.headings()) {
+        spans.push((heading.text_span(), SemanticTokenType::NAMESPACE, 0));
+    }
 
-    for el in elements {
-        let el_span = el.span();
-        let token_type = match el {
-            // SemanticTokenType::CLASS but skip for now as markdown syntax highlighting is already good eneough for headings
-            Element::Heading(..) => continue,
-            Element::InternLink(..) => SemanticTokenType::PROPERTY,
-            _ => continue,
+    let valid_links = note.valid_intern_links();
+    for &link_id in note.intern_link_ids().iter() {
+        let link = strukt.intern_link_by_id(link_id);
+        let modifiers = if valid_links.iter().any(|(id, ..)| *id == link_id) {
+            0
+        } else {
+            SEMANTIC_TOKEN_BROKEN_BIT
         };
-        let el_pos = note.indexed_text().range_to_lsp_range(el_span).unwrap();
-        // Can't handle multiline tokens properly so skip.
-        // Would be nice to improve at some point
-        if el_pos.end.line > el_pos.start.line {
-            continue;
+        let name_span = link.note_name_span();
+        let heading_span = link.heading_span();
+        if name_span.is_none() && heading_span.is_none() {
+            // Reference-style links have the target in the definition
+            spans.push((link.span.clone(), SemanticTokenType::CLASS, modifiers));
+        }
+        if let Some(span) = name_span {
+            spans.push((span, SemanticTokenType::CLASS, modifiers));
+        }
+        if let Some(span) = heading_span {
+            spans.push((span, SemanticTokenType::PROPERTY, modifiers));
+        }
+    }
+
+    let mut tokens: Vec<_> = spans
+        .into_iter()
+        .filter_map(|(span, typ, modifiers)| {
+            let range = indexed_text.range_to_lsp_range(&span)?;
+            // Can't handle multiline tokens properly so skip.
+            // Would be nice to improve at some point
+            (range.end.line == range.start.line).then_some((range, typ, modifiers))
+        })
+        .collect();
+    // Deltas are calculated from sorted tokens
+    tokens.sort_by_key(|(range, ..)| range.start);
+
+    // Tokens can't overlap, so links in a heading split the heading token
+    let mut split: Vec<SemanticTokenSpan> = Vec::new();
+    for (range, typ, modifiers) in tokens {
+        let mut rest = None;
+        let mut drop_last = false;
+        if let Some((last, last_typ, last_modifiers)) = split.last_mut() {
+            if range.start < last.end {
+                if range.end < last.end {
+                    let rest_range = lsp_types::Range::new(range.end, last.end);
+                    rest = Some((rest_range, last_typ.clone(), *last_modifiers));
+                }
+                last.end = range.start;
+                drop_last = last.start == last.end;
+            }
+        }
+        if drop_last {
+            split.pop();
         }
+        split.push((range, typ, modifiers));
+        split.extend(rest);
+    }
+    split
+}
+
+fn semantic_tokens_encode(tokens: Vec<SemanticTokenSpan>) -> Vec<SemanticToken> {
+    let mut encoded = Vec::new();
+    let mut cur_line = 0;
+    let mut cur_char_offset = 0;
 
-        let delta_line = el_pos.start.line - cur_line;
+    for (range, token_type, modifiers) in tokens {
+        let delta_line = range.start.line - cur_line;
         let delta_start = if delta_line == 0 {
-            el_pos.start.character - cur_char_offset
+            range.start.character - cur_char_offset
         } else {
-            el_pos.start.character
+            range.start.character
         };
-        let length = el_pos.end.character - el_pos.start.character;
+        let length = range.end.character - range.start.character;
 
         let token = SemanticToken {
             delta_line,
             delta_start,
             length,
             token_type: semantic_token_type_mapping(&token_type),
-            token_modifiers_bitset: 0,
+            token_modifiers_bitset: modifiers,
         };
         encoded.push(token);
-        cur_line = el_pos.start.line;
-        cur_char_offset = el_pos.start.character;
+        cur_line = range.start.line;
+        cur_char_offset = range.start.character;
     }
 
     encoded
@@ -410,43 +813,102 @@ fn semantic_tokens_encode(
 
 pub fn diag(
     workspace: &Workspace,
-    prev_diag_col: &DiagCollection,
-) -> Option<(Vec<PublishDiagnosticsParams>, DiagCollection)> {
+    diag_col: &mut DiagCollection,
+    config: &Config,
+) -> Vec<PublishDiagnosticsParams> {
     debug!("Diagnostic check initiated");
 
-    let mut changed = false;
-
-    let mut new_col = DiagCollection::default();
-    let mut diag_params = Vec::new();
+    let facts = &workspace.facts;
+    diag_col
+        .recompute_all(facts)
+        .into_iter()
+        .filter_map(|file| publish_params(&file, diag_col, facts, config))
+        .collect()
+}
 
-    for (_, facts, _) in &workspace.folders {
-        for note_id in facts.note_index().ids() {
-            let note = facts.note_facts(note_id);
-            let file = note.file();
-            let diag = note.diag();
-            let diag: HashSet<DiagWithLoc> = diag.iter().cloned().collect();
-
-            let changed_for_file = if let Some(prev_set) = prev_diag_col.store.get(&file) {
-                *prev_set != diag
-            } else {
-                true
-            };
+/// Recompute diagnostics from scratch and publish them for every note, even
+/// if they didn't change, e.g. after the workspace was reindexed. Notes that
+/// are gone get empty diagnostics.
+pub fn diag_republish(
+    workspace: &Workspace,
+    diag_col: &mut DiagCollection,
+    config: &Config,
+) -> Vec<PublishDiagnosticsParams> {
+    let facts = &workspace.facts;
+    let mut files: HashSet<NoteFile> = diag_col.store.keys().cloned().collect();
+    *diag_col = DiagCollection::default();
+    files.extend(diag_col.recompute_all(facts));
+    files.extend(facts.note_index().files().cloned());
+    files
+        .iter()
+        .filter_map(|file| publish_params(file, diag_col, facts, config))
+        .collect()
+}
 
-            if changed_for_file {
-                changed = true;
-                if let Some(param) = diag::to_publish(&file, &diag, facts) {
-                    diag_params.push(param);
-                }
-            }
+/// Update diagnostics after the notes in `changed` were edited.
+pub fn diag_for_changes(
+    workspace: &Workspace,
+    diag_col: &mut DiagCollection,
+    changed: &[PathBuf],
+    config: &Config,
+) -> Vec<PublishDiagnosticsParams> {
+    let mut diag_params = Vec::new();
+    for path in changed {
+        let (folder, facts) = match workspace.owning_folder(path) {
+            Some(folder) => folder,
+            None => continue,
+        };
+        // A removed note still affects the notes that linked to it
+        let file = match facts.note_index().find_by_path(path) {
+            Some(id) => facts.note_index().find_by_id(id),
+            None => NoteFile::new(&folder.root, path),
+        };
 
-            new_col.store.insert(file, diag);
+        for file in diag_col.recompute_for(&file, facts) {
+            diag_params.extend(publish_params(&file, diag_col, facts, config));
         }
     }
 
-    if changed {
-        Some((diag_params, new_col))
-    } else {
-        None
+    diag_params
+}
+
+/// Check the notes at `paths` without the notes linking to them.
+pub fn diag_for_notes(
+    workspace: &Workspace,
+    diag_col: &mut DiagCollection,
+    paths: &[PathBuf],
+    config: &Config,
+) -> Vec<PublishDiagnosticsParams> {
+    let facts = &workspace.facts;
+    let index = facts.note_index();
+    let files: Vec<NoteFile> = paths
+        .iter()
+        .filter_map(|path| index.find_by_path(path))
+        .map(|id| index.find_by_id(id))
+        .collect();
+
+    diag_col
+        .recompute_notes(&files, facts)
+        .into_iter()
+        .filter_map(|file| publish_params(&file, diag_col, facts, config))
+        .collect()
+}
+
+/// Diagnostics of the note to publish, or an empty list to clear them if the
+/// note is gone, e.g. with its folder.
+fn publish_params(
+    file: &NoteFile,
+    diag_col: &DiagCollection,
+    facts: &FactsDB,
+    config: &Config,
+) -> Option<PublishDiagnosticsParams> {
+    match diag_col.store.get(file) {
+        Some(diags) => diag::to_publish(file, diags, facts, config),
+        None => Some(PublishDiagnosticsParams {
+            uri: Url::from_file_path(&file.path).ok()?,
+            diagnostics: Vec::new(),
+            version: None,
+        }),
     }
 }
 
@@ -457,6 +919,7 @@ pub fn diag(
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ReferenceData {
     note_path: PathBuf,
+    heading_id: HeadingID,
     heading_text: String,
 }
 
@@ -485,6 +948,7 @@ pub fn code_lenses(workspace: &Workspace, params: CodeLensParams) -> Option<Vec<
         };
         let ref_data = ReferenceData {
             note_path: path.clone(),
+            heading_id: h_id,
             heading_text: heading.text.to_string(),
         };
         let lens = CodeLens {
@@ -506,7 +970,15 @@ pub struct ShowReferencesData {
     locations: Vec<Location>,
 }
 
-pub fn code_lens_resolve(workspace: &Workspace, lens: &CodeLens) -> Option<CodeLens> {
+/// Fill in the "N references" command of a lens created by [`code_lenses`].
+///
+/// With `show_references` the lens runs the client-side `zetaNote.showReferences`
+/// command, otherwise it runs [`command::BACKLINKS`] for the heading.
+pub fn code_lens_resolve(
+    workspace: &Workspace,
+    lens: &CodeLens,
+    show_references: bool,
+) -> Option<CodeLens> {
     debug!("code_lens_resolve: start");
 
     let lens_data = lens.data.clone()?;
@@ -515,8 +987,15 @@ pub fn code_lens_resolve(workspace: &Workspace, lens: &CodeLens) -> Option<CodeL
     let note = facts.note_facts(facts.note_index().find_by_path(&ref_data.note_path)?);
     let strukt = note.structure();
 
-    let heading_id = note.heading_with_text(&ref_data.heading_text)?;
+    // The note may have changed since the lens was created
+    let heading_id = ref_data.heading_id;
+    if !strukt.headings().contains(&heading_id) {
+        return None;
+    }
     let heading = strukt.heading_by_id(heading_id);
+    if heading.text != ref_data.heading_text {
+        return None;
+    }
     let heading_lsp_pos = note.indexed_text().pos_to_lsp_pos(&heading.span.start)?;
 
     debug!(
@@ -524,49 +1003,177 @@ pub fn code_lens_resolve(workspace: &Workspace, lens: &CodeLens) -> Option<CodeL
         note.id, heading_id
     );
 
-    let references = note.intern_links_to_heading(heading_id);
-    debug!("code_lens_resolve: found {} references", references.len());
-
-    let mut locations: Vec<Location> = Vec::new();
-    for (src_note_id, src_link_id) in references.iter() {
-        let src_note = facts.note_facts(*src_note_id);
-        let src_indexed_text = src_note.indexed_text();
-        let src_strukt = src_note.structure();
+    let locations = heading_references(facts.db(), note.id, heading_id);
+    debug!("code_lens_resolve: found {} references", locations.len());
 
-        let src_link = src_strukt.intern_link_by_id(*src_link_id);
-        let lsp_range = match src_indexed_text.range_to_lsp_range(&src_link.span) {
-            Some(r) => r,
-            _ => continue,
+    let num_locs = locations.len();
+    let uri = Url::from_file_path(note.file().path).unwrap();
+    let lens_command = if show_references {
+        let arguments = if locations.is_empty() {
+            None
+        } else {
+            let data = ShowReferencesData {
+                uri,
+                position: heading_lsp_pos,
+                locations,
+            };
+            Some(vec![serde_json::to_value(data).unwrap()])
         };
+        Command {
+            title: format!("{} references", num_locs),
+            command: "zetaNote.showReferences".to_string(),
+            arguments,
+        }
+    } else {
+        Command {
+            title: format!("{} references", num_locs),
+            command: command::BACKLINKS.to_string(),
+            arguments: Some(vec![uri.to_string().into(), ref_data.heading_text.into()]),
+        }
+    };
 
-        let loc = Location {
-            uri: Url::from_file_path(src_note.file().path).unwrap(),
-            range: lsp_range,
-        };
+    Some(CodeLens {
+        command: Some(lens_command),
+        ..lens.clone()
+    })
+}
+
+//////////////////////////////////////////
+// Stats
+/////////////////////////////////////////
+
+/// Custom request for the statistics of a note, see [`NoteStats`].
+pub enum NoteStatsRequest {}
+
+impl Request for NoteStatsRequest {
+    type Params = TextDocumentIdentifier;
+    type Result = Option<NoteStats>;
+    const METHOD: &'static str = "zeta-note/noteStats";
+}
 
-        locations.push(loc)
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteStats {
+    /// Links from the note to other notes or its own headings.
+    pub outbound_links: usize,
+    /// Links pointing to the note, as in `zeta-note/backlinks`.
+    pub inbound_links: usize,
+    pub headings: usize,
+    /// Words outside of the frontmatter.
+    pub words: usize,
+}
+
+pub fn note_stats_request(
+    workspace: &Workspace,
+    params: TextDocumentIdentifier,
+) -> Option<NoteStats> {
+    let path = params.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note_id = facts.note_index().find_by_path(&path)?;
+    Some(note_stats(facts.db(), note_id))
+}
+
+/// Statistics of the note from the cached facts: links are counted by their
+/// structure, backlinks come from the workspace-wide index.
+pub fn note_stats(facts: &dyn Facts, note_id: NoteID) -> NoteStats {
+    let note = NoteFactsDB::new(facts, note_id);
+    let body_start = note
+        .frontmatter()
+        .map_or(0, |fm| fm.span.end.line as usize + 1);
+    let words = note
+        .text()
+        .content
+        .lines()
+        .skip(body_start)
+        .flat_map(str::split_whitespace)
+        // Markup like `#` or `-` isn't a word
+        .filter(|w| w.chars().any(char::is_alphanumeric))
+        .count();
+
+    NoteStats {
+        outbound_links: note.intern_link_ids().len(),
+        inbound_links: facts
+            .inbound_link_counts(())
+            .get(&note_id)
+            .copied()
+            .unwrap_or_default(),
+        headings: note.headings().len(),
+        words,
     }
+}
 
-    let num_locs = locations.len();
-    let arguments = if locations.is_empty() {
-        None
-    } else {
-        let data = ShowReferencesData {
-            uri: Url::from_file_path(note.file().path).unwrap(),
-            position: heading_lsp_pos,
-            locations,
-        };
-        Some(vec![serde_json::to_value(data).unwrap()])
+//////////////////////////////////////////
+// Link Preview
+/////////////////////////////////////////
+
+/// Custom request for the target of the link at a position, see [`ResolvedLink`].
+pub enum ResolveLinkRequest {}
+
+impl Request for ResolveLinkRequest {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<ResolvedLink>;
+    const METHOD: &'static str = "zeta-note/resolveLink";
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedLink {
+    /// URI of the linked note, if it exists.
+    pub uri: Option<Url>,
+    pub title: Option<String>,
+    /// Section of the linked heading, or the first paragraph of the note for
+    /// links without a heading.
+    pub content: Option<String>,
+    /// The linked note or heading doesn't exist.
+    pub broken: bool,
+}
+
+pub fn resolve_link_request(
+    workspace: &Workspace,
+    params: TextDocumentPositionParams,
+) -> Option<ResolvedLink> {
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note_id = facts.note_index().find_by_path(&path)?;
+    let pos = facts
+        .note_facts(note_id)
+        .indexed_text()
+        .lsp_pos_to_pos(&params.position)?;
+    resolve_link(facts.db(), note_id, pos)
+}
+
+/// The target of the link at `pos` from the cached facts, the same as
+/// [`link_hover`] shows. `None` if there's no link at `pos`.
+pub fn resolve_link(facts: &dyn Facts, note_id: NoteID, pos: Pos) -> Option<ResolvedLink> {
+    let note = NoteFactsDB::new(facts, note_id);
+    let strukt = note.structure();
+    let intern_link = match strukt.element_by_id(note.element_at_pos(pos)?) {
+        Element::InternLink(link) => link,
+        _ => return None,
+    };
+
+    let target_id = match facts::resolve_link_target(facts, note_id, intern_link) {
+        Some(id) => id,
+        _ => {
+            return Some(ResolvedLink {
+                broken: true,
+                ..ResolvedLink::default()
+            })
+        }
     };
-    let command = Command {
-        title: format!("{} references", num_locs),
-        command: "zetaNote.showReferences".to_string(),
-        arguments,
+    let target = NoteFactsDB::new(facts, target_id);
+    let content = match &intern_link.heading {
+        Some(heading) => target
+            .heading_for_link(heading)
+            .and_then(|heading_id| section_text(&target, heading_id)),
+        _ => target.first_paragraph(),
     };
 
-    Some(CodeLens {
-        command: Some(command),
-        ..lens.clone()
+    Some(ResolvedLink {
+        uri: Url::from_file_path(&target.file().path).ok(),
+        title: target.effective_title(),
+        broken: intern_link.heading.is_some() && content.is_none(),
+        content,
     })
 }
 
@@ -574,13 +1181,20 @@ pub fn code_lens_resolve(workspace: &Workspace, lens: &CodeLens) -> Option<CodeL
 // Document Links
 /////////////////////////////////////////
 
+/// Document links of the note. With `follow_links` (an experimental client
+/// capability) valid links run the client's `zetaNote.followLink` command
+/// instead of opening the target file.
 pub fn document_links(
     workspace: &Workspace,
     params: DocumentLinkParams,
+    follow_links: bool,
 ) -> Option<Vec<DocumentLink>> {
     let path = params.text_document.uri.to_file_path().unwrap();
     let (_, facts) = workspace.owning_folder(&path)?;
     let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    if !follow_links {
+        return Some(document_link::document_links(&note, facts.db()));
+    }
     let strukt = note.structure();
 
     let mut links = Vec::new();
@@ -635,3 +1249,587 @@ pub fn document_links(
 
     Some(links)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use lsp_types::NumberOrString;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn deleted_note_breaks_links_to_it() {
+        let root = std::env::temp_dir().join("zeta-note-handlers-on-disk");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.md"), "# A\n\n[:b]\n").unwrap();
+        std::fs::write(root.join("b.md"), "# B\n\n[:a]\n").unwrap();
+
+        let config = Config::default();
+        let folder = NoteFolder::from_root_path(&root);
+        let mut workspace = Workspace::new(&[folder], &config).await.unwrap();
+        let mut diag_col = DiagCollection::default();
+        diag(&workspace, &mut diag_col, &config);
+
+        std::fs::remove_file(root.join("b.md")).unwrap();
+        std::fs::write(root.join("c.md"), "# C\n").unwrap();
+        let paths = [root.join("b.md"), root.join("c.md")];
+        let changed = note_change_on_disk(&mut workspace, &paths, &config)
+            .await
+            .unwrap();
+        assert_eq!(changed, paths);
+        assert_eq!(workspace.note_count(), 2);
+
+        let published = diag_for_changes(&workspace, &mut diag_col, &changed, &config);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+        let codes = |name: &str| {
+            let params = published.iter().find(|p| p.uri == uri(name)).unwrap();
+            let mut codes = params
+                .diagnostics
+                .iter()
+                .filter_map(|d| match &d.code {
+                    Some(NumberOrString::String(code)) => Some(code.clone()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            codes.sort();
+            codes
+        };
+        assert_eq!(codes("a.md"), vec!["broken-note-link", "orphan-note"]);
+        assert_eq!(codes("b.md"), Vec::<String>::new());
+        assert_eq!(codes("c.md"), vec!["orphan-note"]);
+    }
+
+    #[tokio::test]
+    async fn config_change_applies_live() {
+        let root = std::env::temp_dir().join("zeta-note-handlers-config");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.md"), "## A\n").unwrap();
+        std::fs::write(root.join("b.txt"), "# B\n").unwrap();
+
+        let old = Config::default();
+        let folder = NoteFolder::from_root_path(&root);
+        let mut workspace = Workspace::new(&[folder], &old).await.unwrap();
+        let a_title = |workspace: &Workspace| {
+            let id = workspace
+                .facts
+                .note_index()
+                .find_by_path(&root.join("a.md"));
+            workspace.facts.db().note_title(id.unwrap())
+        };
+        assert_eq!(workspace.note_count(), 1);
+        assert!(a_title(&workspace).is_none());
+
+        let new = old.with_settings(&serde_json::json!({
+            "titleHeadingLevel": 2,
+            "extensions": ["md", "txt"]
+        }));
+        note_change_configuration(&mut workspace, &old, &new)
+            .await
+            .unwrap();
+        assert_eq!(workspace.note_count(), 2);
+        assert!(a_title(&workspace).is_some());
+    }
+
+    #[test]
+    fn republish_covers_unchanged_notes() {
+        let root = Path::new("/notes");
+        let workspace = Workspace {
+            folders: vec![(
+                NoteFolder::from_root_path(root),
+                store::NoteFilter::default(),
+            )],
+            facts: FactsDB::from_texts(
+                root,
+                &[("a.md", "# A\n\n[:b]\n"), ("b.md", "# B\n\n[:a]\n")],
+            ),
+        };
+        let config = Config::default();
+        let mut diag_col = DiagCollection::default();
+        diag(&workspace, &mut diag_col, &config);
+        assert!(diag(&workspace, &mut diag_col, &config).is_empty());
+
+        let mut uris = diag_republish(&workspace, &mut diag_col, &config)
+            .into_iter()
+            .map(|params| params.uri)
+            .collect::<Vec<_>>();
+        uris.sort();
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+        assert_eq!(uris, vec![uri("a.md"), uri("b.md")]);
+    }
+
+    #[test]
+    fn code_lenses_run_backlinks() {
+        let root = Path::new("/notes");
+        let workspace = Workspace {
+            folders: vec![(
+                NoteFolder::from_root_path(root),
+                store::NoteFilter::default(),
+            )],
+            facts: FactsDB::from_texts(
+                root,
+                &[
+                    ("a.md", "# A\n\n## Sub\n\n## Unused\n"),
+                    ("b.md", "# B\n\n[:a@## Sub]\n\n[:a@## Sub]\n"),
+                    ("c.md", "No headings\n"),
+                ],
+            ),
+        };
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+        let lenses = |name: &str| {
+            let params = CodeLensParams {
+                text_document: TextDocumentIdentifier { uri: uri(name) },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            };
+            code_lenses(&workspace, params).unwrap()
+        };
+
+        assert!(lenses("c.md").is_empty());
+        let a_lenses = lenses("a.md");
+        assert_eq!(a_lenses.len(), 1);
+        assert_eq!(a_lenses[0].range.start, Position::new(2, 0));
+
+        let lens = code_lens_resolve(&workspace, &a_lenses[0], false).unwrap();
+        let lens_command = lens.command.unwrap();
+        assert_eq!(lens_command.title, "2 references");
+        assert_eq!(lens_command.command, command::BACKLINKS);
+
+        let params = lsp_types::ExecuteCommandParams {
+            command: lens_command.command,
+            arguments: lens_command.arguments.unwrap(),
+            work_done_progress_params: Default::default(),
+        };
+        let locations = command::execute_command(&workspace, params, &Config::default()).unwrap();
+        let locations: Vec<Location> = serde_json::from_value(locations).unwrap();
+        assert_eq!(
+            locations
+                .iter()
+                .map(|loc| loc.range.start.line)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+    }
+
+    #[test]
+    fn code_lens_resolve_duplicate_headings() {
+        let root = Path::new("/notes");
+        let workspace = Workspace {
+            folders: vec![(
+                NoteFolder::from_root_path(root),
+                store::NoteFilter::default(),
+            )],
+            facts: FactsDB::from_texts(
+                root,
+                &[
+                    ("a.md", "# A\n\n## Sub\n\n## Sub\n"),
+                    ("b.md", "# B\n\n[:a@## Sub]\n"),
+                ],
+            ),
+        };
+        let a_path = root.join("a.md");
+        let a_id = workspace.facts.note_index().find_by_path(&a_path).unwrap();
+        let subs = workspace
+            .facts
+            .note_facts(a_id)
+            .headings_matching(|h| h.text == "## Sub");
+        let lens = |heading_id: HeadingID, heading_text: &str| CodeLens {
+            range: lsp_types::Range::default(),
+            command: None,
+            data: Some(
+                serde_json::to_value(ReferenceData {
+                    note_path: a_path.clone(),
+                    heading_id,
+                    heading_text: heading_text.to_string(),
+                })
+                .unwrap(),
+            ),
+        };
+        let title = |lens: Option<CodeLens>| lens.unwrap().command.unwrap().title;
+
+        assert_eq!(
+            title(code_lens_resolve(
+                &workspace,
+                &lens(subs[0], "## Sub"),
+                false
+            )),
+            "1 references"
+        );
+        assert_eq!(
+            title(code_lens_resolve(
+                &workspace,
+                &lens(subs[1], "## Sub"),
+                false
+            )),
+            "0 references"
+        );
+        // Stale lens data
+        assert!(code_lens_resolve(&workspace, &lens(subs[1], "## Other"), false).is_none());
+    }
+
+    #[test]
+    fn heading_references_include_all_linking_notes() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n## Sub\n\n[:@## Sub]\n"),
+                ("b.md", "# B\n\n[:a@## Sub] [:a] [:a@## Other]\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+
+        let a = facts.note_facts(a_id);
+        let sub = a.heading_with_text("## Sub").unwrap();
+        let mut refs = heading_references(facts.db(), a_id, sub)
+            .into_iter()
+            .map(|loc| (loc.uri.path().to_string(), loc.range.start))
+            .collect::<Vec<_>>();
+        refs.sort_by_key(|(path, _)| path.clone());
+        assert_eq!(
+            refs,
+            vec![
+                ("/notes/a.md".to_string(), Position::new(4, 0)),
+                ("/notes/b.md".to_string(), Position::new(2, 0)),
+            ]
+        );
+
+        let title_refs = heading_references(facts.db(), a_id, a.title().unwrap());
+        assert_eq!(title_refs.len(), 1);
+        assert_eq!(title_refs[0].range.start, Position::new(2, 12));
+    }
+
+    #[test]
+    fn heading_references_of_duplicate_headings() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n## Sub\n\n## Sub\n"),
+                ("b.md", "# B\n\n[:a@## Sub]\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let subs = facts
+            .note_facts(a_id)
+            .headings_matching(|h| h.text == "## Sub");
+
+        // Links go to the first of the headings
+        assert_eq!(heading_references(facts.db(), a_id, subs[0]).len(), 1);
+        assert_eq!(heading_references(facts.db(), a_id, subs[1]), vec![]);
+    }
+
+    fn hover_text(facts: &FactsDB, note_id: NoteID, pos: Pos) -> Option<String> {
+        match link_hover(facts.db(), note_id, pos)?.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            other => panic!("Unexpected hover contents: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn hover_shows_title_and_first_paragraph() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:b] [:c] [:b@## Sub] [:d]\n"),
+                (
+                    "b.md",
+                    "# B\n\nFirst line\nsecond line\n\nMore\n\n## Sub\nSub text\n",
+                ),
+                ("c.md", "---\ntitle: C\n---\n\nText of C\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+
+        assert_eq!(
+            hover_text(&facts, a_id, Pos::new(2, 1)),
+            Some("# B\n\nFirst line\nsecond line".to_string())
+        );
+        assert_eq!(
+            hover_text(&facts, a_id, Pos::new(2, 6)),
+            Some("# C\n\nText of C".to_string())
+        );
+        assert_eq!(
+            hover_text(&facts, a_id, Pos::new(2, 12)),
+            Some("## Sub\nSub text\n".to_string())
+        );
+        assert_eq!(
+            hover_text(&facts, a_id, Pos::new(2, 23)),
+            Some("Note not found".to_string())
+        );
+        assert_eq!(hover_text(&facts, a_id, Pos::new(0, 1)), None);
+    }
+
+    #[test]
+    fn resolve_link_with_section() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:b@## Sub] [:b] [:c] [:b@## Nope]\n"),
+                ("b.md", "# B\n\nFirst line\n\n## Sub\nSub text\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let resolve = |col| resolve_link(facts.db(), a_id, Pos::new(2, col));
+        let b = |content: Option<&str>, broken| ResolvedLink {
+            uri: Some(Url::from_file_path(root.join("b.md")).unwrap()),
+            title: Some("B".to_string()),
+            content: content.map(str::to_string),
+            broken,
+        };
+
+        assert_eq!(resolve(1), Some(b(Some("## Sub\nSub text\n"), false)));
+        assert_eq!(resolve(13), Some(b(Some("First line"), false)));
+        assert_eq!(
+            resolve(18),
+            Some(ResolvedLink {
+                broken: true,
+                ..ResolvedLink::default()
+            })
+        );
+        assert_eq!(resolve(23), Some(b(None, true)));
+        assert_eq!(resolve_link(facts.db(), a_id, Pos::new(0, 1)), None);
+    }
+
+    #[test]
+    fn hover_shows_heading_backlinks() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:@## Sub]\n\n## Sub\n\n## Lonely\n"),
+                ("b.md", "# B\n\n[:a@## Sub] [[a#Sub]] [:a]\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let heading_text = |pos| match heading_hover(facts.db(), a_id, pos)?.contents {
+            HoverContents::Markup(markup) => Some(markup.value),
+            other => panic!("Unexpected hover contents: {:?}", other),
+        };
+
+        assert_eq!(
+            heading_text(Pos::new(4, 3)),
+            Some("3 backlinks from:\n\n- `a`\n- `b`".to_string())
+        );
+        assert_eq!(
+            heading_text(Pos::new(0, 1)),
+            Some("1 backlink from:\n\n- `b`".to_string())
+        );
+        assert_eq!(
+            heading_text(Pos::new(6, 3)),
+            Some("No backlinks".to_string())
+        );
+        // Links have their own hover
+        assert_eq!(heading_text(Pos::new(2, 2)), None);
+    }
+
+    #[test]
+    fn document_symbols_nest_by_level() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[(
+                "a.md",
+                "# A\n\n### Deep\n\n## B\n\n### C\n\n## D\n\n# Another\n",
+            )],
+        );
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+
+        fn names(symbols: &[DocumentSymbol]) -> Vec<String> {
+            symbols
+                .iter()
+                .map(|s| match &s.children {
+                    Some(children) => format!("{}{:?}", s.name, names(children)),
+                    _ => s.name.clone(),
+                })
+                .collect()
+        }
+
+        let symbols = document_symbols(&note);
+        assert_eq!(
+            names(&symbols),
+            vec![
+                r#"A["Deep", "B[\"C\"]", "D"]"#.to_string(),
+                "Another".to_string()
+            ]
+        );
+        assert_eq!(symbols[0].kind, lsp_types::SymbolKind::NAMESPACE);
+        assert_eq!(
+            symbols[0].children.as_ref().unwrap()[0].kind,
+            lsp_types::SymbolKind::STRING
+        );
+    }
+
+    #[test]
+    fn workspace_symbols_match_substring() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n## System Architecture\n"),
+                ("b.md", "# B\n\n## Architecture\n\n## Arch notes\n"),
+            ],
+        );
+
+        let mut found = workspace_symbols(facts.db(), "ARCHITECTURE")
+            .into_iter()
+            .map(|s| (s.container_name.unwrap(), s.name))
+            .collect::<Vec<_>>();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                ("a".to_string(), "System Architecture".to_string()),
+                ("b".to_string(), "Architecture".to_string())
+            ]
+        );
+        assert_eq!(workspace_symbols(facts.db(), "").len(), 5);
+    }
+
+    #[test]
+    fn goto_def_lands_on_heading_or_title() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:b@## Sub] [:b] [:@## Missing]\n"),
+                ("b.md", "# B\n\nText\n\n## Sub\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let b_uri = Url::from_file_path(root.join("b.md")).unwrap();
+
+        let to_heading = goto_def(facts.db(), a_id, Pos::new(2, 4)).unwrap();
+        assert_eq!(to_heading.uri, b_uri);
+        assert_eq!(to_heading.range.start, Position::new(4, 0));
+
+        let to_title = goto_def(facts.db(), a_id, Pos::new(2, 13)).unwrap();
+        assert_eq!(to_title.uri, b_uri);
+        assert_eq!(to_title.range.start, Position::new(0, 0));
+
+        assert_eq!(goto_def(facts.db(), a_id, Pos::new(2, 20)), None);
+        assert_eq!(goto_def(facts.db(), a_id, Pos::new(0, 1)), None);
+    }
+
+    #[test]
+    fn goto_def_lands_on_lines() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[x](b#L3-L4) [y](b#L3) [z](b#L9)\n"),
+                ("b.md", "# B\n\nFirst line\nSecond\n"),
+            ],
+        );
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+
+        let to_lines = goto_def(facts.db(), a_id, Pos::new(2, 1)).unwrap();
+        assert_eq!(
+            to_lines.range,
+            lsp_types::Range::new(Position::new(2, 0), Position::new(3, 6))
+        );
+        let to_line = goto_def(facts.db(), a_id, Pos::new(2, 16)).unwrap();
+        assert_eq!(
+            to_line.range,
+            lsp_types::Range::new(Position::new(2, 0), Position::new(2, 10))
+        );
+        assert_eq!(goto_def(facts.db(), a_id, Pos::new(2, 27)), None);
+    }
+
+    #[test]
+    fn semantic_tokens_of_headings_and_links() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# Title [:b]\n\n## Sub\n\n[:b@## Sub] [:missing] [x](b#sub)\n",
+                ),
+                ("b.md", "# B\n\n## Sub\n"),
+            ],
+        );
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+
+        let tokens = semantic_tokens(&note)
+            .data
+            .into_iter()
+            .map(|t| {
+                (
+                    t.delta_line,
+                    t.delta_start,
+                    t.length,
+                    t.token_type,
+                    t.token_modifiers_bitset,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                // The link splits the title
+                (0, 2, 8, 0, 0),
+                (0, 8, 1, 1, 0),
+                (0, 1, 1, 0, 0),
+                (2, 3, 3, 0, 0),
+                (2, 2, 1, 1, 0),
+                (0, 2, 6, 2, 0),
+                (0, 10, 7, 1, 1),
+                (0, 13, 1, 1, 0),
+                (0, 2, 3, 2, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_of_notes() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "---\ntitle: A\n---\n# A\n\nSee [:b] and [:@## Sub].\n\n## Sub\n\n- one, two\n",
+                ),
+                ("b.md", "# B\n\n[[a#Sub]] [:missing]\n"),
+                ("c.md", "# C\n"),
+            ],
+        );
+        let stats = |name: &str| {
+            let note_id = facts.note_index().find_by_path(&root.join(name)).unwrap();
+            note_stats(facts.db(), note_id)
+        };
+
+        assert_eq!(
+            stats("a.md"),
+            NoteStats {
+                outbound_links: 2,
+                inbound_links: 2,
+                headings: 2,
+                words: 8,
+            }
+        );
+        assert_eq!(
+            stats("b.md"),
+            NoteStats {
+                outbound_links: 2,
+                inbound_links: 1,
+                headings: 1,
+                words: 3,
+            }
+        );
+        assert_eq!(
+            stats("c.md"),
+            NoteStats {
+                headings: 1,
+                words: 1,
+                ..NoteStats::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,1029 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use lsp_document::{Pos, TextMap};
+
+use lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CreateFile,
+    CreateFileOptions, Diagnostic, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, ResourceOp, TextDocumentEdit, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+use tracing::debug;
+
+use crate::{
+    config::Config,
+    diag::Diag,
+    facts::{self, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
+    lsp::handlers::rename,
+    parser::{self, InternLink, Node, NoteName},
+    store::{NoteFile, NoteFolder, Workspace},
+    structure::NoteID,
+    util,
+};
+
+pub fn code_actions(
+    workspace: &Workspace,
+    params: CodeActionParams,
+    config: &Config,
+) -> Option<Vec<CodeActionOrCommand>> {
+    let path = params.text_document.uri.to_file_path().unwrap();
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let range = note.indexed_text().lsp_range_to_range(&params.range)?;
+    let file = note.file();
+    let roots = workspace.roots();
+
+    let mut actions = Vec::new();
+    for (diag, loc) in note.diag().iter() {
+        let overlaps = loc.start <= range.end && range.start <= loc.end;
+        if overlaps {
+            let mut diag_actions = code_actions_for_diag(&file, diag, loc, facts, &roots, config);
+            actions.append(&mut diag_actions);
+        }
+    }
+    actions.extend(to_reference_links(&note));
+    actions.extend(to_inline_links(&note));
+    actions.extend(rename_to_title(facts, &note, &range));
+    debug!("code_actions: found {} actions", actions.len());
+
+    Some(
+        actions
+            .into_iter()
+            .map(CodeActionOrCommand::CodeAction)
+            .collect(),
+    )
+}
+
+/// Quick fixes for the diagnostic `diag` reported at `loc` inside the note `file`.
+///
+/// A missing note can be created in any of the workspace `folders`, the folder
+/// of `file` is the preferred one.
+pub fn code_actions_for_diag(
+    file: &NoteFile,
+    diag: &Diag,
+    loc: &Range<Pos>,
+    facts: &FactsDB,
+    folders: &[&NoteFolder],
+    config: &Config,
+) -> Vec<CodeAction> {
+    let mut actions = Vec::new();
+    // Links by path name the note file relative to the linking note
+    let path_link = intern_link_at(facts, file, loc).filter(|link| link.is_path());
+
+    if let Diag::BrokenInternLinkToNote {
+        suggestion: Some(suggestion),
+        ..
+    } = diag
+    {
+        let edit = intern_link_at(facts, file, loc).and_then(|link| {
+            replace_edit(
+                facts,
+                file,
+                &link.note_name_span()?,
+                &link.dest_part(suggestion.to_str()),
+            )
+        });
+        if let Some(edit) = edit {
+            let mut action = quick_fix(
+                format!("Change to `{}`", suggestion),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            );
+            action.is_preferred = Some(true);
+            actions.push(action);
+        }
+    }
+
+    if let Diag::BrokenInternLinkToNote {
+        linked_note,
+        suggestion,
+        ..
+    } = diag
+    {
+        let (path, title) = match &path_link {
+            Some(_) => {
+                let path = note_path(file.path.parent().unwrap_or(&file.root), linked_note);
+                let title = path.file_stem().map(|s| s.to_string_lossy().to_string());
+                (path, title.unwrap_or_else(|| linked_note.to_string()))
+            }
+            None => (linked_note.to_path(&file.root), linked_note.to_string()),
+        };
+        let content = config.new_note_text(&title);
+        if let Some(edit) = create_note_edit(&path, &content) {
+            let mut action = quick_fix(
+                format!("Create note `{}`", linked_note),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            );
+            // A typo is more likely than a missing note
+            if suggestion.is_none() {
+                action.is_preferred = Some(true);
+            }
+            actions.push(action);
+        }
+
+        // Notes linked by name can live in any folder
+        let other_folders = folders.iter().filter(|f| *f.root != *file.root);
+        for folder in other_folders.filter(|_| path_link.is_none()) {
+            let path = linked_note.to_path(&folder.root);
+            if let Some(edit) = create_note_edit(&path, &content) {
+                actions.push(quick_fix(
+                    format!("Create note `{}` in `{}`", linked_note, folder.name),
+                    edit,
+                    lsp_diag(file, diag, loc, facts),
+                ));
+            }
+        }
+    }
+
+    if let Diag::StrayLinkPunctuation { intended, .. } = diag {
+        let edit = intern_link_at(facts, file, loc).and_then(|link| {
+            replace_edit(
+                facts,
+                file,
+                &link.note_name_span()?,
+                &link.dest_part(intended.to_str()),
+            )
+        });
+        if let Some(edit) = edit {
+            let mut action = quick_fix(
+                format!("Change to `{}`", intended),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            );
+            action.is_preferred = Some(true);
+            actions.push(action);
+        }
+    }
+
+    if let Diag::EmptyLinkLabel { linked_note } = diag {
+        let index = facts.note_index();
+        let title = index.find_by_path(&file.path).and_then(|note_id| {
+            // The diagnostic covers the label, which starts the link
+            let note = facts.note_facts(note_id);
+            let strukt = note.structure();
+            let link = strukt
+                .intern_links_with_ids(&note.intern_link_ids())
+                .into_iter()
+                .find(|link| link.span.start == loc.start)?;
+            let target_id = facts::resolve_link_target(facts.db(), note_id, link)?;
+            facts.note_facts(target_id).effective_title()
+        });
+        let title = title.unwrap_or_else(|| linked_note.to_string());
+        if let Some(edit) = replace_edit(facts, file, loc, &format!("[{}]", title)) {
+            let mut action = quick_fix(
+                format!("Fill in the label with `{}`", title),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            );
+            action.is_preferred = Some(true);
+            actions.push(action);
+        }
+    }
+
+    if let Diag::BrokenInternLinkToHeading {
+        suggestion: Some(suggestion),
+        ..
+    } = diag
+    {
+        let edit = intern_link_at(facts, file, loc).and_then(|link| {
+            replace_edit(
+                facts,
+                file,
+                &link.heading_span()?,
+                &link.dest_part(suggestion),
+            )
+        });
+        if let Some(edit) = edit {
+            let mut action = quick_fix(
+                format!("Change to `{}`", suggestion),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            );
+            action.is_preferred = Some(true);
+            actions.push(action);
+        }
+    }
+
+    if let Diag::BrokenInternLinkToHeading {
+        linked_note,
+        heading,
+        ..
+    } = diag
+    {
+        let index = facts.note_index();
+        let target_id = match &path_link {
+            Some(link) => index
+                .find_by_path(&file.path)
+                .and_then(|id| facts::resolve_link_target(facts.db(), id, link)),
+            None => index.find_by_name(linked_note),
+        };
+        if let Some(edit) = target_id.and_then(|id| append_heading_edit(facts, id, heading)) {
+            actions.push(quick_fix(
+                format!("Add heading `{}` to `{}`", heading, linked_note),
+                edit,
+                lsp_diag(file, diag, loc, facts),
+            ));
+        }
+    }
+
+    actions
+}
+
+/// Rewrite inline links to notes `[label](note)` into reference-style links
+/// `[label][n]` and append the definitions to the end of the note.
+///
+/// Links with the same destination share a definition, existing definitions
+/// are reused as well.
+fn to_reference_links(note: &NoteFactsDB) -> Option<CodeAction> {
+    let indexed_text = note.indexed_text();
+    let strukt = note.structure();
+    let defs = parser::scrape_link_defs(&*indexed_text);
+
+    let mut labels: HashSet<String> = defs
+        .iter()
+        .map(|def| parser::link_label_key(&def.label))
+        .collect();
+    let mut refs: HashMap<String, String> = HashMap::new();
+    for def in &defs {
+        refs.entry(def.dest.clone())
+            .or_insert_with(|| def.label.clone());
+    }
+
+    let mut next_ref = 1;
+    let mut new_defs = String::new();
+    let mut edits = Vec::new();
+    for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+        let (label, dest) = match link.inline_parts() {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let reference = match refs.get(dest) {
+            Some(reference) => reference.clone(),
+            None => {
+                while labels.contains(&next_ref.to_string()) {
+                    next_ref += 1;
+                }
+                let reference = next_ref.to_string();
+                labels.insert(reference.clone());
+                refs.insert(dest.to_string(), reference.clone());
+                new_defs.push_str(&format!("[{}]: {}\n", reference, dest));
+                reference
+            }
+        };
+        edits.push(TextEdit {
+            range: indexed_text.range_to_lsp_range(&link.span)?,
+            new_text: format!("{}[{}]", label, reference),
+        });
+    }
+    if edits.is_empty() {
+        return None;
+    }
+
+    if !new_defs.is_empty() {
+        let content = indexed_text.text();
+        let mut separator = if content.ends_with('\n') { "" } else { "\n" }.to_string();
+        // Keep new definitions in the same block as the ones at the end of the note
+        let last_line = content.trim_end().lines().count().saturating_sub(1) as u32;
+        let after_defs = defs.last().map(|def| def.span.start.line) == Some(last_line);
+        if !after_defs && !content.ends_with("\n\n") {
+            separator.push('\n');
+        }
+        let end = indexed_text.pos_to_lsp_pos(&end_pos(&*indexed_text)?)?;
+        edits.push(TextEdit {
+            range: lsp_types::Range::new(end, end),
+            new_text: separator + &new_defs,
+        });
+    }
+
+    refactor("Convert inline links to reference style", note, edits)
+}
+
+/// Rewrite reference-style links to notes into inline links `[label](note)` and
+/// remove the definitions they used.
+fn to_inline_links(note: &NoteFactsDB) -> Option<CodeAction> {
+    let indexed_text = note.indexed_text();
+    let strukt = note.structure();
+    let defs = parser::scrape_link_defs(&*indexed_text);
+    // The first definition of a label wins
+    let defs_by_label: HashMap<String, _> = defs
+        .iter()
+        .rev()
+        .map(|def| (parser::link_label_key(&def.label), def))
+        .collect();
+
+    let mut used_lines = HashSet::new();
+    let mut edits = Vec::new();
+    for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+        let (label, reference) = match link.reference_parts() {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let def = match defs_by_label.get(&parser::link_label_key(reference)) {
+            Some(def) => def,
+            None => continue,
+        };
+        used_lines.insert(def.span.start.line);
+        edits.push(TextEdit {
+            range: indexed_text.range_to_lsp_range(&link.span)?,
+            new_text: format!("{}({})", label, def.dest),
+        });
+    }
+    if edits.is_empty() {
+        return None;
+    }
+
+    let mut used_lines = used_lines.into_iter().collect::<Vec<_>>();
+    used_lines.sort_unstable();
+    for line in used_lines {
+        edits.push(TextEdit {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(line, 0),
+                lsp_types::Position::new(line + 1, 0),
+            ),
+            new_text: String::new(),
+        });
+    }
+
+    refactor("Convert reference-style links to inline", note, edits)
+}
+
+/// Rename the note file to the slug of its title, e.g. `my-note.md` for
+/// `# My Note`, and rewrite links to the note. Offered on the title heading
+/// unless a note with this name already exists.
+fn rename_to_title(facts: &FactsDB, note: &NoteFactsDB, range: &Range<Pos>) -> Option<CodeAction> {
+    let strukt = note.structure();
+    let title = strukt.heading_by_id(note.title()?);
+    if !(title.span.start <= range.end && range.start <= title.span.end) {
+        return None;
+    }
+
+    let file = note.file();
+    let slug = title.slug();
+    // The note stays in its subfolder
+    let new_name: NoteName = match file.name.to_str().rsplit_once('/') {
+        Some((dir, _)) => format!("{}/{}", dir, slug),
+        None => slug.clone(),
+    }
+    .into();
+    if slug.is_empty() || new_name == *file.name {
+        return None;
+    }
+    let new_stem = file.root.join(new_name.to_str());
+    if facts.note_index().find_by_path_stem(&new_stem).is_some()
+        || util::exists_with_any_extension(&new_name.to_path(&file.root))
+    {
+        return None;
+    }
+
+    let edit = rename::rename_note(facts.db(), &file.name, &new_name)?;
+    Some(CodeAction {
+        title: "Rename file to match title".to_string(),
+        kind: Some(CodeActionKind::REFACTOR),
+        edit: Some(edit),
+        ..CodeAction::default()
+    })
+}
+
+fn refactor(title: &str, note: &NoteFactsDB, edits: Vec<TextEdit>) -> Option<CodeAction> {
+    let edit = rename::text_document_edit(note, edits)?;
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![edit])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+fn quick_fix(title: String, edit: WorkspaceEdit, diag: Option<Diagnostic>) -> CodeAction {
+    CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: diag.map(|d| vec![d]),
+        edit: Some(edit),
+        ..CodeAction::default()
+    }
+}
+
+fn lsp_diag(file: &NoteFile, diag: &Diag, loc: &Range<Pos>, facts: &FactsDB) -> Option<Diagnostic> {
+    let note = facts.note_facts(facts.note_index().find_by_path(&file.path)?);
+    let range = note.indexed_text().range_to_lsp_range(loc)?;
+    Some(Diagnostic {
+        range,
+        message: diag.to_message(),
+        ..Diagnostic::default()
+    })
+}
+
+/// The link found in `file` at `loc`.
+fn intern_link_at(facts: &FactsDB, file: &NoteFile, loc: &Range<Pos>) -> Option<Node<InternLink>> {
+    let note = facts.note_facts(facts.note_index().find_by_path(&file.path)?);
+    let strukt = note.structure();
+    let link = strukt
+        .intern_links_with_ids(&note.intern_link_ids())
+        .into_iter()
+        .find(|link| link.span == *loc)?
+        .clone();
+    Some(link)
+}
+
+/// Replace the text at `span` in `file` with `new_text`, e.g. a part of a link.
+fn replace_edit(
+    facts: &FactsDB,
+    file: &NoteFile,
+    span: &Range<Pos>,
+    new_text: &str,
+) -> Option<WorkspaceEdit> {
+    let note = facts.note_facts(facts.note_index().find_by_path(&file.path)?);
+    let range = note.indexed_text().range_to_lsp_range(span)?;
+    let edit = rename::text_document_edit(
+        &note,
+        vec![TextEdit {
+            range,
+            new_text: new_text.to_string(),
+        }],
+    )?;
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(vec![edit])),
+        ..WorkspaceEdit::default()
+    })
+}
+
+/// Path of the note file at `target` relative to `dir`, `.md` is added if
+/// `target` has no extension.
+fn note_path(dir: &Path, target: &NoteName) -> PathBuf {
+    let path = util::normalize_path(&dir.join(target.to_str()));
+    if path.extension().is_some() {
+        path
+    } else {
+        path.with_extension("md")
+    }
+}
+
+fn create_note_edit(path: &Path, content: &str) -> Option<WorkspaceEdit> {
+    if util::exists_with_any_extension(path) {
+        return None;
+    }
+
+    let uri = Url::from_file_path(path).ok()?;
+    let create = DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+        uri: uri.clone(),
+        options: Some(CreateFileOptions {
+            overwrite: Some(false),
+            ignore_if_exists: Some(true),
+        }),
+        annotation_id: None,
+    }));
+    let content = DocumentChangeOperation::Edit(TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+        edits: vec![OneOf::Left(TextEdit {
+            range: lsp_types::Range::default(),
+            new_text: content.to_string(),
+        })],
+    });
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(vec![create, content])),
+        ..WorkspaceEdit::default()
+    })
+}
+
+fn append_heading_edit(facts: &FactsDB, note_id: NoteID, heading: &str) -> Option<WorkspaceEdit> {
+    let note = facts.note_facts(note_id);
+    let indexed_text = note.indexed_text();
+    let end = indexed_text.pos_to_lsp_pos(&end_pos(&*indexed_text)?)?;
+
+    // Links reference headings together with their markers, e.g. `## Heading`
+    let new_heading = if heading.starts_with('#') {
+        heading.to_string()
+    } else {
+        format!("## {}", heading)
+    };
+
+    let edit = TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier {
+            uri: Url::from_file_path(&note.file().path).ok()?,
+            version: note.text().version.to_lsp_version(),
+        },
+        edits: vec![OneOf::Left(TextEdit {
+            range: lsp_types::Range::new(end, end),
+            new_text: format!("\n{}\n", new_heading),
+        })],
+    };
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(vec![edit])),
+        ..WorkspaceEdit::default()
+    })
+}
+
+/// Position after the last character of the text (after the final newline if any).
+pub(crate) fn end_pos(text: &impl TextMap) -> Option<Pos> {
+    let content = text.text();
+    if content.ends_with('\n') {
+        Some(Pos::new(content.lines().count() as u32, 0))
+    } else {
+        text.offset_to_pos(content.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn diag_in_note(
+        facts: &FactsDB,
+        name: &str,
+        pred: impl Fn(&Diag) -> bool,
+    ) -> (NoteFile, Diag, Range<Pos>) {
+        let index = facts.note_index();
+        let file = index.files().find(|f| f.name.to_str() == name).unwrap();
+        let note = facts.note_facts(index.find_by_path(&file.path).unwrap());
+        let (diag, loc) = note.diag().iter().find(|(d, _)| pred(d)).cloned().unwrap();
+        (file.clone(), diag, loc)
+    }
+
+    fn broken_note_link(facts: &FactsDB, name: &str) -> (NoteFile, Diag, Range<Pos>) {
+        diag_in_note(facts, name, |d| {
+            matches!(d, Diag::BrokenInternLinkToNote { .. })
+        })
+    }
+
+    fn broken_heading_link(facts: &FactsDB, name: &str) -> (NoteFile, Diag, Range<Pos>) {
+        diag_in_note(facts, name, |d| {
+            matches!(d, Diag::BrokenInternLinkToHeading { .. })
+        })
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("zeta-note-{}", name));
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    #[test]
+    fn create_missing_note() {
+        let root = temp_root("create-missing-note");
+        let facts = FactsDB::from_texts(&root, &[("a.md", "# A\n\n[:b]\n")]);
+        let (file, diag, loc) = broken_note_link(&facts, "a");
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        assert_eq!(actions.len(), 1);
+
+        let uri = Url::from_file_path(root.join("b.md")).unwrap();
+        let ops = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Operations(ops)) => ops,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert!(
+            matches!(&ops[0], DocumentChangeOperation::Op(ResourceOp::Create(c)) if c.uri == uri)
+        );
+        match &ops[1] {
+            DocumentChangeOperation::Edit(edit) => {
+                assert_eq!(edit.text_document.uri, uri);
+                assert_eq!(
+                    edit.edits,
+                    vec![OneOf::Left(TextEdit {
+                        range: lsp_types::Range::default(),
+                        new_text: "# b\n".to_string()
+                    })]
+                );
+            }
+            other => panic!("Unexpected operation: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn create_missing_note_from_template() {
+        let root = temp_root("create-from-template");
+        let facts = FactsDB::from_texts(&root, &[("a.md", "# A\n\n[:b]\n")]);
+        let (file, diag, loc) = broken_note_link(&facts, "a");
+        let config = Config {
+            new_note_template: "---\ncreated: {date}\n---\n\n# {title}\n\n".to_string(),
+            ..Config::default()
+        };
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &config);
+        let ops = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Operations(ops)) => ops,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        let date = chrono::Local::now().format("%Y-%m-%d");
+        match &ops[1] {
+            DocumentChangeOperation::Edit(edit) => assert_eq!(
+                edit.edits,
+                vec![OneOf::Left(TextEdit {
+                    range: lsp_types::Range::default(),
+                    new_text: format!("---\ncreated: {}\n---\n\n# b\n\n", date)
+                })]
+            ),
+            other => panic!("Unexpected operation: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn create_missing_note_by_path() {
+        let root = temp_root("create-missing-note-by-path");
+        let other = NoteFolder::from_root_path(&temp_root("create-by-path-home"));
+        let facts = FactsDB::from_texts(&root, &[("sub/a.md", "# A\n\n[B](../b)\n")]);
+        let (file, diag, loc) = broken_note_link(&facts, "sub/a");
+
+        let actions =
+            code_actions_for_diag(&file, &diag, &loc, &facts, &[&other], &Config::default());
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Create note `../b`");
+
+        let uri = Url::from_file_path(root.join("b.md")).unwrap();
+        let ops = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Operations(ops)) => ops,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert!(
+            matches!(&ops[0], DocumentChangeOperation::Op(ResourceOp::Create(c)) if c.uri == uri)
+        );
+    }
+
+    #[test]
+    fn create_missing_note_in_other_folders() {
+        let root = temp_root("create-in-folders-work");
+        let other = NoteFolder::from_root_path(&temp_root("create-in-folders-home"));
+        let facts = FactsDB::from_texts(&root, &[("a.md", "# A\n\n[:b]\n")]);
+        let (file, diag, loc) = broken_note_link(&facts, "a");
+
+        let own = NoteFolder::from_root_path(&root);
+        let actions = code_actions_for_diag(
+            &file,
+            &diag,
+            &loc,
+            &facts,
+            &[&own, &other],
+            &Config::default(),
+        );
+        let titles = actions
+            .iter()
+            .map(|a| (a.title.as_str(), a.is_preferred))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                ("Create note `b`", Some(true)),
+                (
+                    "Create note `b` in `zeta-note-create-in-folders-home`",
+                    None
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_create_when_other_extension_exists() {
+        let root = temp_root("other-extension-exists");
+        fs::write(root.join("b.txt"), "b").unwrap();
+        let facts = FactsDB::from_texts(&root, &[("a.md", "# A\n\n[:b]\n")]);
+        let (file, diag, loc) = broken_note_link(&facts, "a");
+
+        assert_eq!(
+            code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default()),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn append_missing_heading() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[:b@## Missing]\n"),
+                ("b.md", "# B\n\nText\n"),
+            ],
+        );
+        let (file, diag, loc) = broken_heading_link(&facts, "a");
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        assert_eq!(actions.len(), 1);
+
+        let edits = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(
+            edits,
+            vec![TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: Url::from_file_path(root.join("b.md")).unwrap(),
+                    version: Some(0),
+                },
+                edits: vec![OneOf::Left(TextEdit {
+                    range: lsp_types::Range::new(
+                        lsp_types::Position::new(3, 0),
+                        lsp_types::Position::new(3, 0)
+                    ),
+                    new_text: "\n## Missing\n".to_string()
+                })]
+            }]
+        );
+    }
+
+    #[test]
+    fn fix_heading_case() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("a.md", "# A\n\n[x](b#Introduction)\n"),
+                ("b.md", "# B\n\n## introduction\n"),
+            ],
+        );
+        let (file, diag, loc) = broken_heading_link(&facts, "a");
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        let titles = actions
+            .iter()
+            .map(|a| (a.title.as_str(), a.is_preferred))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                ("Change to `introduction`", Some(true)),
+                ("Add heading `Introduction` to `b`", None),
+            ]
+        );
+
+        let edits = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(
+            edits[0].text_document.uri,
+            Url::from_file_path(root.join("a.md")).unwrap()
+        );
+        assert_eq!(
+            edits[0].edits,
+            vec![OneOf::Left(TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(2, 6),
+                    lsp_types::Position::new(2, 18)
+                ),
+                new_text: "introduction".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn fix_note_name_typo() {
+        let root = temp_root("fix-note-name-typo");
+        let facts = FactsDB::from_texts(
+            &root,
+            &[
+                ("a.md", "# A\n\n[x](projcet)\n"),
+                ("project.md", "# Project\n"),
+            ],
+        );
+        let (file, diag, loc) = broken_note_link(&facts, "a");
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        let titles = actions
+            .iter()
+            .map(|a| (a.title.as_str(), a.is_preferred))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                ("Change to `project`", Some(true)),
+                ("Create note `projcet`", None),
+            ]
+        );
+
+        let edits = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(
+            edits[0].edits,
+            vec![OneOf::Left(TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(2, 4),
+                    lsp_types::Position::new(2, 11)
+                ),
+                new_text: "project".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn remove_stray_link_punctuation() {
+        let root = temp_root("remove-stray-link-punctuation");
+        let facts = FactsDB::from_texts(
+            &root,
+            &[("a.md", "# A\n\nSee [x](b.)\n"), ("b.md", "# B\n")],
+        );
+        let (file, diag, loc) = diag_in_note(&facts, "a", |d| {
+            matches!(d, Diag::StrayLinkPunctuation { .. })
+        });
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Change to `b`");
+        assert_eq!(actions[0].is_preferred, Some(true));
+
+        let edits = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(
+            edits[0].edits,
+            vec![OneOf::Left(TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(2, 8),
+                    lsp_types::Position::new(2, 10)
+                ),
+                new_text: "b".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn fill_empty_link_label() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("a.md", "# A\n\nSee [](existing-note)\n"),
+                ("existing-note.md", "# Existing Note\n"),
+            ],
+        );
+        let (file, diag, loc) =
+            diag_in_note(&facts, "a", |d| matches!(d, Diag::EmptyLinkLabel { .. }));
+
+        let actions = code_actions_for_diag(&file, &diag, &loc, &facts, &[], &Config::default());
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].title, "Fill in the label with `Existing Note`");
+
+        let edits = match actions[0].edit.clone().unwrap().document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(
+            edits[0].edits,
+            vec![OneOf::Left(TextEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(2, 4),
+                    lsp_types::Position::new(2, 6)
+                ),
+                new_text: "[Existing Note]".to_string()
+            })]
+        );
+    }
+
+    fn refactor_edits(facts: &FactsDB, name: &str) -> Vec<(String, Vec<TextEdit>)> {
+        let note = facts.note_facts(facts.note_index().find_by_name(&name.into()).unwrap());
+        to_reference_links(&note)
+            .into_iter()
+            .chain(to_inline_links(&note))
+            .map(|action| {
+                let edits = match action.edit.unwrap().document_changes {
+                    Some(DocumentChanges::Edits(edits)) => edits,
+                    other => panic!("Unexpected document changes: {:?}", other),
+                };
+                let edits = edits[0]
+                    .edits
+                    .iter()
+                    .map(|e| match e {
+                        OneOf::Left(edit) => edit.clone(),
+                        OneOf::Right(edit) => edit.text_edit.clone(),
+                    })
+                    .collect();
+                (action.title, edits)
+            })
+            .collect()
+    }
+
+    fn text_edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(start.0, start.1),
+                lsp_types::Position::new(end.0, end.1),
+            ),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn convert_to_reference_links() {
+        let root = temp_root("convert-to-reference-links");
+        let facts = FactsDB::from_texts(
+            &root,
+            &[("a.md", "# A\n\n[x](b) [y](c#h) [z](b)\n\n[1]: d\n")],
+        );
+
+        let actions = refactor_edits(&facts, "a");
+        assert_eq!(
+            actions,
+            vec![(
+                "Convert inline links to reference style".to_string(),
+                vec![
+                    text_edit((2, 0), (2, 6), "[x][2]"),
+                    text_edit((2, 7), (2, 15), "[y][3]"),
+                    text_edit((2, 16), (2, 22), "[z][2]"),
+                    text_edit((5, 0), (5, 0), "[2]: b\n[3]: c#h\n"),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn convert_to_inline_links() {
+        let root = temp_root("convert-to-inline-links");
+        let facts = FactsDB::from_texts(
+            &root,
+            &[("a.md", "# A\n\n[x][1] [2] [y][1]\n\n[1]: b\n[2]: c#h\n")],
+        );
+
+        let actions = refactor_edits(&facts, "a");
+        assert_eq!(
+            actions,
+            vec![(
+                "Convert reference-style links to inline".to_string(),
+                vec![
+                    text_edit((2, 0), (2, 6), "[x](b)"),
+                    text_edit((2, 7), (2, 10), "[2](c#h)"),
+                    text_edit((2, 11), (2, 17), "[y](b)"),
+                    text_edit((4, 0), (5, 0), ""),
+                    text_edit((5, 0), (6, 0), ""),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn rename_file_to_title() {
+        let root = temp_root("rename-file-to-title");
+        let _ = fs::remove_file(root.join("my-note.md"));
+        let facts = FactsDB::from_texts(
+            &root,
+            &[
+                ("n1.md", "# My Note\n\nText\n"),
+                ("b.md", "# B\n\n[:n1] [:n1@## Sub] [x](./n1.md)\n"),
+                ("c.md", "# B\n"),
+            ],
+        );
+        let action = |name: &str, line: u32| {
+            let note = facts.note_facts(facts.note_index().find_by_name(&name.into()).unwrap());
+            let pos = Pos::new(line, 0);
+            rename_to_title(&facts, &note, &(pos..pos))
+        };
+
+        let edit = action("n1", 0).unwrap().edit.unwrap();
+        let ops = match edit.document_changes {
+            Some(DocumentChanges::Operations(ops)) => ops,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert_eq!(ops.len(), 2);
+        match &ops[0] {
+            DocumentChangeOperation::Edit(edit) => {
+                let new_texts = edit
+                    .edits
+                    .iter()
+                    .map(|e| match e {
+                        OneOf::Left(e) => e.new_text.as_str(),
+                        OneOf::Right(e) => e.text_edit.new_text.as_str(),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(new_texts, vec!["my-note", "my-note", "./my-note.md"]);
+            }
+            other => panic!("Unexpected operation: {:?}", other),
+        }
+        match &ops[1] {
+            DocumentChangeOperation::Op(ResourceOp::Rename(rename)) => {
+                assert_eq!(
+                    rename.old_uri,
+                    Url::from_file_path(root.join("n1.md")).unwrap()
+                );
+                assert_eq!(
+                    rename.new_uri,
+                    Url::from_file_path(root.join("my-note.md")).unwrap()
+                );
+            }
+            other => panic!("Unexpected operation: {:?}", other),
+        }
+
+        // Only on the title
+        assert!(action("n1", 2).is_none());
+        // The name already matches
+        assert!(action("b", 0).is_none());
+        // Another note has this name
+        assert!(action("c", 0).is_none());
+    }
+}
@@ -0,0 +1,573 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use lsp_types::{DiagnosticSeverity, ExecuteCommandParams, Location, NumberOrString, Url};
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::{
+    config::Config,
+    diag,
+    facts::{self, Facts, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
+    lsp::handlers::{heading_references, rename},
+    parser::NoteName,
+    store::Workspace,
+};
+
+/// Locations of links pointing to a note. The first argument is either a URI of
+/// the note or its name. The optional second argument is a heading of the note
+/// (its text or anchor) to find links to this heading only.
+pub const BACKLINKS: &str = "zeta-note/backlinks";
+
+/// The graph of links between notes in Graphviz DOT format. The optional
+/// argument is a URI of a note or a folder to export, otherwise all folders are
+/// exported.
+pub const EXPORT_GRAPH: &str = "zeta-note/exportGraph";
+
+/// A `WorkspaceEdit` that moves a heading with its section to the end of
+/// another note. Takes the URI of the source note, the heading (its text or
+/// anchor), and the URI of the target note.
+pub const MOVE_SECTION: &str = "zeta-note/moveSection";
+
+/// Moves a heading with its section into a new note and links to it instead.
+/// Takes the URI of the source note and the heading (its text or anchor), and
+/// returns `{"uri": ..., "edit": ...}` with the URI of the new note and the
+/// `WorkspaceEdit` to apply.
+pub const EXTRACT_NOTE: &str = "zeta-note/extractNote";
+
+/// Diagnostics of all notes in the workspace as a JSON list, e.g. to check notes
+/// in CI. Takes no arguments.
+pub const DIAGNOSTICS: &str = "zeta-note/diagnostics";
+
+/// Links to non-existent notes and headings in all notes, grouped by the
+/// linking note. Takes no arguments.
+pub const BROKEN_LINKS: &str = "zeta-note/brokenLinks";
+
+/// Drops everything known about notes and reads all folders from disk again,
+/// e.g. when file watching missed some changes. Takes no arguments and returns
+/// the number of notes indexed as `{"notes": 42}`.
+pub const REINDEX: &str = "zeta-note/reindex";
+
+/// Commands supported via `workspace/executeCommand`.
+pub const COMMANDS: &[&str] = &[
+    BACKLINKS,
+    EXPORT_GRAPH,
+    MOVE_SECTION,
+    EXTRACT_NOTE,
+    DIAGNOSTICS,
+    BROKEN_LINKS,
+    REINDEX,
+];
+
+/// Handle [`REINDEX`]. Unlike other commands, it changes the workspace, so
+/// diagnostics of all notes have to be published again afterwards.
+pub async fn reindex(workspace: &mut Workspace, config: &Config) -> Result<Value> {
+    let notes = workspace.reindex(config).await?;
+    debug!("reindex: indexed {} notes", notes);
+    Ok(json!({ "notes": notes }))
+}
+
+pub fn execute_command(
+    workspace: &Workspace,
+    params: ExecuteCommandParams,
+    config: &Config,
+) -> Option<Value> {
+    debug!("execute_command: {}", params.command);
+    match params.command.as_str() {
+        BACKLINKS => {
+            let arg = params.arguments.first()?.as_str()?;
+            let heading = params.arguments.get(1).and_then(Value::as_str);
+            let facts = &workspace.facts;
+            let note_id = match Url::parse(arg).ok().and_then(|u| u.to_file_path().ok()) {
+                Some(path) => {
+                    let (_, facts) = workspace.owning_folder(&path)?;
+                    Some(facts.note_index().find_by_path(&path)?)
+                }
+                None => facts::resolve_note_name(facts.db(), &arg.into()),
+            };
+            let locations = match (note_id, heading) {
+                (Some(note_id), Some(heading)) => {
+                    let note = facts.note_facts(note_id);
+                    let heading_id = note.heading_for_link(heading)?;
+                    heading_references(facts.db(), note_id, heading_id)
+                }
+                (Some(note_id), None) => {
+                    backlinks(facts.db(), &facts.note_facts(note_id).file().name)
+                }
+                (None, Some(_)) => Vec::new(),
+                (None, None) => backlinks(facts.db(), &arg.into()),
+            };
+            serde_json::to_value(locations).ok()
+        }
+        EXPORT_GRAPH => {
+            let path = params
+                .arguments
+                .first()
+                .and_then(|arg| arg.as_str())
+                .and_then(|arg| Url::parse(arg).ok()?.to_file_path().ok());
+            let dot = match path {
+                Some(path) => {
+                    let (folder, facts) = workspace.owning_folder(&path)?;
+                    export_dot(facts.db(), Some(&folder.root))
+                }
+                None => export_dot(workspace.facts.db(), None),
+            };
+            Some(Value::String(dot))
+        }
+        MOVE_SECTION => {
+            let note_path = |idx: usize| {
+                let arg = params.arguments.get(idx)?.as_str()?;
+                Url::parse(arg).ok()?.to_file_path().ok()
+            };
+            let (source, target) = (note_path(0)?, note_path(2)?);
+            let heading = params.arguments.get(1)?.as_str()?;
+
+            let (_, facts) = workspace.owning_folder(&source)?;
+            let index = facts.note_index();
+            let source_id = index.find_by_path(&source)?;
+            let target_id = index.find_by_path(&target)?;
+            let heading_id = facts.note_facts(source_id).heading_for_link(heading)?;
+            let edit = rename::move_section(facts.db(), source_id, heading_id, target_id)?;
+            serde_json::to_value(edit).ok()
+        }
+        EXTRACT_NOTE => {
+            let arg = params.arguments.first()?.as_str()?;
+            let source = Url::parse(arg).ok()?.to_file_path().ok()?;
+            let heading = params.arguments.get(1)?.as_str()?;
+
+            let (_, facts) = workspace.owning_folder(&source)?;
+            let source_id = facts.note_index().find_by_path(&source)?;
+            let heading_id = facts.note_facts(source_id).heading_for_link(heading)?;
+            let (uri, edit) = rename::extract_note(facts.db(), source_id, heading_id, config)?;
+            Some(json!({ "uri": uri, "edit": edit }))
+        }
+        DIAGNOSTICS => serde_json::to_value(all_diagnostics(&workspace.facts, config)).ok(),
+        BROKEN_LINKS => serde_json::to_value(broken_links(&workspace.facts)).ok(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoteDiagnostic {
+    pub path: PathBuf,
+    pub range: lsp_types::Range,
+    pub severity: DiagnosticSeverity,
+    pub code: String,
+    pub message: String,
+}
+
+/// Diagnostics of all notes ordered by path, the same as the ones published
+/// to the editor.
+///
+/// Notes are scanned from disk when a folder is added, so nothing has to be
+/// open in the editor.
+pub fn all_diagnostics(facts: &FactsDB, config: &Config) -> Vec<NoteDiagnostic> {
+    let index = facts.note_index();
+    let mut files: Vec<_> = index.files().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut diagnostics = Vec::new();
+    for file in files {
+        let note = facts.note_facts(index.find_by_path(&file.path).unwrap());
+        let diags = diag::note_diags(facts, &note);
+        let params = match diag::to_publish(file, &diags, facts, config) {
+            Some(params) => params,
+            None => continue,
+        };
+
+        for d in params.diagnostics {
+            let code = match d.code {
+                Some(NumberOrString::String(code)) => code,
+                _ => String::new(),
+            };
+            diagnostics.push(NoteDiagnostic {
+                path: file.path.to_path_buf(),
+                range: d.range,
+                severity: d.severity.unwrap_or(DiagnosticSeverity::ERROR),
+                code,
+                message: d.message,
+            });
+        }
+    }
+    diagnostics
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BrokenLinkKind {
+    /// The linked note doesn't exist.
+    Note,
+    /// The note exists, but the linked heading doesn't.
+    Heading,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenLink {
+    pub source_note: Url,
+    pub range: lsp_types::Range,
+    pub target: String,
+    pub heading: Option<String>,
+    pub kind: BrokenLinkKind,
+}
+
+/// Broken links of all notes ordered by the path of the linking note and then
+/// by position.
+///
+/// These come from the cached diagnostics of each note, so suppression comments
+/// and severity overrides don't apply.
+pub fn broken_links(facts: &FactsDB) -> Vec<BrokenLink> {
+    let index = facts.note_index();
+    let mut files: Vec<_> = index.files().collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let mut broken = Vec::new();
+    for file in files {
+        let note = facts.note_facts(index.find_by_path(&file.path).unwrap());
+        let indexed_text = note.indexed_text();
+        let mut links = note
+            .diag()
+            .iter()
+            .filter_map(|(diag, span)| {
+                let (target, heading, kind) = match diag {
+                    diag::Diag::BrokenInternLinkToNote {
+                        linked_note,
+                        heading,
+                        ..
+                    } => (linked_note, heading.clone(), BrokenLinkKind::Note),
+                    diag::Diag::BrokenInternLinkToHeading {
+                        linked_note,
+                        heading,
+                        ..
+                    } => (linked_note, Some(heading.clone()), BrokenLinkKind::Heading),
+                    _ => return None,
+                };
+                Some((span.clone(), target.to_string(), heading, kind))
+            })
+            .collect::<Vec<_>>();
+        links.sort_by_key(|(span, ..)| span.start);
+
+        let source_note = Url::from_file_path(&file.path).unwrap();
+        for (span, target, heading, kind) in links {
+            if let Some(range) = indexed_text.range_to_lsp_range(&span) {
+                broken.push(BrokenLink {
+                    source_note: source_note.clone(),
+                    range,
+                    target,
+                    heading,
+                    kind,
+                });
+            }
+        }
+    }
+    broken
+}
+
+/// Locations of all links pointing to the note `target`, with or without a heading.
+///
+/// Links within a note count as links to the note itself.
+pub fn backlinks(facts: &dyn Facts, target: &NoteName) -> Vec<Location> {
+    let index = facts.note_index(());
+    let target_id = facts::resolve_note_name(facts, target);
+    // Links to a non-existent note can still be found by the name
+    let mut locations = Vec::new();
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        let own_name = note.file().name;
+        let strukt = note.structure();
+        let indexed_text = note.indexed_text();
+
+        for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+            let points_to_target = match target_id {
+                Some(id) => facts::resolve_link_target(facts, note_id, link) == Some(id),
+                None => {
+                    let name = link.note_name.as_ref().unwrap_or(&own_name);
+                    index.name_matching().matches(name, target)
+                }
+            };
+            if !points_to_target {
+                continue;
+            }
+
+            if let Some(range) = indexed_text.range_to_lsp_range(&link.span) {
+                locations.push(Location {
+                    uri: Url::from_file_path(&note.file().path).unwrap(),
+                    range,
+                });
+            }
+        }
+    }
+
+    locations.sort_by(|a, b| (a.uri.as_str(), a.range.start).cmp(&(b.uri.as_str(), b.range.start)));
+    locations
+}
+
+/// Render notes as nodes labeled by their titles and links between notes as edges.
+///
+/// Links to non-existent notes point to dashed "missing" nodes. Links within a
+/// note are not shown. With `root` only the notes of this folder are exported.
+pub fn export_dot(facts: &dyn Facts, root: Option<&Path>) -> String {
+    let index = facts.note_index(());
+    let mut nodes = Vec::new();
+    let mut missing = BTreeSet::new();
+    let mut edges = BTreeSet::new();
+
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        if root.is_some_and(|root| *note.file().root != *root) {
+            continue;
+        }
+        let name = note.file().name;
+        let label = note.effective_title().unwrap_or_else(|| name.to_string());
+        nodes.push(format!(
+            "    {} [label={}];",
+            dot_id(name.to_str()),
+            dot_id(&label)
+        ));
+
+        let strukt = note.structure();
+        for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+            let target = match &link.note_name {
+                Some(target) => target,
+                None => continue,
+            };
+            match facts::resolve_link_target(facts, note_id, link) {
+                Some(target_id) => {
+                    let target = index.find_by_id(target_id).name;
+                    edges.insert(format!(
+                        "    {} -> {};",
+                        dot_id(name.to_str()),
+                        dot_id(target.to_str())
+                    ));
+                }
+                None => {
+                    missing.insert(target.to_str().to_string());
+                    edges.insert(format!(
+                        "    {} -> {} [style=dashed];",
+                        dot_id(name.to_str()),
+                        dot_id(&missing_id(target.to_str()))
+                    ));
+                }
+            }
+        }
+    }
+    nodes.sort();
+
+    let mut dot = String::from("digraph notes {\n");
+    for node in nodes {
+        dot.push_str(&node);
+        dot.push('\n');
+    }
+    for name in missing {
+        dot.push_str(&format!(
+            "    {} [label={}, style=dashed, color=red, fontcolor=red];\n",
+            dot_id(&missing_id(&name)),
+            dot_id(&name)
+        ));
+    }
+    for edge in edges {
+        dot.push_str(&edge);
+        dot.push('\n');
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Missing notes get their own ids so that they don't clash with existing notes.
+fn missing_id(name: &str) -> String {
+    format!("missing:{}", name)
+}
+
+/// A quoted DOT identifier.
+fn dot_id(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        facts::FactsDB,
+        store::{NoteFile, NoteFolder, NoteText, Version},
+    };
+    use lsp_types::{Position, Range};
+    use pretty_assertions::assert_eq;
+
+    fn location(name: &str, line: u32, start: u32, end: u32) -> Location {
+        Location {
+            uri: Url::from_file_path(Path::new("/notes").join(name)).unwrap(),
+            range: Range::new(Position::new(line, start), Position::new(line, end)),
+        }
+    }
+
+    #[test]
+    fn backlinks_to_note() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("a.md", "# A\n\n[:a] [:@## Sub] [:b]\n\n## Sub\n"),
+                ("b.md", "# B\n\n[:a@## Sub] [[a]] [:c]\n"),
+                ("c.md", "# C\n"),
+            ],
+        );
+
+        assert_eq!(
+            backlinks(facts.db(), &"a".into()),
+            vec![
+                location("a.md", 2, 0, 4),
+                location("a.md", 2, 5, 15),
+                location("b.md", 2, 0, 11),
+                location("b.md", 2, 12, 17),
+            ]
+        );
+        assert_eq!(
+            backlinks(facts.db(), &"missing".into()),
+            Vec::<Location>::new()
+        );
+    }
+
+    #[test]
+    fn export_graph() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                (
+                    "a.md",
+                    "# Note \"A\"\n\n[:b] [:b@## Sub] [:c] [:@## Sub]\n\n## Sub\n",
+                ),
+                ("b.md", "# B\n\n[:a] [[c]] [:d]\n"),
+                ("e.md", "Text only\n"),
+            ],
+        );
+
+        let dot = export_dot(facts.db(), None);
+        let lines = dot.lines().collect::<Vec<_>>();
+        assert_eq!(lines.first(), Some(&"digraph notes {"));
+        assert_eq!(lines.last(), Some(&"}"));
+
+        let nodes = lines.iter().filter(|l| l.contains("[label=")).count();
+        let edges = lines.iter().filter(|l| l.contains(" -> ")).count();
+        let dashed_edges = lines
+            .iter()
+            .filter(|l| l.contains(" -> ") && l.ends_with("[style=dashed];"))
+            .count();
+        assert_eq!(nodes, 5);
+        assert_eq!(edges, 5);
+        assert_eq!(dashed_edges, 3);
+
+        assert!(lines.contains(&r#"    "a" [label="Note \"A\""];"#));
+        assert!(lines.contains(&r#"    "e" [label="e"];"#));
+        assert!(lines.contains(&r#"    "b" -> "missing:c" [style=dashed];"#));
+    }
+
+    #[test]
+    fn diagnostics_of_all_notes() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("a.md", "# A\n\n[:b] [:c]\n"),
+                ("b.md", "# B\n\n[:a@## Missing]\n"),
+            ],
+        );
+
+        let diags = all_diagnostics(&facts, &Config::default());
+        let summary = diags
+            .iter()
+            .map(|d| (d.path.to_str().unwrap(), d.code.as_str(), d.severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("/notes/a.md", "broken-note-link", DiagnosticSeverity::ERROR),
+                (
+                    "/notes/b.md",
+                    "broken-heading-link",
+                    DiagnosticSeverity::WARNING
+                ),
+            ]
+        );
+        assert_eq!(
+            diags[0].range,
+            Range::new(Position::new(2, 5), Position::new(2, 9))
+        );
+        assert_eq!(diags[0].message, "Reference to non-existent note `c`");
+    }
+
+    #[test]
+    fn broken_links_of_all_notes() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("b.md", "# B\n\n[[x#Y]] [:a]\n"),
+                ("a.md", "# A\n\n[:c] [:b@## Missing]\n"),
+            ],
+        );
+
+        let broken_link =
+            |name: &str, range, target: &str, heading: Option<&str>, kind| BrokenLink {
+                source_note: Url::from_file_path(Path::new("/notes").join(name)).unwrap(),
+                range,
+                target: target.to_string(),
+                heading: heading.map(str::to_string),
+                kind,
+            };
+        let line_range = |start, end| Range::new(Position::new(2, start), Position::new(2, end));
+        assert_eq!(
+            broken_links(&facts),
+            vec![
+                broken_link("a.md", line_range(0, 4), "c", None, BrokenLinkKind::Note),
+                broken_link(
+                    "a.md",
+                    line_range(5, 20),
+                    "b",
+                    Some("## Missing"),
+                    BrokenLinkKind::Heading
+                ),
+                broken_link(
+                    "b.md",
+                    line_range(0, 7),
+                    "x",
+                    Some("Y"),
+                    BrokenLinkKind::Note
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reindex_reads_folders_again() {
+        let root = std::env::temp_dir().join("zeta-note-command-reindex");
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("a.md"), "# A\n").unwrap();
+        std::fs::write(root.join("b.md"), "# B\n").unwrap();
+
+        let config = Config::default();
+        let folder = NoteFolder::from_root_path(&root);
+        let mut workspace = Workspace::new(&[folder], &config).await.unwrap();
+        let open_text = NoteText::new(Version::Vs(3), "# A edited\n".into());
+        workspace
+            .facts
+            .insert_note(NoteFile::new(&root, &root.join("a.md")), open_text.clone());
+
+        // Changes the file watcher didn't report
+        std::fs::remove_file(root.join("b.md")).unwrap();
+        std::fs::write(root.join("c.md"), "# C\n").unwrap();
+        std::fs::write(root.join("d.md"), "# D\n").unwrap();
+
+        let result = reindex(&mut workspace, &config).await.unwrap();
+        assert_eq!(result, json!({ "notes": 3 }));
+
+        let index = workspace.facts.note_index();
+        assert!(index.find_by_path(&root.join("b.md")).is_none());
+        let a = index.find_by_path(&root.join("a.md")).unwrap();
+        assert_eq!(workspace.facts.note_facts(a).text(), open_text);
+    }
+}
@@ -1,23 +1,31 @@
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use anyhow::Result;
 
-use lsp_document::{Pos, TextAdapter, TextMap};
+use lsp_document::{Pos, TextMap};
 
-use lsp_types::{CompletionItem, CompletionParams, Documentation, MarkupContent, TextEdit};
+use lsp_types::{
+    CompletionItem, CompletionParams, Documentation, InsertTextFormat, MarkupContent, TextEdit,
+};
 
 use serde::{Deserialize, Serialize};
 use serde_json;
 
 use tracing::debug;
 
-use crate::facts::NoteFactsDB;
+use crate::config::{Config, LinkFormat};
+use crate::facts::{self, FactsDB, NoteFactsDB};
+use crate::lsp::handlers;
 use crate::parser;
-use crate::store::Workspace;
-use crate::util::text_matches_query;
+use crate::store::{NoteFile, Workspace};
+use crate::structure::NoteID;
+use crate::util::{fuzzy_score, relative_path, text_matches_query};
 use crate::{
     facts::{NoteFacts, NoteFactsExt},
-    parser::{Element, NoteName},
+    parser::{Element, Heading, Node, NoteName},
 };
 
 #[derive(Debug, PartialEq, Eq, Deserialize, Serialize, Clone)]
@@ -26,6 +34,8 @@ enum CompletionType {
         root: PathBuf,
         note_name: NoteName,
         note_title: String,
+        /// How the note is referred to in the link, see [`LinkFormat`].
+        link_text: String,
     },
     HeadingCompletion {
         root: PathBuf,
@@ -43,9 +53,14 @@ impl CompletionType {
     }
 }
 
+/// Completion items at the position of `params`.
+///
+/// Items are only sent as snippets with `snippet_support` of the client.
 pub fn completion_candidates(
     workspace: &Workspace,
     params: CompletionParams,
+    config: &Config,
+    snippet_support: bool,
 ) -> Option<Vec<CompletionItem>> {
     let target_note_path = params
         .text_document_position
@@ -54,6 +69,11 @@ pub fn completion_candidates(
         .to_file_path()
         .unwrap();
     let pos = params.text_document_position.position;
+    let trigger = params
+        .context
+        .as_ref()
+        .and_then(|ctx| ctx.trigger_character.as_ref())
+        .and_then(|c| c.chars().next());
 
     let (folder, facts) = workspace.owning_folder(&target_note_path)?;
     let root = &folder.root;
@@ -61,15 +81,42 @@ pub fn completion_candidates(
     let encl_note_id = facts.note_index().find_by_path(&target_note_path)?;
     let encl_note = facts.note_facts(encl_note_id);
     let encl_structure = encl_note.structure();
+    let snippets = config.completion_snippets && snippet_support;
 
-    let enclosing_el = encl_structure.element_by_id(encl_note.element_at_lsp_pos(&pos)?);
-    let enclosing_link = match &enclosing_el {
-        Element::InternLink(r) => r,
+    let enclosing_el = encl_note
+        .element_at_lsp_pos(&pos)
+        .map(|id| encl_structure.element_by_id(id));
+    // A trigger character narrows down what's being completed, so that typing
+    // plain prose doesn't bring up completions
+    let (enclosing_el, enclosing_link) = match (enclosing_el, trigger) {
+        // Markdown links `[label](target)` are completed as text below
+        (
+            Some(el @ Element::InternLink(r)),
+            None | Some(parser::LINK_START | parser::HEADING_START),
+        ) if !r.is_markdown() => (el, r),
+        (_, None) => {
+            return md_link_candidates(facts, &encl_note, &pos, config, snippets)
+                .or_else(|| ref_label_candidates(&encl_note, &pos))
+                .or_else(|| tag_candidates(facts, &encl_note, &pos))
+        }
+        (_, Some(parser::LINK_START)) => return ref_label_candidates(&encl_note, &pos),
+        (_, Some(parser::LINK_DEST_START)) => {
+            return md_link_candidates(facts, &encl_note, &pos, config, snippets)
+        }
+        (_, Some(parser::HEADING_START)) => {
+            return md_anchor_candidates_at(facts, &encl_note, &pos, config)
+                .or_else(|| tag_candidates(facts, &encl_note, &pos))
+        }
         _ => return None,
     };
 
+    let heading_seps: &[char] = if enclosing_link.is_wiki() {
+        &[parser::HEADING_START]
+    } else {
+        &[parser::SEP_BAR, parser::SEP_AT]
+    };
     let tries_to_match_note =
-        enclosing_link.heading.is_none() && !enclosing_link.text.contains(['|', '@']);
+        enclosing_link.heading.is_none() && !enclosing_link.text.contains(heading_seps);
 
     let mut candidates = Vec::new();
 
@@ -81,30 +128,41 @@ pub fn completion_candidates(
             .map(|n| n.to_string())
             .unwrap_or_default();
 
-        for candidate_id in facts.note_index().ids() {
-            if candidate_id == encl_note_id {
-                // Don't try to complete the current note
-                continue;
-            }
-
-            let cand = facts.note_facts(candidate_id);
-            let cand_struct = cand.structure();
-
-            if let Some(title) = cand.title().map(|id| cand_struct.heading_by_id(id)) {
-                let title = title.text.trim_start_matches('#').trim_start().to_string();
-                if !text_matches_query(&title, &partial_input) {
-                    continue;
-                }
+        let matching = notes_matching(facts, encl_note_id, &partial_input);
+        for (rank, (cand_id, title)) in matching.into_iter().enumerate() {
+            let cand = facts.note_facts(cand_id);
+            let file = cand.file();
+            let link_text = link_text(facts, cand_id, config.completion_link_format)
+                .unwrap_or_else(|| file.name.to_string());
+            let completion_type = CompletionType::NoteCompletion {
+                root: root.clone(),
+                note_name: (*file.name).clone(),
+                note_title: title,
+                link_text,
+            };
+            candidates.push(CompletionItem {
+                detail: Some(root_relative_path(&file)),
+                documentation: Some(markdown(handlers::note_preview(&cand))),
+                sort_text: Some(format!("{:05}", rank)),
+                ..completion_item(&encl_note, enclosing_el, &completion_type)
+            });
+        }
 
-                let name = NoteName::from_path(&cand.file().path, root);
-                let completion_type = CompletionType::NoteCompletion {
-                    root: root.clone(),
-                    note_name: name.clone(),
-                    note_title: title.clone(),
-                };
-                let completion_item = completion_item(&encl_note, enclosing_el, &completion_type);
-                candidates.push(completion_item)
-            }
+        // Existing notes go first
+        let rank_offset = candidates.len();
+        let missing = missing_notes_matching(facts, &partial_input);
+        for (rank, name) in missing.into_iter().enumerate() {
+            let completion_type = CompletionType::NoteCompletion {
+                root: root.clone(),
+                note_name: name.clone(),
+                note_title: name.to_string(),
+                link_text: name.to_string(),
+            };
+            candidates.push(CompletionItem {
+                detail: Some(MISSING_NOTE_DETAIL.to_string()),
+                sort_text: Some(format!("{:05}", rank_offset + rank)),
+                ..completion_item(&encl_note, enclosing_el, &completion_type)
+            });
         }
     } else {
         // tries to match a heading inside a note
@@ -112,13 +170,9 @@ pub fn completion_candidates(
             Some(name) => name.clone(),
             _ => NoteName::from_path(&target_note_path, root),
         };
-        let target_tag = match &enclosing_link.note_name {
-            Some(name) => name.to_path(root),
-            _ => target_note_path,
-        };
-        debug!("Mathing headings inside {:?}...", target_tag);
+        debug!("Mathing headings inside {}...", target_note_name);
 
-        let cand_id = facts.note_index().find_by_path(&target_tag)?;
+        let cand_id = facts::resolve_link_target(facts.db(), encl_note_id, enclosing_link)?;
         let cand = facts.note_facts(cand_id);
         let cand_struct = cand.structure();
 
@@ -138,7 +192,11 @@ pub fn completion_candidates(
                 note_name: target_note_name.clone(),
                 heading: hd.text.to_string(),
             };
-            let completion_item = completion_item(&encl_note, enclosing_el, &completion_type);
+            let completion_item = CompletionItem {
+                detail: Some(format!("Heading level {}", hd.level)),
+                sort_text: Some(heading_sort_text(hd)),
+                ..completion_item(&encl_note, enclosing_el, &completion_type)
+            };
             candidates.push(completion_item)
         }
     }
@@ -150,6 +208,510 @@ pub fn completion_candidates(
     }
 }
 
+/// Notes other than `exclude` that match `query` by the name or the title,
+/// best matches first. Among equally good matches, notes in directories closer
+/// to the one of `exclude` go first. Notes without a title are skipped.
+fn notes_matching(facts: &FactsDB, exclude: NoteID, query: &str) -> Vec<(NoteID, String)> {
+    // Names are compared the same way links are matched against them
+    let name_matching = facts.note_index().name_matching();
+    let name_query = name_matching.normalize(query);
+    let exclude_path = facts.note_facts(exclude).file().path;
+    let note_dir = exclude_path.parent().unwrap_or(Path::new(""));
+
+    let mut scored = Vec::new();
+    for candidate_id in facts.note_index().ids() {
+        if candidate_id == exclude {
+            // Don't try to complete the current note
+            continue;
+        }
+
+        let cand = facts.note_facts(candidate_id);
+        if let Some(title) = cand.effective_title() {
+            let name = cand.file().name;
+            let score = match (
+                fuzzy_score(&name_matching.normalize(name.to_str()), &name_query),
+                fuzzy_score(&title, query),
+            ) {
+                (Some(s1), Some(s2)) => s1.min(s2),
+                (Some(s), None) | (None, Some(s)) => s,
+                (None, None) => continue,
+            };
+            let cand_path = cand.file().path;
+            let distance = dir_distance(note_dir, cand_path.parent().unwrap_or(Path::new("")));
+            scored.push(((score, distance), candidate_id, title));
+        }
+    }
+
+    scored.sort_by(|(s1, _, t1), (s2, _, t2)| s1.cmp(s2).then_with(|| t1.cmp(t2)));
+    scored
+        .into_iter()
+        .map(|(_, id, title)| (id, title))
+        .collect()
+}
+
+/// Number of steps up from `from` to the directory it shares with `to` and
+/// then down to `to`.
+fn dir_distance(from: &Path, to: &Path) -> usize {
+    let shared = from
+        .components()
+        .zip(to.components())
+        .take_while(|(c1, c2)| c1 == c2)
+        .count();
+    from.components().count() + to.components().count() - 2 * shared
+}
+
+/// Detail of completion candidates for notes that are linked to but don't exist.
+const MISSING_NOTE_DETAIL: &str = "(not yet created)";
+
+/// Names of notes that don't exist yet but are linked to by name, e.g.
+/// `roadmap` in `[x](roadmap)`, that match `query`, best matches first.
+///
+/// The name being typed is not a candidate for itself. Names that match each
+/// other, e.g. `My Note` and `my-note` with normalized names, are offered once.
+fn missing_notes_matching(facts: &FactsDB, query: &str) -> Vec<NoteName> {
+    let name_matching = facts.note_index().name_matching();
+    let query = name_matching.normalize(query);
+
+    let mut names: HashMap<String, NoteName> = HashMap::new();
+    for note_id in facts.note_index().ids() {
+        let note = facts.note_facts(note_id);
+        let strukt = note.structure();
+        // Broken links, their targets are resolved once per change of the notes
+        for &(link_id, _) in note.link_targets().iter().filter(|(_, t)| t.is_none()) {
+            let link = strukt.intern_link_by_id(link_id);
+            let name = match &link.note_name {
+                Some(name) if !link.is_path() => name,
+                _ => continue,
+            };
+            let key = name_matching.normalize(name.to_str());
+            if key != query {
+                names
+                    .entry(key.into_owned())
+                    .or_insert_with(|| name.clone());
+            }
+        }
+    }
+
+    let mut scored = names
+        .into_iter()
+        .filter_map(|(key, name)| Some((fuzzy_score(&key, &query)?, name)))
+        .collect::<Vec<_>>();
+    scored.sort();
+    scored.into_iter().map(|(_, name)| name).collect()
+}
+
+/// A reference to the note `cand_id` as a slug or a title, if that's what
+/// `format` asks for and the reference resolves back to the same note.
+fn link_text(facts: &FactsDB, cand_id: NoteID, format: LinkFormat) -> Option<String> {
+    let cand = facts.note_facts(cand_id);
+    let text = match format {
+        LinkFormat::Filename => return None,
+        LinkFormat::Slug => facts::note_name_slug(cand.file().name.to_str()),
+        LinkFormat::Title => cand.effective_title()?,
+    };
+    // Characters that would end the note name in one of the link syntaxes
+    let breaks_link = text.contains(|c| "#@|:[]()<>".contains(c));
+    if text.is_empty() || breaks_link {
+        return None;
+    }
+    let resolved = facts::resolve_note_name(facts.db(), &text.as_str().into());
+    Some(text).filter(|_| resolved == Some(cand_id))
+}
+
+fn root_relative_path(file: &NoteFile) -> String {
+    let path = file.path.strip_prefix(&file.root).unwrap_or(&file.path);
+    path.display().to_string()
+}
+
+/// Complete a note inside an unfinished Markdown link `[label](target`.
+///
+/// The target is the path to the note relative to the current one, or its slug
+/// or title per the configured [`LinkFormat`]. With `snippets` and an empty label
+/// `[](` the whole link is filled in: the label defaults to the note title and
+/// the cursor ends up after the closing `)`.
+fn md_link_candidates(
+    facts: &FactsDB,
+    note: &NoteFactsDB,
+    pos: &lsp_types::Position,
+    config: &Config,
+    snippets: bool,
+) -> Option<Vec<CompletionItem>> {
+    md_target_candidates(facts, note, pos, config, snippets, false)
+}
+
+/// Like [`md_link_candidates`], but only completes the heading anchor of an
+/// unfinished target `[label](target#anchor`.
+fn md_anchor_candidates_at(
+    facts: &FactsDB,
+    note: &NoteFactsDB,
+    pos: &lsp_types::Position,
+    config: &Config,
+) -> Option<Vec<CompletionItem>> {
+    md_target_candidates(facts, note, pos, config, false, true)
+}
+
+fn md_target_candidates(
+    facts: &FactsDB,
+    note: &NoteFactsDB,
+    pos: &lsp_types::Position,
+    config: &Config,
+    snippets: bool,
+    anchors_only: bool,
+) -> Option<Vec<CompletionItem>> {
+    let indexed_text = note.indexed_text();
+    let pos = indexed_text.lsp_pos_to_pos(pos)?;
+    let line = indexed_text.text().lines().nth(pos.line as usize)?;
+    let line_before = line.get(..pos.col as usize)?;
+    let line_after = line.get(pos.col as usize..)?;
+    let (label_start, target_start) = md_link_prefix(line_before)?;
+    debug!("Matching notes for a Markdown link...");
+
+    // The rest of the target after the cursor is replaced too
+    let target_rest = line_after
+        .find(|c: char| c == ')' || c.is_whitespace())
+        .unwrap_or(line_after.len());
+    let has_closing = line_after[target_rest..].starts_with(')');
+
+    let partial = &line_before[target_start..];
+    if let Some((dest, query)) = partial.split_once(parser::HEADING_START) {
+        let anchor_start = target_start + dest.len() + 1;
+        let range = Pos::new(pos.line, anchor_start as u32)
+            ..Pos::new(pos.line, (line_before.len() + target_rest) as u32);
+        let range = indexed_text.range_to_lsp_range(&range)?;
+        return md_anchor_candidates(facts, note, dest, query, range, has_closing);
+    }
+    if anchors_only {
+        return None;
+    }
+
+    let as_snippet = snippets && target_start - label_start == 2;
+
+    let (edit_start, edit_end) = if as_snippet {
+        // An existing `)` is a part of the snippet
+        (label_start, target_rest + usize::from(has_closing))
+    } else {
+        (target_start, target_rest)
+    };
+    let range = Pos::new(pos.line, edit_start as u32)
+        ..Pos::new(pos.line, (line_before.len() + edit_end) as u32);
+    let range = indexed_text.range_to_lsp_range(&range)?;
+
+    let query = Path::new(partial)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let note_dir = note.file().path.parent()?.to_path_buf();
+
+    let mut candidates: Vec<_> = notes_matching(facts, note.id(), &query)
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (cand_id, title))| {
+            let cand = facts.note_facts(cand_id);
+            let file = cand.file();
+            let target = link_text(facts, cand_id, config.completion_link_format)
+                .filter(|text| !parser::is_path_target(text))
+                .unwrap_or_else(|| {
+                    relative_path(&note_dir, &file.path)
+                        .to_string_lossy()
+                        .to_string()
+                });
+            let (new_text, filter_text, format) =
+                md_link_text(&title, &target, as_snippet, has_closing);
+            CompletionItem {
+                label: title,
+                kind: Some(lsp_types::CompletionItemKind::FILE),
+                detail: Some(root_relative_path(&file)),
+                documentation: Some(markdown(handlers::note_preview(&cand))),
+                sort_text: Some(format!("{:05}", rank)),
+                filter_text: Some(filter_text),
+                insert_text_format: Some(format),
+                text_edit: Some(TextEdit { range, new_text }.into()),
+                ..CompletionItem::default()
+            }
+        })
+        .collect();
+
+    // Existing notes go first
+    let rank_offset = candidates.len();
+    let missing = missing_notes_matching(facts, &query);
+    for (rank, name) in missing.into_iter().enumerate() {
+        let title = name.to_string();
+        let (new_text, filter_text, format) = md_link_text(&title, &title, as_snippet, has_closing);
+        candidates.push(CompletionItem {
+            label: title,
+            kind: Some(lsp_types::CompletionItemKind::FILE),
+            detail: Some(MISSING_NOTE_DETAIL.to_string()),
+            sort_text: Some(format!("{:05}", rank_offset + rank)),
+            filter_text: Some(filter_text),
+            insert_text_format: Some(format),
+            text_edit: Some(TextEdit { range, new_text }.into()),
+            ..CompletionItem::default()
+        });
+    }
+
+    if candidates.is_empty() {
+        None
+    } else {
+        Some(candidates)
+    }
+}
+
+/// Text inserted by a completion of a Markdown link with the `label` and the
+/// `target`, the text to filter it by, and the format of the text.
+fn md_link_text(
+    label: &str,
+    target: &str,
+    as_snippet: bool,
+    has_closing: bool,
+) -> (String, String, InsertTextFormat) {
+    // Destinations with spaces have to be in angle brackets
+    let target = if target.contains(char::is_whitespace) {
+        format!("<{}>", target)
+    } else {
+        target.to_string()
+    };
+    if as_snippet {
+        (
+            format!(
+                "${{1:{}}}]({})$0",
+                escape_snippet(label),
+                escape_snippet(&target)
+            ),
+            format!("]({}", target),
+            InsertTextFormat::SNIPPET,
+        )
+    } else if has_closing {
+        (target.clone(), target, InsertTextFormat::PLAIN_TEXT)
+    } else {
+        (format!("{})", target), target, InsertTextFormat::PLAIN_TEXT)
+    }
+}
+
+/// Complete the anchor in an unfinished Markdown link `[label](dest#query`.
+///
+/// Headings of the note `dest` are shown as written and inserted as their
+/// slugs, e.g. `my-heading` for `## My Heading`, which is how anchors resolve.
+fn md_anchor_candidates(
+    facts: &FactsDB,
+    note: &NoteFactsDB,
+    dest: &str,
+    query: &str,
+    range: lsp_types::Range,
+    has_closing: bool,
+) -> Option<Vec<CompletionItem>> {
+    debug!("Matching heading anchors inside {}...", dest);
+    // Nothing to offer for a note that doesn't exist yet
+    let target_id = facts::resolve_link_dest(facts.db(), note.id(), dest)?;
+    let target_note = facts.note_facts(target_id);
+    let target_struct = target_note.structure();
+
+    // A link to the note already points to its title
+    let heading_ids = target_note.headings_matching(|hd| {
+        let slug = hd.slug();
+        hd.level > 1 && !slug.is_empty() && text_matches_query(&slug, query)
+    });
+
+    let mut candidates = Vec::new();
+    for hd in target_struct.headings_with_ids(&heading_ids) {
+        let slug = hd.slug();
+        let new_text = if has_closing {
+            slug.clone()
+        } else {
+            format!("{})", slug)
+        };
+        candidates.push(CompletionItem {
+            label: hd.text.clone(),
+            kind: Some(lsp_types::CompletionItemKind::REFERENCE),
+            detail: Some(format!("Heading level {}", hd.level)),
+            filter_text: Some(slug),
+            sort_text: Some(heading_sort_text(hd)),
+            text_edit: Some(TextEdit { range, new_text }.into()),
+            ..CompletionItem::default()
+        });
+    }
+
+    if candidates.is_empty() {
+        None
+    } else {
+        Some(candidates)
+    }
+}
+
+/// Headings are listed in the order they appear in the note.
+fn heading_sort_text(hd: &Node<Heading>) -> String {
+    format!("{:05}", hd.span.start.line)
+}
+
+/// Offsets of the label and of the target of an unfinished Markdown link
+/// `[label](target` at the end of `line`.
+fn md_link_prefix(line: &str) -> Option<(usize, usize)> {
+    let label_end = line.rfind("](")?;
+    let target_start = label_end + 2;
+    if line[target_start..].contains(|c: char| c == ')' || c.is_whitespace()) {
+        return None;
+    }
+    let label_start = line[..label_end].rfind('[')? + 1;
+    if line[label_start..label_end].contains(']') {
+        return None;
+    }
+    // Images are not notes
+    if line[..label_start - 1].ends_with('!') {
+        return None;
+    }
+    Some((label_start, target_start))
+}
+
+/// Complete the label of an unfinished reference-style link `[label][ref`
+/// with the `[ref]: ...` definitions of the note, showing their destinations.
+fn ref_label_candidates(
+    note: &NoteFactsDB,
+    pos: &lsp_types::Position,
+) -> Option<Vec<CompletionItem>> {
+    let indexed_text = note.indexed_text();
+    let pos = indexed_text.lsp_pos_to_pos(pos)?;
+    let line = indexed_text.text().lines().nth(pos.line as usize)?;
+    let line_before = line.get(..pos.col as usize)?;
+    let line_after = line.get(pos.col as usize..)?;
+    let ref_start = ref_label_prefix(line_before)?;
+    debug!("Matching reference link labels...");
+
+    // The rest of the label after the cursor is replaced too
+    let ref_rest = line_after
+        .find(|c: char| c == parser::LINK_SUFFIX || c == parser::LINK_START || c.is_whitespace())
+        .unwrap_or(line_after.len());
+    let has_closing = line_after[ref_rest..].starts_with(parser::LINK_SUFFIX);
+    let range = Pos::new(pos.line, ref_start as u32)
+        ..Pos::new(pos.line, (line_before.len() + ref_rest) as u32);
+    let range = indexed_text.range_to_lsp_range(&range)?;
+
+    let query = &line_before[ref_start..];
+    let mut seen = HashSet::new();
+    let mut candidates = Vec::new();
+    for def in parser::scrape_link_defs(&*indexed_text) {
+        // The first definition of a label is the one links use
+        if !seen.insert(parser::link_label_key(&def.label))
+            || !text_matches_query(&def.label, query)
+        {
+            continue;
+        }
+        let new_text = if has_closing {
+            def.label.clone()
+        } else {
+            format!("{}{}", def.label, parser::LINK_SUFFIX)
+        };
+        candidates.push(CompletionItem {
+            label: def.label.clone(),
+            kind: Some(lsp_types::CompletionItemKind::REFERENCE),
+            detail: Some(def.dest.clone()),
+            filter_text: Some(def.label.clone()),
+            sort_text: Some(format!("{:05}", def.span.start.line)),
+            text_edit: Some(TextEdit { range, new_text }.into()),
+            ..CompletionItem::default()
+        });
+    }
+
+    if candidates.is_empty() {
+        None
+    } else {
+        Some(candidates)
+    }
+}
+
+/// Offset of the reference of an unfinished reference-style link
+/// `[label][ref` at the end of `line`.
+fn ref_label_prefix(line: &str) -> Option<usize> {
+    let label_end = line.rfind("][")?;
+    let ref_start = label_end + 2;
+    if line[ref_start..].contains([parser::LINK_START, parser::LINK_SUFFIX]) {
+        return None;
+    }
+    let label_start = line[..label_end].rfind(parser::LINK_START)? + 1;
+    if line[label_start..label_end].contains(parser::LINK_SUFFIX) {
+        return None;
+    }
+    Some(ref_start)
+}
+
+/// Escape characters that have a special meaning in snippets.
+fn escape_snippet(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '$' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Complete a tag when the cursor is right after `#tag-prefix` in the text.
+fn tag_candidates(
+    facts: &FactsDB,
+    note: &NoteFactsDB,
+    pos: &lsp_types::Position,
+) -> Option<Vec<CompletionItem>> {
+    let indexed_text = note.indexed_text();
+    let pos = indexed_text.lsp_pos_to_pos(pos)?;
+    let line_before = indexed_text.substr(Pos::new(pos.line, 0)..pos)?;
+    let tag_start = tag_prefix_start(line_before)?;
+    debug!("Matching tags...");
+
+    let range = Pos::new(pos.line, tag_start as u32)..pos;
+    let range = indexed_text.range_to_lsp_range(&range)?;
+
+    let tag_index = facts.tag_index();
+    let mut tags: Vec<_> = tag_index.names().map(|n| (n, tag_index.count(n))).collect();
+    // Most used tags go first
+    tags.sort_by(|(n1, c1), (n2, c2)| c2.cmp(c1).then(n1.cmp(n2)));
+
+    let candidates: Vec<_> = tags
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (name, count))| {
+            let tag = format!("{}{}", parser::HEADING_START, name);
+            CompletionItem {
+                label: tag.clone(),
+                kind: Some(lsp_types::CompletionItemKind::KEYWORD),
+                detail: Some(format!("Used {} time(s)", count)),
+                sort_text: Some(format!("{:05}", rank)),
+                filter_text: Some(tag.clone()),
+                text_edit: Some(
+                    TextEdit {
+                        range,
+                        new_text: tag,
+                    }
+                    .into(),
+                ),
+                ..CompletionItem::default()
+            }
+        })
+        .collect();
+
+    if candidates.is_empty() {
+        None
+    } else {
+        Some(candidates)
+    }
+}
+
+/// Offset of `#` that starts a (possibly empty) tag at the end of `line`.
+fn tag_prefix_start(line: &str) -> Option<usize> {
+    let prefix = line.trim_end_matches(parser::is_tag_char);
+    let before_tag = prefix.strip_suffix(parser::HEADING_START)?;
+    if before_tag.ends_with(|c| parser::is_tag_char(c) || c == parser::HEADING_START) {
+        return None;
+    }
+    // `[:#` starts a link to a heading rather than a tag
+    if before_tag.ends_with(parser::LINK_PREFIX_1) {
+        return None;
+    }
+    // A single `#` at the start of a line is likely a heading
+    if before_tag.is_empty() && prefix.len() == line.len() {
+        return None;
+    }
+    Some(before_tag.len())
+}
+
 pub fn completion_resolve(
     workspace: &Workspace,
     unresolved: &CompletionItem,
@@ -161,23 +723,8 @@ pub fn completion_resolve(
         .and_then(Result::ok)?;
 
     match completion_type {
-        CompletionType::NoteCompletion {
-            root, note_name, ..
-        } => {
-            let (_, facts) = workspace.owning_folder(&root)?;
-            let note_id = facts.note_index().find_by_name(&note_name)?;
-            let note = facts.note_facts(note_id);
-
-            let documentation = Documentation::MarkupContent(MarkupContent {
-                kind: lsp_types::MarkupKind::Markdown,
-                value: note.text().content.to_string(),
-            });
-
-            Some(CompletionItem {
-                documentation: Some(documentation),
-                ..unresolved.clone()
-            })
-        }
+        // Notes are documented upfront
+        CompletionType::NoteCompletion { .. } => Some(unresolved.clone()),
         CompletionType::HeadingCompletion {
             root,
             note_name,
@@ -192,32 +739,40 @@ pub fn completion_resolve(
                 .indexed_text()
                 .substr(heading.scope.clone())?
                 .to_string();
-            let documentation = Documentation::MarkupContent(MarkupContent {
-                kind: lsp_types::MarkupKind::Markdown,
-                value: content,
-            });
-
             Some(CompletionItem {
-                documentation: Some(documentation),
+                documentation: Some(markdown(content)),
                 ..unresolved.clone()
             })
         }
     }
 }
 
+fn markdown(value: String) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: lsp_types::MarkupKind::Markdown,
+        value,
+    })
+}
+
 fn completion_item(
     note_facts: &NoteFactsDB,
     complete_on: &Element,
     completion: &CompletionType,
 ) -> CompletionItem {
     let data = serde_json::to_value(completion).unwrap();
-    let sep = if complete_on.text().contains(parser::SEP_AT) {
-        parser::SEP_AT
+    let wiki = matches!(complete_on, Element::InternLink(l) if l.is_wiki());
+    let sep = if wiki {
+        Some(parser::HEADING_START)
+    } else if complete_on.text().contains(parser::SEP_AT) {
+        Some(parser::SEP_AT)
+    } else if matches!(complete_on, Element::InternLink(l) if l.is_heading_shorthand()) {
+        // `[:## Heading]` doesn't need a separator
+        None
     } else {
-        parser::SEP_BAR
+        Some(parser::SEP_BAR)
     };
     let is_intralink = *note_facts.file().name == *completion.note_name();
-    let (kind, label, detail, filter_text, sort_text) = match completion {
+    let (kind, label, filter_text) = match completion {
         CompletionType::NoteCompletion {
             note_name,
             note_title,
@@ -225,25 +780,20 @@ fn completion_item(
         } => (
             lsp_types::CompletionItemKind::FILE,
             note_title.clone(),
-            Some(note_name.to_string()),
-            None,
-            None,
+            // Notes are matched both by the name and the title
+            Some(format!("{} {}", note_name, note_title)),
         ),
         CompletionType::HeadingCompletion { heading, .. } => (
-            lsp_types::CompletionItemKind::TEXT,
+            lsp_types::CompletionItemKind::REFERENCE,
             heading.clone(),
-            None,
-            Some(completion_text(completion, is_intralink, sep)),
-            Some(heading.to_string()),
+            Some(completion_text(completion, is_intralink, sep, wiki)),
         ),
     };
-    let text_edit = completion_edit(note_facts, complete_on, completion, is_intralink, sep);
+    let text_edit = completion_edit(note_facts, complete_on, completion, is_intralink, sep, wiki);
     CompletionItem {
         label,
-        detail,
         kind: Some(kind),
         filter_text,
-        sort_text,
         text_edit: Some(text_edit.into()),
         data: Some(data),
         ..CompletionItem::default()
@@ -255,11 +805,16 @@ fn completion_edit(
     completion_item: &Element,
     completion: &CompletionType,
     is_intralink: bool,
-    sep: char,
+    sep: Option<char>,
+    wiki: bool,
 ) -> TextEdit {
-    let (start_offset, end_offset) = match completion {
-        CompletionType::NoteCompletion { .. } => (2, 1),
-        CompletionType::HeadingCompletion { .. } => (2, 1),
+    let (start_offset, end_offset) = if wiki {
+        (
+            parser::WIKI_LINK_PREFIX.len() as u32,
+            parser::WIKI_LINK_SUFFIX.len() as u32,
+        )
+    } else {
+        (2, 1)
     };
     let element_span = completion_item.span();
     let completion_range = Pos::new(
@@ -273,7 +828,7 @@ fn completion_edit(
         .range_to_lsp_range(&completion_range)
         .unwrap();
 
-    let completion_text = completion_text(completion, is_intralink, sep);
+    let completion_text = completion_text(completion, is_intralink, sep, wiki);
 
     TextEdit {
         range: completion_range,
@@ -281,19 +836,706 @@ fn completion_edit(
     }
 }
 
-fn completion_text(completion: &CompletionType, is_intralink: bool, sep: char) -> String {
+fn completion_text(
+    completion: &CompletionType,
+    is_intralink: bool,
+    sep: Option<char>,
+    wiki: bool,
+) -> String {
     match completion {
-        CompletionType::NoteCompletion {
-            root: _, note_name, ..
-        } => format!("{note_name}"),
+        CompletionType::NoteCompletion { link_text, .. } => link_text.clone(),
         CompletionType::HeadingCompletion {
             note_name, heading, ..
         } => {
-            if is_intralink {
-                format!("{sep}{heading}")
+            // Wiki-style links refer to headings without the `#` markers
+            let heading = if wiki {
+                heading.trim_start_matches(parser::HEADING_START).trim()
             } else {
-                format!("{note_name}{sep}{heading}")
+                heading.as_str()
+            };
+            match sep {
+                Some(sep) if is_intralink => format!("{sep}{heading}"),
+                None if is_intralink => heading.to_string(),
+                _ => format!("{note_name}{}{heading}", sep.unwrap_or(parser::SEP_AT)),
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        facts::FactsDB,
+        store::{NameMatching, NoteFilter, NoteFolder},
+    };
+    use lsp_types::{
+        CompletionContext, CompletionItemKind, CompletionTriggerKind, Position,
+        TextDocumentIdentifier, TextDocumentPositionParams, Url,
+    };
+    use pretty_assertions::assert_eq;
+
+    fn workspace(root: &Path, notes: &[(&str, &str)]) -> Workspace {
+        Workspace {
+            folders: vec![(NoteFolder::from_root_path(root), NoteFilter::default())],
+            facts: FactsDB::from_texts(root, notes),
+        }
+    }
+
+    fn complete(workspace: &Workspace, file: &Path, pos: Position) -> Vec<CompletionItem> {
+        complete_triggered(workspace, file, pos, None)
+    }
+
+    fn complete_triggered(
+        workspace: &Workspace,
+        file: &Path,
+        pos: Position,
+        trigger: Option<char>,
+    ) -> Vec<CompletionItem> {
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::from_file_path(file).unwrap(),
+                },
+                position: pos,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: trigger.map(|c| CompletionContext {
+                trigger_kind: CompletionTriggerKind::TRIGGER_CHARACTER,
+                trigger_character: Some(c.to_string()),
+            }),
+        };
+        completion_candidates(workspace, params, &Config::default(), true).unwrap_or_default()
+    }
+
+    fn complete_with_format(
+        workspace: &Workspace,
+        file: &Path,
+        pos: Position,
+        format: LinkFormat,
+    ) -> Vec<CompletionItem> {
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::from_file_path(file).unwrap(),
+                },
+                position: pos,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let config = Config {
+            completion_link_format: format,
+            ..Config::default()
+        };
+        completion_candidates(workspace, params, &config, true).unwrap_or_default()
+    }
+
+    fn new_text(item: &CompletionItem) -> String {
+        match &item.text_edit {
+            Some(lsp_types::CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+            other => panic!("Unexpected text edit: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn complete_headings_of_current_note() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[:#]\n\n## First\n\n### Second\n"),
+                ("b.md", "# B\n\n## Other\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 3));
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["## First", "### Second"]);
+        assert!(items
+            .iter()
+            .all(|i| i.kind == Some(CompletionItemKind::REFERENCE)));
+        assert_eq!(new_text(&items[0]), "## First");
+    }
+
+    #[test]
+    fn complete_headings_after_separator() {
+        let root = Path::new("/notes");
+        let ws = workspace(root, &[("a.md", "# A\n\n[:@]\n\n## First\n")]);
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 3));
+        assert_eq!(items.len(), 1);
+        assert_eq!(new_text(&items[0]), "@## First");
+        assert_eq!(items[0].detail.as_deref(), Some("Heading level 2"));
+    }
+
+    #[test]
+    fn note_completion_details() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[:]\n"),
+                ("sub/b.md", "# Bee\n\nFirst paragraph\nof b.\n\nSecond.\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 2));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "Bee");
+        assert_eq!(items[0].detail.as_deref(), Some("sub/b.md"));
+        assert_eq!(
+            items[0].documentation,
+            Some(markdown("# Bee\n\nFirst paragraph\nof b.".to_string()))
+        );
+    }
+
+    #[test]
+    fn complete_tags_by_frequency() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\nText #\n\n#to\n"),
+                ("b.md", "# B\n\n#done #todo #todo\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 6));
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["#todo", "#done", "#to"]);
+        assert_eq!(
+            items[0].text_edit,
+            Some(
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 5), Position::new(2, 6)),
+                    new_text: "#todo".to_string()
+                }
+                .into()
+            )
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(4, 3));
+        assert_eq!(new_text(&items[0]), "#todo");
+        assert_eq!(
+            items[0].text_edit.as_ref().map(|e| match e {
+                lsp_types::CompletionTextEdit::Edit(e) => e.range,
+                _ => unreachable!(),
+            }),
+            Some(lsp_types::Range::new(
+                Position::new(4, 0),
+                Position::new(4, 3)
+            ))
+        );
+    }
+
+    #[test]
+    fn complete_note_names_fuzzy() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[:arch]\n"),
+                ("system-architecture.md", "# System\n"),
+                ("architecture.md", "# Overview\n"),
+                ("notes.md", "# A Rough Chart\n"),
+                ("other.md", "# Other\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 5));
+        let completed: Vec<_> = items
+            .iter()
+            .map(|i| (new_text(i), i.sort_text.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            completed,
+            vec![
+                ("architecture".to_string(), "00000".to_string()),
+                ("system-architecture".to_string(), "00001".to_string()),
+                ("notes".to_string(), "00002".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn complete_nearer_notes_first() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("projects/a.md", "# A\n\n[:pla]\n"),
+                ("archive/plan.md", "# Plan\n"),
+                ("projects/old/plan.md", "# Plan\n"),
+                ("projects/plan.md", "# Plan\n"),
+                ("planning.md", "# Planning\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("projects/a.md"), Position::new(2, 5));
+        let mut items: Vec<_> = items
+            .into_iter()
+            .map(|i| (i.sort_text.unwrap(), i.detail.unwrap()))
+            .collect();
+        items.sort();
+        let paths: Vec<_> = items.into_iter().map(|(_, path)| path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "projects/plan.md",
+                "projects/old/plan.md",
+                "archive/plan.md",
+                // A worse match despite being closer than the archive
+                "planning.md",
+            ]
+        );
+    }
+
+    #[test]
+    fn tag_prefix() {
+        assert_eq!(tag_prefix_start("Text #"), Some(5));
+        assert_eq!(tag_prefix_start("Text #proj/fo"), Some(5));
+        assert_eq!(tag_prefix_start("#tag"), Some(0));
+        assert_eq!(tag_prefix_start("#"), None);
+        assert_eq!(tag_prefix_start("a#b"), None);
+        assert_eq!(tag_prefix_start("Text ##"), None);
+        assert_eq!(tag_prefix_start("Text [:#"), None);
+        assert_eq!(tag_prefix_start("Text"), None);
+    }
+
+    #[test]
+    fn complete_wiki_link_headings() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[("a.md", "# A\n\n[[b#]]\n"), ("b.md", "# B\n\n## Other\n")],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 4));
+        assert_eq!(items.len(), 1);
+        assert_eq!(new_text(&items[0]), "b#Other");
+    }
+
+    #[test]
+    fn headings_sorted_in_document_order() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:#] [:b@] [x](b#)\n\n## Zeta\n\n### Beta\n\n## Alpha\n",
+                ),
+                ("b.md", "# B\n\n## Second\n\n## First\n"),
+            ],
+        );
+        let a = root.join("a.md");
+        let by_sort_text = |mut items: Vec<CompletionItem>| {
+            items.sort_by_key(|i| i.sort_text.clone().unwrap());
+            items.into_iter().map(|i| i.label).collect::<Vec<_>>()
+        };
+
+        let items = complete(&ws, &a, Position::new(2, 3));
+        assert_eq!(by_sort_text(items), vec!["## Zeta", "### Beta", "## Alpha"]);
+
+        let items = complete(&ws, &a, Position::new(2, 9));
+        assert_eq!(by_sort_text(items), vec!["## Second", "## First"]);
+
+        let items = complete_triggered(&ws, &a, Position::new(2, 17), Some('#'));
+        assert_eq!(by_sort_text(items), vec!["## Second", "## First"]);
+    }
+
+    fn complete_md_link(
+        workspace: &Workspace,
+        file: &Path,
+        pos: Position,
+        snippets: bool,
+    ) -> Vec<CompletionItem> {
+        let (_, facts) = workspace.owning_folder(file).unwrap();
+        let note = facts.note_facts(facts.note_index().find_by_path(file).unwrap());
+        md_link_candidates(facts, &note, &pos, &Config::default(), snippets).unwrap_or_default()
+    }
+
+    #[test]
+    fn complete_md_link_snippet() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("sub/a.md", "# A\n\nSee [](\n\nAnd [](b)\n"),
+                ("b.md", "# B $1\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("sub/a.md"), Position::new(2, 7));
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].label, "B $1");
+        assert_eq!(items[0].insert_text_format, Some(InsertTextFormat::SNIPPET));
+        assert_eq!(
+            items[0].text_edit,
+            Some(
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 5), Position::new(2, 7)),
+                    new_text: "${1:B \\$1}](../b.md)$0".to_string()
+                }
+                .into()
+            )
+        );
+
+        // The existing `)` is replaced rather than duplicated
+        let items = complete(&ws, &root.join("sub/a.md"), Position::new(4, 7));
+        assert_eq!(
+            items[0].text_edit,
+            Some(
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(4, 5), Position::new(4, 9)),
+                    new_text: "${1:B \\$1}](../b.md)$0".to_string()
+                }
+                .into()
+            )
+        );
+
+        // Without snippets an empty label is left for the user to fill in
+        let items = complete_md_link(&ws, &root.join("sub/a.md"), Position::new(2, 7), false);
+        assert_eq!(new_text(&items[0]), "../b.md)");
+
+        // Same for clients that don't support snippets
+        let params = CompletionParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier {
+                    uri: Url::from_file_path(root.join("sub/a.md")).unwrap(),
+                },
+                position: Position::new(2, 7),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        };
+        let items = completion_candidates(&ws, params, &Config::default(), false).unwrap();
+        assert_eq!(
+            items[0].insert_text_format,
+            Some(InsertTextFormat::PLAIN_TEXT)
+        );
+        assert_eq!(new_text(&items[0]), "../b.md)");
+    }
+
+    #[test]
+    fn complete_md_link_target() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[Bee](sub/\n\n[Bee](x)\n\n![img](\n"),
+                ("sub/b.md", "# B\n"),
+            ],
+        );
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(2, 10));
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].insert_text_format,
+            Some(InsertTextFormat::PLAIN_TEXT)
+        );
+        assert_eq!(new_text(&items[0]), "sub/b.md)");
+
+        let items = complete(&ws, &root.join("a.md"), Position::new(4, 6));
+        assert_eq!(new_text(&items[0]), "sub/b.md");
+
+        assert!(complete(&ws, &root.join("a.md"), Position::new(6, 7)).is_empty());
+    }
+
+    #[test]
+    fn complete_md_link_excludes_current_note() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("foo.md", "# Foo\n\n[x](\n\n[y](#\n\n## Own\n"),
+                ("food.md", "# Food\n"),
+            ],
+        );
+        let foo = root.join("foo.md");
+
+        let items = complete_triggered(&ws, &foo, Position::new(2, 4), Some('('));
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["Food"]);
+
+        // Headings of the current note are still there
+        let items = complete_triggered(&ws, &foo, Position::new(4, 5), Some('#'));
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["## Own"]);
+    }
+
+    #[test]
+    fn normalized_names_in_completion() {
+        let mut facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                ("a.md", "# A\n\n[:My Idea] [:my_idea]\n"),
+                ("my_note.md", "# Project Plan\n"),
+            ],
+        );
+        let a = facts.note_index().find_by_name(&"a".into()).unwrap();
+        let titles = |facts: &FactsDB, query: &str| {
+            notes_matching(facts, a, query)
+                .into_iter()
+                .map(|(_, title)| title)
+                .collect::<Vec<_>>()
+        };
+
+        assert!(titles(&facts, "my-no").is_empty());
+        assert_eq!(missing_notes_matching(&facts, "").len(), 2);
+
+        facts.set_name_matching(NameMatching::Normalized);
+        assert_eq!(titles(&facts, "my-no"), vec!["Project Plan"]);
+        assert_eq!(
+            missing_notes_matching(&facts, ""),
+            vec![NoteName::from("My Idea")]
+        );
+        // The name being typed isn't offered, however it's written
+        assert!(missing_notes_matching(&facts, "MY IDEA").is_empty());
+    }
+
+    #[test]
+    fn md_link_prefix_offsets() {
+        assert_eq!(md_link_prefix("See []("), Some((5, 7)));
+        assert_eq!(md_link_prefix("[label](../no"), Some((1, 8)));
+        assert_eq!(md_link_prefix("[label](no)"), None);
+        assert_eq!(md_link_prefix("[label](a b"), None);
+        assert_eq!(md_link_prefix("![img]("), None);
+        assert_eq!(md_link_prefix("text]("), None);
+    }
+
+    #[test]
+    fn ref_label_prefix_offsets() {
+        assert_eq!(ref_label_prefix("See [x]["), Some(8));
+        assert_eq!(ref_label_prefix("[label][re"), Some(8));
+        assert_eq!(ref_label_prefix("[label][ref]"), None);
+        assert_eq!(ref_label_prefix("[[note"), None);
+        assert_eq!(ref_label_prefix("text]["), None);
+    }
+
+    #[test]
+    fn complete_ref_labels() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\nSee [x][ and [y][n]\n\n[one]: b.md\n[two]: b.md#sub \"Sub\"\n[One]: c.md\n",
+                ),
+                ("b.md", "# B\n\n[three]: a.md\n"),
+                ("c.md", "# C\n\nSee [x][\n"),
+            ],
+        );
+        let a = root.join("a.md");
+
+        // Definitions of the current note only, the first one of a label wins
+        let items = complete_triggered(&ws, &a, Position::new(2, 8), Some('['));
+        let labels: Vec<_> = items
+            .iter()
+            .map(|i| (i.label.as_str(), i.detail.as_deref().unwrap(), new_text(i)))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("one", "b.md", "one]".to_string()),
+                ("two", "b.md#sub \"Sub\"", "two]".to_string())
+            ]
+        );
+
+        // An existing `]` is kept, and the rest of the label is replaced
+        let items = complete(&ws, &a, Position::new(2, 18));
+        assert_eq!(items.len(), 1);
+        assert_eq!(new_text(&items[0]), "one");
+        match &items[0].text_edit {
+            Some(lsp_types::CompletionTextEdit::Edit(edit)) => assert_eq!(
+                edit.range,
+                lsp_types::Range::new(Position::new(2, 17), Position::new(2, 18))
+            ),
+            other => panic!("Unexpected text edit: {:?}", other),
+        }
+
+        // Nothing to complete without definitions
+        let c = root.join("c.md");
+        assert!(complete_triggered(&ws, &c, Position::new(2, 8), Some('[')).is_empty());
+    }
+
+    #[test]
+    fn complete_by_trigger_character() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:#]\n\nText #\n\n[Bee](\n\nSome (\n\n## Sub\n",
+                ),
+                ("b.md", "# B\n\n#todo\n"),
+            ],
+        );
+        let a = root.join("a.md");
+        let labels =
+            |items: Vec<CompletionItem>| items.into_iter().map(|i| i.label).collect::<Vec<_>>();
+
+        // `#` within a link completes headings, in the body it completes tags
+        let items = complete_triggered(&ws, &a, Position::new(2, 3), Some('#'));
+        assert_eq!(labels(items), vec!["## Sub"]);
+        let items = complete_triggered(&ws, &a, Position::new(4, 6), Some('#'));
+        assert_eq!(labels(items), vec!["#todo"]);
+
+        // `(` after a label completes notes
+        let items = complete_triggered(&ws, &a, Position::new(6, 6), Some('('));
+        assert_eq!(labels(items), vec!["B"]);
+
+        // Prose gets no completions
+        assert!(complete_triggered(&ws, &a, Position::new(8, 6), Some('(')).is_empty());
+        assert!(complete_triggered(&ws, &a, Position::new(4, 6), Some('[')).is_empty());
+        assert!(complete_triggered(&ws, &a, Position::new(6, 6), Some('#')).is_empty());
+    }
+
+    #[test]
+    fn complete_md_link_anchors() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                (
+                    "sub/a.md",
+                    "# A\n\n[x](../b.md#) [y](../b#my) [z](#)\n\n[w](missing# [v](../b#\n\n## Own Heading\n",
+                ),
+                ("b.md", "# B\n\n## My Heading!\n\n### Other\n"),
+            ],
+        );
+        let a = root.join("sub/a.md");
+
+        let items = complete_triggered(&ws, &a, Position::new(2, 12), Some('#'));
+        let completed: Vec<_> = items
+            .iter()
+            .map(|i| (i.label.as_str(), new_text(i)))
+            .collect();
+        assert_eq!(
+            completed,
+            vec![
+                ("## My Heading!", "my-heading".to_string()),
+                ("### Other", "other".to_string())
+            ]
+        );
+        assert_eq!(
+            items[0].text_edit,
+            Some(
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 12), Position::new(2, 12)),
+                    new_text: "my-heading".to_string()
+                }
+                .into()
+            )
+        );
+
+        // The partial anchor is replaced as a whole
+        let items = complete(&ws, &a, Position::new(2, 25));
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0].text_edit,
+            Some(
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 23), Position::new(2, 25)),
+                    new_text: "my-heading".to_string()
+                }
+                .into()
+            )
+        );
+
+        // Headings of the current note, the closing `)` is added if missing
+        let items = complete(&ws, &a, Position::new(2, 32));
+        assert_eq!(new_text(&items[0]), "own-heading");
+        let items = complete(&ws, &a, Position::new(4, 22));
+        assert_eq!(new_text(&items[0]), "my-heading)");
+
+        // No anchors for a note that doesn't exist
+        let items = complete_triggered(&ws, &a, Position::new(4, 12), Some('#'));
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn complete_in_link_format() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[:note]\n\n[x](note\n"),
+                ("My Note.md", "# Meeting Notes\n"),
+            ],
+        );
+        let file = root.join("a.md");
+        let completed = |format| {
+            let colon = complete_with_format(&ws, &file, Position::new(2, 6), format);
+            let md = complete_with_format(&ws, &file, Position::new(4, 8), format);
+            (new_text(&colon[0]), new_text(&md[0]))
+        };
+
+        assert_eq!(
+            completed(LinkFormat::Filename),
+            ("My Note".to_string(), "<My Note.md>)".to_string())
+        );
+        assert_eq!(
+            completed(LinkFormat::Slug),
+            ("my-note".to_string(), "my-note)".to_string())
+        );
+        assert_eq!(
+            completed(LinkFormat::Title),
+            ("Meeting Notes".to_string(), "<Meeting Notes>)".to_string())
+        );
+
+        // Completed links resolve to the note
+        let (_, facts) = ws.owning_folder(&file).unwrap();
+        let my_note = facts.note_index().find_by_path(&root.join("My Note.md"));
+        for name in ["My Note", "my-note", "Meeting Notes", "meeting-notes"] {
+            assert_eq!(facts::resolve_note_name(facts.db(), &name.into()), my_note);
+        }
+    }
+
+    #[test]
+    fn complete_notes_not_yet_created() {
+        let root = Path::new("/notes");
+        let ws = workspace(
+            root,
+            &[
+                ("a.md", "# A\n\n[:road]\n\n[x](road\n"),
+                ("b.md", "# B\n\n[x](roadmap) [[roadmap]] [:road trip]\n"),
+                ("c.md", "# C\n\n[y](roadmap) [:roads]\n"),
+                ("roads.md", "# Roads\n"),
+            ],
+        );
+        let file = root.join("a.md");
+        let completed = |items: Vec<CompletionItem>| {
+            items
+                .iter()
+                .map(|i| (new_text(i), i.detail.clone().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        let missing = MISSING_NOTE_DETAIL.to_string();
+
+        assert_eq!(
+            completed(complete(&ws, &file, Position::new(2, 6))),
+            vec![
+                ("roads".to_string(), "roads.md".to_string()),
+                ("roadmap".to_string(), missing.clone()),
+                ("road trip".to_string(), missing.clone()),
+            ]
+        );
+        assert_eq!(
+            completed(complete_md_link(&ws, &file, Position::new(4, 8), false)),
+            vec![
+                ("roads.md)".to_string(), "roads.md".to_string()),
+                ("roadmap)".to_string(), missing.clone()),
+                ("<road trip>)".to_string(), missing),
+            ]
+        );
+    }
+}
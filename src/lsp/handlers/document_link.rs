@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use lsp_types::{DocumentLink, Url};
+
+use crate::facts::{Facts, NoteFacts, NoteFactsDB, NoteFactsExt};
+
+/// A document link for every link to a note in `note`. The target is the URL of
+/// the linked note with the anchor of the linked heading as a fragment.
+///
+/// Broken links have no target.
+pub fn document_links(note: &impl NoteFactsExt, facts: &dyn Facts) -> Vec<DocumentLink> {
+    let strukt = note.structure();
+    let indexed_text = note.indexed_text();
+    let valid: HashMap<_, _> = note
+        .valid_intern_links()
+        .iter()
+        .map(|&(link_id, note_id, heading_id)| (link_id, (note_id, heading_id)))
+        .collect();
+
+    let mut links = Vec::new();
+    for &link_id in note.intern_link_ids().iter() {
+        let link = strukt.intern_link_by_id(link_id);
+        let range = match indexed_text.range_to_lsp_range(&link.span) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        let target = valid.get(&link_id).and_then(|&(note_id, heading_id)| {
+            let target_note = NoteFactsDB::new(facts, note_id);
+            let mut url = Url::from_file_path(&target_note.file().path).ok()?;
+            // Links to a note point to its title, no need for an anchor
+            match (&link.heading, heading_id) {
+                (Some(_), Some(heading_id)) => {
+                    let heading = target_note.structure().heading_by_id(heading_id).slug();
+                    url.set_fragment(Some(&heading));
+                }
+                // A line anchor like `L10-L20` is kept as is
+                (Some(anchor), None) => url.set_fragment(Some(anchor)),
+                _ => {}
+            }
+            Some(url)
+        });
+
+        links.push(DocumentLink {
+            range,
+            target,
+            tooltip: None,
+            data: None,
+        });
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use lsp_types::{Position, Range};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn links_to_notes_and_headings() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:b] [:b@## Sub Heading] [:c] [:@## Own]\n\n## Own\n",
+                ),
+                ("b.md", "# B\n\n## Sub Heading\n"),
+            ],
+        );
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+
+        let links = document_links(&note, facts.db())
+            .into_iter()
+            .map(|l| (l.range, l.target.map(|t| t.to_string())))
+            .collect::<Vec<_>>();
+        let range = |start, end| Range::new(Position::new(2, start), Position::new(2, end));
+        assert_eq!(
+            links,
+            vec![
+                (range(0, 4), Some("file:///notes/b.md".to_string())),
+                (
+                    range(5, 24),
+                    Some("file:///notes/b.md#sub-heading".to_string())
+                ),
+                (range(25, 29), None),
+                (range(30, 40), Some("file:///notes/a.md#own".to_string())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use lsp_types::{FoldingRange, FoldingRangeKind, FoldingRangeParams};
+
+use crate::{facts::NoteFactsExt, parser::Heading, store::Workspace};
+
+pub fn folding_range_request(
+    workspace: &Workspace,
+    params: FoldingRangeParams,
+) -> Option<Vec<FoldingRange>> {
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    Some(folding_ranges(&note))
+}
+
+/// A fold for the section of every heading: from the heading line to the line
+/// before the next heading of the same or higher level, or to the end of the note.
+pub fn folding_ranges(note: &impl NoteFactsExt) -> Vec<FoldingRange> {
+    let strukt = note.structure();
+    let mut ranges = Vec::new();
+    for &id in note.headings().iter() {
+        let (start_line, end_line) = section_lines(strukt.heading_by_id(id));
+        if end_line <= start_line {
+            continue;
+        }
+
+        ranges.push(FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind: Some(FoldingRangeKind::Region),
+        });
+    }
+    ranges.sort_by_key(|r| (r.start_line, r.end_line));
+    ranges
+}
+
+/// First and last lines of the heading's section.
+pub(crate) fn section_lines(heading: &Heading) -> (u32, u32) {
+    let start_line = heading.scope.start.line;
+    // The section ends at the beginning of the next heading's line
+    let end_line = match heading.scope.end.col {
+        0 => heading.scope.end.line.saturating_sub(1),
+        _ => heading.scope.end.line,
+    };
+    (start_line, end_line.max(start_line))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use pretty_assertions::assert_eq;
+
+    fn folds(text: &str) -> Vec<(u32, u32)> {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(root, &[("a.md", text)]);
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+        folding_ranges(&note)
+            .into_iter()
+            .map(|r| {
+                assert_eq!(r.kind, Some(FoldingRangeKind::Region));
+                (r.start_line, r.end_line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fold_nested_sections() {
+        let text = "Intro\n\n# Title\n\nText\n\n## A\n\nA text\n\n### A.1\n\nMore\n\n## B\n\nB text\n## Empty\n";
+        assert_eq!(folds(text), vec![(2, 17), (6, 13), (10, 13), (14, 16)]);
+    }
+
+    #[test]
+    fn no_fold_for_single_line_section() {
+        assert_eq!(folds("# Title"), vec![]);
+        assert_eq!(folds("# Title\n## Sub\n"), vec![(0, 1)]);
+    }
+}
@@ -0,0 +1,107 @@
+use std::ops::Range;
+
+use lsp_document::Pos;
+use lsp_types::{DocumentRangeFormattingParams, TextEdit};
+
+use crate::{
+    facts::{self, FactsDB, NoteFacts, NoteFactsDB, NoteFactsExt},
+    store::Workspace,
+};
+
+pub fn range_formatting(
+    workspace: &Workspace,
+    params: DocumentRangeFormattingParams,
+) -> Option<Vec<TextEdit>> {
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let range = note.indexed_text().lsp_range_to_range(&params.range)?;
+    Some(normalize_anchors(facts, &note, &range))
+}
+
+/// Rewrite anchors of Markdown links within `range` to the slugs of the
+/// headings they point to, e.g. `[label](note#My Heading)` to
+/// `[label](note#my-heading)`.
+///
+/// Anchors that don't resolve to a heading are left as is.
+pub fn normalize_anchors(facts: &FactsDB, note: &NoteFactsDB, range: &Range<Pos>) -> Vec<TextEdit> {
+    let indexed_text = note.indexed_text();
+    let strukt = note.structure();
+
+    let mut edits = Vec::new();
+    for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+        if !link.is_markdown() || link.span.start < range.start || range.end < link.span.end {
+            continue;
+        }
+        let anchor = match &link.heading {
+            Some(anchor) => anchor,
+            _ => continue,
+        };
+        let target = match facts::resolve_link_target(facts.db(), note.id, link) {
+            Some(target) => facts.note_facts(target),
+            _ => continue,
+        };
+        let slug = match target.heading_for_link(anchor) {
+            Some(id) => target.structure().heading_by_id(id).slug(),
+            _ => continue,
+        };
+        if slug.is_empty() || slug == *anchor {
+            continue;
+        }
+
+        let edit_range = link
+            .heading_span()
+            .and_then(|span| indexed_text.range_to_lsp_range(&span));
+        if let Some(range) = edit_range {
+            edits.push(TextEdit {
+                range,
+                new_text: slug,
+            });
+        }
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use lsp_types::Position;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn anchors_become_slugs_within_range() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[x](b#First) [y](b#second-part) [z](#Own!)\n\n[w](b#First)\n\n## Own!\n",
+                ),
+                ("b.md", "# B\n\n## First\n\n## Second Part\n"),
+            ],
+        );
+        let id = facts
+            .note_index()
+            .find_by_path(Path::new("/notes/a.md"))
+            .unwrap();
+        let note = facts.note_facts(id);
+
+        let edits = normalize_anchors(&facts, &note, &(Pos::new(2, 0)..Pos::new(3, 0)));
+        assert_eq!(
+            edits,
+            vec![
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 6), Position::new(2, 11)),
+                    new_text: "first".to_string()
+                },
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(2, 37), Position::new(2, 41)),
+                    new_text: "own".to_string()
+                }
+            ]
+        );
+    }
+}
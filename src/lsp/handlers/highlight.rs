@@ -0,0 +1,141 @@
+use lsp_document::Pos;
+use lsp_types::{DocumentHighlight, DocumentHighlightKind, DocumentHighlightParams};
+
+use crate::{
+    facts::{NoteFacts, NoteFactsExt},
+    parser::HEADING_START,
+    store::Workspace,
+    structure::ElementID,
+};
+
+pub fn document_highlight_request(
+    workspace: &Workspace,
+    params: DocumentHighlightParams,
+) -> Option<Vec<DocumentHighlight>> {
+    let position = params.text_document_position_params;
+    let path = position.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let pos = note.indexed_text().lsp_pos_to_pos(&position.position)?;
+    Some(document_highlights(&note, pos))
+}
+
+/// Occurrences in the note of what's at `pos`: links to the same note as the
+/// link at `pos`, or headings with the same text as the heading at `pos`.
+///
+/// Broken links are the same when they have the same note name.
+pub fn document_highlights(note: &impl NoteFactsExt, pos: Pos) -> Vec<DocumentHighlight> {
+    let strukt = note.structure();
+    let (mut spans, kind) = match note.element_at_pos(pos) {
+        Some(ElementID::InternLink(link_id)) => {
+            let targets = note.link_targets();
+            let target = targets
+                .iter()
+                .find(|(id, _)| *id == link_id)
+                .and_then(|(_, target)| *target);
+            let name = &strukt.intern_link_by_id(link_id).note_name;
+            let spans: Vec<_> = targets
+                .iter()
+                .filter(|(id, other)| match (target, other) {
+                    (Some(target), Some(other)) => target == *other,
+                    (None, None) => strukt.intern_link_by_id(*id).note_name == *name,
+                    _ => false,
+                })
+                .map(|(id, _)| strukt.intern_link_by_id(*id).span.clone())
+                .collect();
+            (spans, DocumentHighlightKind::READ)
+        }
+        Some(ElementID::Heading(heading_id)) => {
+            let bare = |text: &str| text.trim_start_matches(HEADING_START).trim().to_string();
+            let text = bare(&strukt.heading_by_id(heading_id).text);
+            let spans: Vec<_> = strukt
+                .headings_with_ids(&note.headings())
+                .into_iter()
+                .filter(|hd| bare(&hd.text) == text)
+                .map(|hd| hd.span.clone())
+                .collect();
+            (spans, DocumentHighlightKind::TEXT)
+        }
+        _ => return Vec::new(),
+    };
+    spans.sort_by_key(|span| span.start);
+
+    let indexed_text = note.indexed_text();
+    spans
+        .iter()
+        .filter_map(|span| {
+            Some(DocumentHighlight {
+                range: indexed_text.range_to_lsp_range(span)?,
+                kind: Some(kind),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use pretty_assertions::assert_eq;
+
+    fn highlights(facts: &FactsDB, pos: Pos) -> Vec<(u32, u32, u32)> {
+        let note = facts.note_facts(
+            facts
+                .note_index()
+                .find_by_path(Path::new("/notes/a.md"))
+                .unwrap(),
+        );
+        document_highlights(&note, pos)
+            .into_iter()
+            .map(|h| {
+                assert_eq!(h.range.start.line, h.range.end.line);
+                (
+                    h.range.start.line,
+                    h.range.start.character,
+                    h.range.end.character,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn highlight_links_and_headings() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:b] and [[b]] and [:c] and [x](b)\n\n## Sub\n\n[:b@## Sub] [:c] text\n\n## Sub\n",
+                ),
+                ("b.md", "# B\n\n## Sub\n"),
+            ],
+        );
+
+        // Links to the same note, whatever their form
+        assert_eq!(
+            highlights(&facts, Pos::new(2, 1)),
+            vec![(2, 0, 4), (2, 9, 14), (2, 28, 34), (6, 0, 11)]
+        );
+        // Broken links to the same name
+        assert_eq!(
+            highlights(&facts, Pos::new(2, 20)),
+            vec![(2, 19, 23), (6, 12, 16)]
+        );
+
+        let note = facts.note_facts(
+            facts
+                .note_index()
+                .find_by_path(Path::new("/notes/a.md"))
+                .unwrap(),
+        );
+        let headings = document_highlights(&note, Pos::new(4, 3));
+        let lines: Vec<_> = headings.iter().map(|h| h.range.start.line).collect();
+        assert_eq!(lines, vec![4, 8]);
+        assert_eq!(headings[0].kind, Some(DocumentHighlightKind::TEXT));
+
+        // Nothing over prose
+        assert_eq!(highlights(&facts, Pos::new(2, 6)), vec![]);
+    }
+}
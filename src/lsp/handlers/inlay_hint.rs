@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+use lsp_document::Pos;
+use lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, InlayHintParams};
+
+use crate::{
+    config::Config,
+    facts::{Facts, NoteFacts, NoteFactsDB, NoteFactsExt},
+    store::Workspace,
+};
+
+/// Label of the hint of a link that doesn't resolve to a note.
+const UNRESOLVED_LABEL: &str = "⚠ unresolved";
+
+pub fn inlay_hint_request(
+    workspace: &Workspace,
+    params: InlayHintParams,
+    config: &Config,
+) -> Option<Vec<InlayHint>> {
+    if !config.inlay_hints {
+        return None;
+    }
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let range = note.indexed_text().lsp_range_to_range(&params.range)?;
+    Some(inlay_hints(&note, facts.db(), range))
+}
+
+/// A hint after every link to another note that ends in `range`, with the file
+/// name of the note the link resolves to, e.g. `note.md` for `[:My Note]`.
+/// Links to the note itself like `[:@## Heading]` get no hints.
+pub fn inlay_hints(
+    note: &impl NoteFactsExt,
+    facts: &dyn Facts,
+    range: Range<Pos>,
+) -> Vec<InlayHint> {
+    let strukt = note.structure();
+    let indexed_text = note.indexed_text();
+
+    let mut hints = Vec::new();
+    for &(link_id, target) in note.link_targets().iter() {
+        let link = strukt.intern_link_by_id(link_id);
+        let in_range = range.start <= link.span.end && link.span.end <= range.end;
+        if link.note_name.is_none() || !in_range {
+            continue;
+        }
+        let position = match indexed_text.pos_to_lsp_pos(&link.span.end) {
+            Some(position) => position,
+            None => continue,
+        };
+
+        let label = target
+            .and_then(|note_id| {
+                let file = NoteFactsDB::new(facts, note_id).file();
+                Some(file.path.file_name()?.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| UNRESOLVED_LABEL.to_string());
+        hints.push(InlayHint {
+            position,
+            label: InlayHintLabel::String(label),
+            kind: Some(InlayHintKind::TYPE),
+            text_edits: None,
+            tooltip: None,
+            padding_left: Some(true),
+            padding_right: None,
+            data: None,
+        });
+    }
+    hints.sort_by_key(|hint| (hint.position.line, hint.position.character));
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn hints_with_resolved_file_names() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:My Note] [x](sub/b) [:ghost] [:@## Own]\n\n[:sub/b]\n\n## Own\n",
+                ),
+                ("my-note.md", "# My Note\n"),
+                ("sub/b.md", "# B\n"),
+            ],
+        );
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+        let hints = |range| {
+            inlay_hints(&note, facts.db(), range)
+                .into_iter()
+                .map(|hint| match hint.label {
+                    InlayHintLabel::String(label) => (hint.position, label),
+                    other => panic!("Unexpected label: {:?}", other),
+                })
+                .collect::<Vec<_>>()
+        };
+        let pos = lsp_types::Position::new;
+
+        assert_eq!(
+            hints(Pos::new(0, 0)..Pos::new(8, 0)),
+            vec![
+                (pos(2, 10), "my-note.md".to_string()),
+                (pos(2, 21), "b.md".to_string()),
+                (pos(2, 30), UNRESOLVED_LABEL.to_string()),
+                (pos(4, 8), "b.md".to_string()),
+            ]
+        );
+        // Only links ending in the range
+        assert_eq!(
+            hints(Pos::new(2, 11)..Pos::new(2, 31)),
+            vec![
+                (pos(2, 21), "b.md".to_string()),
+                (pos(2, 30), UNRESOLVED_LABEL.to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1026 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::Path,
+};
+
+use lsp_document::{Pos, TextMap};
+
+use lsp_types::{
+    CreateFile, CreateFileOptions, DocumentChangeOperation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, PrepareRenameResponse, RenameFile, RenameFileOptions,
+    RenameParams, ResourceOp, TextDocumentEdit, TextDocumentPositionParams, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+use tracing::debug;
+
+use crate::{
+    config::Config,
+    facts::{self, Facts, NoteFacts, NoteFactsDB, NoteFactsExt},
+    lsp::handlers::{code_action, folding},
+    parser::{self, InternLink, Node, NoteName, HEADING_START},
+    store::Workspace,
+    structure::{ElementID, HeadingID, InternLinkID, NoteID},
+    util,
+};
+
+pub fn prepare(
+    workspace: &Workspace,
+    params: TextDocumentPositionParams,
+) -> Option<PrepareRenameResponse> {
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let indexed_text = note.indexed_text();
+
+    let pos = indexed_text.lsp_pos_to_pos(&params.position)?;
+    let span = prepare_rename(&note, pos)?;
+    let range = indexed_text.range_to_lsp_range(&span)?;
+    Some(PrepareRenameResponse::Range(range))
+}
+
+/// Span of what [rename] is going to change when invoked at `pos`: the note
+/// name of a link, or the text of a heading without the leading `#`.
+pub fn prepare_rename(note: &impl NoteFactsExt, pos: Pos) -> Option<Range<Pos>> {
+    let strukt = note.structure();
+    match note.element_at_pos(pos)? {
+        ElementID::InternLink(id) => strukt.intern_link_by_id(id).note_name_span(),
+        ElementID::Heading(id) => Some(strukt.heading_by_id(id).text_span()),
+        _ => None,
+    }
+}
+
+pub fn rename(workspace: &Workspace, params: RenameParams) -> Option<WorkspaceEdit> {
+    let path = params
+        .text_document_position
+        .text_document
+        .uri
+        .to_file_path()
+        .unwrap();
+    let pos = params.text_document_position.position;
+
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let strukt = note.structure();
+
+    let old_name = match note.element_at_lsp_pos(&pos)? {
+        ElementID::InternLink(id) => strukt.intern_link_by_id(id).note_name.clone()?,
+        ElementID::Heading(id) if note.title() == Some(id) => (*note.file().name).clone(),
+        ElementID::Heading(id) => {
+            return rename_heading(facts.db(), note.id(), id, params.new_name.trim())
+        }
+        _ => return None,
+    };
+    let new_name: NoteName = params.new_name.trim().into();
+    debug!("rename: {} -> {}", old_name, new_name);
+
+    rename_note(facts.db(), &old_name, &new_name)
+}
+
+/// Rename the note `old` into `new` and rewrite all links that point to it:
+/// path links get the path relative to the new location, links by the note
+/// name get the new name.
+///
+/// Links without an explicit note name (links within a note) and links only by
+/// the title of the note are left as is, as they keep pointing to the note.
+pub fn rename_note(facts: &dyn Facts, old: &NoteName, new: &NoteName) -> Option<WorkspaceEdit> {
+    let index = facts.note_index(());
+    let old_id = index.find_by_name(old)?;
+    let old_file = index.find_by_id(old_id);
+    // Keep the extension of the renamed note, e.g. `.markdown`
+    let old_ext = old_file
+        .path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("md");
+    let new_path = new.to_path_with_extension(&old_file.root, old_ext);
+    let old_slug = facts::note_name_slug(old.to_str());
+    let is_by_name =
+        |name: &NoteName| index.find_by_name(name) == Some(old_id) || name.to_str() == old_slug;
+
+    let mut edits = Vec::new();
+    for note_id in index.ids() {
+        let note = NoteFactsDB::new(facts, note_id);
+        let strukt = note.structure();
+        let indexed_text = note.indexed_text();
+        // Links within the renamed note are relative to its new location
+        let file = note.file();
+        let from_dir = if note_id == old_id {
+            new_path.parent()
+        } else {
+            file.path.parent()
+        };
+
+        let mut note_edits = Vec::new();
+        for link in strukt.intern_links_with_ids(&note.intern_link_ids()) {
+            let name = match &link.note_name {
+                Some(name) => name,
+                None => continue,
+            };
+            if facts::resolve_link_target(facts, note_id, link) != Some(old_id) {
+                continue;
+            }
+
+            let new_text = if link.is_path() {
+                relative_dest(link, from_dir?, &new_path)
+            } else if is_by_name(name) {
+                new.to_string()
+            } else {
+                continue;
+            };
+            let range = link
+                .note_name_span()
+                .and_then(|span| indexed_text.range_to_lsp_range(&span));
+            if let Some(range) = range {
+                let new_text = link.dest_part(&new_text);
+                note_edits.push(TextEdit { range, new_text });
+            }
+        }
+
+        if !note_edits.is_empty() {
+            edits.push(text_document_edit(&note, note_edits)?);
+        }
+    }
+
+    let rename_file = DocumentChangeOperation::Op(ResourceOp::Rename(RenameFile {
+        old_uri: Url::from_file_path(&old_file.path).ok()?,
+        new_uri: Url::from_file_path(&new_path).ok()?,
+        options: Some(RenameFileOptions {
+            overwrite: Some(false),
+            ignore_if_exists: None,
+        }),
+        annotation_id: None,
+    }));
+
+    // Text edits refer to the old file paths, so the file rename goes last
+    let mut operations = edits
+        .into_iter()
+        .map(DocumentChangeOperation::Edit)
+        .collect::<Vec<_>>();
+    operations.push(rename_file);
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..WorkspaceEdit::default()
+    })
+}
+
+/// Rename the heading `heading_id` of the note `note_id` into `new` (without the
+/// leading `#`) and rewrite all links that point to it.
+///
+/// Links keep referring to the heading the way they did: by the text, with or
+/// without `#`, or by the anchor like `[label](note#my-heading)`.
+pub fn rename_heading(
+    facts: &dyn Facts,
+    note_id: NoteID,
+    heading_id: HeadingID,
+    new: &str,
+) -> Option<WorkspaceEdit> {
+    let note = NoteFactsDB::new(facts, note_id);
+    let strukt = note.structure();
+    let heading = strukt.heading_by_id(heading_id);
+    let unmarked = heading.text.trim_start_matches(HEADING_START);
+    let marks = &heading.text[..heading.text.len() - unmarked.len()];
+    let old_text = unmarked.trim();
+    debug!("rename_heading: {} -> {}", old_text, new);
+
+    let new_link_text = |link_heading: &str| {
+        if link_heading == heading.text {
+            format!("{} {}", marks, new)
+        } else if link_heading.trim() == old_text {
+            new.to_string()
+        } else if link_heading.starts_with(HEADING_START) {
+            format!("{}{}", HEADING_START, parser::slugify(new))
+        } else {
+            parser::slugify(new)
+        }
+    };
+
+    let mut link_edits = link_edits(
+        facts,
+        &note.intern_links_to_heading(heading_id),
+        |_, link| {
+            let new_text = new_link_text(link.heading.as_ref()?);
+            Some((link.heading_span()?, link.dest_part(&new_text)))
+        },
+    );
+    let mut edits = Vec::new();
+    for cur_id in facts.note_index(()).ids() {
+        let cur_note = NoteFactsDB::new(facts, cur_id);
+        let mut note_edits = Vec::new();
+        if cur_id == note_id {
+            note_edits.push((heading.text_span(), new.to_string()));
+        }
+        note_edits.extend(link_edits.remove(&cur_id).unwrap_or_default());
+
+        if !note_edits.is_empty() {
+            let note_edits = text_edits(&cur_note, note_edits);
+            edits.push(text_document_edit(&cur_note, note_edits)?);
+        }
+    }
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        ..WorkspaceEdit::default()
+    })
+}
+
+/// Move the heading `heading_id` with its section from the note `source_id`
+/// to the end of the note `target_id` and rewrite links to the headings of the
+/// section to point to the target note.
+///
+/// The section spans until the next heading of the same or higher level, the
+/// same as its fold. Links within the section are moved along with it, see
+/// [`section_link_edits`].
+pub fn move_section(
+    facts: &dyn Facts,
+    source_id: NoteID,
+    heading_id: HeadingID,
+    target_id: NoteID,
+) -> Option<WorkspaceEdit> {
+    let source = NoteFactsDB::new(facts, source_id);
+    if source_id == target_id || source.title() == Some(heading_id) {
+        return None;
+    }
+    let strukt = source.structure();
+    let (start_line, end_line) = folding::section_lines(strukt.heading_by_id(heading_id));
+    debug!("move_section: lines {}..={}", start_line, end_line);
+
+    let index = facts.note_index(());
+    let target_file = index.find_by_id(target_id);
+    let retarget = |link: &Node<InternLink>, _: HeadingID, from_dir: &Path| {
+        let new_name = link_target(link, from_dir, &target_file.path, &target_file.name);
+        match link.note_name_span() {
+            Some(span) => Some((span, new_name)),
+            None => {
+                let (pos, sep) = link.note_name_insertion()?;
+                Some((pos..pos, format!("{}{}", new_name, sep)))
+            }
+        }
+    };
+    let (moved_edits, mut link_edits) =
+        section_link_edits(facts, &source, heading_id, &target_file.path, retarget)?;
+    let moved_text = section_text(&source, start_line, end_line, moved_edits)?;
+
+    let mut source_edits = link_edits.remove(&source_id).unwrap_or_default();
+    source_edits.push((
+        Pos::new(start_line, 0)..Pos::new(end_line + 1, 0),
+        String::new(),
+    ));
+
+    let target = NoteFactsDB::new(facts, target_id);
+    let target_text = target.indexed_text();
+    let target_content = target_text.text();
+    let mut separator = if target_content.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    }
+    .to_string();
+    if !target_content.trim().is_empty() && !target_content.ends_with("\n\n") {
+        separator.push('\n');
+    }
+    let end = target_text.pos_to_lsp_pos(&code_action::end_pos(&*target_text)?)?;
+    let mut target_edits = text_edits(&target, link_edits.remove(&target_id).unwrap_or_default());
+    target_edits.push(TextEdit {
+        range: lsp_types::Range::new(end, end),
+        new_text: format!("{}{}\n", separator, moved_text.trim_end()),
+    });
+
+    let mut edits = vec![
+        text_document_edit(&source, text_edits(&source, source_edits))?,
+        text_document_edit(&target, target_edits)?,
+    ];
+    for cur_id in index.ids() {
+        if let Some(note_edits) = link_edits.remove(&cur_id) {
+            let cur_note = NoteFactsDB::new(facts, cur_id);
+            edits.push(text_document_edit(
+                &cur_note,
+                text_edits(&cur_note, note_edits),
+            )?);
+        }
+    }
+
+    Some(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(edits)),
+        ..WorkspaceEdit::default()
+    })
+}
+
+/// Move the heading `heading_id` with its section from the note `source_id`
+/// into a new note next to it, named after the slug of the heading. Returns
+/// the URI of the new note and the edit.
+///
+/// The new note starts with `config.new_note_text` titled with the heading,
+/// followed by the rest of the section. The section is replaced with a link to
+/// the new note, links to the heading are rewritten to point to the new note
+/// itself and links to its subheadings to the subheadings in the new note.
+pub fn extract_note(
+    facts: &dyn Facts,
+    source_id: NoteID,
+    heading_id: HeadingID,
+    config: &Config,
+) -> Option<(Url, WorkspaceEdit)> {
+    let source = NoteFactsDB::new(facts, source_id);
+    if source.title() == Some(heading_id) {
+        return None;
+    }
+    let strukt = source.structure();
+    let heading = strukt.heading_by_id(heading_id);
+    let title = heading.text.trim_start_matches(HEADING_START).trim();
+    let slug = parser::slugify(title);
+    if slug.is_empty() {
+        return None;
+    }
+
+    let index = facts.note_index(());
+    let source_file = index.find_by_id(source_id);
+    let new_path = source_file.path.parent()?.join(format!("{}.md", slug));
+    if index.find_by_path(&new_path).is_some() || util::exists_with_any_extension(&new_path) {
+        return None;
+    }
+    let new_name = NoteName::from_path(&new_path, &source_file.root);
+    let (start_line, end_line) = folding::section_lines(heading);
+    debug!(
+        "extract_note: lines {}..={} into {}",
+        start_line, end_line, new_name
+    );
+
+    // Links to the heading lose the heading part and point to the new note
+    let retarget = |link: &Node<InternLink>, link_heading: HeadingID, from_dir: &Path| {
+        let new_text = link_target(link, from_dir, &new_path, &new_name);
+        let (name_span, sep) = match link.note_name_span() {
+            Some(span) => (span, ""),
+            None => {
+                let (pos, sep) = link.note_name_insertion()?;
+                (pos..pos, sep)
+            }
+        };
+        if link_heading == heading_id {
+            Some((name_span.start..link.heading_span()?.end, new_text))
+        } else {
+            Some((name_span, format!("{}{}", new_text, sep)))
+        }
+    };
+    let (moved_edits, mut link_edits) =
+        section_link_edits(facts, &source, heading_id, &new_path, retarget)?;
+    let moved_text = section_text(&source, start_line, end_line, moved_edits)?;
+
+    // The heading line itself is replaced by the title from the template
+    let mut new_text = config.new_note_text(title);
+    let body = moved_text
+        .split_once('\n')
+        .map_or("", |(_, body)| body)
+        .trim();
+    if !body.is_empty() {
+        if !new_text.ends_with('\n') {
+            new_text.push('\n');
+        }
+        if !new_text.ends_with("\n\n") {
+            new_text.push('\n');
+        }
+        new_text.push_str(body);
+        new_text.push('\n');
+    }
+
+    let line_count = source.text().content.lines().count() as u32;
+    let separator = if end_line + 1 < line_count {
+        "\n\n"
+    } else {
+        "\n"
+    };
+    let mut source_edits = link_edits.remove(&source_id).unwrap_or_default();
+    source_edits.push((
+        Pos::new(start_line, 0)..Pos::new(end_line + 1, 0),
+        format!("[:{}]{}", new_name, separator),
+    ));
+
+    let new_uri = Url::from_file_path(&new_path).ok()?;
+    let mut operations = vec![
+        DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+            uri: new_uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(false),
+            }),
+            annotation_id: None,
+        })),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: new_uri.clone(),
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit {
+                range: lsp_types::Range::default(),
+                new_text,
+            })],
+        }),
+        DocumentChangeOperation::Edit(text_document_edit(
+            &source,
+            text_edits(&source, source_edits),
+        )?),
+    ];
+    for cur_id in index.ids() {
+        if let Some(note_edits) = link_edits.remove(&cur_id) {
+            let cur_note = NoteFactsDB::new(facts, cur_id);
+            operations.push(DocumentChangeOperation::Edit(text_document_edit(
+                &cur_note,
+                text_edits(&cur_note, note_edits),
+            )?));
+        }
+    }
+
+    let edit = WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..WorkspaceEdit::default()
+    };
+    Some((new_uri, edit))
+}
+
+/// Replacement of the text at the span.
+type SpanEdit = (Range<Pos>, String);
+
+/// Edits by the notes they are in.
+type NoteEdits = HashMap<NoteID, Vec<SpanEdit>>;
+
+/// Link edits for moving the section of the heading `heading_id` out of the
+/// note `source` into the note at `new_path`: the edits of the moved text, and
+/// the edits of the links elsewhere by the notes they are in.
+///
+/// Links to the headings of the section are rewritten by `retarget`, given the
+/// directory the link is going to be in. Links from within the section without
+/// a note name keep pointing to the subheadings, as these move along, while
+/// the ones pointing to the rest of the source note get its name. Path links
+/// from within the section get paths relative to the new note.
+fn section_link_edits(
+    facts: &dyn Facts,
+    source: &NoteFactsDB,
+    heading_id: HeadingID,
+    new_path: &Path,
+    retarget: impl Fn(&Node<InternLink>, HeadingID, &Path) -> Option<SpanEdit>,
+) -> Option<(Vec<SpanEdit>, NoteEdits)> {
+    let index = facts.note_index(());
+    let source_id = source.id();
+    let source_file = source.file();
+    let strukt = source.structure();
+    let (start_line, end_line) = folding::section_lines(strukt.heading_by_id(heading_id));
+    let in_section = |span: &Range<Pos>| (start_line..=end_line).contains(&span.start.line);
+    let new_dir = new_path.parent()?;
+
+    let mut moved_edits = Vec::new();
+    let mut link_edits = NoteEdits::new();
+    let mut retargeted = HashSet::new();
+    for section_heading in source.headings_matching(|h| in_section(&h.scope)) {
+        for &(cur_id, link_id) in source.intern_links_to_heading(section_heading).iter() {
+            let cur_note = NoteFactsDB::new(facts, cur_id);
+            let cur_strukt = cur_note.structure();
+            let link = cur_strukt.intern_link_by_id(link_id);
+            let is_moved = cur_id == source_id && in_section(&link.span);
+            if cur_id == source_id {
+                retargeted.insert(link_id);
+            }
+            if is_moved && link.note_name.is_none() && section_heading != heading_id {
+                continue;
+            }
+
+            let from_dir = if is_moved {
+                new_dir.to_path_buf()
+            } else {
+                cur_note.file().path.parent()?.to_path_buf()
+            };
+            if let Some(edit) = retarget(link, section_heading, &from_dir) {
+                if is_moved {
+                    moved_edits.push(edit);
+                } else {
+                    link_edits.entry(cur_id).or_default().push(edit);
+                }
+            }
+        }
+    }
+
+    for &link_id in source.intern_link_ids().iter() {
+        let link = strukt.intern_link_by_id(link_id);
+        if retargeted.contains(&link_id) || !in_section(&link.span) {
+            continue;
+        }
+        let edit = match &link.note_name {
+            None => link.note_name_insertion().map(|(pos, sep)| {
+                let name = link_target(link, new_dir, &source_file.path, &source_file.name);
+                (pos..pos, format!("{}{}", name, sep))
+            }),
+            Some(name) if link.is_path() => facts::resolve_link_target(facts, source_id, link)
+                .map(|target_id| relative_dest(link, new_dir, &index.find_by_id(target_id).path))
+                .filter(|dest| dest != name.to_str())
+                .and_then(|dest| Some((link.note_name_span()?, link.dest_part(&dest)))),
+            Some(_) => None,
+        };
+        moved_edits.extend(edit);
+    }
+
+    Some((moved_edits, link_edits))
+}
+
+/// Text of the lines `start_line..=end_line` of the note with the `edits`
+/// within these lines applied.
+fn section_text(
+    note: &NoteFactsDB,
+    start_line: u32,
+    end_line: u32,
+    mut edits: Vec<(Range<Pos>, String)>,
+) -> Option<String> {
+    let indexed_text = note.indexed_text();
+    let start = Pos::new(start_line, 0);
+    let end = indexed_text.line_range(end_line)?.end;
+    let mut text = indexed_text.substr(start..end)?.to_string();
+
+    // Later edits go first, so that the offsets of the earlier ones stay valid
+    edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+    for (span, new_text) in edits {
+        let offset = |pos: Pos| indexed_text.substr(start..pos).map(str::len);
+        text.replace_range(offset(span.start)?..offset(span.end)?, &new_text);
+    }
+    Some(text)
+}
+
+/// How a link going to be in `from_dir` refers to the note at `path` named
+/// `name`: by a path relative to `from_dir` for path links, by the name otherwise.
+fn link_target(link: &Node<InternLink>, from_dir: &Path, path: &Path, name: &NoteName) -> String {
+    if link.is_path() {
+        link.dest_part(&relative_dest(link, from_dir, path))
+    } else {
+        link.dest_part(name.to_str())
+    }
+}
+
+/// Destination of the path `link` rewritten to point to the note file at
+/// `path` from `from_dir`, with or without the extension and the leading `./`
+/// like the original destination.
+fn relative_dest(link: &InternLink, from_dir: &Path, path: &Path) -> String {
+    let old_dest = link.note_name.as_ref().map_or("", |name| name.to_str());
+    let mut dest = util::relative_path(from_dir, path);
+    if Path::new(old_dest).extension().is_none() {
+        dest.set_extension("");
+    }
+    let dest = dest.to_string_lossy().replace('\\', "/");
+    if old_dest.starts_with("./") && !dest.starts_with("..") {
+        format!("./{}", dest)
+    } else {
+        dest
+    }
+}
+
+/// Edits of the `links` by the notes they are in, where `link_edit` gives the
+/// span of the link to replace and the new text.
+fn link_edits(
+    facts: &dyn Facts,
+    links: &[(NoteID, InternLinkID)],
+    link_edit: impl Fn(NoteID, &Node<InternLink>) -> Option<SpanEdit>,
+) -> NoteEdits {
+    let mut edits = NoteEdits::new();
+    for &(note_id, link_id) in links {
+        let note = NoteFactsDB::new(facts, note_id);
+        let strukt = note.structure();
+        if let Some(edit) = link_edit(note_id, strukt.intern_link_by_id(link_id)) {
+            edits.entry(note_id).or_default().push(edit);
+        }
+    }
+    edits
+}
+
+fn text_edits(note: &NoteFactsDB, edits: Vec<(Range<Pos>, String)>) -> Vec<TextEdit> {
+    let indexed_text = note.indexed_text();
+    edits
+        .into_iter()
+        .filter_map(|(span, new_text)| {
+            Some(TextEdit {
+                range: indexed_text.range_to_lsp_range(&span)?,
+                new_text,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn text_document_edit(
+    note: &NoteFactsDB,
+    edits: Vec<TextEdit>,
+) -> Option<TextDocumentEdit> {
+    Some(TextDocumentEdit {
+        text_document: OptionalVersionedTextDocumentIdentifier {
+            uri: Url::from_file_path(&note.file().path).ok()?,
+            version: note.text().version.to_lsp_version(),
+        },
+        edits: edits.into_iter().map(OneOf::Left).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use lsp_types::Position;
+    use pretty_assertions::assert_eq;
+
+    /// Collect text edits from a workspace edit grouped by a document.
+    fn edits_by_uri(edit: &WorkspaceEdit) -> HashMap<Url, Vec<TextEdit>> {
+        let mut by_uri: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        let edits = match &edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits.iter().collect::<Vec<_>>(),
+            Some(DocumentChanges::Operations(ops)) => ops
+                .iter()
+                .filter_map(|op| match op {
+                    DocumentChangeOperation::Edit(e) => Some(e),
+                    _ => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        for e in edits {
+            let text_edits = e.edits.iter().map(|te| match te {
+                OneOf::Left(te) => te.clone(),
+                OneOf::Right(ate) => ate.text_edit.clone(),
+            });
+            by_uri
+                .entry(e.text_document.uri.clone())
+                .or_default()
+                .extend(text_edits);
+        }
+        by_uri
+    }
+
+    fn edit(line: u32, start: u32, end: u32, text: &str) -> TextEdit {
+        TextEdit {
+            range: lsp_types::Range::new(Position::new(line, start), Position::new(line, end)),
+            new_text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn prepare_rename_spans() {
+        let facts = FactsDB::from_texts(
+            Path::new("/notes"),
+            &[
+                (
+                    "a.md",
+                    "# My  Title \n\nSee [:b@## Sub], [[b#Sub|B]].\n\n## Sub\n",
+                ),
+                ("b.md", "# B\n\n## Sub\n"),
+            ],
+        );
+        let note = facts.note_facts(
+            facts
+                .note_index()
+                .find_by_path(Path::new("/notes/a.md"))
+                .unwrap(),
+        );
+
+        assert_eq!(
+            prepare_rename(&note, Pos::new(0, 0)),
+            Some(Pos::new(0, 2)..Pos::new(0, 11))
+        );
+        assert_eq!(
+            prepare_rename(&note, Pos::new(2, 10)),
+            Some(Pos::new(2, 6)..Pos::new(2, 7))
+        );
+        assert_eq!(
+            prepare_rename(&note, Pos::new(2, 19)),
+            Some(Pos::new(2, 19)..Pos::new(2, 20))
+        );
+        assert_eq!(
+            prepare_rename(&note, Pos::new(4, 3)),
+            Some(Pos::new(4, 3)..Pos::new(4, 6))
+        );
+        // Prose
+        assert_eq!(prepare_rename(&note, Pos::new(2, 1)), None);
+    }
+
+    #[test]
+    fn rename_note_rewrites_links() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n[:@## Inner] and [:a@## Inner]\n\n## Inner\n",
+                ),
+                ("b.md", "# B\n\n[:a] and [:a@## Inner], [:c]\n"),
+            ],
+        );
+
+        let ws_edit = rename_note(facts.db(), &"a".into(), &"renamed".into()).unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(by_uri.len(), 2);
+        assert_eq!(by_uri[&uri("a.md")], vec![edit(2, 19, 20, "renamed")]);
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![edit(2, 2, 3, "renamed"), edit(2, 11, 12, "renamed")]
+        );
+
+        let ops = match ws_edit.document_changes {
+            Some(DocumentChanges::Operations(ops)) => ops,
+            other => panic!("Unexpected document changes: {:?}", other),
+        };
+        assert!(matches!(
+            ops.last(),
+            Some(DocumentChangeOperation::Op(ResourceOp::Rename(r)))
+                if r.old_uri == uri("a.md") && r.new_uri == uri("renamed.md")
+        ));
+    }
+
+    #[test]
+    fn rename_note_rewrites_path_links() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("sub/a.md", "# Alpha\n\n[self](a.md)\n"),
+                (
+                    "b.md",
+                    "# B\n\n[x](sub/a.md) [y](./sub/a) [:Alpha] [:sub/a] [z](a.md)\n",
+                ),
+                ("sub/c.md", "# C\n\n[x](a.md) [y](../sub/a)\n"),
+            ],
+        );
+
+        let ws_edit = rename_note(facts.db(), &"sub/a".into(), &"other/z".into()).unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(by_uri.len(), 3);
+        assert_eq!(by_uri[&uri("sub/a.md")], vec![edit(2, 7, 11, "z.md")]);
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![
+                edit(2, 4, 12, "other/z.md"),
+                edit(2, 18, 25, "./other/z"),
+                edit(2, 38, 43, "other/z"),
+            ]
+        );
+        assert_eq!(
+            by_uri[&uri("sub/c.md")],
+            vec![
+                edit(2, 4, 8, "../other/z.md"),
+                edit(2, 14, 22, "../other/z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename_percent_encoded_links() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                ("my note.md", "# My Note\n\n## Sub Heading\n"),
+                (
+                    "b.md",
+                    "# B\n\n[x](my%20note) [y](my%20note#Sub%20Heading) [z](<my note>)\n",
+                ),
+            ],
+        );
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        let ws_edit = rename_note(facts.db(), &"my note".into(), &"new note".into()).unwrap();
+        assert_eq!(
+            edits_by_uri(&ws_edit)[&uri("b.md")],
+            vec![
+                edit(2, 4, 13, "new%20note"),
+                edit(2, 19, 28, "new%20note"),
+                edit(2, 49, 56, "new note"),
+            ]
+        );
+
+        let note_id = facts.note_index().find_by_name(&"my note".into()).unwrap();
+        let heading = facts
+            .note_facts(note_id)
+            .heading_for_link("Sub Heading")
+            .unwrap();
+        let ws_edit = rename_heading(facts.db(), note_id, heading, "New Sub").unwrap();
+        assert_eq!(
+            edits_by_uri(&ws_edit)[&uri("b.md")],
+            vec![edit(2, 29, 42, "New%20Sub")]
+        );
+    }
+
+    #[test]
+    fn rename_heading_rewrites_links() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n## Old Heading\n\n[x](#old-heading) [:## Old Heading]\n",
+                ),
+                (
+                    "b.md",
+                    "# B\n\n[x](a#old-heading) [[a#Old Heading]] [:a@## Old Heading] [:a]\n",
+                ),
+            ],
+        );
+        let a = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let heading = facts.note_facts(a).headings_matching(|h| h.level == 2)[0];
+
+        let ws_edit = rename_heading(facts.db(), a, heading, "New One").unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(by_uri.len(), 2);
+        assert_eq!(
+            by_uri[&uri("a.md")],
+            vec![
+                edit(2, 3, 14, "New One"),
+                edit(4, 5, 16, "new-one"),
+                edit(4, 20, 34, "## New One")
+            ]
+        );
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![
+                edit(2, 6, 17, "new-one"),
+                edit(2, 23, 34, "New One"),
+                edit(2, 41, 55, "## New One")
+            ]
+        );
+    }
+
+    #[test]
+    fn move_section_to_other_note() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\nSee [:@## Sub] and [:## Sub].\n\n## Sub\n\nText [:a@## Sub]\n\n## Other\n",
+                ),
+                ("b.md", "# B\n\n[[a#Sub]] [x](a.md#sub)\n"),
+                ("c.md", "# C\n\nText"),
+            ],
+        );
+        let index = facts.note_index();
+        let a = index.find_by_path(&root.join("a.md")).unwrap();
+        let c = index.find_by_path(&root.join("c.md")).unwrap();
+        let heading = facts.note_facts(a).heading_for_link("Sub").unwrap();
+
+        let ws_edit = move_section(facts.db(), a, heading, c).unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(by_uri.len(), 3);
+        assert_eq!(
+            by_uri[&uri("a.md")],
+            vec![
+                edit(2, 6, 6, "c"),
+                edit(2, 21, 21, "c@"),
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(4, 0), Position::new(8, 0)),
+                    new_text: String::new(),
+                }
+            ]
+        );
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![edit(2, 2, 3, "c"), edit(2, 14, 18, "c.md")]
+        );
+        assert_eq!(
+            by_uri[&uri("c.md")],
+            vec![edit(2, 4, 4, "\n\n## Sub\n\nText [:c@## Sub]\n")]
+        );
+
+        // The title stays where it is
+        let title = facts.note_facts(a).title().unwrap();
+        assert_eq!(move_section(facts.db(), a, title, c), None);
+    }
+
+    #[test]
+    fn move_section_with_subheadings() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n## Sub\n\n[:@### Child], [:@## Other], [x](#other)\n[multi\nline](d.md)\n\n### Child\n\n## Other\n",
+                ),
+                ("b.md", "# B\n\n[:a@### Child] [:a@## Other]\n"),
+                ("d.md", "# D\n"),
+                ("sub/c.md", "# C\n"),
+            ],
+        );
+        let index = facts.note_index();
+        let a = index.find_by_path(&root.join("a.md")).unwrap();
+        let c = index.find_by_path(&root.join("sub/c.md")).unwrap();
+        let heading = facts.note_facts(a).heading_for_link("Sub").unwrap();
+
+        let ws_edit = move_section(facts.db(), a, heading, c).unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(by_uri.len(), 3);
+        assert_eq!(by_uri[&uri("b.md")], vec![edit(2, 2, 3, "sub/c")]);
+        assert_eq!(
+            by_uri[&uri("sub/c.md")],
+            vec![edit(
+                1,
+                0,
+                0,
+                "\n## Sub\n\n[:@### Child], [:a@## Other], [x](a#other)\n[multi\nline](../d.md)\n\n### Child\n"
+            )]
+        );
+    }
+
+    #[test]
+    fn extract_section_with_subheadings() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\n## Sub\n\n[:@### Child], [:@## Other], [:@## Sub]\n[multi\nline](#other)\n\n### Child\n\n## Other\n",
+                ),
+                ("b.md", "# B\n\n[:a@### Child] [:a@## Sub]\n"),
+            ],
+        );
+        let a = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let heading = facts.note_facts(a).heading_for_link("Sub").unwrap();
+
+        let (_, ws_edit) = extract_note(facts.db(), a, heading, &Config::default()).unwrap();
+        let by_uri = edits_by_uri(&ws_edit);
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+
+        assert_eq!(
+            by_uri[&uri("sub.md")],
+            vec![edit(
+                0,
+                0,
+                0,
+                "# Sub\n\n[:@### Child], [:a@## Other], [:sub]\n[multi\nline](a#other)\n\n### Child\n"
+            )]
+        );
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![edit(2, 17, 25, "sub"), edit(2, 2, 3, "sub")]
+        );
+    }
+
+    #[test]
+    fn extract_section_into_note() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[
+                (
+                    "a.md",
+                    "# A\n\nSee [:@## Sub] and [:## Sub].\n\n## Sub\n\nText [:a@## Sub]\n\n## Other\n",
+                ),
+                ("b.md", "# B\n\n[[a#Sub]] [x](a.md#sub)\n"),
+            ],
+        );
+        let a = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let heading = facts.note_facts(a).heading_for_link("Sub").unwrap();
+
+        let (new_uri, ws_edit) = extract_note(facts.db(), a, heading, &Config::default()).unwrap();
+        let uri = |name: &str| Url::from_file_path(root.join(name)).unwrap();
+        assert_eq!(new_uri, uri("sub.md"));
+
+        let by_uri = edits_by_uri(&ws_edit);
+        assert_eq!(by_uri.len(), 3);
+        assert_eq!(
+            by_uri[&uri("sub.md")],
+            vec![edit(0, 0, 0, "# Sub\n\nText [:sub]\n")]
+        );
+        assert_eq!(
+            by_uri[&uri("a.md")],
+            vec![
+                edit(2, 6, 13, "sub"),
+                edit(2, 21, 27, "sub"),
+                TextEdit {
+                    range: lsp_types::Range::new(Position::new(4, 0), Position::new(8, 0)),
+                    new_text: "[:sub]\n\n".to_string(),
+                }
+            ]
+        );
+        assert_eq!(
+            by_uri[&uri("b.md")],
+            vec![edit(2, 2, 7, "sub"), edit(2, 14, 22, "sub.md")]
+        );
+
+        // The title can't be extracted, and an existing note isn't overwritten
+        let title = facts.note_facts(a).title().unwrap();
+        assert_eq!(extract_note(facts.db(), a, title, &Config::default()), None);
+        let facts = FactsDB::from_texts(
+            root,
+            &[("a.md", "# A\n\n## Other\n"), ("other.md", "# Other\n")],
+        );
+        let a = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        let other = facts.note_facts(a).heading_for_link("Other").unwrap();
+        assert_eq!(extract_note(facts.db(), a, other, &Config::default()), None);
+    }
+}
@@ -0,0 +1,174 @@
+use std::ops::Range;
+
+use lsp_document::Pos;
+use lsp_types::{SelectionRange, SelectionRangeParams};
+
+use crate::{
+    facts::{NoteFacts, NoteFactsExt},
+    lsp::handlers::folding::section_lines,
+    parser::Element,
+    store::Workspace,
+};
+
+pub fn selection_range_request(
+    workspace: &Workspace,
+    params: SelectionRangeParams,
+) -> Option<Vec<SelectionRange>> {
+    let path = params.text_document.uri.to_file_path().ok()?;
+    let (_, facts) = workspace.owning_folder(&path)?;
+    let note = facts.note_facts(facts.note_index().find_by_path(&path)?);
+    let indexed_text = note.indexed_text();
+
+    let positions = params
+        .positions
+        .iter()
+        .map(|pos| indexed_text.lsp_pos_to_pos(pos))
+        .collect::<Option<Vec<_>>>()?;
+    Some(selection_ranges(&note, &positions))
+}
+
+/// For every position the ranges to expand the selection to, from the
+/// innermost: the word, the link, the sections of the enclosing headings, and
+/// the whole note.
+pub fn selection_ranges(note: &impl NoteFactsExt, positions: &[Pos]) -> Vec<SelectionRange> {
+    let indexed_text = note.indexed_text();
+    let text = note.text().content;
+    let lines: Vec<&str> = text.split('\n').collect();
+    let line_end = |line: u32| {
+        let len = lines
+            .get(line as usize)
+            .map_or(0, |l| l.trim_end_matches('\r').len());
+        Pos::new(line, len as u32)
+    };
+    let doc_span = Pos::new(0, 0)..line_end(lines.len().saturating_sub(1) as u32);
+    let strukt = note.structure();
+
+    let mut result = Vec::new();
+    for &pos in positions {
+        // From the outermost to the innermost
+        let mut spans: Vec<Range<Pos>> = vec![doc_span.clone()];
+
+        let mut sections = note
+            .headings()
+            .iter()
+            .map(|&id| {
+                let (start, end) = section_lines(strukt.heading_by_id(id));
+                Pos::new(start, 0)..line_end(end)
+            })
+            .filter(|span| span.start <= pos && pos <= span.end)
+            .collect::<Vec<_>>();
+        sections.sort_by(|a, b| a.start.cmp(&b.start).then(b.end.cmp(&a.end)));
+        spans.extend(sections);
+
+        if let Some(id) = note.element_at_pos(pos) {
+            if let Element::InternLink(link) = strukt.element_by_id(id) {
+                spans.push(link.span.clone());
+            }
+        }
+        spans.extend(word_at(
+            lines.get(pos.line as usize).copied().unwrap_or(""),
+            pos,
+        ));
+
+        let mut parent: Option<SelectionRange> = None;
+        let mut last: Option<Range<Pos>> = None;
+        for span in spans {
+            if last.as_ref() == Some(&span) {
+                continue;
+            }
+            let range = match indexed_text.range_to_lsp_range(&span) {
+                Some(range) => range,
+                None => continue,
+            };
+            parent = Some(SelectionRange {
+                range,
+                parent: parent.map(Box::new),
+            });
+            last = Some(span);
+        }
+        if let Some(range) = parent {
+            result.push(range);
+        }
+    }
+    result
+}
+
+/// Span of the word (letters, digits, `-` and `_`) around `pos` in the `line`.
+fn word_at(line: &str, pos: Pos) -> Option<Range<Pos>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let col = (pos.col as usize).min(line.len());
+    if !line.is_char_boundary(col) {
+        return None;
+    }
+    let start = line[..col]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| is_word(c))
+        .last()
+        .map_or(col, |(idx, _)| idx);
+    let end = line[col..]
+        .char_indices()
+        .find(|&(_, c)| !is_word(c))
+        .map_or(line.len(), |(idx, _)| col + idx);
+    if start == end {
+        return None;
+    }
+    Some(Pos::new(pos.line, start as u32)..Pos::new(pos.line, end as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::facts::FactsDB;
+    use pretty_assertions::assert_eq;
+
+    /// Ranges of the selection hierarchy from the innermost as `(line, col)` pairs.
+    fn hierarchy(range: &SelectionRange) -> Vec<((u32, u32), (u32, u32))> {
+        let mut ranges = Vec::new();
+        let mut cur = Some(range);
+        while let Some(r) = cur {
+            ranges.push((
+                (r.range.start.line, r.range.start.character),
+                (r.range.end.line, r.range.end.character),
+            ));
+            cur = r.parent.as_deref();
+        }
+        ranges
+    }
+
+    #[test]
+    fn expand_from_link_to_sections() {
+        let root = Path::new("/notes");
+        let facts = FactsDB::from_texts(
+            root,
+            &[(
+                "a.md",
+                "# A\n\n## Sub\n\nSee [:my-note@## H] here\n\n## Other\n",
+            )],
+        );
+        let note = facts.note_facts(facts.note_index().find_by_path(&root.join("a.md")).unwrap());
+
+        let ranges = selection_ranges(&note, &[Pos::new(4, 8), Pos::new(4, 1)]);
+        assert_eq!(
+            hierarchy(&ranges[0]),
+            vec![
+                ((4, 6), (4, 13)),
+                ((4, 4), (4, 19)),
+                ((2, 0), (5, 0)),
+                ((0, 0), (6, 8)),
+                ((0, 0), (7, 0)),
+            ]
+        );
+        assert_eq!(
+            hierarchy(&ranges[1]),
+            vec![
+                ((4, 0), (4, 3)),
+                ((2, 0), (5, 0)),
+                ((0, 0), (6, 8)),
+                ((0, 0), (7, 0)),
+            ]
+        );
+    }
+}
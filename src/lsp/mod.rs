@@ -1,4 +1,7 @@
+pub(crate) mod debounce;
 pub mod handlers;
 #[macro_use]
 pub(crate) mod macros;
+pub(crate) mod progress;
 pub mod server;
+pub(crate) mod watcher;
@@ -0,0 +1,214 @@
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use lsp_server::{Connection, Message, Notification, Request, RequestId};
+use lsp_types::{
+    notification::{self, Notification as _},
+    request::{Request as _, WorkDoneProgressCreate},
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+
+const TOKEN: &str = "zeta-note/indexing";
+
+/// How long to wait for the client to create the progress token.
+const CREATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Progress of a long-running task reported to the client via `$/progress`.
+///
+/// Without a connection, i.e. for clients that don't support
+/// `window.workDoneProgress`, nothing is sent.
+#[derive(Debug)]
+pub struct Progress {
+    sender: Option<Sender<Message>>,
+    percentage: u32,
+}
+
+impl Progress {
+    /// Start reporting the task with the `title`.
+    ///
+    /// Progress can only be reported once the client has created the token, so
+    /// this waits for the response to `window/workDoneProgress/create`. Other
+    /// messages received meanwhile are returned to be handled afterwards.
+    pub fn begin(connection: Option<&Connection>, title: &str) -> (Progress, Vec<Message>) {
+        let mut received = Vec::new();
+        let sender = connection
+            .filter(|connection| create_token(connection, &mut received))
+            .map(|connection| connection.sender.clone());
+        if let Some(sender) = &sender {
+            send(
+                sender,
+                WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                    title: title.to_string(),
+                    cancellable: Some(false),
+                    message: None,
+                    percentage: Some(0),
+                }),
+            );
+        }
+        let progress = Progress {
+            sender,
+            percentage: 0,
+        };
+        (progress, received)
+    }
+
+    /// Report that `percentage` of the work is done. As clients expect the
+    /// percentage to grow, anything not above the last reported one is skipped.
+    pub fn report(&mut self, message: &str, percentage: u32) {
+        let percentage = percentage.min(100);
+        if percentage <= self.percentage {
+            return;
+        }
+        self.percentage = percentage;
+        if let Some(sender) = &self.sender {
+            send(
+                sender,
+                WorkDoneProgress::Report(WorkDoneProgressReport {
+                    cancellable: None,
+                    message: Some(message.to_string()),
+                    percentage: Some(percentage),
+                }),
+            );
+        }
+    }
+
+    /// Finish the task. Later calls do nothing.
+    pub fn end(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            send(
+                &sender,
+                WorkDoneProgress::End(WorkDoneProgressEnd { message: None }),
+            );
+        }
+    }
+}
+
+/// Ask the client to create the progress token. Fails if the client refuses
+/// or doesn't respond in time.
+fn create_token(connection: &Connection, received: &mut Vec<Message>) -> bool {
+    let id = RequestId::from(TOKEN.to_string());
+    let create = Request::new(
+        id.clone(),
+        WorkDoneProgressCreate::METHOD.to_string(),
+        WorkDoneProgressCreateParams { token: token() },
+    );
+    if connection.sender.send(create.into()).is_err() {
+        return false;
+    }
+
+    let deadline = Instant::now() + CREATE_TIMEOUT;
+    while let Ok(msg) = connection.receiver.recv_deadline(deadline) {
+        match msg {
+            Message::Response(resp) if resp.id == id => return resp.error.is_none(),
+            msg => received.push(msg),
+        }
+    }
+    false
+}
+
+fn token() -> NumberOrString {
+    NumberOrString::String(TOKEN.to_string())
+}
+
+fn send(sender: &Sender<Message>, progress: WorkDoneProgress) {
+    let params = ProgressParams {
+        token: token(),
+        value: ProgressParamsValue::WorkDone(progress),
+    };
+    let not = Notification::new(notification::Progress::METHOD.to_string(), params);
+    sender.send(not.into()).unwrap_or(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_server::{ErrorCode, Response};
+    use pretty_assertions::assert_eq;
+
+    fn kinds(messages: &[Message]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|msg| match msg {
+                Message::Request(req) => req.method.clone(),
+                Message::Notification(not) => not.params["value"]["kind"].to_string(),
+                Message::Response(_) => "response".to_string(),
+            })
+            .collect()
+    }
+
+    fn created_token(client: &Connection) {
+        let id = RequestId::from(TOKEN.to_string());
+        let resp = Response::new_ok(id, ());
+        client.sender.send(resp.into()).unwrap();
+    }
+
+    #[test]
+    fn reports_growing_percentage() {
+        let (server, client) = Connection::memory();
+        created_token(&client);
+        let (mut progress, received) = Progress::begin(Some(&server), "Indexing notes");
+        assert!(received.is_empty());
+        progress.report("Reading notes", 10);
+        progress.report("Reading notes", 10);
+        progress.report("Reading notes", 5);
+        progress.report("Checking notes", 150);
+        progress.end();
+        progress.end();
+
+        let messages = client.receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            kinds(&messages),
+            vec![
+                "window/workDoneProgress/create",
+                "\"begin\"",
+                "\"report\"",
+                "\"report\"",
+                "\"end\""
+            ]
+        );
+        match &messages[3] {
+            Message::Notification(not) => assert_eq!(not.params["value"]["percentage"], 100),
+            msg => panic!("unexpected message {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn begins_after_token_is_created() {
+        let (server, client) = Connection::memory();
+        let not = Notification::new("initialized".to_string(), ());
+        client.sender.send(not.into()).unwrap();
+        created_token(&client);
+        let (_, received) = Progress::begin(Some(&server), "Indexing notes");
+
+        // Messages received before the response are kept
+        assert_eq!(kinds(&received), vec!["null"]);
+        let messages = client.receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            kinds(&messages),
+            vec!["window/workDoneProgress/create", "\"begin\""]
+        );
+    }
+
+    #[test]
+    fn silent_when_token_is_refused() {
+        let (server, client) = Connection::memory();
+        let id = RequestId::from(TOKEN.to_string());
+        let resp = Response::new_err(id, ErrorCode::InternalError as i32, "no".to_string());
+        client.sender.send(resp.into()).unwrap();
+        let (mut progress, _) = Progress::begin(Some(&server), "Indexing notes");
+        progress.report("Reading notes", 50);
+        progress.end();
+
+        let messages = client.receiver.try_iter().collect::<Vec<_>>();
+        assert_eq!(kinds(&messages), vec!["window/workDoneProgress/create"]);
+    }
+
+    #[test]
+    fn silent_without_connection() {
+        let (mut progress, _) = Progress::begin(None, "Indexing notes");
+        progress.report("Reading notes", 50);
+        progress.end();
+        assert_eq!(progress.percentage, 50);
+    }
+}
@@ -1,12 +1,24 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::default::Default;
-use tracing::{debug, info, trace};
+use tracing::{debug, info, trace, warn};
 
 use crate::{
+    config::Config,
     diag::DiagCollection,
-    lsp::handlers,
+    encoding::PositionEncoding,
+    lsp::{
+        debounce::Debouncer,
+        handlers::{self, NoteStatsRequest, ResolveLinkRequest},
+        progress::Progress,
+        watcher::FsWatcher,
+    },
     parser,
     store::{self, NoteFolder},
 };
@@ -15,19 +27,24 @@ use anyhow::{anyhow, Result};
 use lsp_server::{Connection, IoThreads, Message};
 use lsp_types::{
     notification::{
-        DidChangeTextDocument, DidChangeWorkspaceFolders, DidCloseTextDocument,
-        DidOpenTextDocument, Notification, PublishDiagnostics,
+        DidChangeConfiguration, DidChangeTextDocument, DidChangeWatchedFiles,
+        DidChangeWorkspaceFolders, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+        Notification, PublishDiagnostics,
     },
     request::{
-        CodeLensRequest, CodeLensResolve, Completion, DocumentLinkRequest, DocumentSymbolRequest,
-        GotoDefinition, HoverRequest, ResolveCompletionItem, SemanticTokensFullRequest,
-        SemanticTokensRangeRequest, WorkspaceSymbol,
+        CodeActionRequest, CodeLensRequest, CodeLensResolve, Completion, DocumentHighlightRequest,
+        DocumentLinkRequest, DocumentSymbolRequest, ExecuteCommand, FoldingRangeRequest,
+        GotoDefinition, HoverRequest, InlayHintRequest, PrepareRenameRequest, RangeFormatting,
+        References, Rename, ResolveCompletionItem, SelectionRangeRequest,
+        SemanticTokensFullRequest, SemanticTokensRangeRequest, WorkspaceSymbol,
     },
-    ClientCapabilities, CodeLensOptions, CompletionOptions, DocumentLinkOptions,
-    HoverProviderCapability, InitializeParams, InitializeResult, OneOf, SemanticTokens,
-    SemanticTokensFullOptions, SemanticTokensOptions, ServerCapabilities, ServerInfo,
-    TextDocumentSyncCapability, TextDocumentSyncKind, WorkDoneProgressOptions,
-    WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities,
+    ClientCapabilities, CodeActionKind, CodeActionOptions, CodeActionProviderCapability,
+    CodeLensOptions, CompletionOptions, DocumentLinkOptions, ExecuteCommandOptions,
+    FoldingRangeProviderCapability, HoverProviderCapability, InitializeParams, InitializeResult,
+    OneOf, RenameOptions, SelectionRangeProviderCapability, SemanticTokensFullOptions,
+    SemanticTokensOptions, ServerCapabilities, ServerInfo, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions,
+    WorkDoneProgressOptions, WorkspaceFoldersServerCapabilities, WorkspaceServerCapabilities,
 };
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
@@ -53,7 +70,14 @@ pub struct Ctx {
     pub root: PathBuf,
     pub client_name: ClientName,
     pub experimental: ExperimentalCapabilities,
+    /// The client accepts `$/progress` notifications for server-initiated work.
+    pub work_done_progress: bool,
+    /// The client accepts snippets in completion items.
+    pub snippet_support: bool,
     pub folders: Vec<NoteFolder>,
+    pub config: Config,
+    /// Encoding of positions negotiated with the client.
+    pub position_encoding: PositionEncoding,
 }
 
 #[derive(Default, PartialEq, Eq, Deserialize, Serialize)]
@@ -71,6 +95,8 @@ pub fn init_connection() -> Result<(Connection, IoThreads, Ctx)> {
     let (connection, io_threads) = Connection::stdio();
 
     let (id, params) = connection.initialize_start()?;
+    // `positionEncodings` isn't known to `ClientCapabilities` of lsp-types yet
+    let position_encoding = extract_position_encoding(&params);
     let init_params: InitializeParams = serde_json::from_value(params).unwrap();
     trace!("init_connection: received init params");
 
@@ -90,12 +116,37 @@ pub fn init_connection() -> Result<(Connection, IoThreads, Ctx)> {
         .unwrap_or(ClientName::Other);
 
     let experimental = extract_experimental(&init_params.capabilities);
+    let work_done_progress = init_params
+        .capabilities
+        .window
+        .as_ref()
+        .and_then(|window| window.work_done_progress)
+        .unwrap_or(false);
+    let snippet_support = init_params
+        .capabilities
+        .text_document
+        .as_ref()
+        .and_then(|text_document| text_document.completion.as_ref())
+        .and_then(|completion| completion.completion_item.as_ref())
+        .and_then(|item| item.snippet_support)
+        .unwrap_or(false);
+
+    let config = init_params
+        .initialization_options
+        .as_ref()
+        .map(Config::from_json)
+        .unwrap_or_default();
+    debug!("init_connection: config={:?}", config);
 
     let ctx = Ctx {
         root,
         client_name,
         experimental,
+        work_done_progress,
+        snippet_support,
         folders,
+        config,
+        position_encoding,
     };
 
     let capabilities = mk_server_caps(&ctx);
@@ -106,10 +157,12 @@ pub fn init_connection() -> Result<(Connection, IoThreads, Ctx)> {
 
     let init_result = InitializeResult {
         capabilities,
+        offset_encoding: None,
         server_info: Some(server_info),
     };
 
-    let init_result = serde_json::to_value(init_result).unwrap();
+    let mut init_result = serde_json::to_value(init_result).unwrap();
+    init_result["capabilities"]["positionEncoding"] = ctx.position_encoding.as_str().into();
 
     trace!("init_connection: finalizing init");
     connection.initialize_finish(id, init_result)?;
@@ -118,6 +171,14 @@ pub fn init_connection() -> Result<(Connection, IoThreads, Ctx)> {
     Ok((connection, io_threads, ctx))
 }
 
+fn extract_position_encoding(init_params: &Value) -> PositionEncoding {
+    let offered: Vec<&str> = init_params["capabilities"]["general"]["positionEncodings"]
+        .as_array()
+        .map(|encodings| encodings.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    PositionEncoding::negotiate(offered)
+}
+
 fn extract_experimental(cap: &ClientCapabilities) -> ExperimentalCapabilities {
     cap.experimental
         .as_ref()
@@ -144,15 +205,21 @@ fn mk_server_caps(ctx: &Ctx) -> ServerCapabilities {
         file_operations: None,
     });
 
-    server_capabilities.text_document_sync = Some(TextDocumentSyncCapability::Kind(
-        TextDocumentSyncKind::INCREMENTAL,
+    server_capabilities.text_document_sync = Some(TextDocumentSyncCapability::Options(
+        TextDocumentSyncOptions {
+            open_close: Some(true),
+            change: Some(TextDocumentSyncKind::INCREMENTAL),
+            // Saving a note flushes pending diagnostics
+            save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+            ..TextDocumentSyncOptions::default()
+        },
     ));
 
     server_capabilities.completion_provider = Some(CompletionOptions {
         trigger_characters: Some(vec![
-            parser::START_COLON.to_string(),
-            parser::SEP_AT.to_string(),
-            parser::SEP_BAR.to_string(),
+            parser::LINK_START.to_string(),
+            parser::LINK_DEST_START.to_string(),
+            parser::HEADING_START.to_string(),
         ]),
         resolve_provider: Some(true),
         ..CompletionOptions::default()
@@ -162,6 +229,32 @@ fn mk_server_caps(ctx: &Ctx) -> ServerCapabilities {
 
     server_capabilities.definition_provider = Some(OneOf::Left(true));
 
+    server_capabilities.references_provider = Some(OneOf::Left(true));
+
+    server_capabilities.rename_provider = Some(OneOf::Right(RenameOptions {
+        prepare_provider: Some(true),
+        work_done_progress_options: WorkDoneProgressOptions::default(),
+    }));
+
+    server_capabilities.code_action_provider =
+        Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![
+                CodeActionKind::QUICKFIX,
+                CodeActionKind::REFACTOR,
+                CodeActionKind::REFACTOR_REWRITE,
+            ]),
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+            resolve_provider: None,
+        }));
+
+    server_capabilities.execute_command_provider = Some(ExecuteCommandOptions {
+        commands: handlers::command::COMMANDS
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        work_done_progress_options: WorkDoneProgressOptions::default(),
+    });
+
     server_capabilities.semantic_tokens_provider = Some(
         SemanticTokensOptions {
             legend: handlers::semantic_tokens_legend().clone(),
@@ -172,18 +265,26 @@ fn mk_server_caps(ctx: &Ctx) -> ServerCapabilities {
         .into(),
     );
 
-    if ctx.experimental.code_lens_show_references {
-        server_capabilities.code_lens_provider = Some(CodeLensOptions {
-            resolve_provider: Some(true),
-        });
-    }
+    server_capabilities.code_lens_provider = Some(CodeLensOptions {
+        resolve_provider: Some(true),
+    });
 
-    if ctx.experimental.follow_links {
-        server_capabilities.document_link_provider = Some(DocumentLinkOptions {
-            resolve_provider: None,
-            work_done_progress_options: WorkDoneProgressOptions::default(),
-        });
-    }
+    server_capabilities.document_highlight_provider = Some(OneOf::Left(true));
+
+    server_capabilities.selection_range_provider =
+        Some(SelectionRangeProviderCapability::Simple(true));
+
+    server_capabilities.folding_range_provider = Some(FoldingRangeProviderCapability::Simple(true));
+
+    server_capabilities.document_range_formatting_provider = Some(OneOf::Left(true));
+
+    server_capabilities.document_link_provider = Some(DocumentLinkOptions {
+        resolve_provider: None,
+        work_done_progress_options: WorkDoneProgressOptions::default(),
+    });
+
+    // Hints are off unless enabled in the config, which can change at any time
+    server_capabilities.inlay_hint_provider = Some(OneOf::Left(true));
 
     server_capabilities
 }
@@ -206,16 +307,63 @@ pub fn extract_workspace_folders(init_params: &InitializeParams) -> Vec<NoteFold
     }
 }
 
-pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
+fn root_paths(workspace: &store::Workspace) -> Vec<&Path> {
+    workspace
+        .roots()
+        .into_iter()
+        .map(|folder| folder.root.as_path())
+        .collect()
+}
+
+/// Number of notes checked by the initial pass between handling two messages.
+const INITIAL_DIAG_BATCH: usize = 50;
+
+pub async fn main_loop(connection: Connection, mut ctx: Ctx) -> Result<()> {
     let connection = Arc::new(connection);
 
     info!("Starting zeta-note main loop at {}", ctx.root.display());
     debug!("Folders in the workspace: {:?}", ctx.folders);
 
-    let mut workspace = store::Workspace::new(&ctx.folders).await?;
+    // Reading notes takes the first half of the initial progress and checking
+    // them the second one, unless notes aren't checked on startup.
+    let progress_connection = ctx.work_done_progress.then(|| connection.as_ref());
+    let (mut progress, received) = Progress::begin(progress_connection, "Indexing notes");
+    // Messages that came while waiting for the client to accept the progress
+    let mut received = received.into_iter();
+    let read_share = if ctx.config.diag_on_startup { 50 } else { 100 };
+    let mut workspace = store::Workspace::new_reporting(&ctx.folders, &ctx.config, |percentage| {
+        progress.report("Reading notes", percentage * read_share / 100)
+    })
+    .await?;
+    workspace.facts.set_position_encoding(ctx.position_encoding);
 
     let mut diag_col = DiagCollection::default();
+    // Notes edited since the last diagnostics update. Workspace-wide changes
+    // (e.g. folders added or rescanned) require a full recheck instead.
+    let mut changed_notes = Debouncer::new(ctx.config.diag_debounce());
+    let mut recheck_all = false;
+    // Diagnostics of all notes are published again, even if they didn't change
+    let mut republish_all = false;
     let mut last_note_count = workspace.note_count();
+    // Notes not checked by the initial pass yet. These are checked in batches,
+    // so that requests are served while a large workspace is being checked.
+    let mut unchecked: Vec<PathBuf> = if ctx.config.diag_on_startup {
+        let index = workspace.facts.note_index();
+        let mut paths: Vec<_> = index.files().map(|file| file.path.to_path_buf()).collect();
+        paths.sort();
+        paths
+    } else {
+        Vec::new()
+    };
+    let num_unchecked = unchecked.len();
+    if unchecked.is_empty() {
+        progress.end();
+    }
+
+    // Changes made outside of the editor come in bursts, e.g. on `git pull`
+    let mut fs_watcher = FsWatcher::new();
+    fs_watcher.sync_roots(&root_paths(&workspace));
+    let mut changed_on_disk = Debouncer::new(ctx.config.diag_debounce());
 
     let (pending_not_tx, mut pending_not_rx) = tokio::sync::mpsc::channel(100);
     pending_not_tx
@@ -232,9 +380,40 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
         }
     });
 
-    for msg in &connection.receiver {
+    loop {
+        // Wake up when pending work is due even if there are no messages
+        let deadline = [
+            changed_notes.next_deadline(),
+            changed_on_disk.next_deadline(),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let timeout = match deadline {
+            _ if !unchecked.is_empty() => crossbeam_channel::after(Duration::ZERO),
+            Some(deadline) => crossbeam_channel::at(deadline),
+            None => crossbeam_channel::never(),
+        };
+        let msg = if let Some(msg) = received.next() {
+            Some(msg)
+        } else {
+            crossbeam_channel::select! {
+                recv(connection.receiver) -> msg => match msg {
+                    Ok(msg) => Some(msg),
+                    Err(_) => break,
+                },
+                recv(fs_watcher.receiver()) -> path => {
+                    if let Ok(path) = path {
+                        changed_on_disk.touch(path);
+                    }
+                    None
+                },
+                recv(timeout) -> _ => None,
+            }
+        };
+
         match msg {
-            Message::Request(req) => {
+            Some(Message::Request(req)) => {
                 if connection.handle_shutdown(&req)? {
                     return Ok(());
                 }
@@ -244,14 +423,21 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
                     req,
                     DocumentSymbolRequest => params -> {
                         let file = params.text_document.uri.to_file_path().unwrap();
-                        let symbols = handlers::document_symbols(&workspace, &file, "");
+                        let symbols = workspace
+                            .owning_folder(&file)
+                            .and_then(|(_, facts)| {
+                                let note_id = facts.note_index().find_by_path(&file)?;
+                                Some(handlers::document_symbols(&facts.note_facts(note_id)))
+                            })
+                            .unwrap_or_default();
                         Ok(Some(symbols.into()))
                     },
                     WorkspaceSymbol => params -> {
-                        Ok(Some(handlers::workspace_symbols(&workspace, &params.query)))
+                        let symbols = handlers::workspace_symbols(workspace.facts.db(), &params.query);
+                        Ok(Some(symbols))
                     },
                     Completion => params -> {
-                        let candidates = handlers::completion::completion_candidates(&workspace, params)
+                        let candidates = handlers::completion::completion_candidates(&workspace, params, &ctx.config, ctx.snippet_support)
                             .unwrap_or_default();
                         Ok(Some(candidates.into()))
                     },
@@ -265,36 +451,66 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
                         Ok(handlers::goto_definition(&workspace, params).map(|loc| loc.into()))
                     },
                     SemanticTokensFullRequest => params -> {
-                        let tokens = handlers::semantic_tokens_full(&workspace, params).map(|tv| {
-                            SemanticTokens {
-                                data: tv,
-                                ..SemanticTokens::default()
-                            }.into()
-                        });
-                        Ok(tokens)
+                        Ok(handlers::semantic_tokens_full(&workspace, params).map(|tokens| tokens.into()))
                     },
                     SemanticTokensRangeRequest => params -> {
-                        let tokens = handlers::semantic_tokens_range(&workspace, params).map(|tv| {
-                            SemanticTokens {
-                                data: tv,
-                                ..SemanticTokens::default()
-                            }.into()
-                        });
-                        Ok(tokens)
+                        Ok(handlers::semantic_tokens_range(&workspace, params).map(|tokens| tokens.into()))
                     },
                     CodeLensRequest => params -> {
                         Ok(handlers::code_lenses(&workspace, params))
                     },
                     CodeLensResolve => params -> {
-                        Ok(handlers::code_lens_resolve(&workspace, &params).unwrap_or(params))
+                        let show_references = ctx.experimental.code_lens_show_references;
+                        Ok(handlers::code_lens_resolve(&workspace, &params, show_references).unwrap_or(params))
+                    },
+                    DocumentHighlightRequest => params -> {
+                        Ok(handlers::highlight::document_highlight_request(&workspace, params))
+                    },
+                    SelectionRangeRequest => params -> {
+                        Ok(handlers::selection::selection_range_request(&workspace, params))
+                    },
+                    RangeFormatting => params -> {
+                        Ok(handlers::formatting::range_formatting(&workspace, params))
+                    },
+                    FoldingRangeRequest => params -> {
+                        Ok(handlers::folding::folding_range_request(&workspace, params))
                     },
                     DocumentLinkRequest => params -> {
-                        Ok(handlers::document_links(&workspace, params))
+                        Ok(handlers::document_links(&workspace, params, ctx.experimental.follow_links))
+                    },
+                    InlayHintRequest => params -> {
+                        Ok(handlers::inlay_hint::inlay_hint_request(&workspace, params, &ctx.config))
+                    },
+                    CodeActionRequest => params -> {
+                        Ok(handlers::code_action::code_actions(&workspace, params, &ctx.config))
+                    },
+                    PrepareRenameRequest => params -> {
+                        Ok(handlers::rename::prepare(&workspace, params))
+                    },
+                    Rename => params -> {
+                        Ok(handlers::rename::rename(&workspace, params))
+                    },
+                    References => params -> {
+                        Ok(handlers::references(&workspace, params))
+                    },
+                    NoteStatsRequest => params -> {
+                        Ok(handlers::note_stats_request(&workspace, params))
+                    },
+                    ResolveLinkRequest => params -> {
+                        Ok(handlers::resolve_link_request(&workspace, params))
+                    },
+                    ExecuteCommand => params -> {
+                        if params.command == handlers::command::REINDEX {
+                            republish_all = true;
+                            handlers::command::reindex(&mut workspace, &ctx.config).await.map(Some)
+                        } else {
+                            Ok(handlers::command::execute_command(&workspace, params, &ctx.config))
+                        }
                     }
                 )
             }
-            Message::Response(_) => {}
-            Message::Notification(not) => {
+            Some(Message::Response(_)) | None => {}
+            Some(Message::Notification(not)) => {
                 handle_notification!(
                     not,
                     DidOpenTextDocument => params -> {
@@ -304,10 +520,16 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
                             .to_file_path()
                             .expect("Failed to turn uri into path");
                         handlers::note_open(&mut workspace, &path, &params.text_document);
+                        changed_notes.touch(path.clone());
+                        changed_notes.flush(&path);
                     },
                     DidCloseTextDocument => params -> {
-                        handlers::note_close(&mut workspace, &params.text_document)
-                            .await.unwrap();
+                        if let Err(err) = handlers::note_close(&mut workspace, &params.text_document).await {
+                            warn!("Failed to reread the closed note: {}", err);
+                        }
+                        if let Ok(path) = params.text_document.uri.to_file_path() {
+                            changed_notes.touch(path);
+                        }
                     },
                     DidChangeTextDocument => params -> {
                         let path = params
@@ -316,9 +538,36 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
                             .to_file_path()
                             .expect("Failed to turn uri into path");
                         handlers::note_apply_changes(&mut workspace, ctx.client_name, &path, &params);
+                        changed_notes.touch(path);
+                    },
+                    DidSaveTextDocument => params -> {
+                        if let Ok(path) = params.text_document.uri.to_file_path() {
+                            changed_notes.flush(&path);
+                        }
                     },
                     DidChangeWorkspaceFolders => params -> {
-                        handlers::note_change_workspace_folders(&mut workspace, &params.event).await.unwrap();
+                        if let Err(err) = handlers::note_change_workspace_folders(&mut workspace, &params.event, &ctx.config).await {
+                            warn!("Failed to scan the workspace folders: {}", err);
+                        }
+                        fs_watcher.sync_roots(&root_paths(&workspace));
+                        recheck_all = true;
+                    },
+                    DidChangeWatchedFiles => params -> {
+                        if let Err(err) = handlers::note_change_watched_files(&mut workspace, &params, &ctx.config).await {
+                            warn!("Failed to apply changes of watched files: {}", err);
+                        }
+                        recheck_all = true;
+                    },
+                    DidChangeConfiguration => params -> {
+                        let config = ctx.config.with_settings(&params.settings);
+                        debug!("Configuration changed: {:?}", config);
+                        if let Err(err) = handlers::note_change_configuration(&mut workspace, &ctx.config, &config).await {
+                            warn!("Failed to rescan the workspace with the new configuration: {}", err);
+                        }
+                        changed_notes.set_delay(config.diag_debounce());
+                        changed_on_disk.set_delay(config.diag_debounce());
+                        ctx.config = config;
+                        recheck_all = true;
                     }
                 )
             }
@@ -331,6 +580,21 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
         // 3. We process the notification (diagnostics are still stale).
         // 4. We need one more message to update diagnostics based on the notification received on step 1.
 
+        let now = Instant::now();
+        let on_disk = changed_on_disk.take_due(now);
+        let mut due = if on_disk.is_empty() {
+            Vec::new()
+        } else {
+            // A note that can't be read shouldn't take the server down
+            match handlers::note_change_on_disk(&mut workspace, &on_disk, &ctx.config).await {
+                Ok(due) => due,
+                Err(err) => {
+                    warn!("Failed to apply changes on disk: {}", err);
+                    Vec::new()
+                }
+            }
+        };
+
         let current_notes_count = workspace.note_count();
         if current_notes_count != last_note_count {
             pending_not_tx
@@ -339,16 +603,43 @@ pub async fn main_loop(connection: Connection, ctx: Ctx) -> Result<()> {
             last_note_count = current_notes_count;
         }
 
-        if let Some((publish_params, new_col)) = handlers::diag(&workspace, &diag_col) {
-            diag_col = new_col;
-            for param in publish_params {
-                let param = serde_json::to_value(param).unwrap();
-                let not = lsp_server::Notification {
-                    method: PublishDiagnostics::METHOD.to_string(),
-                    params: param,
-                };
-                pending_not_tx.send(not).await?;
-            }
+        let mut publish_params = if republish_all {
+            republish_all = false;
+            recheck_all = false;
+            unchecked.clear();
+            handlers::diag_republish(&workspace, &mut diag_col, &ctx.config)
+        } else if recheck_all {
+            recheck_all = false;
+            unchecked.clear();
+            handlers::diag(&workspace, &mut diag_col, &ctx.config)
+        } else {
+            due.extend(changed_notes.take_due(now));
+            handlers::diag_for_changes(&workspace, &mut diag_col, &due, &ctx.config)
+        };
+        if !unchecked.is_empty() {
+            let batch: Vec<_> = unchecked
+                .drain(..unchecked.len().min(INITIAL_DIAG_BATCH))
+                .collect();
+            publish_params.extend(handlers::diag_for_notes(
+                &workspace,
+                &mut diag_col,
+                &batch,
+                &ctx.config,
+            ));
+            let checked = num_unchecked - unchecked.len();
+            progress.report("Checking notes", 50 + (checked * 50 / num_unchecked) as u32);
+        }
+
+        for param in publish_params {
+            let param = serde_json::to_value(param).unwrap();
+            let not = lsp_server::Notification {
+                method: PublishDiagnostics::METHOD.to_string(),
+                params: param,
+            };
+            pending_not_tx.send(not).await?;
+        }
+        if unchecked.is_empty() {
+            progress.end();
         }
     }
 
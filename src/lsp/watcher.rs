@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use crossbeam_channel::{Receiver, Sender};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tracing::{debug, warn};
+
+/// Watches workspace folders for changes made outside of the editor, e.g. by
+/// `git pull`, and reports the paths of changed files.
+pub struct FsWatcher {
+    watcher: Option<RecommendedWatcher>,
+    watched: Vec<PathBuf>,
+    receiver: Receiver<PathBuf>,
+}
+
+impl FsWatcher {
+    /// Create a watcher. If the platform watcher can't be created, no changes
+    /// are reported.
+    pub fn new() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let watcher = notify::recommended_watcher(move |res| forward_event(&sender, res))
+            .map_err(|err| warn!("Failed to create a file watcher: {}", err))
+            .ok();
+
+        Self {
+            watcher,
+            watched: Vec::new(),
+            receiver,
+        }
+    }
+
+    pub fn receiver(&self) -> &Receiver<PathBuf> {
+        &self.receiver
+    }
+
+    /// Watch exactly the folders in `roots`.
+    pub fn sync_roots(&mut self, roots: &[&Path]) {
+        let watcher = match &mut self.watcher {
+            Some(watcher) => watcher,
+            None => return,
+        };
+
+        self.watched.retain(|root| {
+            let keep = roots.contains(&root.as_path());
+            if !keep {
+                debug!("Unwatching {}", root.display());
+                let _ = watcher.unwatch(root);
+            }
+            keep
+        });
+
+        for root in roots {
+            if self.watched.iter().any(|r| r == root) {
+                continue;
+            }
+            debug!("Watching {}", root.display());
+            match watcher.watch(root, RecursiveMode::Recursive) {
+                Ok(()) => self.watched.push(root.to_path_buf()),
+                Err(err) => warn!("Failed to watch {}: {}", root.display(), err),
+            }
+        }
+    }
+}
+
+fn forward_event(sender: &Sender<PathBuf>, res: notify::Result<Event>) {
+    match res {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            for path in event.paths {
+                let _ = sender.send(path);
+            }
+        }
+        Ok(_) => (),
+        Err(err) => debug!("File watcher error: {}", err),
+    }
+}
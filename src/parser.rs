@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     fmt::{Debug, Display},
     iter::Peekable,
     ops::{Deref, DerefMut, Range},
@@ -6,20 +7,40 @@ use std::{
 };
 
 use lsp_document::{Pos, TextMap};
-use pulldown_cmark::{BrokenLink, CowStr, Event, LinkType, OffsetIter, Options, Parser, Tag};
+use pulldown_cmark::{
+    BrokenLink, CowStr, Event, LinkType, OffsetIter, Options, Parser, Tag as MdTag,
+};
 use serde::{Deserialize, Serialize};
 
+use crate::util::percent_decode;
+
 pub const LINK_PREFIX_1: &str = "[:";
 pub const LINK_SUFFIX: char = ']';
+pub const LINK_START: char = '[';
+pub const LINK_DEST_START: char = '(';
 pub const START_COLON: char = ':';
 pub const SEP_AT: char = '@';
 pub const SEP_BAR: char = '|';
+pub const HEADING_START: char = '#';
+/// Extensions of note files in Markdown link targets, e.g. `[label](note.md)`.
+pub const NOTE_EXTENSIONS: [&str; 2] = ["md", "markdown"];
+pub const WIKI_LINK_PREFIX: &str = "[[";
+pub const WIKI_LINK_SUFFIX: &str = "]]";
+pub const FRONTMATTER_FENCE: &str = "---";
+pub const FRONTMATTER_END: &str = "...";
+pub const SUPPRESSION_PREFIX: &str = "zeta-note:";
+pub const COMMENT_START: &str = "<!--";
+pub const COMMENT_END: &str = "-->";
+pub const CODE_FENCES: [&str; 2] = ["```", "~~~"];
+pub const URL_SCHEMES: [&str; 2] = ["https://", "http://"];
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub enum Element {
     Heading(Node<Heading>),
     ExternLink(Node<ExternLink>),
     InternLink(Node<InternLink>),
+    Tag(Node<Tag>),
+    UndefinedLinkRef(Node<UndefinedLinkRef>),
 }
 
 impl From<Node<InternLink>> for Element {
@@ -34,6 +55,18 @@ impl From<Node<ExternLink>> for Element {
     }
 }
 
+impl From<Node<Tag>> for Element {
+    fn from(v: Node<Tag>) -> Self {
+        Self::Tag(v)
+    }
+}
+
+impl From<Node<UndefinedLinkRef>> for Element {
+    fn from(v: Node<UndefinedLinkRef>) -> Self {
+        Self::UndefinedLinkRef(v)
+    }
+}
+
 impl From<Node<Heading>> for Element {
     fn from(v: Node<Heading>) -> Self {
         Self::Heading(v)
@@ -46,6 +79,8 @@ impl Element {
             Element::Heading(hd) => &hd.text,
             Element::ExternLink(el) => &el.text,
             Element::InternLink(il) => &il.text,
+            Element::Tag(t) => &t.text,
+            Element::UndefinedLinkRef(r) => &r.text,
         }
     }
 
@@ -54,6 +89,8 @@ impl Element {
             Element::Heading(n) => &n.span,
             Element::ExternLink(n) => &n.span,
             Element::InternLink(n) => &n.span,
+            Element::Tag(n) => &n.span,
+            Element::UndefinedLinkRef(n) => &n.span,
         }
     }
 
@@ -108,6 +145,40 @@ pub struct Heading {
     pub children: Vec<Element>,
 }
 
+impl Heading {
+    /// GitHub-style anchor of the heading, e.g. `my-heading` for `## My Heading`.
+    pub fn slug(&self) -> String {
+        slugify(self.text.trim_start_matches(HEADING_START))
+    }
+
+    /// Whether the heading has no text besides the `#` markers, e.g. a stray `##`.
+    pub fn is_empty(&self) -> bool {
+        self.text
+            .trim_start_matches(HEADING_START)
+            .trim()
+            .is_empty()
+    }
+}
+
+/// Turn heading text into an anchor the way GitHub does: lowercase, drop
+/// punctuation except `-` and `_`, and replace runs of whitespace with `-`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    let mut pending_hyphen = false;
+    for c in text.trim().chars() {
+        if c.is_whitespace() {
+            pending_hyphen = true;
+        } else if c.is_alphanumeric() || c == '-' || c == '_' {
+            if pending_hyphen {
+                slug.push('-');
+                pending_hyphen = false;
+            }
+            slug.extend(c.to_lowercase());
+        }
+    }
+    slug
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct InternLink {
     pub text: String,
@@ -115,6 +186,261 @@ pub struct InternLink {
     pub heading: Option<String>,
 }
 
+impl InternLink {
+    pub fn is_wiki(&self) -> bool {
+        self.text.starts_with(WIKI_LINK_PREFIX)
+    }
+
+    /// Markdown links `[label](target)` and `[label][ref]` have their target
+    /// outside of the label, in the destination or in the `[ref]: ...` definition.
+    pub fn is_markdown(&self) -> bool {
+        !self.text.starts_with(LINK_PREFIX_1) && !self.is_wiki()
+    }
+
+    /// Markdown links with a target like `../note.md` or `sub/note` point to a
+    /// file relative to the linking note rather than to a note name.
+    pub fn is_path(&self) -> bool {
+        self.is_markdown()
+            && self
+                .note_name
+                .as_ref()
+                .is_some_and(|name| is_path_target(name.to_str()))
+    }
+
+    /// Label (with brackets) and destination of an inline link `[label](dest)`.
+    pub fn inline_parts(&self) -> Option<(&str, &str)> {
+        if !self.is_markdown() {
+            return None;
+        }
+        let label_end = self.text.rfind("](")? + 1;
+        let dest = self.text[label_end + 1..].strip_suffix(')')?;
+        Some((&self.text[..label_end], dest.trim()))
+    }
+
+    /// Label (with brackets) and reference of a reference-style link:
+    /// `[label][ref]`, `[ref][]` or `[ref]`.
+    pub fn reference_parts(&self) -> Option<(&str, &str)> {
+        if !self.is_markdown() || self.inline_parts().is_some() {
+            return None;
+        }
+        let (label, reference) = match self.text.strip_suffix("[]") {
+            Some(label) => (label, label),
+            None => match self.text.rfind("][") {
+                Some(idx) => (&self.text[..idx + 1], &self.text[idx + 1..]),
+                None => (self.text.as_str(), self.text.as_str()),
+            },
+        };
+        let reference = reference
+            .strip_prefix(LINK_START)?
+            .strip_suffix(LINK_SUFFIX)?;
+        Some((label, reference.trim()))
+    }
+}
+
+/// Whether a Markdown link target like `../note.md` or `sub/note` is a path
+/// rather than a note name.
+pub fn is_path_target(target: &str) -> bool {
+    target.contains('/') || has_note_extension(target)
+}
+
+fn has_note_extension(target: &str) -> bool {
+    Path::new(target)
+        .extension()
+        .is_some_and(|ext| NOTE_EXTENSIONS.iter().any(|e| ext == *e))
+}
+
+impl Node<Heading> {
+    /// Span of the heading text without the leading `#`.
+    pub fn text_span(&self) -> Range<Pos> {
+        let text = self.text.trim_start_matches(HEADING_START).trim_start();
+        let start = self.span.start.col + (self.text.len() - text.len()) as u32;
+        let end = start + text.trim_end().len() as u32;
+        Pos::new(self.span.start.line, start)..Pos::new(self.span.start.line, end)
+    }
+}
+
+impl Node<InternLink> {
+    /// Span of the note name part of the link, e.g. `note` in `[:note@## Heading]`
+    /// or in `[label](note#heading)`. In Markdown links it's the name as written,
+    /// e.g. `my%20note`.
+    ///
+    /// Reference-style links have the note name in the `[ref]: ...` definition,
+    /// so there is no span for them.
+    pub fn note_name_span(&self) -> Option<Range<Pos>> {
+        let name = self.note_name.as_ref()?.to_str();
+        if self.is_markdown() {
+            let (start, dest) = self.raw_dest()?;
+            let path = dest.split(HEADING_START).next()?;
+            return Some(self.trimmed_span(start, path));
+        }
+        let search_from = self.prefix_len();
+        let offset = self.text[search_from..].find(name)? + search_from;
+        Some(self.col_span(offset, name.len()))
+    }
+
+    /// Span of the heading part of the link, e.g. `## Heading` in `[:note@## Heading]`
+    /// or `heading` in `[label](note#heading)`. In Markdown links it's the
+    /// heading as written, e.g. `my%20heading`.
+    ///
+    /// Reference-style links have the heading in the `[ref]: ...` definition, so
+    /// there is no span for them.
+    pub fn heading_span(&self) -> Option<Range<Pos>> {
+        let heading = self.heading.as_ref()?;
+        if self.is_markdown() {
+            let (start, dest) = self.raw_dest()?;
+            let (path, heading) = dest.split_once(HEADING_START)?;
+            return Some(self.trimmed_span(start + path.len() + 1, heading));
+        }
+        let name_len = self
+            .note_name
+            .as_ref()
+            .map(|n| n.to_str().len())
+            .unwrap_or_default();
+        let search_from = self.prefix_len() + name_len;
+        let offset = self.text.get(search_from..)?.find(heading.as_str())? + search_from;
+        Some(self.col_span(offset, heading.len()))
+    }
+
+    /// Text to put into the `part` of the link destination, e.g. a new note name:
+    /// Markdown destinations can't contain spaces, so these get percent-encoded.
+    pub fn dest_part(&self, part: &str) -> String {
+        let in_brackets = self
+            .raw_dest()
+            .is_some_and(|(start, _)| self.text[..start].ends_with('<'));
+        if self.is_markdown() && !in_brackets {
+            part.replace(' ', "%20")
+        } else {
+            part.to_string()
+        }
+    }
+
+    /// Destination of an inline Markdown link as written, without the `<>` and
+    /// the title, and its offset in the link text.
+    fn raw_dest(&self) -> Option<(usize, &str)> {
+        let dest_start = self.text.rfind("](")? + 2;
+        let rest = &self.text[dest_start..];
+        let rest = rest.strip_suffix(')').unwrap_or(rest);
+        let dest = rest.trim_start();
+        let start = dest_start + rest.len() - dest.len();
+        match dest.strip_prefix('<') {
+            Some(dest) => Some((start + 1, &dest[..dest.find('>').unwrap_or(dest.len())])),
+            None => Some((
+                start,
+                &dest[..dest.find(char::is_whitespace).unwrap_or(dest.len())],
+            )),
+        }
+    }
+
+    /// Span of `part` of the link text at `offset` without the surrounding whitespace.
+    fn trimmed_span(&self, offset: usize, part: &str) -> Range<Pos> {
+        let leading = part.len() - part.trim_start().len();
+        self.col_span(offset + leading, part.trim().len())
+    }
+
+    /// Where a note name goes into a link that doesn't have one, e.g. after `[:`
+    /// in `[:@## Heading]`, and the separator the name needs after it.
+    pub fn note_name_insertion(&self) -> Option<(Pos, &'static str)> {
+        if self.note_name.is_some() {
+            return None;
+        }
+        let (offset, sep) = if self.is_markdown() {
+            (self.text.rfind("](")? + 2, "")
+        } else if self.is_heading_shorthand() {
+            (self.prefix_len(), "@")
+        } else {
+            (self.prefix_len(), "")
+        };
+        Some((self.col_span(offset, 0).start, sep))
+    }
+
+    fn prefix_len(&self) -> usize {
+        if self.is_wiki() {
+            WIKI_LINK_PREFIX.len()
+        } else {
+            LINK_PREFIX_1.len()
+        }
+    }
+
+    /// Span of `len` bytes at `offset` in the link text. The label of a Markdown
+    /// link may span multiple lines, but the parts within it never do.
+    fn col_span(&self, offset: usize, len: usize) -> Range<Pos> {
+        let before = &self.text[..offset];
+        let start = match before.rfind('\n') {
+            Some(line_start) => Pos::new(
+                self.span.start.line + before.matches('\n').count() as u32,
+                (offset - line_start - 1) as u32,
+            ),
+            None => Pos::new(self.span.start.line, self.span.start.col + offset as u32),
+        };
+        let end = Pos::new(start.line, start.col + len as u32);
+        start..end
+    }
+
+    /// Whether the link uses `[:## Heading]` shorthand for a heading in the same note.
+    pub fn is_heading_shorthand(&self) -> bool {
+        self.text
+            .trim_start_matches(LINK_PREFIX_1)
+            .starts_with(HEADING_START)
+    }
+}
+
+/// Reference-style link, e.g. `[label][ref]`, without a `[ref]: ...` definition.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct UndefinedLinkRef {
+    pub text: String,
+    pub reference: String,
+}
+
+/// Link reference definition, e.g. `[ref]: note#heading`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct LinkDef {
+    /// Label without the brackets, e.g. `ref`.
+    pub label: String,
+    /// Destination together with the title if any, e.g. `note#heading`.
+    pub dest: String,
+}
+
+/// Link labels are matched case-insensitively and with whitespace collapsed.
+pub fn link_label_key(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// YAML frontmatter at the beginning of a note, fenced with `---`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Frontmatter {
+    /// YAML source between the fences.
+    pub yaml: String,
+}
+
+/// A `<!-- zeta-note: ignore [code...] -->` comment that silences diagnostics
+/// on its line and on the next one.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Suppression {
+    /// Codes of the silenced diagnostics, all diagnostics if empty.
+    pub codes: Vec<String>,
+}
+
+impl Suppression {
+    pub fn applies_to(&self, code: &str) -> bool {
+        self.codes.is_empty() || self.codes.iter().any(|c| c == code)
+    }
+}
+
+/// An inline tag like `#project/foo`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct Tag {
+    /// Tag as written in the text, e.g. `#project/foo`.
+    pub text: String,
+    /// Tag without the leading `#`, e.g. `project/foo`.
+    pub name: String,
+}
+
+/// A link to anything but a note: `[label](https://...)`, an autolink
+/// `<https://...>`, or a bare URL in the text.
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct ExternLink {
     text: String,
@@ -122,7 +448,13 @@ pub struct ExternLink {
     title: Option<String>,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+impl ExternLink {
+    pub fn dest(&self) -> Option<&str> {
+        self.dest.as_deref()
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
 pub struct NoteName(String);
 
 impl From<String> for NoteName {
@@ -157,7 +489,11 @@ impl NoteName {
     }
 
     pub fn to_path(&self, root: &Path) -> PathBuf {
-        root.join(&self.0).with_extension("md")
+        self.to_path_with_extension(root, "md")
+    }
+
+    pub fn to_path_with_extension(&self, root: &Path, ext: &str) -> PathBuf {
+        root.join(format!("{}.{}", self.0, ext))
     }
 
     pub fn to_str(&self) -> &str {
@@ -170,9 +506,14 @@ pub fn parse_intern_link(text: &str) -> Option<InternLink> {
         let content = text
             .trim_start_matches(LINK_PREFIX_1)
             .trim_end_matches(LINK_SUFFIX);
-        let (name, heading) = match content.split_once([SEP_AT, SEP_BAR]) {
-            Some((n, h)) => (n, h),
-            _ => (content, ""),
+        let (name, heading) = if content.starts_with(HEADING_START) {
+            // `[:## Heading]` is a shorthand for `[:@## Heading]`
+            ("", content)
+        } else {
+            match content.split_once([SEP_AT, SEP_BAR]) {
+                Some((n, h)) => (n, h),
+                _ => (content, ""),
+            }
         };
         let name = Some(name)
             .filter(|s| !s.trim().is_empty())
@@ -191,6 +532,34 @@ pub fn parse_intern_link(text: &str) -> Option<InternLink> {
     }
 }
 
+/// Parse a wiki-style link: `[[note]]`, `[[note#Heading]]` or `[[note#Heading|Label]]`.
+pub fn parse_wiki_link(text: &str) -> Option<InternLink> {
+    let content = text
+        .strip_prefix(WIKI_LINK_PREFIX)?
+        .strip_suffix(WIKI_LINK_SUFFIX)?;
+    // The label after the bar is only for display
+    let target = match content.split_once(SEP_BAR) {
+        Some((t, _)) => t,
+        _ => content,
+    };
+    let (name, heading) = match target.split_once(HEADING_START) {
+        Some((n, h)) => (n, h),
+        _ => (target, ""),
+    };
+    let name = Some(name.trim())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+    let heading = Some(heading.trim())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string);
+
+    Some(InternLink {
+        text: text.to_string(),
+        note_name: name.map(Into::into),
+        heading,
+    })
+}
+
 pub fn parse_link_regular(text: &str, dest: CowStr, title: CowStr) -> ExternLink {
     let text = text.to_string();
     let dest = if dest.is_empty() {
@@ -206,12 +575,155 @@ pub fn parse_link_regular(text: &str, dest: CowStr, title: CowStr) -> ExternLink
     ExternLink { text, dest, title }
 }
 
+/// Parse YAML frontmatter at the very beginning of the note.
+pub fn scrape_frontmatter(index: &impl TextMap) -> Option<Node<Frontmatter>> {
+    let (block, yaml) = frontmatter_ranges(index.text())?;
+    let frontmatter = Frontmatter {
+        yaml: index.text()[yaml].to_string(),
+    };
+    Some(Node::new(frontmatter, index.offset_range_to_range(block)?))
+}
+
+/// Find suppression comments in HTML blocks and inline HTML of the note.
+pub fn scrape_suppressions(index: &impl TextMap) -> Vec<Node<Suppression>> {
+    let text = mask_frontmatter(index.text());
+    let mut suppressions = Vec::new();
+    for (event, span) in Parser::new_ext(&text, Options::all()).into_offset_iter() {
+        let html = match event {
+            Event::Html(html) => html,
+            _ => continue,
+        };
+
+        let mut offset = 0;
+        while let Some(start) = html[offset..].find(COMMENT_START) {
+            let body_start = offset + start + COMMENT_START.len();
+            let body_end = match html[body_start..].find(COMMENT_END) {
+                Some(end) => body_start + end,
+                None => break,
+            };
+            offset = body_end + COMMENT_END.len();
+
+            let suppression = match parse_suppression(&html[body_start..body_end]) {
+                Some(s) => s,
+                None => continue,
+            };
+            let comment_start = span.start + body_start - COMMENT_START.len();
+            let comment_end = span.start + offset;
+            if let Some(range) = index.offset_range_to_range(comment_start..comment_end) {
+                suppressions.push(Node::new(suppression, range));
+            }
+        }
+    }
+    suppressions
+}
+
+/// Find link reference definitions `[ref]: dest` outside of fenced code blocks.
+///
+/// Definitions spanning several lines and footnotes `[^note]: ...` are skipped.
+pub fn scrape_link_defs(index: &impl TextMap) -> Vec<Node<LinkDef>> {
+    let text = mask_frontmatter(index.text());
+    let mut defs = Vec::new();
+    let mut fence: Option<&str> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+
+        let content = line.trim_end();
+        let trimmed = content.trim_start();
+        if content.len() - trimmed.len() > 3 {
+            continue;
+        }
+        if let Some(marker) = CODE_FENCES.iter().find(|m| trimmed.starts_with(**m)) {
+            match fence {
+                Some(open) if open == *marker => fence = None,
+                None => fence = Some(*marker),
+                _ => (),
+            }
+            continue;
+        }
+        if fence.is_some() {
+            continue;
+        }
+
+        let def = trimmed.strip_prefix(LINK_START).and_then(|rest| {
+            let (label, dest) = rest.split_once("]:")?;
+            let dest = dest.trim();
+            if label.trim().is_empty() || label.starts_with('^') || dest.is_empty() {
+                return None;
+            }
+            Some(LinkDef {
+                label: label.to_string(),
+                dest: dest.to_string(),
+            })
+        });
+        let span = line_start + (line.len() - line.trim_start().len())..line_start + content.len();
+        if let (Some(def), Some(range)) = (def, index.offset_range_to_range(span)) {
+            defs.push(Node::new(def, range));
+        }
+    }
+    defs
+}
+
+/// Parse the body of an HTML comment like ` zeta-note: ignore broken-note-link `.
+fn parse_suppression(comment: &str) -> Option<Suppression> {
+    let directive = comment.trim().strip_prefix(SUPPRESSION_PREFIX)?;
+    let mut words = directive.split_whitespace();
+    if words.next()? != "ignore" {
+        return None;
+    }
+    Some(Suppression {
+        codes: words.map(|w| w.to_string()).collect(),
+    })
+}
+
+/// Byte ranges of the whole frontmatter block (including fences) and of the YAML inside it.
+fn frontmatter_ranges(text: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let mut lines = text.split_inclusive('\n');
+    let first = lines.next()?;
+    if first.trim_end() != FRONTMATTER_FENCE {
+        return None;
+    }
+
+    let yaml_start = first.len();
+    let mut offset = yaml_start;
+    for line in lines {
+        let line_text = line.trim_end();
+        if line_text == FRONTMATTER_FENCE || line_text == FRONTMATTER_END {
+            return Some((0..(offset + line_text.len()), yaml_start..offset));
+        }
+        offset += line.len();
+    }
+
+    None
+}
+
+/// Markdown parser doesn't know about frontmatter and would see a thematic break and
+/// headings there. Blank it out while keeping the offsets of the rest of the text intact.
+fn mask_frontmatter(text: &str) -> Cow<'_, str> {
+    match frontmatter_ranges(text) {
+        Some((block, _)) => {
+            let mut masked = String::with_capacity(text.len());
+            for c in text[block.clone()].chars() {
+                if c == '\n' {
+                    masked.push(c);
+                } else {
+                    masked.push_str(&" ".repeat(c.len_utf8()));
+                }
+            }
+            masked.push_str(&text[block.end..]);
+            Cow::Owned(masked)
+        }
+        _ => Cow::Borrowed(text),
+    }
+}
+
 type ParseIter<'a, 'b> = Peekable<OffsetIter<'a, 'b>>;
 
 pub fn scrape(index: &impl TextMap) -> Vec<Element> {
+    let text = mask_frontmatter(index.text());
     let mut callback = |_: BrokenLink<'_>| Some(("".into(), "".into()));
-    let parser =
-        Parser::new_with_broken_link_callback(index.text(), Options::all(), Some(&mut callback));
+    let parser = Parser::new_with_broken_link_callback(&text, Options::all(), Some(&mut callback));
     let stop_when = |_: &Event<'_>| false;
 
     let mut iter: ParseIter<'_, '_> = parser.into_offset_iter().peekable();
@@ -247,8 +759,22 @@ fn scrape_document<'a, 'b>(
                     tag, text_span
                 );
             }
-            Event::Text(txt) => {
-                let partial_links = scrape_partial_links(index, txt, next_span);
+            Event::Text(_) => {
+                // The parser splits text on potential delimiters like `_`, so
+                // adjacent text events are merged back together
+                let mut text_span = next_span;
+                while let Some(end) = match iter.peek() {
+                    Some((Event::Text(_), span)) if span.start == text_span.end => Some(span.end),
+                    _ => None,
+                } {
+                    text_span.end = end;
+                    iter.next();
+                }
+
+                elements.extend(scrape_tags(index, text_span.clone()));
+                elements.extend(scrape_bare_urls(index, text_span.clone()));
+                let txt = index.text()[text_span.clone()].into();
+                let partial_links = scrape_partial_links(index, txt, text_span);
                 elements.extend(partial_links);
             }
             _ => (),
@@ -260,14 +786,20 @@ fn scrape_document<'a, 'b>(
 
 fn scrape_block<'a, 'b>(
     index: &impl TextMap,
-    start_tag: &Tag<'a>,
+    start_tag: &MdTag<'a>,
     start_span: Range<usize>,
     iter: &mut ParseIter<'a, 'b>,
 ) -> Vec<Element> {
     match start_tag {
-        Tag::Heading(..) => scrape_heading(index, start_tag, start_span, iter),
-        Tag::Link(..) => scrape_link(index, start_tag, start_span, iter),
-        Tag::Paragraph | Tag::List(..) | Tag::Item => {
+        MdTag::Heading(..) => scrape_heading(index, start_tag, start_span, iter),
+        MdTag::Link(..) => scrape_link(index, start_tag, start_span, iter),
+        // Links in fenced and indented code are examples rather than references.
+        // Code spans are `Event::Code` and are skipped in `scrape_document`.
+        MdTag::CodeBlock(..) => {
+            skip_block(start_tag, iter);
+            Vec::new()
+        }
+        MdTag::Paragraph | MdTag::List(..) | MdTag::Item => {
             let stop_when =
                 |seen_event: &Event<'_>| matches!(seen_event, Event::End(t) if t == start_tag);
             let par_elements = scrape_document(index, iter, stop_when);
@@ -291,12 +823,12 @@ fn scrape_block<'a, 'b>(
 
 fn scrape_heading<'a, 'b>(
     index: &impl TextMap,
-    start_tag: &Tag<'a>,
+    start_tag: &MdTag<'a>,
     start_span: Range<usize>,
     iter: &mut ParseIter<'a, 'b>,
 ) -> Vec<Element> {
     let mut elements = Vec::new();
-    let current_heading_level = if let Tag::Heading(level, ..) = start_tag {
+    let current_heading_level = if let MdTag::Heading(level, ..) = start_tag {
         level
     } else {
         panic!("Unexpected start tag for heading: {:?}", start_tag)
@@ -313,7 +845,7 @@ fn scrape_heading<'a, 'b>(
     );
 
     // Process all child elements (until the heading of <= level)
-    let stop_when = |seen_event: &Event<'_>| matches!(seen_event, Event::Start(Tag::Heading(level, ..)) if level <= current_heading_level);
+    let stop_when = |seen_event: &Event<'_>| matches!(seen_event, Event::Start(MdTag::Heading(level, ..)) if level <= current_heading_level);
     elements.extend(scrape_document(index, iter, stop_when));
 
     let next_section_event = iter.peek();
@@ -342,14 +874,14 @@ fn scrape_heading<'a, 'b>(
 
 fn scrape_link<'a, 'b>(
     index: &impl TextMap,
-    start_tag: &Tag<'a>,
+    start_tag: &MdTag<'a>,
     start_span: Range<usize>,
     iter: &mut ParseIter<'a, 'b>,
 ) -> Vec<Element> {
     let mut elements = Vec::new();
 
     match start_tag {
-        Tag::Link(typ, dest, title) => match typ {
+        MdTag::Link(typ, dest, title) => match typ {
             LinkType::Inline
             | LinkType::Reference
             | LinkType::ReferenceUnknown
@@ -357,10 +889,17 @@ fn scrape_link<'a, 'b>(
             | LinkType::CollapsedUnknown
             | LinkType::Shortcut
             | LinkType::ShortcutUnknown => {
+                if let Some(wiki_link) = scrape_wiki_link(index, typ, &start_span) {
+                    elements.push(wiki_link);
+                    skip_block(start_tag, iter);
+                    return elements;
+                }
+
                 let pos_span = index.offset_range_to_range(start_span.clone()).unwrap();
                 let link_text = index.text()[start_span].trim();
                 let link = parse_intern_link(link_text)
                     .map(|l| Node::new(l, pos_span.clone()).into())
+                    .or_else(|| parse_link_ref(typ, link_text, dest, pos_span.clone()))
                     .unwrap_or_else(|| {
                         let fallback_extern_link =
                             parse_link_regular(link_text, dest.clone(), title.clone());
@@ -368,7 +907,13 @@ fn scrape_link<'a, 'b>(
                     });
                 elements.push(link);
             }
-            _ => (),
+            // `<https://example.com>` and `<me@example.com>`
+            LinkType::Autolink | LinkType::Email => {
+                let pos_span = index.offset_range_to_range(start_span.clone()).unwrap();
+                let link_text = index.text()[start_span].trim();
+                let link = parse_link_regular(link_text, dest.clone(), title.clone());
+                elements.push(Node::new(link, pos_span).into());
+            }
         },
         _ => panic!("scrape_link: unexpected link tag: {:?}", start_tag),
     }
@@ -377,7 +922,95 @@ fn scrape_link<'a, 'b>(
     elements
 }
 
-fn skip_block<'a, 'b>(tag: &Tag<'a>, iter: &mut ParseIter<'a, 'b>) {
+/// Inline links `[label](dest)` and reference-style links: `[label][ref]`, `[ref][]` and `[ref]`.
+///
+/// A link whose destination points to a note (`note`, `../note.md#Heading`)
+/// becomes an intern link. Links without a definition are reported as undefined,
+/// except for shortcut links `[text]`, which are just as likely to be plain text.
+fn parse_link_ref(typ: &LinkType, text: &str, dest: &str, span: Range<Pos>) -> Option<Element> {
+    match typ {
+        LinkType::Inline | LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut => {
+            let (note_name, heading) = parse_note_dest(dest)?;
+            let link = InternLink {
+                text: text.to_string(),
+                note_name,
+                heading,
+            };
+            Some(Node::new(link, span).into())
+        }
+        LinkType::ReferenceUnknown | LinkType::CollapsedUnknown => {
+            // The span of a collapsed link `[ref][]` may not include the `[]`
+            let label = match text.strip_suffix("[]") {
+                Some(label) => label,
+                None => text.rfind("][").map_or(text, |idx| &text[idx + 1..]),
+            };
+            let reference = label.strip_prefix('[')?.strip_suffix(']')?.trim();
+            let link_ref = UndefinedLinkRef {
+                text: text.to_string(),
+                reference: reference.to_string(),
+            };
+            Some(Node::new(link_ref, span).into())
+        }
+        _ => None,
+    }
+}
+
+/// Split a link destination pointing to a note into the note name and the heading.
+///
+/// Paths like `../note.md` or `sub/note` are kept as written, they are
+/// resolved relative to the linking note. URLs and files that are not notes
+/// (judging by the extension) give `None`. Percent-encoded destinations like
+/// `my%20note` are decoded.
+fn parse_note_dest(dest: &str) -> Option<(Option<NoteName>, Option<String>)> {
+    if dest.is_empty() || dest.contains(':') {
+        return None;
+    }
+
+    let (path, heading) = match dest.split_once(HEADING_START) {
+        Some((path, heading)) => (path, Some(percent_decode(heading).trim().to_string())),
+        None => (dest, None),
+    };
+    let path = percent_decode(path);
+    let name = path.trim();
+    // A trailing dot as in `note.` is a typo rather than an extension
+    let has_extension = Path::new(name)
+        .extension()
+        .is_some_and(|ext| !ext.is_empty());
+    if has_extension && !has_note_extension(name) {
+        return None;
+    }
+
+    let note_name = Some(name).filter(|n| !n.is_empty()).map(NoteName::from);
+    if note_name.is_none() && heading.is_none() {
+        return None;
+    }
+    Some((note_name, heading))
+}
+
+/// First and last lines (1-based) of a GitHub-style line anchor like `L10` or
+/// `L10-L20`.
+pub fn parse_line_anchor(anchor: &str) -> Option<(u32, u32)> {
+    let line = |l: &str| l.strip_prefix('L')?.parse::<u32>().ok();
+    match anchor.split_once('-') {
+        Some((start, end)) => Some((line(start)?, line(end)?)),
+        None => line(anchor).map(|l| (l, l)),
+    }
+}
+
+/// Markdown parser sees `[[note]]` as a shortcut link `[note]` surrounded by brackets.
+fn scrape_wiki_link(index: &impl TextMap, typ: &LinkType, span: &Range<usize>) -> Option<Element> {
+    if !matches!(typ, LinkType::Shortcut | LinkType::ShortcutUnknown) {
+        return None;
+    }
+
+    let text = index.text();
+    let wiki_span = span.start.checked_sub(1)?..(span.end + 1);
+    let link = parse_wiki_link(text.get(wiki_span.clone())?)?;
+    let pos_span = index.offset_range_to_range(wiki_span)?;
+    Some(Node::new(link, pos_span).into())
+}
+
+fn skip_block<'a, 'b>(tag: &MdTag<'a>, iter: &mut ParseIter<'a, 'b>) {
     for (event, _) in iter {
         match event {
             Event::End(end_tag) if end_tag == *tag => break,
@@ -386,6 +1019,87 @@ fn skip_block<'a, 'b>(tag: &Tag<'a>, iter: &mut ParseIter<'a, 'b>) {
     }
 }
 
+pub fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '/'
+}
+
+/// Find tags inside a text span.
+///
+/// Code spans and code blocks never produce text events, so tags are not looked for there.
+fn scrape_tags(index: &impl TextMap, span: Range<usize>) -> Vec<Element> {
+    let text = &index.text()[span.clone()];
+    let mut tags = Vec::new();
+
+    let mut prev_char = None;
+    for (idx, c) in text.char_indices() {
+        let after_word = prev_char.is_some_and(|p| is_tag_char(p) || p == HEADING_START);
+        prev_char = Some(c);
+        if c != HEADING_START || after_word {
+            continue;
+        }
+
+        let name_start = idx + c.len_utf8();
+        let name_len = text[name_start..]
+            .find(|c| !is_tag_char(c))
+            .unwrap_or(text.len() - name_start);
+        // Hierarchy separators are only allowed between the parts of a tag
+        let name = text[name_start..name_start + name_len].trim_end_matches('/');
+        if !name.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+
+        let tag_span = (span.start + idx)..(span.start + name_start + name.len());
+        let tag = Tag {
+            text: text[idx..name_start + name.len()].to_string(),
+            name: name.to_string(),
+        };
+        tags.push(Node::new(tag, index.offset_range_to_range(tag_span).unwrap()).into());
+    }
+
+    tags
+}
+
+/// Find bare URLs like `https://example.com` inside a text span.
+///
+/// Markdown doesn't turn them into links, but they are links to the reader and
+/// must never be mistaken for references to notes.
+fn scrape_bare_urls(index: &impl TextMap, span: Range<usize>) -> Vec<Element> {
+    let text = &index.text()[span.clone()];
+    let mut urls = Vec::new();
+
+    let mut offset = 0;
+    while let Some((start, _)) = URL_SCHEMES
+        .iter()
+        .filter_map(|scheme| {
+            text[offset..]
+                .find(scheme)
+                .map(|idx| (offset + idx, scheme))
+        })
+        .min()
+    {
+        let starts_word = !text[..start].ends_with(|c: char| c.is_alphanumeric());
+        let len = text[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"'`".contains(c))
+            .unwrap_or(text.len() - start);
+        // Punctuation at the end belongs to the sentence, not to the URL
+        let url = text[start..start + len].trim_end_matches(|c| ".,;:!?)]".contains(c));
+        offset = start + len;
+        if !starts_word || URL_SCHEMES.contains(&url) {
+            continue;
+        }
+
+        let url_span = (span.start + start)..(span.start + start + url.len());
+        let link = ExternLink {
+            text: url.to_string(),
+            dest: Some(url.to_string()),
+            title: None,
+        };
+        urls.push(Node::new(link, index.offset_range_to_range(url_span).unwrap()).into());
+    }
+
+    urls
+}
+
 fn scrape_partial_links(_index: &impl TextMap, _txt: CowStr, _span: Range<usize>) -> Vec<Element> {
     Vec::new()
 }
@@ -430,6 +1144,349 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_intern_link_heading_shorthand() {
+        let parsed = parse_intern_link("[:## Heading]");
+        assert_eq!(
+            Some(InternLink {
+                text: "[:## Heading]".to_string(),
+                note_name: None,
+                heading: Some("## Heading".to_string())
+            }),
+            parsed
+        );
+
+        let parsed = parse_intern_link("[:note@## Heading]");
+        assert_eq!(
+            Some(InternLink {
+                text: "[:note@## Heading]".to_string(),
+                note_name: Some("note".into()),
+                heading: Some("## Heading".to_string())
+            }),
+            parsed
+        );
+    }
+
+    #[test]
+    fn heading_slugs() {
+        assert_eq!(slugify("My Heading"), "my-heading");
+        assert_eq!(slugify("My-Heading"), "my-heading");
+        assert_eq!(slugify("  What's  new?  "), "whats-new");
+        assert_eq!(slugify("snake_case & C++"), "snake_case-c");
+        assert_eq!(slugify("Ünïcode Text"), "ünïcode-text");
+    }
+
+    #[test]
+    fn scrape_wiki_links() {
+        let text = "[[note]] and [[other note#Heading|Label]]";
+        let elements = scrape(&IndexedText::new(text));
+        assert_eq!(
+            elements,
+            vec![
+                Element::InternLink(Node::new(
+                    InternLink {
+                        text: "[[note]]".to_string(),
+                        note_name: Some("note".into()),
+                        heading: None
+                    },
+                    Pos::new(0, 0)..Pos::new(0, 8)
+                )),
+                Element::InternLink(Node::new(
+                    InternLink {
+                        text: "[[other note#Heading|Label]]".to_string(),
+                        note_name: Some("other note".into()),
+                        heading: Some("Heading".to_string())
+                    },
+                    Pos::new(0, 13)..Pos::new(0, 41)
+                ))
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_wiki_link_spans() {
+        let elements = scrape(&IndexedText::new("See [[note#Heading]]"));
+        let link = match &elements[..] {
+            [Element::InternLink(link)] => link,
+            other => panic!("Unexpected elements: {:?}", other),
+        };
+        assert_eq!(link.note_name_span(), Some(Pos::new(0, 6)..Pos::new(0, 10)));
+        assert_eq!(link.heading_span(), Some(Pos::new(0, 11)..Pos::new(0, 18)));
+    }
+
+    #[test]
+    fn scrape_md_link_heading_spans() {
+        let elements = scrape(&IndexedText::new("[note#x](note#my-heading) [x](#top)"));
+        let spans = elements
+            .iter()
+            .filter_map(|e| match e {
+                Element::InternLink(link) => Some((link.note_name_span(), link.heading_span())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec![
+                (
+                    Some(Pos::new(0, 9)..Pos::new(0, 13)),
+                    Some(Pos::new(0, 14)..Pos::new(0, 24))
+                ),
+                (None, Some(Pos::new(0, 31)..Pos::new(0, 34)))
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_md_link_before_punctuation() {
+        for punct in [".", ",", "?"] {
+            let text = format!("See [x](note){}", punct);
+            let elements = scrape(&IndexedText::new(text.as_str()));
+            let link = match &elements[..] {
+                [Element::InternLink(link)] => link,
+                other => panic!("Unexpected elements: {:?}", other),
+            };
+            assert_eq!(link.note_name, Some(NoteName::from("note")));
+            assert_eq!(link.span, Pos::new(0, 4)..Pos::new(0, 13));
+        }
+
+        let elements = scrape(&IndexedText::new("See [x](note.)"));
+        let link = match &elements[..] {
+            [Element::InternLink(link)] => link,
+            other => panic!("Unexpected elements: {:?}", other),
+        };
+        assert_eq!(link.note_name, Some(NoteName::from("note.")));
+    }
+
+    #[test]
+    fn scrape_percent_encoded_md_link() {
+        let text = "[a](my%20note) [b](sub%2Fnote#my%20heading) [c](100%zz)";
+        let elements = scrape(&IndexedText::new(text));
+        let links = elements
+            .iter()
+            .map(|el| match el {
+                Element::InternLink(link) => link,
+                other => panic!("Unexpected element: {:?}", other),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(links[0].note_name, Some(NoteName::from("my note")));
+        // The span covers the link as written
+        assert_eq!(links[0].span, Pos::new(0, 0)..Pos::new(0, 14));
+        assert_eq!(links[1].note_name, Some(NoteName::from("sub/note")));
+        assert_eq!(links[1].heading, Some("my heading".to_string()));
+        assert_eq!(links[2].note_name, Some(NoteName::from("100%zz")));
+
+        // Spans of the parts are of the text as written too
+        assert_eq!(
+            links[0].note_name_span(),
+            Some(Pos::new(0, 4)..Pos::new(0, 13))
+        );
+        assert_eq!(
+            links[1].note_name_span(),
+            Some(Pos::new(0, 19)..Pos::new(0, 29))
+        );
+        assert_eq!(
+            links[1].heading_span(),
+            Some(Pos::new(0, 30)..Pos::new(0, 42))
+        );
+    }
+
+    #[test]
+    fn line_anchors() {
+        assert_eq!(parse_line_anchor("L10"), Some((10, 10)));
+        assert_eq!(parse_line_anchor("L10-L20"), Some((10, 20)));
+        assert_eq!(parse_line_anchor("L10-20"), None);
+        assert_eq!(parse_line_anchor("Line"), None);
+        assert_eq!(parse_line_anchor("l10"), None);
+    }
+
+    #[test]
+    fn scrape_no_links_in_code() {
+        let text = "# A\n\n```markdown\n[example](foo) [:bar] [[baz]]\n```\n\nInline `[x](y)` and `[:z]`.\n\n    [indented](foo)\n\n- Item\n\n  ~~~\n  [:in-list]\n  ~~~\n\n[real](foo)\n";
+        let elements = scrape(&IndexedText::new(text));
+        let mut links = Vec::new();
+        let mut stack = elements.iter().collect::<Vec<_>>();
+        while let Some(el) = stack.pop() {
+            match el {
+                Element::InternLink(link) => links.push(link.text.clone()),
+                Element::Heading(heading) => stack.extend(heading.children.iter()),
+                _ => (),
+            }
+        }
+        assert_eq!(links, vec!["[real](foo)".to_string()]);
+    }
+
+    #[test]
+    fn scrape_external_links() {
+        let text = "# A\n\n<https://example.com> and https://example.org/a?b=1. See [x](https://example.net) and (http://example.edu).\n\nNot a URL: xhttps://a.b or https://\n";
+        let elements = scrape(&IndexedText::new(text));
+        let mut externs = Vec::new();
+        let mut interns = 0;
+        let mut stack = elements.iter().collect::<Vec<_>>();
+        while let Some(el) = stack.pop() {
+            match el {
+                Element::ExternLink(link) => {
+                    externs.push((link.dest().unwrap().to_string(), link.span.clone()))
+                }
+                Element::InternLink(_) => interns += 1,
+                Element::Heading(heading) => stack.extend(heading.children.iter()),
+                _ => (),
+            }
+        }
+        externs.sort_by_key(|(_, span)| span.start);
+
+        assert_eq!(interns, 0);
+        assert_eq!(
+            externs,
+            vec![
+                (
+                    "https://example.com".to_string(),
+                    Pos::new(2, 0)..Pos::new(2, 21)
+                ),
+                (
+                    "https://example.org/a?b=1".to_string(),
+                    Pos::new(2, 26)..Pos::new(2, 51)
+                ),
+                (
+                    "https://example.net".to_string(),
+                    Pos::new(2, 57)..Pos::new(2, 81)
+                ),
+                (
+                    "http://example.edu".to_string(),
+                    Pos::new(2, 87)..Pos::new(2, 105)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_unterminated_wiki_link() {
+        let elements = scrape(&IndexedText::new("[[note\n\n[[note]"));
+        assert!(elements
+            .iter()
+            .all(|e| !matches!(e, Element::InternLink(_))));
+    }
+
+    #[test]
+    fn scrape_tags() {
+        let text =
+            "# Title #title\n\nText #project/foo, a#b ## #_x #1/\n\n`#code`\n\n```\n#block\n```\n";
+        let elements = scrape(&IndexedText::new(text));
+
+        fn collect_tags(elements: &[Element], tags: &mut Vec<(String, Range<Pos>)>) {
+            for el in elements {
+                match el {
+                    Element::Tag(t) => tags.push((t.name.clone(), t.span.clone())),
+                    Element::Heading(h) => collect_tags(&h.children, tags),
+                    _ => (),
+                }
+            }
+        }
+        let mut tags = Vec::new();
+        collect_tags(&elements, &mut tags);
+
+        assert_eq!(
+            tags,
+            vec![
+                ("title".to_string(), Pos::new(0, 8)..Pos::new(0, 14)),
+                ("project/foo".to_string(), Pos::new(2, 5)..Pos::new(2, 17)),
+                ("_x".to_string(), Pos::new(2, 26)..Pos::new(2, 29)),
+                ("1".to_string(), Pos::new(2, 30)..Pos::new(2, 32)),
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_after_frontmatter() {
+        let text = IndexedText::new("---\ntitle: Note\n---\n# Title\n");
+        let frontmatter = scrape_frontmatter(&text).unwrap();
+        assert_eq!(frontmatter.yaml, "title: Note\n");
+        assert_eq!(frontmatter.span, Pos::new(0, 0)..Pos::new(2, 3));
+
+        let elements = scrape(&text);
+        let headings: Vec<_> = elements
+            .iter()
+            .filter_map(|e| e.as_heading())
+            .map(|h| (h.text.as_str(), h.span.clone()))
+            .collect();
+        assert_eq!(headings, vec![("# Title", Pos::new(3, 0)..Pos::new(3, 7))]);
+    }
+
+    #[test]
+    fn frontmatter_only_on_first_line() {
+        assert_eq!(
+            scrape_frontmatter(&IndexedText::new("\n---\ntitle: Note\n---\n")),
+            None
+        );
+        assert_eq!(
+            scrape_frontmatter(&IndexedText::new("---\ntitle: Note\n")),
+            None
+        );
+    }
+
+    #[test]
+    fn scrape_suppression_comments() {
+        let text = IndexedText::new(
+            "# Title\n\n<!-- zeta-note: ignore -->\n[:a]\n\nText <!-- zeta-note: ignore dup-heading broken-note-link --> [:b]\n\n<!-- regular comment -->\n",
+        );
+        let suppressions = scrape_suppressions(&text)
+            .into_iter()
+            .map(|s| (s.codes.clone(), s.span.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            suppressions,
+            vec![
+                (vec![], Pos::new(2, 0)..Pos::new(2, 26)),
+                (
+                    vec!["dup-heading".to_string(), "broken-note-link".to_string()],
+                    Pos::new(5, 5)..Pos::new(5, 60)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn scrape_link_definitions() {
+        let text = IndexedText::new(
+            "# Title\n\n[1]: note#heading\n  [Two Words]: <other.md> \"Title\"\n\n```\n[3]: code\n```\n[^4]: footnote\n",
+        );
+        let defs = scrape_link_defs(&text)
+            .into_iter()
+            .map(|d| (d.label.clone(), d.dest.clone(), d.span.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            defs,
+            vec![
+                (
+                    "1".to_string(),
+                    "note#heading".to_string(),
+                    Pos::new(2, 0)..Pos::new(2, 17)
+                ),
+                (
+                    "Two Words".to_string(),
+                    "<other.md> \"Title\"".to_string(),
+                    Pos::new(3, 2)..Pos::new(3, 33)
+                ),
+            ]
+        );
+        assert_eq!(link_label_key(" Two\tWords "), "two words");
+    }
+
+    #[test]
+    fn intern_link_parts() {
+        let link = |text: &str| InternLink {
+            text: text.to_string(),
+            note_name: Some("note".into()),
+            heading: None,
+        };
+        assert_eq!(link("[a](note)").inline_parts(), Some(("[a]", "note")));
+        assert_eq!(link("[a](note)").reference_parts(), None);
+        assert_eq!(link("[a][1]").reference_parts(), Some(("[a]", "1")));
+        assert_eq!(link("[ref][]").reference_parts(), Some(("[ref]", "ref")));
+        assert_eq!(link("[ref]").reference_parts(), Some(("[ref]", "ref")));
+        assert_eq!(link("[:note]").inline_parts(), None);
+    }
+
     #[test]
     fn scrape_note() -> Result<()> {
         let text = IndexedText::new(read_resource("example1.md")?);
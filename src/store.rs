@@ -1,10 +1,13 @@
 use anyhow::Result;
 
-use glob::Pattern;
+use ignore::{gitignore::Gitignore, WalkBuilder};
 use lsp_types::WorkspaceFolder;
+use once_cell::sync::OnceCell;
 
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
     time::SystemTime,
@@ -14,49 +17,91 @@ use tokio::fs;
 use tracing::debug;
 
 use crate::{
-    facts::{self, FactsDB},
+    config::Config,
+    facts::{FactsDB, NoteFacts},
     parser::NoteName,
     store,
     structure::NoteID,
 };
 
-#[derive(Default)]
+/// Notes from all workspace folders share the same facts, so that links
+/// resolve across folders.
 pub struct Workspace {
-    pub folders: Vec<(NoteFolder, FactsDB, Vec<Pattern>)>,
+    pub folders: Vec<(NoteFolder, NoteFilter)>,
+    pub facts: FactsDB,
 }
 
 impl Workspace {
-    pub async fn new(input_folders: &[NoteFolder]) -> Result<Workspace> {
-        let mut workspace = Workspace::default();
-        for f in input_folders {
-            workspace.add_folder(f.clone()).await?;
+    pub fn empty(config: &Config) -> Workspace {
+        let mut workspace = Workspace {
+            folders: Vec::new(),
+            facts: FactsDB::empty(),
+        };
+        workspace.apply_config(config);
+        workspace
+    }
+
+    /// Pass the settings that affect facts about notes to the facts database.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.facts.set_name_matching(config.note_name_matching());
+        self.facts.set_link_resolution(config.link_resolution);
+        self.facts.set_title_level(config.title_heading_level);
+        self.facts.set_title_fallback(config.title_fallback);
+        self.facts.set_diag_options(config.diag_options());
+        self.facts.set_resource_roots(config.resource_roots.clone());
+    }
+
+    pub async fn new(input_folders: &[NoteFolder], config: &Config) -> Result<Workspace> {
+        Workspace::new_reporting(input_folders, config, |_| {}).await
+    }
+
+    /// Same as [`Workspace::new`], but calls `on_progress` with the percentage of
+    /// notes read so far.
+    pub async fn new_reporting(
+        input_folders: &[NoteFolder],
+        config: &Config,
+        mut on_progress: impl FnMut(u32),
+    ) -> Result<Workspace> {
+        let mut workspace = Workspace::empty(config);
+        let num_folders = input_folders.len();
+        for (idx, f) in input_folders.iter().enumerate() {
+            let mut on_read = |read: usize, total: usize| {
+                let done = (idx * total + read) * 100 / (num_folders * total);
+                on_progress(done as u32);
+            };
+            workspace
+                .add_folder_reporting(f.clone(), config, &mut on_read)
+                .await?;
         }
+        workspace.facts.parse_all();
 
         Ok(workspace)
     }
 
     pub fn note_count(&self) -> usize {
-        self.folders
-            .iter()
-            .map(|(_, facts, _)| facts.note_index().size())
-            .sum()
+        self.facts.note_index().size()
+    }
+
+    pub fn roots(&self) -> Vec<&NoteFolder> {
+        self.folders.iter().map(|(folder, _)| folder).collect()
     }
 
     pub fn owning_folder(&self, file: &Path) -> Option<(&NoteFolder, &FactsDB)> {
         self.folders
             .iter()
-            .find(|(folder, _, _)| file.starts_with(&folder.root))
-            .map(|(folder, facts, _)| (folder, facts))
+            .find(|(folder, _)| file.starts_with(&folder.root))
+            .map(|(folder, _)| (folder, &self.facts))
     }
 
     pub fn owning_folder_mut(
         &mut self,
         file: &Path,
-    ) -> Option<(&mut NoteFolder, &mut FactsDB, &[Pattern])> {
+    ) -> Option<(&mut NoteFolder, &mut FactsDB, &NoteFilter)> {
+        let facts = &mut self.facts;
         self.folders
             .iter_mut()
-            .find(|(folder, _, _)| file.starts_with(&folder.root))
-            .map(|(folder, facts, ignores)| (folder, facts, ignores.as_slice()))
+            .find(|(folder, _)| file.starts_with(&folder.root))
+            .map(|(folder, filter)| (folder, facts, &*filter))
     }
 
     pub fn remove_folder(&mut self, path: &Path) {
@@ -64,28 +109,242 @@ impl Workspace {
             .folders
             .iter()
             .enumerate()
-            .find(|(_, (folder, _, _))| folder.root == path)
+            .find(|(_, (folder, _))| folder.root == path)
         {
             self.folders.remove(idx);
+            self.facts.remove_root(path);
         }
     }
 
-    pub async fn add_folder(&mut self, folder: NoteFolder) -> Result<()> {
+    pub async fn add_folder(&mut self, folder: NoteFolder, config: &Config) -> Result<()> {
+        self.add_folder_reporting(folder, config, &mut |_, _| {})
+            .await
+    }
+
+    async fn add_folder_reporting(
+        &mut self,
+        folder: NoteFolder,
+        config: &Config,
+        on_read: &mut dyn FnMut(usize, usize),
+    ) -> Result<()> {
         if self.owning_folder(&folder.root).is_some() {
             return Ok(());
         }
 
-        let ignores = store::find_ignores(&folder.root).await?;
-        let note_files = store::find_notes(&folder.root, &ignores).await?;
-        debug!(
-            "Workspace {}: found {} note files",
-            folder.root.display(),
-            note_files.len()
-        );
-        let facts = facts::FactsDB::from_files(&folder.root, &note_files, &ignores).await?;
-        self.folders.push((folder, facts, ignores));
+        let filter = scan_folder(&folder, &mut self.facts, config, on_read).await?;
+        self.folders.push((folder, filter));
+        Ok(())
+    }
+
+    /// Scan the folder owning `path` from scratch, e.g. after its ignore files changed.
+    ///
+    /// Notes opened in the editor keep their in-memory content.
+    pub async fn rescan_folder(&mut self, path: &Path, config: &Config) -> Result<()> {
+        let (folder, old_filter) = match self
+            .folders
+            .iter_mut()
+            .find(|(folder, _)| path.starts_with(&folder.root))
+        {
+            Some(entry) => entry,
+            _ => return Ok(()),
+        };
+
+        let open_notes: Vec<(NoteFile, NoteText)> = open_notes(&self.facts)
+            .into_iter()
+            .filter(|(file, _)| *file.root == *folder.root)
+            .collect();
+
+        self.facts.remove_root(&folder.root);
+        *old_filter = scan_folder(folder, &mut self.facts, config, &mut |_, _| {}).await?;
+        self.facts.insert_notes(open_notes);
         Ok(())
     }
+
+    /// Discard all facts and read the notes of all folders from disk again.
+    /// Returns the number of notes indexed.
+    ///
+    /// Notes opened in the editor keep their in-memory content.
+    pub async fn reindex(&mut self, config: &Config) -> Result<usize> {
+        let open_notes = open_notes(&self.facts);
+        let position_encoding = self.facts.db().position_encoding(());
+
+        self.facts = FactsDB::empty();
+        self.apply_config(config);
+        self.facts.set_position_encoding(position_encoding);
+        for (folder, filter) in self.folders.iter_mut() {
+            *filter = scan_folder(folder, &mut self.facts, config, &mut |_, _| {}).await?;
+        }
+        self.facts.insert_notes(open_notes);
+        self.facts.parse_all();
+        Ok(self.note_count())
+    }
+}
+
+/// Notes opened in the editor along with their in-memory content.
+fn open_notes(facts: &FactsDB) -> Vec<(NoteFile, NoteText)> {
+    let index = facts.note_index();
+    index
+        .files()
+        .filter_map(|file| {
+            let text = facts.note_facts(index.find_by_path(&file.path)?).text();
+            match text.version {
+                Version::Vs(_) => Some((file.clone(), text)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Number of notes read between two calls of the progress callback of [`scan_folder`].
+const SCAN_BATCH: usize = 100;
+
+/// Add notes found in `folder` to `facts`. `on_read` is called with the number
+/// of notes read so far and the total number of notes.
+async fn scan_folder(
+    folder: &NoteFolder,
+    facts: &mut FactsDB,
+    config: &Config,
+    on_read: &mut dyn FnMut(usize, usize),
+) -> Result<NoteFilter> {
+    let (note_files, filter) = store::scan_notes(&folder.root, config)?;
+    debug!(
+        "Workspace {}: found {} note files",
+        folder.root.display(),
+        note_files.len()
+    );
+    let mut read = 0;
+    for batch in note_files.chunks(SCAN_BATCH) {
+        facts.add_files(&folder.root, batch, &filter).await?;
+        read += batch.len();
+        on_read(read, note_files.len());
+    }
+    Ok(filter)
+}
+
+/// What links to notes are matched against.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkResolution {
+    /// File names only.
+    Filename,
+    /// Note titles only, e.g. for notes named by a date like `20230101.md`.
+    Title,
+    /// File names first, then titles.
+    #[default]
+    Both,
+}
+
+/// Decides which files inside a folder are notes, the same way as the scan of
+/// the folder in [`scan_notes`].
+#[derive(Debug, Clone)]
+pub struct NoteFilter {
+    ignores: IgnoreRules,
+    extensions: Vec<String>,
+    scan_hidden: bool,
+}
+
+impl NoteFilter {
+    pub fn is_note_file(&self, path: &Path, root: &Path) -> bool {
+        let rel_path = match path.strip_prefix(root) {
+            Ok(rel_path) => rel_path,
+            _ => return false,
+        };
+        if !has_note_extension(path, &self.extensions) || is_hidden(rel_path, self.scan_hidden) {
+            return false;
+        }
+
+        // The walker doesn't descend into ignored directories
+        let mut components = rel_path.components().peekable();
+        let mut cur_path = root.to_path_buf();
+        while let Some(component) = components.next() {
+            cur_path.push(component);
+            let is_dir = components.peek().is_some();
+            if self.ignores.is_ignored(&cur_path, is_dir) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Default for NoteFilter {
+    fn default() -> Self {
+        Self {
+            ignores: IgnoreRules::default(),
+            extensions: Config::default().extensions,
+            scan_hidden: false,
+        }
+    }
+}
+
+/// Rules of the ignore files of a folder, matched the way the walker of
+/// [`scan_notes`] matches them: the rules of a deeper directory win, and
+/// [`NOTE_IGNORE_FILE`] wins over `.ignore`, which wins over `.gitignore`.
+#[derive(Debug, Clone, Default)]
+struct IgnoreRules {
+    /// Rules of each of [`IGNORE_FILES`], deepest directories first.
+    by_file: [Vec<Gitignore>; IGNORE_FILES.len()],
+}
+
+impl IgnoreRules {
+    /// Add the rules of the ignore files in `dir`.
+    fn add_dir(&mut self, dir: &Path, respect_gitignore: bool) {
+        for (rules, name) in self.by_file.iter_mut().zip(IGNORE_FILES) {
+            if !respect_gitignore && name != NOTE_IGNORE_FILE {
+                continue;
+            }
+            let file = dir.join(name);
+            if !file.is_file() {
+                continue;
+            }
+            debug!("Found ignore file: {}", file.display());
+            let (matcher, err) = Gitignore::new(&file);
+            if let Some(err) = err {
+                debug!("Failed to read ignore file: {}", err);
+            }
+            rules.push(matcher);
+        }
+    }
+
+    /// Order the rules so that the deepest directories come first.
+    fn finish(&mut self) {
+        for rules in &mut self.by_file {
+            rules.sort_by_key(|rule| std::cmp::Reverse(rule.path().components().count()));
+        }
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for rules in &self.by_file {
+            let matched = rules
+                .iter()
+                .filter(|rule| path.starts_with(rule.path()))
+                .map(|rule| rule.matched(path, is_dir))
+                .find(|matched| !matched.is_none());
+            if let Some(matched) = matched {
+                return matched.is_ignore();
+            }
+        }
+        false
+    }
+}
+
+/// Directory that is never scanned for notes, even with `scan_hidden`.
+const GIT_DIR: &str = ".git";
+
+/// Whether a path relative to the folder root is skipped for being hidden:
+/// it's in `.git`, or any of its components starts with `.` and hidden files
+/// are not scanned.
+fn is_hidden(rel_path: &Path, scan_hidden: bool) -> bool {
+    rel_path.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy();
+        name == GIT_DIR || (!scan_hidden && name.starts_with('.'))
+    })
+}
+
+fn has_note_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.iter().any(|e| e.to_lowercase() == ext))
 }
 
 #[derive(Debug, Clone)]
@@ -135,26 +394,142 @@ impl NoteFile {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// Where the title of a note without a title heading comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TitleFallback {
+    /// Only the title heading, notes without one have no title.
+    #[default]
+    Heading,
+    /// The first non-empty line of the note that's not a heading.
+    FirstLine,
+}
+
+/// How links are matched against note names.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NameMatching {
+    #[default]
+    CaseSensitive,
+    /// Useful on case-insensitive file systems, where `My-Note` and `my-note` are the same file.
+    CaseInsensitive,
+    /// Case-insensitive, and spaces, hyphens and underscores are the same
+    /// separator, so that `My Note`, `my-note` and `my_note` match.
+    Normalized,
+}
+
+impl NameMatching {
+    pub fn matches(&self, a: &NoteName, b: &NoteName) -> bool {
+        self.key(a) == self.key(b)
+    }
+
+    /// Names that match have the same key.
+    fn key<'a>(&self, name: &'a NoteName) -> Cow<'a, str> {
+        self.normalize(name.to_str())
+    }
+
+    /// The form of `name` that is compared with other names, e.g. to filter
+    /// completion candidates the same way links are matched.
+    pub fn normalize<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            NameMatching::CaseSensitive => Cow::Borrowed(name),
+            NameMatching::CaseInsensitive => Cow::Owned(name.to_lowercase()),
+            NameMatching::Normalized => {
+                let mut normalized = String::with_capacity(name.len());
+                let mut after_sep = false;
+                for c in name.chars() {
+                    if matches!(c, ' ' | '-' | '_') {
+                        if !after_sep {
+                            normalized.push('-');
+                        }
+                        after_sep = true;
+                    } else {
+                        normalized.extend(c.to_lowercase());
+                        after_sep = false;
+                    }
+                }
+                Cow::Owned(normalized)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct NoteIndex {
     notes: Arc<[NoteFile]>,
+    name_matching: NameMatching,
+    /// Lookup tables built on the first lookup. Every change of the index
+    /// produces a new `NoteIndex`, while clones share the tables.
+    lookup: Arc<OnceCell<NoteLookup>>,
+}
+
+#[derive(Debug, Default)]
+struct NoteLookup {
+    by_path: HashMap<Arc<Path>, NoteID>,
+    /// Paths without the extension, e.g. `/notes/a` for `/notes/a.md`.
+    by_stem: HashMap<PathBuf, NoteID>,
+    by_name: HashMap<String, Vec<NoteID>>,
+    /// Trailing parts of names in subfolders, e.g. `b/note` and `note` for `a/b/note`.
+    by_name_suffix: HashMap<String, Vec<NoteID>>,
 }
 
+impl PartialEq for NoteIndex {
+    fn eq(&self, other: &Self) -> bool {
+        self.notes == other.notes && self.name_matching == other.name_matching
+    }
+}
+
+impl Eq for NoteIndex {}
+
 impl Default for NoteIndex {
     fn default() -> Self {
-        Self {
-            notes: Vec::new().into(),
-        }
+        Self::new(Vec::new().into(), NameMatching::default())
     }
 }
 
 impl NoteIndex {
+    fn new(notes: Arc<[NoteFile]>, name_matching: NameMatching) -> Self {
+        Self {
+            notes,
+            name_matching,
+            lookup: Arc::default(),
+        }
+    }
+
+    fn lookup(&self) -> &NoteLookup {
+        self.lookup.get_or_init(|| {
+            let mut lookup = NoteLookup::default();
+            for (idx, file) in self.notes.iter().enumerate() {
+                lookup.by_path.insert(file.path.clone(), idx.into());
+                lookup
+                    .by_stem
+                    .entry(file.path.with_extension(""))
+                    .or_insert_with(|| idx.into());
+                lookup
+                    .by_name
+                    .entry(self.name_matching.key(&file.name).into_owned())
+                    .or_default()
+                    .push(idx.into());
+                let parts: Vec<_> = file.name.to_str().split('/').collect();
+                for start in 1..parts.len() {
+                    let suffix: NoteName = parts[start..].join("/").into();
+                    lookup
+                        .by_name_suffix
+                        .entry(self.name_matching.key(&suffix).into_owned())
+                        .or_default()
+                        .push(idx.into());
+                }
+            }
+            lookup
+        })
+    }
+
     pub fn size(&self) -> usize {
         self.notes.len()
     }
 
     pub fn ids(&self) -> impl Iterator<Item = NoteID> {
-        (0..self.notes.len()).into_iter().map(|i| i.into())
+        (0..self.notes.len()).map(|i| i.into())
     }
 
     pub fn files(&self) -> impl Iterator<Item = &NoteFile> {
@@ -162,23 +537,55 @@ impl NoteIndex {
     }
 
     pub fn find_by_path(&self, path: &Path) -> Option<NoteID> {
-        self.notes.iter().enumerate().find_map(|(idx, nf)| {
-            if nf.path.as_ref() == path {
-                Some(idx.into())
-            } else {
-                None
-            }
-        })
+        self.lookup().by_path.get(path).copied()
     }
 
+    /// Find a note by its path with the extension omitted, e.g. `/notes/a` for `/notes/a.md`.
+    pub fn find_by_path_stem(&self, stem: &Path) -> Option<NoteID> {
+        self.lookup().by_stem.get(stem).copied()
+    }
+
+    /// Find a note by name respecting the name matching mode. An exact match is
+    /// preferred. There's no match if several notes have exactly this name, e.g.
+    /// in different folders.
+    ///
+    /// A name without a note matches the trailing part of a name in a subfolder,
+    /// e.g. `note` or `b/note` match `a/b/note`. It's ambiguous, so there's no
+    /// match either, if several names end with it.
     pub fn find_by_name(&self, name: &NoteName) -> Option<NoteID> {
-        self.notes.iter().enumerate().find_map(|(idx, nf)| {
-            if NoteName::from_path(&nf.path, &nf.root) == *name {
-                Some(idx.into())
-            } else {
-                None
-            }
-        })
+        let found = self.find_all_by_name(name);
+        let mut exact = found
+            .iter()
+            .filter(|&&id| *self.notes[id.to_usize()].name == *name);
+        match (exact.next(), exact.next()) {
+            (Some(&id), None) => Some(id),
+            (Some(_), Some(_)) => None,
+            (None, _) => match found.as_slice() {
+                &[id] => Some(id),
+                _ => None,
+            },
+        }
+    }
+
+    /// All notes matching the name. There could be several in the case-insensitive
+    /// mode or when the name matches notes in different subfolders.
+    pub fn find_all_by_name(&self, name: &NoteName) -> Vec<NoteID> {
+        let lookup = self.lookup();
+        let key = self.name_matching.key(name);
+        lookup
+            .by_name
+            .get(key.as_ref())
+            .or_else(|| lookup.by_name_suffix.get(key.as_ref()))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn name_matching(&self) -> NameMatching {
+        self.name_matching
+    }
+
+    pub fn with_name_matching(&self, name_matching: NameMatching) -> NoteIndex {
+        NoteIndex::new(self.notes.clone(), name_matching)
     }
 
     pub fn find_by_id(&self, id: NoteID) -> NoteFile {
@@ -186,17 +593,40 @@ impl NoteIndex {
     }
 
     pub fn with_note_file(&self, file: NoteFile) -> NoteIndex {
+        self.with_note_files(std::iter::once(file))
+    }
+
+    pub fn with_note_files(&self, files: impl IntoIterator<Item = NoteFile>) -> NoteIndex {
         let mut notes: HashSet<NoteFile> = self
             .notes
             .iter()
             .map(|x| x.to_owned())
             .collect::<HashSet<_>>();
-        notes.insert(file);
+        notes.extend(files);
 
-        let notes = notes.into_iter().collect::<Vec<_>>();
-        NoteIndex {
-            notes: notes.into(),
-        }
+        // IDs don't depend on the order the notes were added in
+        let mut notes = notes.into_iter().collect::<Vec<_>>();
+        notes.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+        NoteIndex::new(notes.into(), self.name_matching)
+    }
+
+    /// Index without the notes of the folder `root`.
+    pub fn without_root(&self, root: &Path) -> NoteIndex {
+        self.without(|file| *file.root == *root)
+    }
+
+    pub fn without_path(&self, path: &Path) -> NoteIndex {
+        self.without(|file| *file.path == *path)
+    }
+
+    fn without(&self, pred: impl Fn(&NoteFile) -> bool) -> NoteIndex {
+        let notes = self
+            .notes
+            .iter()
+            .filter(|file| !pred(file))
+            .cloned()
+            .collect::<Vec<_>>();
+        NoteIndex::new(notes.into(), self.name_matching)
     }
 }
 
@@ -227,8 +657,8 @@ impl Version {
     }
 }
 
-pub async fn read_note(path: &Path, root: &Path, ignores: &[Pattern]) -> Result<Option<NoteText>> {
-    if is_note_file(path, root, ignores) {
+pub async fn read_note(path: &Path, root: &Path, filter: &NoteFilter) -> Result<Option<NoteText>> {
+    if filter.is_note_file(path, root) {
         let content = fs::read_to_string(path).await?;
         let meta = fs::metadata(path).await?;
         let version = Version::Fs(meta.modified()?);
@@ -239,83 +669,277 @@ pub async fn read_note(path: &Path, root: &Path, ignores: &[Pattern]) -> Result<
     }
 }
 
-pub async fn find_notes(root_path: &Path, ignores: &[Pattern]) -> Result<Vec<PathBuf>> {
-    find_notes_inner(root_path, ignores).await
+/// Find all notes under `root_path`, skipping the ones excluded by ignore files
+/// (including nested ones) if `respect_gitignore` is set. Files excluded by
+/// [`NOTE_IGNORE_FILE`] are skipped regardless, and so are hidden files unless
+/// `scan_hidden` is set.
+pub async fn find_notes(root_path: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    scan_notes(root_path, config).map(|(notes, _)| notes)
 }
 
-async fn find_notes_inner<'a>(root_path: &Path, ignores: &[Pattern]) -> Result<Vec<PathBuf>> {
-    let mut remaining_dirs = vec![root_path.to_path_buf()];
+/// Same as [`find_notes`], but also returns the filter that tells whether a
+/// single file of the folder is a note by the same rules.
+pub fn scan_notes(root_path: &Path, config: &Config) -> Result<(Vec<PathBuf>, NoteFilter)> {
+    let walker = WalkBuilder::new(root_path)
+        .standard_filters(config.respect_gitignore)
+        .add_custom_ignore_filename(NOTE_IGNORE_FILE)
+        .hidden(!config.scan_hidden)
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != GIT_DIR)
+        .build();
+
     let mut found_files = vec![];
-    while let Some(dir_path) = remaining_dirs.pop() {
-        let mut dir_contents = fs::read_dir(dir_path).await?;
-        while let Some(entry) = dir_contents.next_entry().await? {
-            let entry_type = entry.file_type().await?;
-            let entry_path = entry.path();
-            if entry_type.is_file() && is_note_file(&entry_path, root_path, ignores) {
-                found_files.push(entry_path);
-            } else if entry_type.is_dir() {
-                remaining_dirs.push(entry_path);
-            }
+    let mut ignores = IgnoreRules::default();
+    for entry in walker {
+        let entry = entry?;
+        let file_type = entry.file_type();
+        if file_type.is_some_and(|t| t.is_dir()) {
+            ignores.add_dir(entry.path(), config.respect_gitignore);
+        } else if file_type.is_some_and(|t| t.is_file())
+            && has_note_extension(entry.path(), &config.extensions)
+        {
+            found_files.push(entry.into_path());
         }
     }
-    Ok(found_files)
-}
+    ignores.finish();
 
-fn is_note_file(path: &Path, root: &Path, ignores: &[Pattern]) -> bool {
-    let path_str = match path.strip_prefix(root).ok().and_then(|p| p.to_str()) {
-        Some(str) => str,
-        _ => return false,
+    let filter = NoteFilter {
+        ignores,
+        extensions: config.extensions.clone(),
+        scan_hidden: config.scan_hidden,
     };
-    let is_md = path
-        .extension()
-        .filter(|ext| ext.to_string_lossy().to_lowercase() == "md")
-        .is_some();
-    if !is_md {
-        return false;
-    }
+    Ok((found_files, filter))
+}
 
-    for pat in ignores {
-        if pat.matches(path_str) {
-            return false;
-        }
-    }
+/// Notes excluded from the workspace whether or not `.gitignore` is respected,
+/// e.g. archived material.
+pub const NOTE_IGNORE_FILE: &str = ".zeta-note-ignore";
 
-    true
+/// Files with ignore rules, from the highest precedence to the lowest; changes
+/// to these should trigger a rescan.
+pub const IGNORE_FILES: [&str; 3] = [NOTE_IGNORE_FILE, ".ignore", ".gitignore"];
+
+pub fn is_ignore_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| IGNORE_FILES.contains(&name))
 }
 
-pub async fn find_ignores(root_path: &Path) -> Result<Vec<Pattern>> {
-    let supported_ignores = [".ignore", ".gitignore"];
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("zeta-note-store-{}", name));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("vendor")).unwrap();
+        std::fs::write(root.join("a.md"), "# A\n").unwrap();
+        std::fs::write(root.join("c.markdown"), "# C\n").unwrap();
+        std::fs::write(root.join("d.txt"), "D\n").unwrap();
+        std::fs::write(root.join("vendor").join("b.md"), "# B\n").unwrap();
+        std::fs::write(root.join(".gitignore"), "vendor/\n").unwrap();
+        root
+    }
 
-    for ignore in &supported_ignores {
-        let file = root_path.join(ignore);
+    fn note_names(workspace: &Workspace) -> Vec<String> {
+        let mut names: Vec<_> = workspace
+            .facts
+            .note_index()
+            .files()
+            .map(|f| f.name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
 
-        if file.exists() {
-            debug!("Found ignore file: {}", file.display());
+    #[tokio::test]
+    async fn find_notes_respects_gitignore() {
+        let root = temp_root("find-notes");
+
+        let mut config = Config::default();
+        let mut found = find_notes(&root, &config).await.unwrap();
+        found.sort();
+        assert_eq!(found, vec![root.join("a.md"), root.join("c.markdown")]);
+
+        config.respect_gitignore = false;
+        config.extensions = vec!["md".to_string()];
+        let mut found = find_notes(&root, &config).await.unwrap();
+        found.sort();
+        assert_eq!(
+            found,
+            vec![root.join("a.md"), root.join("vendor").join("b.md")]
+        );
+    }
 
-            let content = fs::read_to_string(file).await?;
-            let mut patterns = Vec::new();
-            for line in content.lines() {
-                if let Ok(pat) = Pattern::new(line) {
-                    patterns.push(pat);
-                }
+    #[tokio::test]
+    async fn note_ignore_file_excludes_link_targets() {
+        let root = temp_root("note-ignore");
+        std::fs::create_dir_all(root.join("archive")).unwrap();
+        std::fs::write(root.join("a.md"), "# A\n\n[:old]\n").unwrap();
+        std::fs::write(root.join("archive").join("old.md"), "# Old\n").unwrap();
+        let broken_links = |workspace: &Workspace| {
+            let facts = &workspace.facts;
+            let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+            facts
+                .note_facts(a_id)
+                .diag()
+                .iter()
+                .filter(|(d, _)| d.code() == "broken-note-link")
+                .count()
+        };
+
+        let mut config = Config::default();
+        let folder = NoteFolder::from_root_path(&root);
+        let mut workspace = Workspace::new(std::slice::from_ref(&folder), &config)
+            .await
+            .unwrap();
+        assert_eq!(broken_links(&workspace), 0);
+
+        std::fs::write(root.join(NOTE_IGNORE_FILE), "archive/\n").unwrap();
+        workspace.rescan_folder(&root, &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c"]);
+        assert_eq!(broken_links(&workspace), 1);
+
+        // Independent of `.gitignore`
+        config.respect_gitignore = false;
+        let workspace = Workspace::new(&[folder], &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c", "vendor/b"]);
+        assert_eq!(broken_links(&workspace), 1);
+    }
 
-                // Because 'glob' searches for a "full match" we need to add a
-                // _catch-all_ tail to all patterns
-                let rest_pattern = if line.ends_with('/') {
-                    line.to_string() + "**/*"
-                } else {
-                    line.to_string() + "/**/*"
-                };
-                if let Ok(pat) = Pattern::new(&rest_pattern) {
-                    patterns.push(pat);
-                }
-            }
-            debug!("Found {} ignore patterns", patterns.len());
+    #[tokio::test]
+    async fn hidden_notes_only_with_scan_hidden() {
+        let root = temp_root("hidden");
+        std::fs::create_dir_all(root.join(".notes")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::write(root.join(".notes").join("n.md"), "# N\n").unwrap();
+        std::fs::write(root.join(".git").join("g.md"), "# G\n").unwrap();
+        let folder = NoteFolder::from_root_path(&root);
+
+        let mut config = Config::default();
+        let workspace = Workspace::new(std::slice::from_ref(&folder), &config)
+            .await
+            .unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c"]);
+        let (_, filter) = scan_notes(&root, &config).unwrap();
+        assert!(!filter.is_note_file(&root.join(".notes").join("n.md"), &root));
+
+        config.scan_hidden = true;
+        let workspace = Workspace::new(&[folder], &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec![".notes/n", "a", "c"]);
+        let (_, filter) = scan_notes(&root, &config).unwrap();
+        assert!(filter.is_note_file(&root.join(".notes").join("n.md"), &root));
+        assert!(!filter.is_note_file(&root.join(".git").join("g.md"), &root));
+    }
 
-            return Ok(patterns);
-        }
+    #[tokio::test]
+    async fn nested_ignore_files() {
+        let root = temp_root("nested");
+        std::fs::create_dir_all(root.join("sub").join("deep")).unwrap();
+        std::fs::write(root.join("sub").join("x.md"), "# X\n").unwrap();
+        std::fs::write(root.join("sub").join("y.md"), "# Y\n").unwrap();
+        std::fs::write(root.join("sub").join("deep").join("y.md"), "# Y\n").unwrap();
+        std::fs::write(root.join("sub").join(".gitignore"), "x.md\ny.md\n").unwrap();
+        std::fs::write(root.join("sub").join("deep").join(".gitignore"), "!y.md\n").unwrap();
+        let folder = NoteFolder::from_root_path(&root);
+
+        let config = Config::default();
+        let workspace = Workspace::new(&[folder], &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c", "sub/deep/y"]);
+
+        let (_, filter) = scan_notes(&root, &config).unwrap();
+        assert!(!filter.is_note_file(&root.join("sub").join("x.md"), &root));
+        assert!(!filter.is_note_file(&root.join("sub").join("y.md"), &root));
+        assert!(filter.is_note_file(&root.join("sub").join("deep").join("y.md"), &root));
+        assert!(!filter.is_note_file(&root.join("vendor").join("new.md"), &root));
+        assert!(filter.is_note_file(&root.join("sub").join("new.md"), &root));
     }
 
-    debug!("Found no ignore file");
-    Ok(vec![])
+    #[tokio::test]
+    async fn rescan_picks_up_unignored_notes() {
+        let root = temp_root("rescan");
+        let folder = NoteFolder::from_root_path(&root);
+        let config = Config::default();
+        let mut workspace = Workspace::new(&[folder], &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c"]);
+
+        // An open note keeps its editor content
+        let (_, facts, _) = workspace.owning_folder_mut(&root).unwrap();
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        facts.update_note(a_id, NoteText::new(Version::Vs(1), "# Edited\n".into()));
+
+        std::fs::write(root.join(".gitignore"), "").unwrap();
+        workspace.rescan_folder(&root, &config).await.unwrap();
+        assert_eq!(note_names(&workspace), vec!["a", "c", "vendor/b"]);
+
+        let (_, facts) = workspace.owning_folder(&root).unwrap();
+        let a_id = facts.note_index().find_by_path(&root.join("a.md")).unwrap();
+        assert_eq!(&*facts.note_facts(a_id).text().content, "# Edited\n");
+    }
+
+    #[tokio::test]
+    async fn links_resolve_across_folders() {
+        let base = std::env::temp_dir().join("zeta-note-store-multi-root");
+        let _ = std::fs::remove_dir_all(&base);
+        let (work, home) = (base.join("work"), base.join("home"));
+        std::fs::create_dir_all(&work).unwrap();
+        std::fs::create_dir_all(&home).unwrap();
+        std::fs::write(work.join("a.md"), "# A\n\n[:b] [:c] [:@## Sub]\n\n## Sub\n").unwrap();
+        std::fs::write(work.join("c.md"), "# Work C\n").unwrap();
+        std::fs::write(home.join("b.md"), "# B\n").unwrap();
+        std::fs::write(home.join("c.md"), "# Home C\n").unwrap();
+
+        let config = Config::default();
+        let folders = [
+            NoteFolder::from_root_path(&work),
+            NoteFolder::from_root_path(&home),
+        ];
+        let mut workspace = Workspace::new(&folders, &config).await.unwrap();
+        assert_eq!(workspace.note_count(), 4);
+
+        let diag_codes = |workspace: &Workspace| {
+            let (_, facts) = workspace.owning_folder(&work).unwrap();
+            let a_id = facts.note_index().find_by_path(&work.join("a.md")).unwrap();
+            let mut codes = facts
+                .note_facts(a_id)
+                .diag()
+                .iter()
+                .map(|(d, _)| d.code())
+                .filter(|&code| code != "orphan-note")
+                .collect::<Vec<_>>();
+            codes.sort_unstable();
+            codes
+        };
+        // `c` exists in both folders
+        assert_eq!(diag_codes(&workspace), vec!["ambiguous-note-link"]);
+        assert_eq!(workspace.facts.note_index().find_by_name(&"c".into()), None);
+
+        workspace.remove_folder(&home);
+        assert_eq!(workspace.note_count(), 2);
+        assert_eq!(diag_codes(&workspace), vec!["broken-note-link"]);
+    }
+
+    #[test]
+    fn note_ids_independent_of_order() {
+        let root = Path::new("/notes");
+        let files: Vec<_> = ["b.md", "sub/a.md", "a.md", "c.md"]
+            .iter()
+            .map(|path| NoteFile::new(root, &root.join(path)))
+            .collect();
+        let forward = NoteIndex::default().with_note_files(files.clone());
+        let backward = NoteIndex::default().with_note_files(files.into_iter().rev());
+
+        let paths = |index: &NoteIndex| {
+            index
+                .ids()
+                .map(|id| index.find_by_id(id).path.to_path_buf())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(paths(&forward), paths(&backward));
+        assert_eq!(
+            forward.find_all_by_name(&"a".into()),
+            backward.find_all_by_name(&"a".into())
+        );
+    }
 }
@@ -1,6 +1,8 @@
-use std::{fmt::Debug, sync::Arc};
+use std::{collections::BTreeMap, fmt::Debug, sync::Arc};
 
-use crate::parser::{Element, Heading, InternLink, Node};
+use serde::{Deserialize, Serialize};
+
+use crate::parser::{Element, ExternLink, Heading, InternLink, Node, Tag, UndefinedLinkRef};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct NoteID(u32);
@@ -66,7 +68,8 @@ impl Structure {
                 Element::InternLink(..) => {
                     ids.push(ElementID::InternLink(InternLinkID(idx as u32)))
                 }
-                Element::ExternLink(..) => (),
+                Element::Tag(..) => ids.push(ElementID::Tag(TagID(idx as u32))),
+                Element::ExternLink(..) | Element::UndefinedLinkRef(..) => (),
             }
         }
 
@@ -81,7 +84,8 @@ impl Structure {
                 Element::InternLink(..) => {
                     els.push((ElementID::InternLink(InternLinkID(idx as u32)), ewl))
                 }
-                Element::ExternLink(..) => (),
+                Element::Tag(..) => els.push((ElementID::Tag(TagID(idx as u32)), ewl)),
+                Element::ExternLink(..) | Element::UndefinedLinkRef(..) => (),
             }
         }
 
@@ -154,18 +158,96 @@ impl Structure {
             .map(move |&id| self.intern_link_by_id(id))
             .collect()
     }
+
+    pub fn undefined_link_refs(&self) -> Vec<&Node<UndefinedLinkRef>> {
+        self.elements_flat
+            .iter()
+            .filter_map(|el| match el {
+                Element::UndefinedLinkRef(r) => Some(r),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Links to web pages and other non-notes, e.g. for validating URLs.
+    pub fn external_links(&self) -> Vec<&Node<ExternLink>> {
+        self.elements_flat
+            .iter()
+            .filter_map(|el| match el {
+                Element::ExternLink(l) => Some(l),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn tags(&self) -> Vec<TagID> {
+        let mut tags = Vec::new();
+        for (idx, el) in self.elements_flat.iter().enumerate() {
+            if let Element::Tag(..) = el {
+                tags.push(TagID(idx as u32))
+            }
+        }
+
+        tags
+    }
+
+    pub fn tag_by_id(&self, id: TagID) -> &Node<Tag> {
+        let el = &self.elements_flat[id.0 as usize];
+        if let Element::Tag(t) = el {
+            t
+        } else {
+            panic!("Expected a tag at idx {:?} in {:?}", id, self.elements_flat)
+        }
+    }
+
+    pub fn tags_with_ids(&self, ids: &[TagID]) -> Vec<&Node<Tag>> {
+        ids.iter().map(move |&id| self.tag_by_id(id)).collect()
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Occurrences of tags across all notes keyed by a tag name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TagIndex {
+    tags: BTreeMap<String, Vec<(NoteID, TagID)>>,
+}
+
+impl TagIndex {
+    pub fn insert(&mut self, name: &str, note_id: NoteID, tag_id: TagID) {
+        self.tags
+            .entry(name.to_string())
+            .or_default()
+            .push((note_id, tag_id));
+    }
+
+    /// Tag names in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    pub fn find_by_name(&self, name: &str) -> &[(NoteID, TagID)] {
+        self.tags.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Number of times the tag is used across all notes.
+    pub fn count(&self, name: &str) -> usize {
+        self.find_by_name(name).len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct HeadingID(u32);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct InternLinkID(u32);
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TagID(u32);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ElementID {
     Heading(HeadingID),
     InternLink(InternLinkID),
+    Tag(TagID),
 }
 
 impl ElementID {
@@ -173,6 +255,7 @@ impl ElementID {
         match self {
             ElementID::Heading(HeadingID(id)) => *id,
             ElementID::InternLink(InternLinkID(id)) => *id,
+            ElementID::Tag(TagID(id)) => *id,
         }
     }
 
@@ -208,7 +291,7 @@ heading 2.3
         let elements = parser::scrape(&index);
         let strukt = Structure::new(elements);
         let headings = strukt.headings_with_ids(&strukt.headings());
-        let title = *headings.get(0).unwrap();
+        let title = *headings.first().unwrap();
         assert_eq!(title.text, "# Title");
         assert_eq!(title.scope, Pos::new(1, 0)..Pos::new(11, 12));
 
@@ -1,3 +1,9 @@
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
 /// Test the the text matches all characters in the query in order.
 ///
 /// ```rust
@@ -30,3 +36,172 @@ pub fn text_matches_query(text: &str, query: &str) -> bool {
 
     true
 }
+
+/// Score how well the text matches the query, lower is better. `None` means the
+/// text doesn't contain all characters of the query in order.
+///
+/// Prefix matches rank first, then matches of a contiguous substring, then the
+/// rest ranked by how spread out the matched characters are.
+///
+/// ```rust
+/// use zeta_note::util::fuzzy_score;
+///
+/// let arch = |text| fuzzy_score(text, "arch");
+/// assert!(arch("architecture") < arch("system-architecture"));
+/// assert!(arch("system-architecture") < arch("a-r-c-h"));
+/// assert!(arch("Arch") < arch("architecture"));
+/// assert_eq!(arch("notes"), None);
+/// assert_eq!(fuzzy_score("notes", ""), Some(0));
+/// ```
+pub fn fuzzy_score(text: &str, query: &str) -> Option<u32> {
+    const TIER: usize = 100_000;
+
+    let text = text.to_lowercase();
+    let query = query.to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let score = if text.starts_with(&query) {
+        text.len() - query.len()
+    } else if let Some(offset) = text.find(&query) {
+        TIER + offset
+    } else {
+        let mut gaps = 0;
+        let mut start = 0;
+        for c in query.chars() {
+            let char_pos = text[start..].find(c)?;
+            gaps += char_pos;
+            start += char_pos + c.len_utf8();
+        }
+        2 * TIER + gaps
+    };
+    Some(score.min(3 * TIER - 1) as u32)
+}
+
+/// Number of single-character insertions, deletions, and substitutions that
+/// turn `a` into `b`.
+///
+/// ```rust
+/// use zeta_note::util::levenshtein;
+///
+/// assert_eq!(levenshtein("note", "note"), 0);
+/// assert_eq!(levenshtein("note", "nots"), 1);
+/// assert_eq!(levenshtein("kitten", "sitting"), 3);
+/// assert_eq!(levenshtein("", "abc"), 3);
+/// ```
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    // Distances from the prefix of `a` seen so far to each prefix of `b`
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = diag + usize::from(ca != *cb);
+            diag = row[j + 1];
+            row[j + 1] = subst.min(row[j] + 1).min(diag + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Path to `to` relative to the directory `from_dir`. Both paths are expected
+/// to be absolute.
+///
+/// ```rust
+/// use std::path::Path;
+/// use zeta_note::util::relative_path;
+///
+/// let rel = |from, to| relative_path(Path::new(from), Path::new(to));
+/// assert_eq!(rel("/notes", "/notes/a.md"), Path::new("a.md"));
+/// assert_eq!(rel("/notes/sub", "/notes/a.md"), Path::new("../a.md"));
+/// assert_eq!(rel("/notes/x", "/notes/y/b.md"), Path::new("../y/b.md"));
+/// ```
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from: Vec<Component> = from_dir.components().collect();
+    let to: Vec<Component> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut rel = PathBuf::new();
+    for _ in common..from.len() {
+        rel.push(Component::ParentDir);
+    }
+    rel.extend(&to[common..]);
+    rel
+}
+
+/// Resolve `.` and `..` components of the path without touching the file system.
+///
+/// ```rust
+/// use std::path::Path;
+/// use zeta_note::util::normalize_path;
+///
+/// assert_eq!(normalize_path(Path::new("/notes/sub/../a.md")), Path::new("/notes/a.md"));
+/// assert_eq!(normalize_path(Path::new("/notes/./sub/b.md")), Path::new("/notes/sub/b.md"));
+/// ```
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Check if there is a file with the same stem as `path` (e.g. `note.txt` for `note.md`).
+pub fn exists_with_any_extension(path: &Path) -> bool {
+    let (dir, stem) = match (path.parent(), path.file_stem()) {
+        (Some(dir), Some(stem)) => (dir, stem),
+        _ => return false,
+    };
+
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .any(|entry| entry.path().file_stem() == Some(stem)),
+        _ => false,
+    }
+}
+
+/// Decode `%XX` escapes in a URL-encoded text, e.g. `my%20note`. Invalid escapes
+/// are kept as is, and so is the whole text if it doesn't decode to UTF-8.
+///
+/// ```rust
+/// use zeta_note::util::percent_decode;
+///
+/// assert_eq!(percent_decode("my%20note"), "my note");
+/// assert_eq!(percent_decode("sub%2Fnote"), "sub/note");
+/// assert_eq!(percent_decode("100%zz"), "100%zz");
+/// assert_eq!(percent_decode("%C3%A9t%C3%A9"), "été");
+/// assert_eq!(percent_decode("%FF"), "%FF");
+/// ```
+pub fn percent_decode(text: &str) -> Cow<'_, str> {
+    if !text.contains('%') {
+        return Cow::Borrowed(text);
+    }
+
+    let hex = |b: u8| (b as char).to_digit(16);
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                decoded.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8(decoded)
+        .map(Cow::Owned)
+        .unwrap_or(Cow::Borrowed(text))
+}